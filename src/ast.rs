@@ -0,0 +1,464 @@
+// Syntax tree produced by the parser. Names are kept as written;
+// resolving them against the table happens when the statement is prepared.
+
+use std::fmt;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Statement {
+    Select(Select),
+    Insert(Insert),
+    Update(Update),
+    Delete(Delete),
+    CreateTable(CreateTable),
+    DropTable(DropTable),
+    CreateIndex(CreateIndex),
+    DropIndex(DropIndex),
+    Pragma(Pragma),
+    Begin,
+    Commit,
+    /// `ROLLBACK`, or `ROLLBACK TO` the named savepoint.
+    Rollback(Option<String>),
+    Savepoint(String),
+    Release(String),
+    /// `VACUUM`, or `VACUUM INTO` the named file.
+    Vacuum(Option<String>),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Select {
+    pub columns: Vec<ResultColumn>,
+    /// The table read from, `None` for a select of values without FROM
+    pub table: Option<String>,
+    pub where_clause: Option<Expr>,
+    pub order_by: Vec<OrderingTerm>,
+    pub limit: Option<Expr>,
+    pub offset: Option<Expr>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ResultColumn {
+    /// `*`, every column of the table
+    Wildcard,
+    Expr(Expr),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct OrderingTerm {
+    pub expr: Expr,
+    pub descending: bool,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Insert {
+    pub table: String,
+    pub columns: Option<Vec<String>>,
+    pub rows: Vec<Vec<Expr>>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Update {
+    pub table: String,
+    pub assignments: Vec<(String, Expr)>,
+    pub where_clause: Option<Expr>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Delete {
+    pub table: String,
+    pub where_clause: Option<Expr>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct CreateTable {
+    pub name: String,
+    pub columns: Vec<ColumnDef>,
+}
+
+/// `CREATE [UNIQUE] INDEX [name] ON table (column, ...)`. An index
+/// created without a name is named after its table and columns.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CreateIndex {
+    pub name: Option<String>,
+    pub table: String,
+    pub columns: Vec<String>,
+    pub unique: bool,
+}
+
+/// `PRAGMA name` reads a setting, `PRAGMA name = value` changes it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Pragma {
+    pub name: String,
+    pub value: Option<Expr>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct DropTable {
+    pub name: String,
+    /// `IF EXISTS`, dropping a missing table is not an error
+    pub if_exists: bool,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct DropIndex {
+    pub name: String,
+    /// `IF EXISTS`, dropping a missing index is not an error
+    pub if_exists: bool,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ColumnDef {
+    pub name: String,
+    /// Type name as written, e.g. `VARCHAR(32)`.
+    pub type_name: Option<TypeName>,
+    pub primary_key: bool,
+    /// `PRIMARY KEY AUTOINCREMENT`, keys are never reused
+    pub autoincrement: bool,
+    pub not_null: bool,
+    pub unique: bool,
+    /// `DEFAULT value`, the value of the column when an insert leaves it out
+    pub default: Option<Expr>,
+    /// `CHECK (condition)`, one for each given
+    pub checks: Vec<Expr>,
+    pub references: Option<ForeignKey>,
+}
+
+/// `REFERENCES table [(column)]` on a column: each of its values that is
+/// not NULL must be a value of the parent column, which defaults to the
+/// parent table's primary key.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ForeignKey {
+    pub table: String,
+    pub column: Option<String>,
+    pub on_delete: ForeignKeyAction,
+    pub on_update: ForeignKeyAction,
+    /// `DEFERRABLE INITIALLY DEFERRED`, checked when the transaction
+    /// commits rather than when the statement ends
+    pub deferred: bool,
+}
+
+/// What happens to the rows of the child table when the parent value they
+/// reference is deleted or changed.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ForeignKeyAction {
+    /// Fail unless no row references the value by the end of the statement
+    NoAction,
+    /// Fail right away if a row references the value
+    Restrict,
+    SetNull,
+    SetDefault,
+    /// Delete the referencing rows, or change them to the new value
+    Cascade,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TypeName {
+    pub name: String,
+    pub arguments: Vec<i64>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Literal {
+    Null,
+    Integer(i64),
+    Float(f64),
+    String(String),
+    Blob(Vec<u8>),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum UnaryOperator {
+    Negate,
+    Plus,
+    Not,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BinaryOperator {
+    Or,
+    And,
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Modulo,
+    Concat,
+}
+
+impl BinaryOperator {
+    pub fn is_comparison(self) -> bool {
+        matches!(
+            self,
+            BinaryOperator::Equal
+                | BinaryOperator::NotEqual
+                | BinaryOperator::Less
+                | BinaryOperator::LessEqual
+                | BinaryOperator::Greater
+                | BinaryOperator::GreaterEqual
+        )
+    }
+
+    /// The operator with its operands swapped, `a < b` is `b > a`.
+    pub fn flip(self) -> Self {
+        match self {
+            BinaryOperator::Less => BinaryOperator::Greater,
+            BinaryOperator::LessEqual => BinaryOperator::GreaterEqual,
+            BinaryOperator::Greater => BinaryOperator::Less,
+            BinaryOperator::GreaterEqual => BinaryOperator::LessEqual,
+            operator => operator,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Expr {
+    Literal(Literal),
+    /// Bound parameter, numbered from 1
+    Parameter(usize),
+    Column(String),
+    Unary(UnaryOperator, Box<Expr>),
+    Binary(Box<Expr>, BinaryOperator, Box<Expr>),
+    IsNull {
+        expr: Box<Expr>,
+        negated: bool,
+    },
+    Like {
+        expr: Box<Expr>,
+        pattern: Box<Expr>,
+        negated: bool,
+    },
+    InList {
+        expr: Box<Expr>,
+        list: Vec<Expr>,
+        negated: bool,
+    },
+    Between {
+        expr: Box<Expr>,
+        low: Box<Expr>,
+        high: Box<Expr>,
+        negated: bool,
+    },
+    /// Call of a built-in function
+    Function {
+        name: String,
+        args: Vec<Expr>,
+    },
+}
+
+impl Statement {
+    /// Replace every parameter with the literal `value` gives for its number.
+    pub fn bind_parameters(&mut self, value: &impl Fn(usize) -> Literal) {
+        let exprs: Vec<&mut Expr> = match self {
+            Statement::Select(select) => select
+                .columns
+                .iter_mut()
+                .filter_map(|column| match column {
+                    ResultColumn::Wildcard => None,
+                    ResultColumn::Expr(expr) => Some(expr),
+                })
+                .chain(select.where_clause.iter_mut())
+                .chain(select.order_by.iter_mut().map(|term| &mut term.expr))
+                .chain(select.limit.iter_mut())
+                .chain(select.offset.iter_mut())
+                .collect(),
+            Statement::Insert(insert) => insert.rows.iter_mut().flatten().collect(),
+            Statement::Update(update) => update
+                .assignments
+                .iter_mut()
+                .map(|(_, expr)| expr)
+                .chain(update.where_clause.iter_mut())
+                .collect(),
+            Statement::Delete(delete) => delete.where_clause.iter_mut().collect(),
+            Statement::Pragma(pragma) => pragma.value.iter_mut().collect(),
+            Statement::CreateTable(create) => create
+                .columns
+                .iter_mut()
+                .flat_map(|column| column.default.iter_mut().chain(column.checks.iter_mut()))
+                .collect(),
+            _ => Vec::new(),
+        };
+        for expr in exprs {
+            expr.bind_parameters(value);
+        }
+    }
+}
+
+impl Expr {
+    fn bind_parameters(&mut self, value: &impl Fn(usize) -> Literal) {
+        match self {
+            Expr::Parameter(number) => *self = Expr::Literal(value(*number)),
+            Expr::Literal(_) | Expr::Column(_) => {}
+            Expr::Unary(_, expr) | Expr::IsNull { expr, .. } => expr.bind_parameters(value),
+            Expr::Binary(left, _, right)
+            | Expr::Like {
+                expr: left,
+                pattern: right,
+                ..
+            } => {
+                left.bind_parameters(value);
+                right.bind_parameters(value);
+            }
+            Expr::InList { expr, list, .. } => {
+                expr.bind_parameters(value);
+                list.iter_mut().for_each(|expr| expr.bind_parameters(value));
+            }
+            Expr::Function { args, .. } => {
+                args.iter_mut().for_each(|expr| expr.bind_parameters(value));
+            }
+            Expr::Between {
+                expr, low, high, ..
+            } => {
+                expr.bind_parameters(value);
+                low.bind_parameters(value);
+                high.bind_parameters(value);
+            }
+        }
+    }
+}
+
+impl fmt::Display for Literal {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Literal::Null => write!(f, "NULL"),
+            Literal::Integer(integer) => write!(f, "{}", integer),
+            // Too large a number reads back as infinity
+            Literal::Float(float) if float.is_infinite() => write!(f, "9e999"),
+            Literal::Float(float) => write!(f, "{:?}", float),
+            Literal::String(string) => write!(f, "'{}'", string.replace('\'', "''")),
+            Literal::Blob(blob) => {
+                write!(f, "X'")?;
+                for byte in blob {
+                    write!(f, "{:02X}", byte)?;
+                }
+                write!(f, "'")
+            }
+        }
+    }
+}
+
+impl fmt::Display for ForeignKeyAction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let action = match self {
+            ForeignKeyAction::NoAction => "NO ACTION",
+            ForeignKeyAction::Restrict => "RESTRICT",
+            ForeignKeyAction::SetNull => "SET NULL",
+            ForeignKeyAction::SetDefault => "SET DEFAULT",
+            ForeignKeyAction::Cascade => "CASCADE",
+        };
+        write!(f, "{}", action)
+    }
+}
+
+impl fmt::Display for UnaryOperator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            UnaryOperator::Negate => write!(f, "-"),
+            UnaryOperator::Plus => write!(f, "+"),
+            UnaryOperator::Not => write!(f, "NOT "),
+        }
+    }
+}
+
+impl fmt::Display for BinaryOperator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let operator = match self {
+            BinaryOperator::Or => "OR",
+            BinaryOperator::And => "AND",
+            BinaryOperator::Equal => "=",
+            BinaryOperator::NotEqual => "!=",
+            BinaryOperator::Less => "<",
+            BinaryOperator::LessEqual => "<=",
+            BinaryOperator::Greater => ">",
+            BinaryOperator::GreaterEqual => ">=",
+            BinaryOperator::Add => "+",
+            BinaryOperator::Subtract => "-",
+            BinaryOperator::Multiply => "*",
+            BinaryOperator::Divide => "/",
+            BinaryOperator::Modulo => "%",
+            BinaryOperator::Concat => "||",
+        };
+        write!(f, "{}", operator)
+    }
+}
+
+/// SQL text of the expression, which parses back to the same expression.
+/// Operands that are not a single literal, parameter or column are
+/// parenthesized, so no precedence rules are needed.
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let not = |negated: &bool| if *negated { "NOT " } else { "" };
+        match self {
+            Expr::Literal(literal) => write!(f, "{}", literal),
+            Expr::Parameter(number) => write!(f, "?{}", number),
+            Expr::Column(column) => write!(f, "{}", column),
+            Expr::Unary(operator, expr) => write!(f, "{}{}", operator, Operand(expr)),
+            Expr::Binary(left, operator, right) => {
+                write!(f, "{} {} {}", Operand(left), operator, Operand(right))
+            }
+            Expr::IsNull { expr, negated } => {
+                write!(f, "{} IS {}NULL", Operand(expr), not(negated))
+            }
+            Expr::Like {
+                expr,
+                pattern,
+                negated,
+            } => write!(
+                f,
+                "{} {}LIKE {}",
+                Operand(expr),
+                not(negated),
+                Operand(pattern)
+            ),
+            Expr::InList {
+                expr,
+                list,
+                negated,
+            } => {
+                let list: Vec<_> = list.iter().map(Expr::to_string).collect();
+                write!(
+                    f,
+                    "{} {}IN ({})",
+                    Operand(expr),
+                    not(negated),
+                    list.join(", ")
+                )
+            }
+            Expr::Between {
+                expr,
+                low,
+                high,
+                negated,
+            } => write!(
+                f,
+                "{} {}BETWEEN {} AND {}",
+                Operand(expr),
+                not(negated),
+                Operand(low),
+                Operand(high)
+            ),
+            Expr::Function { name, args } => {
+                let args: Vec<_> = args.iter().map(Expr::to_string).collect();
+                write!(f, "{}({})", name, args.join(", "))
+            }
+        }
+    }
+}
+
+// An operand of an operator, parenthesized unless it is a single term
+struct Operand<'a>(&'a Expr);
+
+impl fmt::Display for Operand<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0 {
+            Expr::Literal(_) | Expr::Parameter(_) | Expr::Column(_) | Expr::Function { .. } => {
+                write!(f, "{}", self.0)
+            }
+            expr => write!(f, "({})", expr),
+        }
+    }
+}
@@ -0,0 +1,84 @@
+use crate::error::{CorruptErr, Error};
+use crate::row::Row;
+use crate::schema::TableSchema;
+use crate::value::Value;
+
+/// Name of the table listing every table of the database.
+pub const CATALOG_TABLE_NAME: &str = "sqlite_master";
+// The catalog's B-tree starts on the first page after the header,
+// so it can be found without a lookup
+pub const CATALOG_ROOT_PAGE_NUM: u32 = 1;
+
+// Longest name and CREATE statement the catalog can hold
+pub const CATALOG_NAME_MAX_SIZE: usize = 64;
+pub const CATALOG_SQL_MAX_SIZE: usize = 1024;
+
+const CATALOG_SQL: &str = "CREATE TABLE sqlite_master (rowid INTEGER PRIMARY KEY, \
+    type TEXT(8), name TEXT(64), tbl_name TEXT(64), rootpage INTEGER, sql TEXT(1024))";
+
+/// Name of the table holding the largest key each AUTOINCREMENT table has
+/// held, created along with the first such table. Like the catalog it is
+/// keyed by a rowid column of its own.
+pub const SEQUENCE_TABLE_NAME: &str = "sqlite_sequence";
+
+const SEQUENCE_SQL: &str =
+    "CREATE TABLE sqlite_sequence (rowid INTEGER PRIMARY KEY, name TEXT(64), seq INTEGER)";
+
+/// Kind of object a catalog entry describes, stored as its `type` column.
+pub const CATALOG_TYPE_TABLE: &str = "table";
+pub const CATALOG_TYPE_INDEX: &str = "index";
+
+/// A row of the catalog: a named object, the table it belongs to,
+/// the page its B-tree starts on and the SQL that created it.
+#[derive(Clone, Debug)]
+pub struct CatalogEntry {
+    pub key: i64,
+    pub kind: String,
+    pub name: String,
+    pub table_name: String,
+    pub root_page_num: u32,
+    pub sql: String,
+}
+
+impl CatalogEntry {
+    pub fn to_row(&self) -> Row {
+        Row::new(vec![
+            Value::Integer(self.key),
+            Value::Text(self.kind.clone()),
+            Value::Text(self.name.clone()),
+            Value::Text(self.table_name.clone()),
+            Value::Integer(self.root_page_num as i64),
+            Value::Text(self.sql.clone()),
+        ])
+    }
+
+    pub fn from_row(row: &Row) -> Result<Self, Error> {
+        let [Value::Integer(key), Value::Text(kind), Value::Text(name), Value::Text(table_name), Value::Integer(root_page_num), Value::Text(sql)] =
+            row.values.as_slice()
+        else {
+            return Err(CorruptErr::Invalid("Catalog row has the wrong columns").into());
+        };
+        let Ok(root_page_num) = u32::try_from(*root_page_num) else {
+            return Err(CorruptErr::Invalid("Catalog row has an invalid page number").into());
+        };
+
+        Ok(Self {
+            key: *key,
+            kind: kind.clone(),
+            name: name.clone(),
+            table_name: table_name.clone(),
+            root_page_num,
+            sql: sql.clone(),
+        })
+    }
+}
+
+/// Schema of the catalog table itself.
+pub fn catalog_schema() -> TableSchema {
+    TableSchema::from_sql(CATALOG_SQL).expect("catalog_schema: the catalog schema is valid")
+}
+
+/// Schema of the table of AUTOINCREMENT sequences.
+pub fn sequence_schema() -> TableSchema {
+    TableSchema::from_sql(SEQUENCE_SQL).expect("sequence_schema: the sequence schema is valid")
+}
@@ -0,0 +1,204 @@
+use std::cell::{RefCell, RefMut};
+
+use crate::ast;
+use crate::database::Database;
+use crate::error::Error;
+use crate::parser::Parser;
+use crate::rows::Rows;
+use crate::statement::{self, PrepareStatementErr};
+use crate::value::Value;
+
+/// An open database, the entry point for running SQL against it.
+///
+/// Outside a transaction every statement commits on its own, or is
+/// rolled back if it fails. `BEGIN` groups statements until `COMMIT`.
+pub struct Connection {
+    database: RefCell<Database>,
+}
+
+impl Connection {
+    /// Open the database file at `path`, creating it if it does not exist.
+    pub fn open(path: &str) -> Result<Self, Error> {
+        Ok(Self {
+            database: RefCell::new(Database::db_open(path)?),
+        })
+    }
+
+    /// Run a statement once with `params` bound to its parameters, returning
+    /// the number of rows it changed. Rows returned by the statement are read
+    /// and dropped.
+    pub fn execute(&self, sql: &str, params: &[Value]) -> Result<usize, Error> {
+        self.prepare(sql)?.execute(params)
+    }
+
+    /// Parse a statement to run it any number of times. A statement without
+    /// parameters is planned right away, one with parameters when it first
+    /// runs, so that errors depending on the bound values come from running it.
+    pub fn prepare(&self, sql: &str) -> Result<Statement<'_>, Error> {
+        let mut parser = Parser::new(sql)?;
+        let ast = parser.parse_statement()?;
+        let parameters = parser.parameters().to_vec();
+
+        let mut statement = Statement {
+            connection: self,
+            ast,
+            bindings: vec![Value::Null; parameters.len()],
+            parameters,
+            plan: None,
+            schema_cookie: 0,
+        };
+        if statement.parameters.is_empty() {
+            statement.prepare_plan(&self.database.borrow())?;
+        }
+        Ok(statement)
+    }
+
+    /// Key of the row most recently inserted through this connection, 0 if
+    /// none was. A key left out of an insert is found here.
+    pub fn last_insert_rowid(&self) -> i64 {
+        self.database.borrow().last_insert_rowid()
+    }
+
+    /// The database underneath, for inspecting its pages and tables.
+    /// Statements cannot run while it is borrowed.
+    pub fn database(&self) -> RefMut<'_, Database> {
+        self.database.borrow_mut()
+    }
+}
+
+/// A prepared statement of a connection, with values bound to its
+/// parameters. Parameters are numbered from 1 and start out bound to NULL.
+///
+/// The bound values are put into the statement as literals when it is
+/// planned. The statement is planned again when a value is bound or the
+/// schema changed since it was last planned.
+pub struct Statement<'conn> {
+    connection: &'conn Connection,
+    ast: ast::Statement,
+    // Name of each parameter, `None` for `?` and `?NNN`
+    parameters: Vec<Option<String>>,
+    bindings: Vec<Value>,
+    plan: Option<statement::Statement>,
+    // Schema cookie of the database when the plan was made
+    schema_cookie: u32,
+}
+
+impl Statement<'_> {
+    /// Number of parameters, which is the largest parameter number used.
+    pub fn parameter_count(&self) -> usize {
+        self.parameters.len()
+    }
+
+    /// Number of the parameter with the given name, including its `:`.
+    pub fn parameter_index(&self, name: &str) -> Option<usize> {
+        self.parameters
+            .iter()
+            .position(|parameter| parameter.as_deref() == Some(name))
+            .map(|index| index + 1)
+    }
+
+    /// Name of a parameter, `None` for `?` and `?NNN` parameters.
+    pub fn parameter_name(&self, index: usize) -> Option<&str> {
+        self.parameters.get(index.checked_sub(1)?)?.as_deref()
+    }
+
+    /// Bind a value to the parameter numbered `index`.
+    pub fn bind(&mut self, index: usize, value: Value) -> Result<(), Error> {
+        let binding = index
+            .checked_sub(1)
+            .and_then(|index| self.bindings.get_mut(index))
+            .ok_or(PrepareStatementErr::ParameterOutOfRange(index))?;
+        *binding = value;
+        self.plan = None;
+        Ok(())
+    }
+
+    pub fn bind_int(&mut self, index: usize, value: i64) -> Result<(), Error> {
+        self.bind(index, Value::Integer(value))
+    }
+
+    pub fn bind_text(&mut self, index: usize, value: &str) -> Result<(), Error> {
+        self.bind(index, Value::Text(value.to_owned()))
+    }
+
+    pub fn bind_blob(&mut self, index: usize, value: &[u8]) -> Result<(), Error> {
+        self.bind(index, Value::Blob(value.to_vec()))
+    }
+
+    pub fn bind_null(&mut self, index: usize) -> Result<(), Error> {
+        self.bind(index, Value::Null)
+    }
+
+    /// Bind NULL to every parameter.
+    pub fn clear_bindings(&mut self) {
+        self.bindings.fill(Value::Null);
+        self.plan = None;
+    }
+
+    // Plan the statement with the values bound to its parameters
+    fn prepare_plan(&mut self, database: &Database) -> Result<(), Error> {
+        let mut ast = self.ast.clone();
+        ast.bind_parameters(&|number| match &self.bindings[number - 1] {
+            // NaN is not a number SQL can hold, so it binds as NULL
+            Value::Real(real) if real.is_nan() => ast::Literal::Null,
+            value => value.to_literal(),
+        });
+        self.plan = Some(statement::Statement::plan(ast, database)?);
+        self.schema_cookie = database.pager.header.schema_cookie;
+        Ok(())
+    }
+
+    /// Run the statement, returning the number of rows it changed.
+    /// Values in `params`, if any, are bound to the parameters first.
+    pub fn execute(&mut self, params: &[Value]) -> Result<usize, Error> {
+        let mut rows = self.query(params)?;
+        for row in rows.by_ref() {
+            row?;
+        }
+        Ok(rows.rows_changed().unwrap_or(0))
+    }
+
+    /// Run the statement, returning the rows it produces. The rows of a
+    /// select are read from the table as the returned rows are iterated.
+    /// Values in `params`, if any, are bound to the parameters first and
+    /// there must be one for every parameter.
+    pub fn query(&mut self, params: &[Value]) -> Result<Rows<'_>, Error> {
+        if !params.is_empty() {
+            if params.len() != self.parameters.len() {
+                return Err(PrepareStatementErr::ParameterCountMismatch {
+                    values: params.len(),
+                    parameters: self.parameters.len(),
+                }
+                .into());
+            }
+            self.bindings.clone_from_slice(params);
+            self.plan = None;
+        }
+
+        let cell = &self.connection.database;
+        let mut database = cell.borrow_mut();
+        if self.plan.is_none() || database.pager.header.schema_cookie != self.schema_cookie {
+            self.prepare_plan(&database)?;
+        }
+        let plan = self
+            .plan
+            .as_ref()
+            .expect("query: the statement was just planned");
+
+        // A select only reads, so there is nothing to commit
+        if let statement::Statement::Select(select) = plan {
+            drop(database);
+            return Rows::select(select, cell);
+        }
+
+        let mut result = plan.execute_statement(&mut database);
+        if !database.in_transaction() {
+            result = result.and_then(|ok| database.commit().map(|()| ok));
+            // A journal left by a failed rollback is rolled back on the next open
+            if result.is_err() {
+                let _ = database.roll_back();
+            }
+        }
+        Ok(Rows::from(result?))
+    }
+}
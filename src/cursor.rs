@@ -1,51 +1,104 @@
 use crate::{
-    node::{
-        LeafNodeCell, Node, LEAF_NODE_LEFT_SPLIT_COUNT, LEAF_NODE_MAX_CELLS,
-        LEAF_NODE_RIGHT_SPLIT_COUNT,
-    },
-    row::Row,
+    error::{CorruptErr, Error},
+    node::{LeafNodeCell, Node, LEAF_NODE_MIN_USED_SPACE},
     table::Table,
 };
 
 pub struct Cursor<'a> {
-    pub table: &'a mut Table,
+    pub table: Table<'a>,
     page_num: u32,
     pub cell_num: u32,      // Indicates the row num
     pub end_of_table: bool, // Indicates a position one past the last element
+    // Leaves moved on to by advancing. More than there are pages means the
+    // leaves link up in a loop.
+    leaves_visited: u32,
+}
+
+/// Where a cursor is in its tree, to pick it up again later. It only
+/// holds as long as the tree does not change.
+#[derive(Clone, Copy)]
+pub struct CursorPosition {
+    page_num: u32,
+    cell_num: u32,
+    leaves_visited: u32,
 }
 
 impl<'a> Cursor<'a> {
-    pub fn table_start(table: &'a mut Table) -> Self {
-        let cursor = Cursor::table_find(table, 0);
+    /// A cursor back at a position it was at before.
+    pub fn table_at(table: Table<'a>, position: CursorPosition) -> Self {
+        Self {
+            table,
+            page_num: position.page_num,
+            cell_num: position.cell_num,
+            end_of_table: false,
+            leaves_visited: position.leaves_visited,
+        }
+    }
 
-        let page_num = cursor.page_num;
-        let node = table.pager.get_page(page_num);
-        let num_cells = *node.leaf_node_num_cells();
-        let end_of_table = num_cells == 0;
+    pub fn position(&self) -> CursorPosition {
+        CursorPosition {
+            page_num: self.page_num,
+            cell_num: self.cell_num,
+            leaves_visited: self.leaves_visited,
+        }
+    }
+
+    pub fn table_start(table: Table<'a>) -> Result<Self, Error> {
+        let mut cursor = Cursor::table_find(table, i64::MIN)?;
 
-        let mut cursor = Cursor::table_find(table, 0);
-        cursor.end_of_table = end_of_table;
+        let node = cursor.table.pager.read_page(cursor.page_num)?;
+        let num_cells = *node.leaf_node_num_cells()?;
+        cursor.end_of_table = num_cells == 0;
 
-        cursor
+        Ok(cursor)
     }
 
     /// Return the position of the given key.
     /// If the key is not present, return the position
     /// where it should be inserted
-    pub fn table_find(table: &'a mut Table, key: u32) -> Self {
+    pub fn table_find(table: Table<'a>, key: i64) -> Result<Self, Error> {
         let root_page_num = table.root_page_num;
-        let root_node = table.pager.get_page(root_page_num);
+        let root_node = table.pager.read_page(root_page_num)?;
 
         match root_node {
             Node::Leaf { .. } => Cursor::leaf_node_find(table, root_page_num, key),
             Node::Internal { .. } => Cursor::internal_node_find(table, root_page_num, key),
+            Node::FreelistTrunk { .. } => {
+                Err(CorruptErr::Invalid("Freelist page is part of a B-tree").into())
+            }
+            Node::Overflow { .. } => {
+                Err(CorruptErr::Invalid("Overflow page is part of a B-tree").into())
+            }
+            Node::IndexLeaf { .. } | Node::IndexInternal { .. } => {
+                Err(CorruptErr::Invalid("Index page is part of a table B-tree").into())
+            }
+        }
+    }
+
+    /// Return a cursor at the first key greater than or equal to the given key,
+    /// moving on to the next leaf if the key sorts after every key in its leaf.
+    pub fn table_seek(table: Table<'a>, key: i64) -> Result<Self, Error> {
+        let mut cursor = Cursor::table_find(table, key)?;
+
+        let node = cursor.table.pager.read_page(cursor.page_num)?;
+        let num_cells = *node.leaf_node_num_cells()?;
+
+        if cursor.cell_num >= num_cells {
+            if num_cells == 0 {
+                cursor.end_of_table = true;
+            } else {
+                cursor.cell_num = num_cells - 1;
+                cursor.advance()?;
+            }
         }
+
+        Ok(cursor)
     }
 
-    fn leaf_node_find(table: &'a mut Table, page_num: u32, key: u32) -> Self {
-        let node = table.pager.get_page(page_num);
+    fn leaf_node_find(table: Table<'a>, page_num: u32, key: i64) -> Result<Self, Error> {
+        let node = table.pager.read_page(page_num)?;
 
-        let num_cells = node.leaf_node_num_cells();
+        let num_cells = node.leaf_node_num_cells()?;
 
         // Binary search
         let mut min_index = 0;
@@ -54,7 +107,7 @@ impl<'a> Cursor<'a> {
 
         while one_past_max_index != min_index {
             let index = (min_index + one_past_max_index) / 2;
-            let key_at_index = node.leaf_node_key(index);
+            let key_at_index = node.leaf_node_key(index)?;
 
             if key == *key_at_index {
                 cell_num = Some(index);
@@ -68,159 +121,270 @@ impl<'a> Cursor<'a> {
 
         let cell_num = cell_num.unwrap_or(min_index);
 
-        Self {
+        Ok(Self {
             table,
             page_num,
             cell_num,
             end_of_table: false,
-        }
+            leaves_visited: 0,
+        })
     }
 
-    fn internal_node_find(table: &'a mut Table, page_num: u32, key: u32) -> Self {
-        let node = table.pager.get_page(page_num);
+    fn internal_node_find(table: Table<'a>, page_num: u32, key: i64) -> Result<Self, Error> {
+        let node = table.pager.read_page(page_num)?;
 
-        let child_index = node.internal_node_find_child(key);
-        let child_num = *node.internal_node_child(child_index);
-        let child = table.pager.get_page(child_num);
+        let child_index = node.internal_node_find_child(key)?;
+        let child_num = *node.internal_node_child(child_index)?;
+        let child = table.pager.read_page(child_num)?;
 
         match child {
             Node::Leaf { .. } => Cursor::leaf_node_find(table, child_num, key),
             Node::Internal { .. } => Cursor::internal_node_find(table, child_num, key),
+            Node::FreelistTrunk { .. } => {
+                Err(CorruptErr::Invalid("Freelist page is part of a B-tree").into())
+            }
+            Node::Overflow { .. } => {
+                Err(CorruptErr::Invalid("Overflow page is part of a B-tree").into())
+            }
+            Node::IndexLeaf { .. } | Node::IndexInternal { .. } => {
+                Err(CorruptErr::Invalid("Index page is part of a table B-tree").into())
+            }
         }
     }
 
-    pub fn value(&mut self) -> &mut Row {
-        let page_num = self.page_num;
-        let page = self.table.pager.get_page(page_num as u32);
-        page.leaf_node_value(self.cell_num)
+    /// The record stored at the cursor, read from its overflow pages if it has them.
+    pub fn value(&mut self) -> Result<Vec<u8>, Error> {
+        self.table.cell_value(self.page_num, self.cell_num)
+    }
+
+    /// Overwrite the record stored at the cursor, replacing its overflow
+    /// pages. A longer record that no longer fits in the leaf splits it.
+    /// The cursor must not be used to read after a change of length; find the key again.
+    pub fn set_value(&mut self, value: Vec<u8>) -> Result<(), Error> {
+        let page = self.table.pager.get_page(self.page_num)?;
+        let old_cell = page.leaf_node_remove_cell(self.cell_num)?;
+        if let Some(overflow_page_num) = old_cell.overflow_page() {
+            self.table.free_overflow_pages(overflow_page_num)?;
+        }
+
+        let cell = self.table.create_cell(old_cell.key(), value)?;
+        let page = self.table.pager.get_page(self.page_num)?;
+        if page.leaf_node_fits(&cell)? {
+            page.leaf_node_insert_cell(self.cell_num, cell)?;
+            return Ok(());
+        }
+        self.leaf_node_split_and_insert(cell)
     }
 
-    pub fn advance(&mut self) {
-        let node = self.table.pager.get_page(self.page_num);
+    pub fn advance(&mut self) -> Result<(), Error> {
+        let node = self.table.pager.read_page(self.page_num)?;
         self.cell_num += 1;
-        let num_cells = *node.leaf_node_num_cells();
+        let num_cells = *node.leaf_node_num_cells()?;
 
         if self.cell_num >= num_cells {
             // Advance to next leaf node
-            let next_page_num = *node.leaf_node_next_leaf();
+            let next_page_num = *node.leaf_node_next_leaf()?;
 
             if next_page_num == 0 {
                 // This is the right most leaf
                 self.end_of_table = true;
             } else {
+                self.leaves_visited += 1;
+                if self.leaves_visited >= self.table.pager.num_pages {
+                    return Err(CorruptErr::Invalid("Leaves of a table link up in a loop").into());
+                }
                 self.page_num = next_page_num;
                 self.cell_num = 0;
             }
         }
+        Ok(())
     }
 
-    pub fn leaf_node_insert(&mut self, key: u32, row: Row) {
-        let node = self.table.pager.get_page(self.page_num);
-        let num_cells = *node.leaf_node_num_cells();
+    pub fn key(&mut self) -> Result<i64, Error> {
+        let page = self.table.pager.read_page(self.page_num)?;
+        Ok(*page.leaf_node_key(self.cell_num)?)
+    }
 
-        if num_cells as usize >= LEAF_NODE_MAX_CELLS {
-            self.leaf_node_split_and_insert(key, row);
-            return;
+    // Whether the cursor points to a cell holding the given key,
+    // rather than to the position where the key would be inserted
+    pub fn is_at_key(&mut self, key: i64) -> Result<bool, Error> {
+        let node = self.table.pager.read_page(self.page_num)?;
+        Ok(self.cell_num < *node.leaf_node_num_cells()?
+            && *node.leaf_node_key(self.cell_num)? == key)
+    }
+
+    /// Remove the cell the cursor points to.
+    /// If the leaf underflows, borrow a cell from or merge with a sibling leaf.
+    /// The cursor must not be used to read after a delete; find the next key again.
+    pub fn leaf_node_delete(&mut self) -> Result<(), Error> {
+        let node = self.table.pager.get_page(self.page_num)?;
+        let cell = node.leaf_node_remove_cell(self.cell_num)?;
+        let underflows =
+            !node.is_node_root() && node.leaf_node_used_space()? < LEAF_NODE_MIN_USED_SPACE;
+        if let Some(overflow_page_num) = cell.overflow_page() {
+            self.table.free_overflow_pages(overflow_page_num)?;
         }
 
-        if self.cell_num < num_cells {
-            // Make room for new cell
-            let mut i = num_cells;
-            while i > self.cell_num {
-                let prev = std::mem::replace(node.leaf_node_cell(i - 1), LeafNodeCell::new());
-                *node.leaf_node_cell(i) = prev;
-                i -= 1;
-            }
+        if !underflows {
+            return Ok(());
         }
 
-        *node.leaf_node_num_cells() += 1;
-        *node.leaf_node_key(self.cell_num) = key;
-        *node.leaf_node_value(self.cell_num) = row;
+        self.leaf_node_rebalance()
     }
 
-    /// Create a new node and move half the cells over.
-    /// Insert the new value in one of the two nodes.
-    /// Update parent or create a new parent.
-    fn leaf_node_split_and_insert(&mut self, key: u32, row: Row) {
-        let new_page_num = self.table.pager.get_unused_page_num();
+    /// Fix an underflowing leaf by borrowing a cell from a sibling that
+    /// can spare one, or else merging the leaf with a sibling and removing
+    /// the right one of the pair from the parent.
+    fn leaf_node_rebalance(&mut self) -> Result<(), Error> {
+        let page_num = self.page_num;
+        let parent_page_num = *self.table.pager.get_page(page_num)?.parent()?;
 
-        let old_node_page_num = self.page_num;
-        let old_max = self.table.pager.get_node_max_key(self.page_num);
-        let next_node = *self
-            .table
-            .pager
-            .get_page(old_node_page_num)
-            .leaf_node_next_leaf();
-        let old_node_parent = *self.table.pager.get_page(old_node_page_num).parent();
-        *self
-            .table
-            .pager
-            .get_page(old_node_page_num)
-            .leaf_node_next_leaf() = new_page_num;
-
-        *self
-            .table
-            .pager
-            .get_page(new_page_num)
-            .leaf_node_next_leaf() = next_node;
-        *self.table.pager.get_page(new_page_num).parent() = old_node_parent;
-
-        // All existing keys plus new key should be divided
-        // evenly between old (left) and new (right) nodes.
-        // Starting from the right, move each key to correct position.
-        // Update cell count on both leaf nodes
-        for i in (0..=LEAF_NODE_MAX_CELLS).rev() {
-            let destination_node_page_num = if i >= LEAF_NODE_LEFT_SPLIT_COUNT {
-                new_page_num
-            } else {
-                old_node_page_num
-            };
-
-            let index_within_node = i % LEAF_NODE_LEFT_SPLIT_COUNT;
-
-            if i == self.cell_num as usize {
-                let destination_node = self.table.pager.get_page(destination_node_page_num);
-                *destination_node.leaf_node_value(index_within_node as u32) = row.clone();
-                *destination_node.leaf_node_key(index_within_node as u32) = key;
-            } else if i > self.cell_num as usize {
-                let old_node = self.table.pager.get_page(old_node_page_num);
-                let old_leaf_node_cell =
-                    std::mem::replace(old_node.leaf_node_cell(i as u32 - 1), LeafNodeCell::new());
-                let destination = self
+        let parent = self.table.pager.get_page(parent_page_num)?;
+        let index = parent.internal_node_child_index(page_num)?;
+        let parent_num_keys = *parent.internal_node_num_keys()?;
+
+        let left_page_num = if index > 0 {
+            Some(*parent.internal_node_child(index - 1)?)
+        } else {
+            None
+        };
+        let right_page_num = if index < parent_num_keys {
+            Some(*parent.internal_node_child(index + 1)?)
+        } else {
+            None
+        };
+
+        if let Some(left_page_num) = left_page_num {
+            let left = self.table.pager.get_page(left_page_num)?;
+            let left_num_cells = *left.leaf_node_num_cells()?;
+
+            if left_num_cells > 1 && left.leaf_node_can_spare(left_num_cells - 1)? {
+                // Borrow the largest cell of the left sibling
+                let cell = left.leaf_node_remove_cell(left_num_cells - 1)?;
+                let left_max_key = *left.leaf_node_key(left_num_cells - 2)?;
+                self.table
+                    .pager
+                    .get_page(page_num)?
+                    .leaf_node_insert_cell(0, cell)?;
+                *self
                     .table
                     .pager
-                    .get_page(destination_node_page_num)
-                    .leaf_node_cell(index_within_node as u32);
-                *destination = old_leaf_node_cell;
-            } else {
-                let old_node = self.table.pager.get_page(old_node_page_num);
-                let old_leaf_node_cell =
-                    std::mem::replace(old_node.leaf_node_cell(i as u32), LeafNodeCell::new());
-                let destination = self
+                    .get_page(parent_page_num)?
+                    .internal_node_key(index - 1)? = left_max_key;
+                return Ok(());
+            }
+        }
+
+        if let Some(right_page_num) = right_page_num {
+            let right = self.table.pager.get_page(right_page_num)?;
+            let right_num_cells = *right.leaf_node_num_cells()?;
+
+            if right_num_cells > 1 && right.leaf_node_can_spare(0)? {
+                // Borrow the smallest cell of the right sibling
+                let cell = right.leaf_node_remove_cell(0)?;
+                let node = self.table.pager.get_page(page_num)?;
+                let num_cells = *node.leaf_node_num_cells()?;
+                node.leaf_node_insert_cell(num_cells, cell)?;
+                let max_key = *node.leaf_node_key(num_cells)?;
+                *self
                     .table
                     .pager
-                    .get_page(destination_node_page_num)
-                    .leaf_node_cell(index_within_node as u32);
-                *destination = old_leaf_node_cell;
+                    .get_page(parent_page_num)?
+                    .internal_node_key(index)? = max_key;
+                return Ok(());
             }
         }
 
-        // Update cell count on both leaf nodes
-        let new_node = self.table.pager.get_page(new_page_num);
-        *new_node.leaf_node_num_cells() = LEAF_NODE_RIGHT_SPLIT_COUNT as u32;
+        // Neither sibling can spare a cell, so merge with one of them.
+        // Cells always move from the right node of the pair into the left one.
+        let (left_page_num, right_page_num, right_index) = match (left_page_num, right_page_num) {
+            (Some(left_page_num), _) => (left_page_num, page_num, index),
+            (None, Some(right_page_num)) => (page_num, right_page_num, index + 1),
+            (None, None) => {
+                return Err(CorruptErr::Invalid("Internal node has a single child").into())
+            }
+        };
+
+        let right = self.table.pager.get_page(right_page_num)?;
+        let right_next_leaf = *right.leaf_node_next_leaf()?;
+        let cells = right.leaf_node_take_cells()?;
 
-        let old_node = self.table.pager.get_page(old_node_page_num);
-        *old_node.leaf_node_num_cells() = LEAF_NODE_LEFT_SPLIT_COUNT as u32;
+        let left = self.table.pager.get_page(left_page_num)?;
+        for cell in cells {
+            let num_cells = *left.leaf_node_num_cells()?;
+            left.leaf_node_insert_cell(num_cells, cell)?;
+        }
+        *left.leaf_node_next_leaf()? = right_next_leaf;
+
+        self.page_num = left_page_num;
+        self.table
+            .internal_node_remove_child(parent_page_num, right_index)?;
+        self.table.pager.free_page(right_page_num)
+    }
+
+    pub fn leaf_node_insert(&mut self, key: i64, value: Vec<u8>) -> Result<(), Error> {
+        let cell = self.table.create_cell(key, value)?;
+        let node = self.table.pager.get_page(self.page_num)?;
+        if !node.leaf_node_fits(&cell)? {
+            return self.leaf_node_split_and_insert(cell);
+        }
+
+        node.leaf_node_insert_cell(self.cell_num, cell)?;
+        Ok(())
+    }
+
+    /// Create a new node and move the cells holding the second half of
+    /// the bytes over, the new value included.
+    /// Update parent or create a new parent.
+    fn leaf_node_split_and_insert(&mut self, cell: LeafNodeCell) -> Result<(), Error> {
+        let new_page_num = self.table.pager.get_unused_page_num()?;
+
+        let old_node_page_num = self.page_num;
+        let old_max = self.table.pager.get_node_max_key(self.page_num)?;
+        let old_node = self.table.pager.get_page(old_node_page_num)?;
+        let next_node = *old_node.leaf_node_next_leaf()?;
+        let old_node_parent = *old_node.parent()?;
+        *old_node.leaf_node_next_leaf()? = new_page_num;
+
+        // All existing cells plus the new one are divided between old (left)
+        // and new (right) nodes, each cell going to the side holding its
+        // middle byte, keeping at least one cell on each side. Neither half
+        // can then overflow, as no cell takes up more than a third of a leaf.
+        let mut cells = old_node.leaf_node_take_cells()?;
+        cells.insert(self.cell_num as usize, cell);
+        let total_size: usize = cells.iter().map(LeafNodeCell::size).sum();
+        let mut left_size = 0;
+        let mut left_split_count = 0;
+        while left_split_count < cells.len()
+            && left_size * 2 + cells[left_split_count].size() <= total_size
+        {
+            left_size += cells[left_split_count].size();
+            left_split_count += 1;
+        }
+        let left_split_count = left_split_count.clamp(1, cells.len() - 1);
+
+        let right_cells = cells.split_off(left_split_count);
+        for (i, cell) in cells.into_iter().enumerate() {
+            old_node.leaf_node_insert_cell(i as u32, cell)?;
+        }
+
+        let new_node = self.table.pager.get_page(new_page_num)?;
+        *new_node.leaf_node_next_leaf()? = next_node;
+        *new_node.parent()? = old_node_parent;
+        for (i, cell) in right_cells.into_iter().enumerate() {
+            new_node.leaf_node_insert_cell(i as u32, cell)?;
+        }
 
+        let old_node = self.table.pager.get_page(old_node_page_num)?;
         if old_node.is_node_root() {
-            self.table.create_new_root(new_page_num);
+            self.table.create_new_root(new_page_num)
         } else {
-            let parent_page_num = *old_node.parent();
-            let new_max = self.table.pager.get_node_max_key(old_node_page_num);
-            let parent = self.table.pager.get_page(parent_page_num);
-            parent.update_internal_node_key(old_max, new_max);
+            let parent_page_num = *old_node.parent()?;
+            let new_max = self.table.pager.get_node_max_key(old_node_page_num)?;
+            let parent = self.table.pager.get_page(parent_page_num)?;
+            parent.update_internal_node_key(old_max, new_max)?;
             self.table
-                .internal_node_insert(parent_page_num, new_page_num);
+                .internal_node_insert(parent_page_num, new_page_num)
         }
     }
 }
@@ -0,0 +1,637 @@
+use crate::{
+    catalog::{
+        catalog_schema, sequence_schema, CatalogEntry, CATALOG_ROOT_PAGE_NUM, CATALOG_TYPE_INDEX,
+        CATALOG_TYPE_TABLE, SEQUENCE_TABLE_NAME,
+    },
+    cursor::Cursor,
+    error::{ConstraintErr, CorruptErr, Error},
+    eval::Context,
+    index::{compare_prefix, Index},
+    journal::journal_path,
+    pager::Pager,
+    row::Row,
+    schema::{IndexSchema, TableSchema},
+    table::Table,
+    value::Value,
+};
+
+/// A table of the database: the page its B-tree starts on, its columns
+/// and the indexes on them.
+#[derive(Clone, Debug)]
+pub struct TableEntry {
+    // Key of the table's row in the catalog, which also keys its row of
+    // sqlite_sequence. The catalog itself has none and takes 0.
+    pub catalog_key: i64,
+    pub root_page_num: u32,
+    pub schema: TableSchema,
+    pub indexes: Vec<IndexEntry>,
+}
+
+/// An index of a table: the page its B-tree starts on and its columns.
+#[derive(Clone, Debug)]
+pub struct IndexEntry {
+    pub root_page_num: u32,
+    pub schema: IndexSchema,
+}
+
+/// A foreign key check left for the end of a statement, or of the
+/// transaction when the foreign key is deferred. Checks are made against
+/// the rows as they are by then, so a check whose rows were changed again
+/// since it was left still gives the right answer.
+#[derive(Clone, Debug)]
+pub enum ForeignKeyCheck {
+    /// The row with the key, if it is still there, must have a parent
+    Row {
+        table: String,
+        key: i64,
+        column: usize,
+    },
+    /// The rows whose column has the value, if any, must have a parent
+    Value {
+        table: String,
+        column: usize,
+        value: Value,
+    },
+}
+
+// Name of the savepoint a statement inside a transaction runs under. The
+// parser takes no empty names, so it cannot clash with the user's.
+const STATEMENT_SAVEPOINT: &str = "";
+
+/// An open database file. The catalog B-tree on page 1 lists every
+/// table with the root page of its B-tree and its CREATE TABLE statement.
+pub struct Database {
+    pub pager: Pager,
+    pub catalog: TableEntry,
+    tables: Vec<TableEntry>,
+    // Inside BEGIN ... COMMIT, statements are not committed one by one
+    in_transaction: bool,
+    // The transaction was started by a SAVEPOINT, and commits when it is released
+    savepoint_transaction: bool,
+    // Checks of deferred foreign keys, made when the transaction commits
+    deferred_foreign_key_checks: Vec<ForeignKeyCheck>,
+    // Key of the row inserted last, 0 before any
+    last_insert_rowid: i64,
+}
+
+impl Drop for Database {
+    // There is no one left to report an error to, the journal or log
+    // left behind is recovered on the next open
+    fn drop(&mut self) {
+        let _ = self.db_close();
+    }
+}
+
+impl Database {
+    pub fn db_open(filename: &str) -> Result<Self, Error> {
+        let mut pager = Pager::pager_open(filename)?;
+
+        if pager.num_pages <= CATALOG_ROOT_PAGE_NUM {
+            // New database file. Initialize page 1 as the root of an empty catalog.
+            let root_node = pager.get_page(CATALOG_ROOT_PAGE_NUM)?;
+            root_node.set_node_root(true)?;
+            pager.commit()?;
+        }
+
+        let mut database = Self {
+            pager,
+            catalog: TableEntry {
+                catalog_key: 0,
+                root_page_num: CATALOG_ROOT_PAGE_NUM,
+                schema: catalog_schema(),
+                indexes: Vec::new(),
+            },
+            tables: Vec::new(),
+            in_transaction: false,
+            savepoint_transaction: false,
+            deferred_foreign_key_checks: Vec::new(),
+            last_insert_rowid: 0,
+        };
+        database.load_tables()?;
+
+        Ok(database)
+    }
+
+    // Read the tables listed in the catalog, then their indexes
+    fn load_tables(&mut self) -> Result<(), Error> {
+        self.tables.clear();
+        let entries = self.catalog_entries()?;
+        for entry in entries.iter() {
+            if entry.kind != CATALOG_TYPE_TABLE {
+                continue;
+            }
+            let Some(schema) = TableSchema::from_sql(&entry.sql) else {
+                return Err(CorruptErr::Invalid("Table has an invalid schema").into());
+            };
+            self.tables.push(TableEntry {
+                catalog_key: entry.key,
+                root_page_num: entry.root_page_num,
+                schema,
+                indexes: Vec::new(),
+            });
+        }
+
+        for entry in entries.iter() {
+            if entry.kind != CATALOG_TYPE_INDEX {
+                continue;
+            }
+            let table = self
+                .tables
+                .iter_mut()
+                .find(|table| table.schema.name.eq_ignore_ascii_case(&entry.table_name));
+            let Some(table) = table else {
+                return Err(CorruptErr::Invalid("Index is on a missing table").into());
+            };
+            let Some(schema) = IndexSchema::from_sql(&entry.sql, &table.schema) else {
+                return Err(CorruptErr::Invalid("Index has an invalid schema").into());
+            };
+            table.indexes.push(IndexEntry {
+                root_page_num: entry.root_page_num,
+                schema,
+            });
+        }
+        Ok(())
+    }
+
+    // A transaction left open is rolled back
+    fn db_close(&mut self) -> Result<(), Error> {
+        if self.in_transaction {
+            self.roll_back()?;
+        }
+        self.pager.close()
+    }
+
+    pub fn in_transaction(&self) -> bool {
+        self.in_transaction
+    }
+
+    /// Start grouping statements into one transaction, until `commit` or `roll_back`.
+    pub fn begin(&mut self) {
+        self.in_transaction = true;
+    }
+
+    /// Make the changes made so far durable, ending the transaction if one is open.
+    pub fn commit(&mut self) -> Result<(), Error> {
+        self.pager.commit()?;
+        self.in_transaction = false;
+        self.savepoint_transaction = false;
+        self.deferred_foreign_key_checks.clear();
+        Ok(())
+    }
+
+    /// Undo the changes made since the last commit, ending the transaction if one is open.
+    pub fn roll_back(&mut self) -> Result<(), Error> {
+        self.in_transaction = false;
+        self.savepoint_transaction = false;
+        self.deferred_foreign_key_checks.clear();
+        self.pager.roll_back()?;
+        self.load_tables()
+    }
+
+    /// Set a savepoint, starting a transaction if none is open.
+    pub fn savepoint(&mut self, name: String) {
+        if !self.in_transaction {
+            self.in_transaction = true;
+            self.savepoint_transaction = true;
+        }
+        self.pager.savepoint(name);
+    }
+
+    pub fn has_savepoint(&self, name: &str) -> bool {
+        self.pager.has_savepoint(name)
+    }
+
+    /// Release a savepoint and the ones set after it. Releasing the savepoint
+    /// that started the transaction commits it.
+    pub fn release_savepoint(&mut self, name: &str) -> Result<(), Error> {
+        self.pager.release_savepoint(name);
+        if self.savepoint_transaction && self.pager.savepoint_depth() == 0 {
+            self.commit()?;
+        }
+        Ok(())
+    }
+
+    /// Whether releasing the named savepoint commits the transaction.
+    pub fn release_commits(&self, name: &str) -> bool {
+        self.savepoint_transaction && self.pager.find_savepoint(name) == Some(0)
+    }
+
+    /// Run a statement so that it changes nothing when it fails. Outside a
+    /// transaction the caller rolls it back; inside one, it is rolled back
+    /// to a savepoint set before it, keeping the statements before it.
+    pub fn run_statement<T>(
+        &mut self,
+        run: impl FnOnce(&mut Self) -> Result<T, Error>,
+    ) -> Result<T, Error> {
+        if !self.in_transaction {
+            return run(self);
+        }
+        self.pager.savepoint(STATEMENT_SAVEPOINT.to_owned());
+        let result = run(self);
+        if result.is_err() {
+            self.pager.roll_back_to_savepoint(STATEMENT_SAVEPOINT)?;
+        }
+        self.pager.release_savepoint(STATEMENT_SAVEPOINT);
+        result
+    }
+
+    /// Leave foreign key checks for the end of the transaction.
+    pub fn defer_foreign_key_checks(&mut self, checks: Vec<ForeignKeyCheck>) {
+        self.deferred_foreign_key_checks.extend(checks);
+    }
+
+    pub fn deferred_foreign_key_checks(&self) -> &[ForeignKeyCheck] {
+        &self.deferred_foreign_key_checks
+    }
+
+    /// Key of the row most recently inserted on this database, 0 if none was.
+    pub fn last_insert_rowid(&self) -> i64 {
+        self.last_insert_rowid
+    }
+
+    /// What the built-in functions of expressions read from the database.
+    pub fn context(&self) -> Context {
+        Context {
+            last_insert_rowid: self.last_insert_rowid,
+        }
+    }
+
+    pub fn set_last_insert_rowid(&mut self, key: i64) {
+        self.last_insert_rowid = key;
+    }
+
+    /// Undo the changes made since a savepoint was set, keeping the savepoint.
+    pub fn roll_back_to_savepoint(&mut self, name: &str) -> Result<(), Error> {
+        self.pager.roll_back_to_savepoint(name)?;
+        self.load_tables()
+    }
+
+    /// The B-tree whose root is on the given page.
+    pub fn table(&mut self, root_page_num: u32) -> Table<'_> {
+        Table::new(&mut self.pager, root_page_num)
+    }
+
+    /// The index B-tree whose root is on the given page.
+    pub fn index(&mut self, root_page_num: u32) -> Index<'_> {
+        Index::new(&mut self.pager, root_page_num)
+    }
+
+    /// Tables created by the user, in the order they were created.
+    pub fn tables(&self) -> &[TableEntry] {
+        &self.tables
+    }
+
+    /// Look up a table by name. The catalog can be looked up as well.
+    pub fn find_table(&self, name: &str) -> Option<&TableEntry> {
+        std::iter::once(&self.catalog)
+            .chain(self.tables.iter())
+            .find(|table| table.schema.name.eq_ignore_ascii_case(name))
+    }
+
+    /// Look up an index by name, along with the table it is on.
+    pub fn find_index(&self, name: &str) -> Option<(&TableEntry, &IndexEntry)> {
+        self.tables.iter().find_map(|table| {
+            let index = table
+                .indexes
+                .iter()
+                .find(|index| index.schema.name.eq_ignore_ascii_case(name))?;
+            Some((table, index))
+        })
+    }
+
+    // Every row of the catalog, in key order
+    fn catalog_entries(&mut self) -> Result<Vec<CatalogEntry>, Error> {
+        let schema = self.catalog.schema.clone();
+        let mut entries = Vec::new();
+
+        let mut cursor = Cursor::table_start(self.table(CATALOG_ROOT_PAGE_NUM))?;
+        while !cursor.end_of_table {
+            let row = Row::deserialize(&schema, &cursor.value()?)?;
+            entries.push(CatalogEntry::from_row(&row)?);
+            cursor.advance()?;
+        }
+
+        Ok(entries)
+    }
+
+    /// Start an empty B-tree for a new table and add the table to the
+    /// catalog, along with the indexes backing its UNIQUE constraints.
+    /// The first AUTOINCREMENT table brings sqlite_sequence with it.
+    pub fn create_table(&mut self, schema: TableSchema) -> Result<(), Error> {
+        let autoincrement = schema.columns.iter().any(|column| column.autoincrement);
+        let root_page_num = self.pager.get_unused_page_num()?;
+        let root_node = self.pager.get_page(root_page_num)?;
+        root_node.set_node_root(true)?;
+
+        let catalog_key = self.add_catalog_entry(
+            CATALOG_TYPE_TABLE,
+            &schema.name,
+            &schema.name,
+            root_page_num,
+            schema.to_sql(),
+        )?;
+
+        let unique_indexes = schema.unique_indexes();
+        self.tables.push(TableEntry {
+            catalog_key,
+            root_page_num,
+            schema,
+            indexes: Vec::new(),
+        });
+        self.schema_changed();
+        for index in unique_indexes {
+            self.create_index(index)?;
+        }
+        if autoincrement && self.find_table(SEQUENCE_TABLE_NAME).is_none() {
+            self.create_table(sequence_schema())?;
+        }
+        Ok(())
+    }
+
+    /// Key for a row inserted without one: one more than the largest key
+    /// of the table. An AUTOINCREMENT table goes past the largest key it
+    /// ever held instead, so the keys of deleted rows are not used again.
+    pub fn next_key(&mut self, table: &TableEntry) -> Result<i64, Error> {
+        let mut max_key = self.table(table.root_page_num).max_key()?;
+        if table.schema.columns[table.schema.key_column()].autoincrement {
+            max_key = max_key.max(self.sequence(table)?);
+        }
+        match max_key {
+            None => Ok(1),
+            // Every larger key is taken
+            Some(max_key) => max_key.checked_add(1).ok_or(Error::Full),
+        }
+    }
+
+    /// Record a key inserted into an AUTOINCREMENT table in sqlite_sequence,
+    /// if it is the largest the table has held.
+    pub fn update_sequence(&mut self, table: &TableEntry, key: i64) -> Result<(), Error> {
+        let Some(sequence_table) = self.find_table(SEQUENCE_TABLE_NAME).cloned() else {
+            return Err(CorruptErr::Invalid("AUTOINCREMENT table without sqlite_sequence").into());
+        };
+        if self.sequence(table)?.is_some_and(|seq| seq >= key) {
+            return Ok(());
+        }
+
+        let row = Row::new(vec![
+            Value::Integer(table.catalog_key),
+            Value::Text(table.schema.name.clone()),
+            Value::Integer(key),
+        ]);
+        let mut cursor =
+            Cursor::table_find(self.table(sequence_table.root_page_num), table.catalog_key)?;
+        if cursor.is_at_key(table.catalog_key)? {
+            cursor.set_value(row.serialize())
+        } else {
+            cursor.leaf_node_insert(table.catalog_key, row.serialize())
+        }
+    }
+
+    // The largest key a table has held, from its row of sqlite_sequence,
+    // which has the key of the table in the catalog. The row can be changed
+    // like any other, a value that is not an integer is read as 0.
+    fn sequence(&mut self, table: &TableEntry) -> Result<Option<i64>, Error> {
+        let Some(sequence_table) = self.find_table(SEQUENCE_TABLE_NAME).cloned() else {
+            return Ok(None);
+        };
+
+        let mut cursor =
+            Cursor::table_find(self.table(sequence_table.root_page_num), table.catalog_key)?;
+        if !cursor.is_at_key(table.catalog_key)? {
+            return Ok(None);
+        }
+        let row = Row::deserialize(&sequence_table.schema, &cursor.value()?)?;
+        match row.values.as_slice() {
+            [_, _, Value::Integer(seq)] => Ok(Some(*seq)),
+            _ => Ok(Some(0)),
+        }
+    }
+
+    /// Build an index of the rows of its table and add it to the catalog.
+    /// Nothing is changed if a unique index finds two rows with the same values.
+    pub fn create_index(&mut self, schema: IndexSchema) -> Result<(), Error> {
+        let Some(table) = self.find_table(&schema.table_name).cloned() else {
+            return Err(CorruptErr::Invalid("Index is on a missing table").into());
+        };
+
+        let mut keys = Vec::new();
+        let mut cursor = Cursor::table_start(self.table(table.root_page_num))?;
+        while !cursor.end_of_table {
+            let row = Row::deserialize(&table.schema, &cursor.value()?)?;
+            keys.push(schema.key(&table.schema, &row));
+            cursor.advance()?;
+        }
+
+        if schema.unique {
+            keys.sort_by(|a, b| compare_prefix(a, b));
+            let columns = schema.columns.len();
+            let duplicate = keys.windows(2).any(|pair| {
+                let values = &pair[0][..columns];
+                !values.iter().any(Value::is_null)
+                    && compare_prefix(values, &pair[1][..columns]).is_eq()
+            });
+            if duplicate {
+                return Err(ConstraintErr::Unique(schema.column_names(&table.schema)).into());
+            }
+        }
+
+        let mut index = Index::create(&mut self.pager)?;
+        for key in keys.iter() {
+            index.insert(key)?;
+        }
+        let root_page_num = index.root_page_num;
+
+        self.add_catalog_entry(
+            CATALOG_TYPE_INDEX,
+            &schema.name,
+            &table.schema.name,
+            root_page_num,
+            schema.to_sql(&table.schema),
+        )?;
+
+        let table = self
+            .tables
+            .iter_mut()
+            .find(|entry| entry.root_page_num == table.root_page_num)
+            .expect("create_index: the table was found above");
+        table.indexes.push(IndexEntry {
+            root_page_num,
+            schema,
+        });
+        self.schema_changed();
+        Ok(())
+    }
+
+    // Add an entry after the last one of the catalog, returning its key
+    fn add_catalog_entry(
+        &mut self,
+        kind: &str,
+        name: &str,
+        table_name: &str,
+        root_page_num: u32,
+        sql: String,
+    ) -> Result<i64, Error> {
+        let key = self
+            .catalog_entries()?
+            .last()
+            .map_or(1, |entry| entry.key + 1);
+        let entry = CatalogEntry {
+            key,
+            kind: kind.to_owned(),
+            name: name.to_owned(),
+            table_name: table_name.to_owned(),
+            root_page_num,
+            sql,
+        };
+        let record = entry.to_row().serialize();
+
+        let mut cursor = Cursor::table_find(self.table(CATALOG_ROOT_PAGE_NUM), key)?;
+        cursor.leaf_node_insert(key, record)?;
+        Ok(key)
+    }
+
+    // Remove an entry from the catalog, returning it
+    fn remove_catalog_entry(&mut self, kind: &str, name: &str) -> Result<CatalogEntry, Error> {
+        let Some(entry) = self
+            .catalog_entries()?
+            .into_iter()
+            .find(|entry| entry.kind == kind && entry.name.eq_ignore_ascii_case(name))
+        else {
+            return Err(CorruptErr::Invalid("Object is missing from the catalog").into());
+        };
+
+        let mut cursor = Cursor::table_find(self.table(CATALOG_ROOT_PAGE_NUM), entry.key)?;
+        cursor.leaf_node_delete()?;
+        Ok(entry)
+    }
+
+    /// Remove a table and its indexes from the catalog and free the pages
+    /// of their B-trees, forgetting its AUTOINCREMENT sequence.
+    pub fn drop_table(&mut self, name: &str) -> Result<(), Error> {
+        let Some(table) = self.find_table(name).cloned() else {
+            return Err(CorruptErr::Invalid("Object is missing from the catalog").into());
+        };
+        for index in table.indexes.iter() {
+            self.drop_index(&index.schema.name)?;
+        }
+
+        if let Some(sequence_table) = self.find_table(SEQUENCE_TABLE_NAME).cloned() {
+            let mut cursor =
+                Cursor::table_find(self.table(sequence_table.root_page_num), table.catalog_key)?;
+            if cursor.is_at_key(table.catalog_key)? {
+                cursor.leaf_node_delete()?;
+            }
+        }
+
+        let entry = self.remove_catalog_entry(CATALOG_TYPE_TABLE, name)?;
+        self.table(entry.root_page_num).free_pages()?;
+        self.tables
+            .retain(|table| !table.schema.name.eq_ignore_ascii_case(name));
+        self.schema_changed();
+        Ok(())
+    }
+
+    /// Remove an index from the catalog and free the pages of its B-tree.
+    pub fn drop_index(&mut self, name: &str) -> Result<(), Error> {
+        let entry = self.remove_catalog_entry(CATALOG_TYPE_INDEX, name)?;
+        self.index(entry.root_page_num).free_pages()?;
+        for table in self.tables.iter_mut() {
+            table
+                .indexes
+                .retain(|index| !index.schema.name.eq_ignore_ascii_case(name));
+        }
+        self.schema_changed();
+        Ok(())
+    }
+
+    /// Rebuild the database into a new file at `filename`, which must not
+    /// hold a database yet.
+    pub fn vacuum_into(&mut self, filename: &str) -> Result<(), Error> {
+        let mut pager = Pager::pager_open(filename)?;
+        self.copy_trees(&mut pager)?;
+        pager.close()
+    }
+
+    /// Rebuild the database into a scratch file, then copy it back over the
+    /// database file in one commit. The file is cut to the rebuilt length.
+    pub fn vacuum(&mut self) -> Result<(), Error> {
+        let filename = format!("{}-vacuum", self.pager.filename());
+        // A scratch file left behind by a crash is of no use
+        let _ = std::fs::remove_file(&filename);
+        let _ = std::fs::remove_file(journal_path(&filename));
+
+        let mut pager = Pager::pager_open(&filename)?;
+        let copied = self
+            .copy_trees(&mut pager)
+            .and_then(|()| self.pager.copy_pages_from(&mut pager));
+
+        // The scratch file is never committed, so its journal is left as well.
+        // An error copying the pages wins over one removing the files.
+        drop(pager);
+        let removed = remove_scratch_file(&filename)
+            .and_then(|()| remove_scratch_file(&journal_path(&filename)));
+        copied?;
+        removed?;
+
+        self.schema_changed();
+        self.pager.commit()?;
+        self.pager.checkpoint()?;
+        self.load_tables()
+    }
+
+    // Copy every tree listed in the catalog into an empty database,
+    // packing its leaves, and list the copies in the new catalog
+    fn copy_trees(&mut self, pager: &mut Pager) -> Result<(), Error> {
+        pager.get_page(CATALOG_ROOT_PAGE_NUM)?.set_node_root(true)?;
+
+        let entries = self.catalog_entries()?;
+        let mut catalog_cells = Vec::new();
+        for mut entry in entries {
+            if entry.kind == CATALOG_TYPE_INDEX {
+                let mut index = Index::create(pager)?;
+                self.index(entry.root_page_num)
+                    .for_each_key(|key| index.insert(&Row::from_record(key)?.values))?;
+                entry.root_page_num = index.root_page_num;
+                catalog_cells.push((entry.key, entry.to_row().serialize()));
+                continue;
+            }
+
+            // The cells go straight from a cursor into the new tree. A read
+            // error leaves the copy half built, but the scratch file is thrown away.
+            let mut cursor = Cursor::table_start(self.table(entry.root_page_num))?;
+            let cells = std::iter::from_fn(|| {
+                if cursor.end_of_table {
+                    return None;
+                }
+                let cell = cursor.key().and_then(|key| {
+                    let value = cursor.value()?;
+                    cursor.advance()?;
+                    Ok((key, value))
+                });
+                Some(cell)
+            });
+
+            entry.root_page_num = pager.get_unused_page_num()?;
+            pager.get_page(entry.root_page_num)?.set_node_root(true)?;
+            Table::new(pager, entry.root_page_num).load(cells)?;
+
+            let record = entry.to_row().serialize();
+            catalog_cells.push((entry.key, record));
+        }
+
+        Table::new(pager, CATALOG_ROOT_PAGE_NUM).load(catalog_cells.into_iter().map(Ok))
+    }
+
+    fn schema_changed(&mut self) {
+        let header = &mut self.pager.header;
+        header.schema_cookie = header.schema_cookie.wrapping_add(1);
+    }
+}
+
+// Remove a file of the vacuum, which may never have been created
+fn remove_scratch_file(filename: &str) -> Result<(), Error> {
+    match std::fs::remove_file(filename) {
+        Err(err) if err.kind() != std::io::ErrorKind::NotFound => Err(err.into()),
+        _ => Ok(()),
+    }
+}
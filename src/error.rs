@@ -0,0 +1,169 @@
+use std::fmt;
+use std::io;
+
+use crate::header::FORMAT_VERSION;
+use crate::pager::PAGE_SIZE;
+use crate::parser::ParseError;
+use crate::statement::{ExecuteErr, PrepareStatementErr};
+
+/// Everything that can go wrong while opening a database or running a
+/// statement. The engine returns these instead of panicking, so a caller
+/// can report the error and carry on with the next statement.
+#[derive(Debug)]
+pub enum Error {
+    /// Reading, writing or syncing a file failed.
+    Io(io::Error),
+    /// The file is not a database, or its pages do not make sense.
+    Corrupt(CorruptErr),
+    /// The database file cannot grow any further.
+    Full,
+    /// The statement would break a constraint of a table.
+    Constraint(ConstraintErr),
+    /// The statement is not valid SQL, or does not fit the schema.
+    Syntax(PrepareStatementErr),
+    /// The statement cannot run in the current state of the database.
+    Execute(ExecuteErr),
+}
+
+#[derive(Debug)]
+pub enum CorruptErr {
+    NotADatabase,
+    UnsupportedFormatVersion(u32),
+    PageSizeMismatch(u32),
+    Invalid(&'static str),
+}
+
+#[derive(Debug)]
+pub enum ConstraintErr {
+    DuplicateKey,
+    /// Names of the columns of the unique index, as in `users.email`
+    Unique(String),
+    /// Name of the column, as in `users.email`
+    NotNull(String),
+    /// Name of the column the check belongs to
+    Check(String),
+    /// Name of the column with the foreign key
+    ForeignKey(String),
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        if err.kind() == io::ErrorKind::StorageFull {
+            return Error::Full;
+        }
+        Error::Io(err)
+    }
+}
+
+impl From<CorruptErr> for Error {
+    fn from(err: CorruptErr) -> Self {
+        Error::Corrupt(err)
+    }
+}
+
+impl From<ConstraintErr> for Error {
+    fn from(err: ConstraintErr) -> Self {
+        Error::Constraint(err)
+    }
+}
+
+impl From<PrepareStatementErr> for Error {
+    fn from(err: PrepareStatementErr) -> Self {
+        Error::Syntax(err)
+    }
+}
+
+impl From<ParseError> for Error {
+    fn from(err: ParseError) -> Self {
+        Error::Syntax(PrepareStatementErr::SyntaxError(err))
+    }
+}
+
+impl From<ExecuteErr> for Error {
+    fn from(err: ExecuteErr) -> Self {
+        Error::Execute(err)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io(err) => {
+                write!(f, "Error: Disk I/O error: {}.", err)
+            }
+            Error::Corrupt(err) => {
+                write!(f, "{}", err)
+            }
+            Error::Full => {
+                write!(f, "Error: Database full.")
+            }
+            Error::Constraint(err) => {
+                write!(f, "{}", err)
+            }
+            Error::Syntax(err) => {
+                write!(f, "{}", err)
+            }
+            Error::Execute(err) => {
+                write!(f, "{}", err)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for CorruptErr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CorruptErr::NotADatabase => {
+                write!(f, "Error: File is not a database.")
+            }
+            CorruptErr::UnsupportedFormatVersion(version) => {
+                write!(
+                    f,
+                    "Error: Unsupported file format version {}, expected {}.",
+                    version, FORMAT_VERSION
+                )
+            }
+            CorruptErr::PageSizeMismatch(page_size) => {
+                write!(
+                    f,
+                    "Error: File has a page size of {} bytes, expected {}.",
+                    page_size, PAGE_SIZE
+                )
+            }
+            CorruptErr::Invalid(reason) => {
+                write!(f, "Error: {}. Corrupt file.", reason)
+            }
+        }
+    }
+}
+
+impl fmt::Display for ConstraintErr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConstraintErr::DuplicateKey => {
+                write!(f, "Error: Duplicate key.")
+            }
+            ConstraintErr::Unique(columns) => {
+                write!(f, "Error: UNIQUE constraint failed: {}.", columns)
+            }
+            ConstraintErr::NotNull(column) => {
+                write!(f, "Error: NOT NULL constraint failed: {}.", column)
+            }
+            ConstraintErr::Check(column) => {
+                write!(f, "Error: CHECK constraint failed: {}.", column)
+            }
+            ConstraintErr::ForeignKey(column) => {
+                write!(f, "Error: FOREIGN KEY constraint failed: {}.", column)
+            }
+        }
+    }
+}
@@ -0,0 +1,283 @@
+use std::cmp::Ordering;
+
+use crate::ast::{BinaryOperator, Expr, UnaryOperator};
+use crate::row::Row;
+use crate::schema::{Affinity, TableSchema};
+use crate::value::Value;
+
+/// State of the connection read by built-in functions.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Context {
+    pub last_insert_rowid: i64,
+}
+
+/// Number of arguments a built-in function takes, `None` if there is no
+/// function with that name.
+pub fn function_arity(name: &str) -> Option<usize> {
+    if name.eq_ignore_ascii_case("last_insert_rowid") {
+        Some(0)
+    } else {
+        None
+    }
+}
+
+/// Evaluate an expression against a row of a table. The columns and
+/// functions the expression refers to must have been checked to exist beforehand.
+pub fn evaluate(expr: &Expr, schema: &TableSchema, row: &Row, context: Context) -> Value {
+    let evaluate = |expr: &Expr| evaluate(expr, schema, row, context);
+    match expr {
+        Expr::Literal(literal) => Value::from_literal(literal),
+        Expr::Parameter(_) => {
+            unreachable!("evaluate: parameters are bound before the statement is planned")
+        }
+        Expr::Column(column) => row
+            .column_value(schema, column)
+            .expect("evaluate: column names are checked when the statement is prepared"),
+        Expr::Unary(operator, expr) => {
+            let value = evaluate(expr);
+            match operator {
+                UnaryOperator::Plus => value,
+                UnaryOperator::Negate => match value.to_numeric() {
+                    Value::Integer(integer) => match integer.checked_neg() {
+                        Some(integer) => Value::Integer(integer),
+                        None => Value::Real(-(integer as f64)),
+                    },
+                    Value::Real(real) => Value::Real(-real),
+                    _ => Value::Null,
+                },
+                UnaryOperator::Not => match value.to_bool() {
+                    Some(value) => Value::from_bool(!value),
+                    None => Value::Null,
+                },
+            }
+        }
+        Expr::Binary(left, BinaryOperator::And, right) => {
+            // False wins over NULL, NULL wins over true
+            let left = evaluate(left).to_bool();
+            if left == Some(false) {
+                return Value::from_bool(false);
+            }
+            match (left, evaluate(right).to_bool()) {
+                (_, Some(false)) => Value::from_bool(false),
+                (Some(true), Some(true)) => Value::from_bool(true),
+                _ => Value::Null,
+            }
+        }
+        Expr::Binary(left, BinaryOperator::Or, right) => {
+            // True wins over NULL, NULL wins over false
+            let left = evaluate(left).to_bool();
+            if left == Some(true) {
+                return Value::from_bool(true);
+            }
+            match (left, evaluate(right).to_bool()) {
+                (_, Some(true)) => Value::from_bool(true),
+                (Some(false), Some(false)) => Value::from_bool(false),
+                _ => Value::Null,
+            }
+        }
+        Expr::Binary(left, operator, right) if operator.is_comparison() => {
+            let affinity = comparison_affinity(left, right, schema);
+            let left = evaluate(left).apply_affinity(affinity);
+            let right = evaluate(right).apply_affinity(affinity);
+            binary_operation(&left, *operator, &right)
+        }
+        Expr::Binary(left, operator, right) => {
+            let left = evaluate(left);
+            let right = evaluate(right);
+            binary_operation(&left, *operator, &right)
+        }
+        Expr::IsNull { expr, negated } => {
+            let is_null = evaluate(expr).is_null();
+            Value::from_bool(is_null != *negated)
+        }
+        Expr::Like {
+            expr,
+            pattern,
+            negated,
+        } => {
+            let value = evaluate(expr);
+            let pattern = evaluate(pattern);
+            if value.is_null() || pattern.is_null() {
+                return Value::Null;
+            }
+            let is_match = like(&pattern.to_text(), &value.to_text());
+            Value::from_bool(is_match != *negated)
+        }
+        Expr::InList {
+            expr,
+            list,
+            negated,
+        } => {
+            let value = evaluate(expr);
+            if value.is_null() {
+                return Value::Null;
+            }
+
+            let mut saw_null = false;
+            for item in list {
+                let affinity = comparison_affinity(expr, item, schema);
+                let value = value.clone().apply_affinity(affinity);
+                match value.compare(&evaluate(item).apply_affinity(affinity)) {
+                    Some(Ordering::Equal) => return Value::from_bool(!*negated),
+                    Some(_) => {}
+                    None => saw_null = true,
+                }
+            }
+
+            // x IN (..., NULL) is NULL rather than false when nothing matched
+            if saw_null {
+                Value::Null
+            } else {
+                Value::from_bool(*negated)
+            }
+        }
+        Expr::Between {
+            expr,
+            low,
+            high,
+            negated,
+        } => {
+            let compare = |bound: &Expr| {
+                let affinity = comparison_affinity(expr, bound, schema);
+                let value = evaluate(expr).apply_affinity(affinity);
+                value.compare(&evaluate(bound).apply_affinity(affinity))
+            };
+            let above_low = compare(low).map(Ordering::is_ge);
+            let below_high = compare(high).map(Ordering::is_le);
+            let between = match (above_low, below_high) {
+                (Some(false), _) | (_, Some(false)) => Some(false),
+                (Some(true), Some(true)) => Some(true),
+                _ => None,
+            };
+
+            match between {
+                Some(between) => Value::from_bool(between != *negated),
+                None => Value::Null,
+            }
+        }
+        Expr::Function { name, .. } if name.eq_ignore_ascii_case("last_insert_rowid") => {
+            Value::Integer(context.last_insert_rowid)
+        }
+        Expr::Function { .. } => {
+            unreachable!("evaluate: functions are checked when the statement is prepared")
+        }
+    }
+}
+
+// Affinity applied to both sides of a comparison. Only columns have an
+// affinity: a column compared with something that is not a column lends
+// it its affinity, and two columns compare as numbers if either of them
+// is numeric. Blob affinity leaves the values as they are.
+fn comparison_affinity(left: &Expr, right: &Expr, schema: &TableSchema) -> Affinity {
+    let affinity = |expr: &Expr| match expr {
+        Expr::Column(column) => schema
+            .column_index(column)
+            .map(|index| schema.columns[index].affinity),
+        _ => None,
+    };
+    match (affinity(left), affinity(right)) {
+        (Some(left), Some(right)) if left.is_numeric() || right.is_numeric() => Affinity::Numeric,
+        (Some(affinity), None) | (None, Some(affinity)) => affinity,
+        _ => Affinity::Blob,
+    }
+}
+
+fn binary_operation(left: &Value, operator: BinaryOperator, right: &Value) -> Value {
+    if left.is_null() || right.is_null() {
+        return Value::Null;
+    }
+
+    let comparison = |predicate: fn(Ordering) -> bool| match left.compare(right) {
+        Some(ordering) => Value::from_bool(predicate(ordering)),
+        None => Value::Null,
+    };
+
+    match operator {
+        BinaryOperator::Equal => comparison(Ordering::is_eq),
+        BinaryOperator::NotEqual => comparison(Ordering::is_ne),
+        BinaryOperator::Less => comparison(Ordering::is_lt),
+        BinaryOperator::LessEqual => comparison(Ordering::is_le),
+        BinaryOperator::Greater => comparison(Ordering::is_gt),
+        BinaryOperator::GreaterEqual => comparison(Ordering::is_ge),
+        BinaryOperator::Concat => Value::Text(left.to_text() + &right.to_text()),
+        BinaryOperator::Add
+        | BinaryOperator::Subtract
+        | BinaryOperator::Multiply
+        | BinaryOperator::Divide
+        | BinaryOperator::Modulo => arithmetic(&left.to_numeric(), operator, &right.to_numeric()),
+        BinaryOperator::And | BinaryOperator::Or => {
+            unreachable!("binary_operation: AND and OR short-circuit in evaluate")
+        }
+    }
+}
+
+// Integer arithmetic falls back to floating point on overflow.
+// Division by zero gives NULL.
+fn arithmetic(left: &Value, operator: BinaryOperator, right: &Value) -> Value {
+    if let (Value::Integer(a), Value::Integer(b)) = (left, right) {
+        let result = match operator {
+            BinaryOperator::Add => a.checked_add(*b),
+            BinaryOperator::Subtract => a.checked_sub(*b),
+            BinaryOperator::Multiply => a.checked_mul(*b),
+            BinaryOperator::Divide if *b == 0 => return Value::Null,
+            BinaryOperator::Divide => a.checked_div(*b),
+            BinaryOperator::Modulo if *b == 0 => return Value::Null,
+            // i64::MIN % -1 overflows, but any integer modulo -1 is 0
+            BinaryOperator::Modulo if *b == -1 => Some(0),
+            BinaryOperator::Modulo => a.checked_rem(*b),
+            _ => unreachable!("arithmetic: not an arithmetic operator"),
+        };
+        if let Some(result) = result {
+            return Value::Integer(result);
+        }
+    }
+
+    let as_f64 = |value: &Value| match value {
+        Value::Integer(integer) => *integer as f64,
+        Value::Real(real) => *real,
+        _ => 0.0,
+    };
+    let (a, b) = (as_f64(left), as_f64(right));
+
+    match operator {
+        BinaryOperator::Add => Value::Real(a + b),
+        BinaryOperator::Subtract => Value::Real(a - b),
+        BinaryOperator::Multiply => Value::Real(a * b),
+        BinaryOperator::Divide if b == 0.0 => Value::Null,
+        BinaryOperator::Divide => Value::Real(a / b),
+        BinaryOperator::Modulo if b == 0.0 => Value::Null,
+        BinaryOperator::Modulo => Value::Real(a % b),
+        _ => unreachable!("arithmetic: not an arithmetic operator"),
+    }
+}
+
+/// SQL LIKE: `%` matches any run of characters and `_` matches a single one.
+/// ASCII letters match regardless of case.
+pub fn like(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+
+    // Position in the text and pattern to go back to when a `%` has to match more
+    let mut backtrack: Option<(usize, usize)> = None;
+    let (mut p, mut t) = (0, 0);
+
+    while t < text.len() {
+        if p < pattern.len() && pattern[p] == '%' {
+            p += 1;
+            backtrack = Some((p, t));
+        } else if p < pattern.len()
+            && (pattern[p] == '_' || pattern[p].eq_ignore_ascii_case(&text[t]))
+        {
+            p += 1;
+            t += 1;
+        } else if let Some((backtrack_p, backtrack_t)) = backtrack {
+            p = backtrack_p;
+            t = backtrack_t + 1;
+            backtrack = Some((backtrack_p, t));
+        } else {
+            return false;
+        }
+    }
+
+    pattern[p..].iter().all(|c| *c == '%')
+}
@@ -0,0 +1,152 @@
+use crate::error::CorruptErr;
+use crate::pager::PAGE_SIZE;
+
+// Page 0 holds the file header, B-tree pages start at page 1
+pub const HEADER_PAGE_NUM: u32 = 0;
+
+pub const HEADER_MAGIC: &[u8; HEADER_MAGIC_SIZE] = b"rust-sqlite fmt\0";
+// Bumped whenever the layout of pages changes incompatibly
+pub const FORMAT_VERSION: u32 = 3;
+
+// Header Layout
+pub const HEADER_MAGIC_SIZE: usize = 16;
+pub const HEADER_MAGIC_OFFSET: usize = 0;
+pub const FORMAT_VERSION_SIZE: usize = std::mem::size_of::<u32>();
+pub const FORMAT_VERSION_OFFSET: usize = HEADER_MAGIC_OFFSET + HEADER_MAGIC_SIZE;
+pub const HEADER_PAGE_SIZE_SIZE: usize = std::mem::size_of::<u32>();
+pub const HEADER_PAGE_SIZE_OFFSET: usize = FORMAT_VERSION_OFFSET + FORMAT_VERSION_SIZE;
+pub const PAGE_COUNT_SIZE: usize = std::mem::size_of::<u32>();
+pub const PAGE_COUNT_OFFSET: usize = HEADER_PAGE_SIZE_OFFSET + HEADER_PAGE_SIZE_SIZE;
+pub const FREELIST_HEAD_SIZE: usize = std::mem::size_of::<u32>();
+pub const FREELIST_HEAD_OFFSET: usize = PAGE_COUNT_OFFSET + PAGE_COUNT_SIZE;
+pub const SCHEMA_COOKIE_SIZE: usize = std::mem::size_of::<u32>();
+pub const SCHEMA_COOKIE_OFFSET: usize = FREELIST_HEAD_OFFSET + FREELIST_HEAD_SIZE;
+pub const JOURNAL_MODE_SIZE: usize = std::mem::size_of::<u32>();
+pub const JOURNAL_MODE_OFFSET: usize = SCHEMA_COOKIE_OFFSET + SCHEMA_COOKIE_SIZE;
+
+// Header Format
+// |-------------+----------------+-------------+-------------+---------------+---------------+--------------|
+// | bytes 0-15  | bytes 16-19    | bytes 20-23 | bytes 24-27 | bytes 28-31   | bytes 32-35   | bytes 36-39  |
+// | magic       | format_version | page_size   | page_count  | freelist_head | schema_cookie | journal_mode |
+// |-------------+----------------+-------------+-------------+---------------+---------------+--------------|
+// |                                      rest of the page, zeros                                           |
+// |--------------------------------------------------------------------------------------------------------|
+
+/// How changes reach the database file.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum JournalMode {
+    /// Pages are overwritten in place, after their original images
+    /// are saved to a rollback journal that is deleted on commit.
+    Delete,
+    /// Changed pages are appended to a write-ahead log and copied
+    /// into the database file by checkpoints.
+    Wal,
+}
+
+impl JournalMode {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "delete" => Some(JournalMode::Delete),
+            "wal" => Some(JournalMode::Wal),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            JournalMode::Delete => "delete",
+            JournalMode::Wal => "wal",
+        }
+    }
+}
+
+/// Fields of the file header besides the magic string and format version.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Header {
+    pub page_size: u32,
+    /// Pages in the file, the header page included.
+    pub page_count: u32,
+    /// First trunk page of the freelist, 0 when no page is free.
+    pub freelist_head: u32,
+    /// Changed whenever a table is created or dropped.
+    pub schema_cookie: u32,
+    pub journal_mode: JournalMode,
+}
+
+impl Header {
+    /// Header of a new file holding only the header page.
+    pub fn for_new_file() -> Self {
+        Self {
+            page_size: PAGE_SIZE as u32,
+            page_count: 1,
+            freelist_head: 0,
+            schema_cookie: 0,
+            journal_mode: JournalMode::Delete,
+        }
+    }
+
+    /// Read and validate the header page of an existing file.
+    pub fn from_bytes(bytes: &[u8; PAGE_SIZE]) -> Result<Self, CorruptErr> {
+        let magic = &bytes[HEADER_MAGIC_OFFSET..HEADER_MAGIC_OFFSET + HEADER_MAGIC_SIZE];
+        if magic != HEADER_MAGIC {
+            return Err(CorruptErr::NotADatabase);
+        }
+
+        let format_version = read_u32(bytes, FORMAT_VERSION_OFFSET);
+        if format_version != FORMAT_VERSION {
+            return Err(CorruptErr::UnsupportedFormatVersion(format_version));
+        }
+
+        let header = Self {
+            page_size: read_u32(bytes, HEADER_PAGE_SIZE_OFFSET),
+            page_count: read_u32(bytes, PAGE_COUNT_OFFSET),
+            freelist_head: read_u32(bytes, FREELIST_HEAD_OFFSET),
+            schema_cookie: read_u32(bytes, SCHEMA_COOKIE_OFFSET),
+            journal_mode: match read_u32(bytes, JOURNAL_MODE_OFFSET) {
+                0 => JournalMode::Delete,
+                1 => JournalMode::Wal,
+                _ => return Err(CorruptErr::Invalid("Unknown journal mode in the header")),
+            },
+        };
+
+        if header.page_size != PAGE_SIZE as u32 {
+            return Err(CorruptErr::PageSizeMismatch(header.page_size));
+        }
+        if header.freelist_head >= header.page_count.max(1) {
+            return Err(CorruptErr::Invalid(
+                "Freelist head is past the end of the file",
+            ));
+        }
+
+        Ok(header)
+    }
+
+    pub fn to_bytes(&self) -> [u8; PAGE_SIZE] {
+        let mut bytes = [0; PAGE_SIZE];
+
+        bytes[HEADER_MAGIC_OFFSET..HEADER_MAGIC_OFFSET + HEADER_MAGIC_SIZE]
+            .copy_from_slice(HEADER_MAGIC);
+        write_u32(&mut bytes, FORMAT_VERSION_OFFSET, FORMAT_VERSION);
+        write_u32(&mut bytes, HEADER_PAGE_SIZE_OFFSET, self.page_size);
+        write_u32(&mut bytes, PAGE_COUNT_OFFSET, self.page_count);
+        write_u32(&mut bytes, FREELIST_HEAD_OFFSET, self.freelist_head);
+        write_u32(&mut bytes, SCHEMA_COOKIE_OFFSET, self.schema_cookie);
+        let journal_mode = match self.journal_mode {
+            JournalMode::Delete => 0,
+            JournalMode::Wal => 1,
+        };
+        write_u32(&mut bytes, JOURNAL_MODE_OFFSET, journal_mode);
+
+        bytes
+    }
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    let mut u32_bytes = [0; std::mem::size_of::<u32>()];
+    u32_bytes.copy_from_slice(&bytes[offset..offset + std::mem::size_of::<u32>()]);
+    u32::from_le_bytes(u32_bytes)
+}
+
+fn write_u32(bytes: &mut [u8], offset: usize, value: u32) {
+    bytes[offset..offset + std::mem::size_of::<u32>()].copy_from_slice(&value.to_le_bytes());
+}
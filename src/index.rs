@@ -0,0 +1,438 @@
+use std::cmp::Ordering;
+use std::ops::Bound;
+
+use crate::{
+    error::{CorruptErr, Error},
+    node::{
+        index_node_cell_size, Node, INDEX_MAX_KEY_SIZE, INDEX_NODE_MAX_CELLS,
+        INDEX_NODE_MIN_USED_SPACE,
+    },
+    pager::Pager,
+    row::Row,
+    statement::PrepareStatementErr,
+    value::Value,
+};
+
+// Following leaf pointers through more leaves than there are pages means they link up in a loop
+const LEAVES_LOOP: CorruptErr = CorruptErr::Invalid("Leaves of an index link up in a loop");
+
+/// Order of an index key and a key or the prefix of one, comparing their
+/// values in turn with NULL first. A key starting with the prefix is equal to it.
+pub fn compare_prefix(key: &[Value], prefix: &[Value]) -> Ordering {
+    key.iter()
+        .zip(prefix)
+        .map(|(value, prefix_value)| value.sort_cmp(prefix_value))
+        .find(|ordering| ordering.is_ne())
+        .unwrap_or(Ordering::Equal)
+}
+
+/// Whether an index key comes after the start of a range of keys.
+pub fn is_after(key: &[Value], start: Bound<&[Value]>) -> bool {
+    match start {
+        Bound::Included(start) => compare_prefix(key, start).is_ge(),
+        Bound::Excluded(start) => compare_prefix(key, start).is_gt(),
+        Bound::Unbounded => true,
+    }
+}
+
+/// Whether an index key comes before the end of a range of keys.
+pub fn is_before(key: &[Value], end: Bound<&[Value]>) -> bool {
+    match end {
+        Bound::Included(end) => compare_prefix(key, end).is_le(),
+        Bound::Excluded(end) => compare_prefix(key, end).is_lt(),
+        Bound::Unbounded => true,
+    }
+}
+
+/// An index B-tree in the database file, found by the page its root is on.
+/// Its keys are records, see `IndexSchema::key`, and it has no values.
+pub struct Index<'a> {
+    pub pager: &'a mut Pager,
+    pub root_page_num: u32,
+}
+
+impl<'a> Index<'a> {
+    pub fn new(pager: &'a mut Pager, root_page_num: u32) -> Self {
+        Self {
+            pager,
+            root_page_num,
+        }
+    }
+
+    /// Start an empty index on an unused page.
+    pub fn create(pager: &'a mut Pager) -> Result<Self, Error> {
+        let root_page_num = pager.get_unused_page_num()?;
+        *pager.get_page(root_page_num)? = Node::IndexLeaf {
+            next_leaf_pointer: 0,
+            keys: Vec::new(),
+        };
+        Ok(Self::new(pager, root_page_num))
+    }
+
+    /// Give every page of the tree back to the pager for reuse.
+    /// The tree must not be used afterwards.
+    pub fn free_pages(&mut self) -> Result<(), Error> {
+        let mut page_nums = vec![self.root_page_num];
+        let mut i = 0;
+        while i < page_nums.len() {
+            if let Node::IndexInternal { children, .. } = self.pager.read_page(page_nums[i])? {
+                let children = children.clone();
+                page_nums.extend(children);
+            }
+            i += 1;
+        }
+
+        for page_num in page_nums {
+            self.pager.free_page(page_num)?;
+        }
+        Ok(())
+    }
+
+    /// Call a function with every key of the index as it is stored, in order.
+    pub fn for_each_key(
+        &mut self,
+        mut f: impl FnMut(&[u8]) -> Result<(), Error>,
+    ) -> Result<(), Error> {
+        let (_, mut page_num) = self.find_leaf(Bound::Unbounded)?;
+        for _ in 0..self.pager.num_pages {
+            let (next_leaf_pointer, keys) = self.leaf(page_num)?;
+            for key in keys {
+                f(key)?;
+            }
+            if next_leaf_pointer == 0 {
+                return Ok(());
+            }
+            page_num = next_leaf_pointer;
+        }
+        Err(LEAVES_LOOP.into())
+    }
+
+    /// The first key after the start of a range, `None` if there is none.
+    pub fn seek(&mut self, start: Bound<&[Value]>) -> Result<Option<Vec<Value>>, Error> {
+        let (_, mut page_num) = self.find_leaf(start)?;
+        // The key is in the leaf found, unless every key of the leaf comes
+        // before the start. It is then the first key of the next leaf.
+        for _ in 0..self.pager.num_pages {
+            let (next_leaf_pointer, keys) = self.leaf(page_num)?;
+            let index = first_after(keys, start)?;
+            if let Some(key) = keys.get(index) {
+                return Ok(Some(Row::from_record(key)?.values));
+            }
+            if next_leaf_pointer == 0 {
+                return Ok(None);
+            }
+            page_num = next_leaf_pointer;
+        }
+        Err(LEAVES_LOOP.into())
+    }
+
+    /// Add a key that is not in the index yet, splitting nodes that overflow.
+    /// The key of a row with long values in unbounded columns can be too
+    /// long for a node.
+    pub fn insert(&mut self, key: &[Value]) -> Result<(), Error> {
+        let record = Row::new(key.to_vec()).serialize();
+        if record.len() > INDEX_MAX_KEY_SIZE {
+            return Err(PrepareStatementErr::IndexKeyTooLarge(record.len()).into());
+        }
+        let (path, page_num) = self.find_leaf(Bound::Included(key))?;
+        let mut node = self.take_node(page_num)?;
+        let keys = node.index_node_keys()?;
+        let index = first_after(keys, Bound::Included(key))?;
+        keys.insert(index, record);
+        self.balance(path, page_num, node)
+    }
+
+    /// Remove a key of the index, merging nodes that underflow.
+    pub fn delete(&mut self, key: &[Value]) -> Result<(), Error> {
+        let (path, page_num) = self.find_leaf(Bound::Included(key))?;
+        let mut node = self.take_node(page_num)?;
+        let keys = node.index_node_keys()?;
+        let index = first_after(keys, Bound::Included(key))?;
+        let found = match keys.get(index) {
+            Some(found) => compare_prefix(&Row::from_record(found)?.values, key).is_eq(),
+            None => false,
+        };
+        if !found {
+            return Err(CorruptErr::Invalid("Index is missing the key of a row").into());
+        }
+        keys.remove(index);
+        self.balance(path, page_num, node)
+    }
+
+    // Go down from the root to the leaf holding the first key after the
+    // start of a range, or the leaf before it if it starts a leaf. Returns
+    // the leaf and the internal nodes on the way, each with the index of
+    // the child taken.
+    fn find_leaf(&mut self, start: Bound<&[Value]>) -> Result<(Vec<(u32, usize)>, u32), Error> {
+        let mut path = Vec::new();
+        let mut page_num = self.root_page_num;
+        loop {
+            match self.pager.read_page(page_num)? {
+                Node::IndexLeaf { .. } => return Ok((path, page_num)),
+                Node::IndexInternal { keys, children } => {
+                    let index = first_after(keys, start)?;
+                    path.push((page_num, index));
+                    page_num = children[index];
+                }
+                _ => return Err(CorruptErr::Invalid("Table page is part of an index").into()),
+            }
+        }
+    }
+
+    // Next leaf pointer and keys of an index leaf
+    fn leaf(&mut self, page_num: u32) -> Result<(u32, &Vec<Vec<u8>>), Error> {
+        match self.pager.read_page(page_num)? {
+            Node::IndexLeaf {
+                next_leaf_pointer,
+                keys,
+            } => Ok((*next_leaf_pointer, keys)),
+            _ => Err(CorruptErr::Invalid("Index leaf is not a leaf").into()),
+        }
+    }
+
+    // Put a node taken out of its page back, splitting it if it overflows
+    // or fixing it up with a sibling if it underflows, then do the same
+    // for its parent and so on up to the root. A node that overflows never
+    // goes back to the pager, which could not write it out.
+    fn balance(
+        &mut self,
+        mut path: Vec<(u32, usize)>,
+        mut page_num: u32,
+        mut node: Node,
+    ) -> Result<(), Error> {
+        loop {
+            let parent = path.pop();
+            if node.index_node_fits()? {
+                let underflows = node.index_node_used_space()? < INDEX_NODE_MIN_USED_SPACE;
+                *self.pager.get_page(page_num)? = node;
+                let Some((parent_page_num, child_index)) = parent else {
+                    return self.balance_root();
+                };
+                if !underflows {
+                    return Ok(());
+                }
+
+                node = self.take_node(parent_page_num)?;
+                self.merge_child(&mut node, child_index)?;
+                page_num = parent_page_num;
+                continue;
+            }
+
+            let right_page_num = self.new_page()?;
+            let (left, separator, right) = split(node, right_page_num)?;
+            *self.pager.get_page(right_page_num)? = right;
+            let Some((parent_page_num, child_index)) = parent else {
+                // The root stays on its page, so the left half moves to a new one
+                let left_page_num = self.new_page()?;
+                *self.pager.get_page(left_page_num)? = left;
+                *self.pager.get_page(page_num)? = Node::IndexInternal {
+                    keys: vec![separator],
+                    children: vec![left_page_num, right_page_num],
+                };
+                return Ok(());
+            };
+            *self.pager.get_page(page_num)? = left;
+
+            node = self.take_node(parent_page_num)?;
+            if let Node::IndexInternal { keys, children } = &mut node {
+                keys.insert(child_index, separator);
+                children.insert(child_index + 1, right_page_num);
+            }
+            page_num = parent_page_num;
+        }
+    }
+
+    // A root internal node left with one child is replaced by it
+    fn balance_root(&mut self) -> Result<(), Error> {
+        let child_page_num = match self.pager.read_page(self.root_page_num)? {
+            Node::IndexInternal { keys, children } if keys.is_empty() => children[0],
+            _ => return Ok(()),
+        };
+        let child = self.take_node(child_page_num)?;
+        *self.pager.get_page(self.root_page_num)? = child;
+        self.pager.free_page(child_page_num)
+    }
+
+    // Merge a child of an internal node with a sibling, or if the cells of
+    // both do not fit in one node, share them out evenly between the two.
+    // The parent is changed in place, and can overflow if the key
+    // separating the two grows.
+    fn merge_child(&mut self, parent: &mut Node, child_index: usize) -> Result<(), Error> {
+        let Node::IndexInternal { keys, children } = parent else {
+            return Err(CorruptErr::Invalid("Index leaf has children").into());
+        };
+        if children.len() < 2 {
+            return Ok(());
+        }
+        let left_index = child_index.min(children.len() - 2);
+        let left_page_num = children[left_index];
+        let right_page_num = children[left_index + 1];
+        let separator = keys[left_index].clone();
+
+        let left = self.take_node(left_page_num)?;
+        let right = self.take_node(right_page_num)?;
+        let merged = match (left, right) {
+            (
+                Node::IndexLeaf { keys: mut left, .. },
+                Node::IndexLeaf {
+                    next_leaf_pointer,
+                    keys: right,
+                },
+            ) => {
+                left.extend(right);
+                Node::IndexLeaf {
+                    next_leaf_pointer,
+                    keys: left,
+                }
+            }
+            (
+                Node::IndexInternal {
+                    keys: mut left,
+                    children: mut left_children,
+                },
+                Node::IndexInternal {
+                    keys: right,
+                    children: right_children,
+                },
+            ) => {
+                // The separator comes down between the children of the two
+                left.push(separator);
+                left.extend(right);
+                left_children.extend(right_children);
+                Node::IndexInternal {
+                    keys: left,
+                    children: left_children,
+                }
+            }
+            _ => return Err(CorruptErr::Invalid("Index leaves are at different depths").into()),
+        };
+
+        if merged.index_node_fits()? {
+            *self.pager.get_page(left_page_num)? = merged;
+            self.pager.free_page(right_page_num)?;
+            keys.remove(left_index);
+            children.remove(left_index + 1);
+            return Ok(());
+        }
+
+        let (left, separator, right) = split(merged, right_page_num)?;
+        *self.pager.get_page(left_page_num)? = left;
+        *self.pager.get_page(right_page_num)? = right;
+        keys[left_index] = separator;
+        Ok(())
+    }
+
+    // Reserve an unused page for a node
+    fn new_page(&mut self) -> Result<u32, Error> {
+        let page_num = self.pager.get_unused_page_num()?;
+        *self.pager.get_page(page_num)? = Node::initialize_leaf_node();
+        Ok(page_num)
+    }
+
+    // Take a node out of its page to rebuild it, leaving an empty leaf
+    // until it is put back
+    fn take_node(&mut self, page_num: u32) -> Result<Node, Error> {
+        let node = self.pager.get_page(page_num)?;
+        if !matches!(node, Node::IndexLeaf { .. } | Node::IndexInternal { .. }) {
+            return Err(CorruptErr::Invalid("Table page is part of an index").into());
+        }
+        Ok(std::mem::replace(node, Node::initialize_leaf_node()))
+    }
+}
+
+// Position of the first of the ordered keys that comes after the start of
+// a range, the number of keys if none does
+fn first_after(keys: &[Vec<u8>], start: Bound<&[Value]>) -> Result<usize, Error> {
+    let mut min_index = 0;
+    let mut max_index = keys.len();
+    while min_index != max_index {
+        let index = (min_index + max_index) / 2;
+        if is_after(&Row::from_record(&keys[index])?.values, start) {
+            max_index = index;
+        } else {
+            min_index = index + 1;
+        }
+    }
+    Ok(min_index)
+}
+
+// Divide the cells of a node between a left and a right node, the right
+// one to go on the given page. Returns them with the key separating them
+// in their parent: the last key of a left leaf, or the middle key of an
+// internal node, which moves up.
+fn split(node: Node, right_page_num: u32) -> Result<(Node, Vec<u8>, Node), Error> {
+    match node {
+        Node::IndexLeaf {
+            next_leaf_pointer,
+            mut keys,
+        } => {
+            let sizes: Vec<_> = keys
+                .iter()
+                .map(|key| index_node_cell_size(key, true))
+                .collect();
+            let right = keys.split_off(split_point(&sizes, false));
+            let separator = keys[keys.len() - 1].clone();
+            Ok((
+                Node::IndexLeaf {
+                    next_leaf_pointer: right_page_num,
+                    keys,
+                },
+                separator,
+                Node::IndexLeaf {
+                    next_leaf_pointer,
+                    keys: right,
+                },
+            ))
+        }
+        Node::IndexInternal {
+            mut keys,
+            mut children,
+        } => {
+            let sizes: Vec<_> = keys
+                .iter()
+                .map(|key| index_node_cell_size(key, false))
+                .collect();
+            let split_at = split_point(&sizes, true);
+            let right = keys.split_off(split_at + 1);
+            let right_children = children.split_off(split_at + 1);
+            let separator = keys.pop().expect("split: the middle key is on the left");
+            Ok((
+                Node::IndexInternal { keys, children },
+                separator,
+                Node::IndexInternal {
+                    keys: right,
+                    children: right_children,
+                },
+            ))
+        }
+        _ => Err(CorruptErr::Invalid("Table page is part of an index").into()),
+    }
+}
+
+// Number of cells to keep on the left when splitting cells of the given
+// sizes in two, so that both halves take up about as much space. With
+// `separator`, the cell after them moves up rather than to the right.
+// Each half keeps at least one cell and at most INDEX_NODE_MAX_CELLS.
+fn split_point(sizes: &[usize], separator: bool) -> usize {
+    let moved_up = separator as usize;
+    let total_size: usize = sizes.iter().sum();
+    let first = sizes
+        .len()
+        .saturating_sub(INDEX_NODE_MAX_CELLS + moved_up)
+        .max(1);
+    let last = INDEX_NODE_MAX_CELLS.min(sizes.len() - 1 - moved_up);
+
+    let mut left_size: usize = sizes[..first].iter().sum();
+    let mut best = first;
+    let mut best_difference = usize::MAX;
+    for (split_at, &size) in sizes.iter().enumerate().take(last + 1).skip(first) {
+        let right_size = total_size - left_size - moved_up * size;
+        let difference = left_size.abs_diff(right_size);
+        if difference < best_difference {
+            best = split_at;
+            best_difference = difference;
+        }
+        left_size += size;
+    }
+    best
+}
@@ -0,0 +1,181 @@
+use std::collections::HashSet;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Seek, Write};
+
+use crate::pager::PAGE_SIZE;
+
+pub const JOURNAL_MAGIC: &[u8; JOURNAL_MAGIC_SIZE] = b"rust-sqlite jrnl";
+
+// Journal Header Layout
+pub const JOURNAL_MAGIC_SIZE: usize = 16;
+pub const JOURNAL_MAGIC_OFFSET: usize = 0;
+pub const ORIGINAL_PAGE_COUNT_SIZE: usize = std::mem::size_of::<u32>();
+pub const ORIGINAL_PAGE_COUNT_OFFSET: usize = JOURNAL_MAGIC_OFFSET + JOURNAL_MAGIC_SIZE;
+pub const JOURNAL_HEADER_SIZE: usize = JOURNAL_MAGIC_SIZE + ORIGINAL_PAGE_COUNT_SIZE;
+
+// Journal Record Layout
+pub const RECORD_PAGE_NUM_SIZE: usize = std::mem::size_of::<u32>();
+pub const RECORD_PAGE_NUM_OFFSET: usize = 0;
+pub const RECORD_PAGE_OFFSET: usize = RECORD_PAGE_NUM_OFFSET + RECORD_PAGE_NUM_SIZE;
+pub const RECORD_CHECKSUM_SIZE: usize = std::mem::size_of::<u32>();
+pub const RECORD_CHECKSUM_OFFSET: usize = RECORD_PAGE_OFFSET + PAGE_SIZE;
+pub const JOURNAL_RECORD_SIZE: usize = RECORD_CHECKSUM_OFFSET + RECORD_CHECKSUM_SIZE;
+
+// Journal Format
+// |-------------+--------------------------+----------+-----+----------|
+// | bytes 0-15  | bytes 16-19              | record 0 | ... | record n |
+// | magic       | page count before writes |          |     |          |
+// |-------------+--------------------------+----------+-----+----------|
+//
+// Journal Record Format
+// |-------------+-----------------------------+-----------|
+// | 4 bytes     | 4096 bytes                  | 4 bytes   |
+// | page_num    | page image before the write | checksum  |
+// |-------------+-----------------------------+-----------|
+
+/// Path of the rollback journal kept next to a database file.
+pub fn journal_path(filename: &str) -> String {
+    format!("{filename}-journal")
+}
+
+/// The rollback journal of an open write. It holds the original image of
+/// every page changed since the last commit, so those changes can be
+/// undone if the process dies before the commit completes.
+pub struct Journal {
+    file: File,
+    path: String,
+    original_page_count: u32,
+    // Pages whose original image is in the journal
+    pages: HashSet<u32>,
+    // Whether everything appended so far has reached the disk
+    synced: bool,
+}
+
+impl Journal {
+    /// Start a journal for a database file that is `original_page_count` pages long.
+    pub fn create(path: String, original_page_count: u32) -> io::Result<Self> {
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&path)?;
+
+        let mut header = [0; JOURNAL_HEADER_SIZE];
+        header[JOURNAL_MAGIC_OFFSET..JOURNAL_MAGIC_OFFSET + JOURNAL_MAGIC_SIZE]
+            .copy_from_slice(JOURNAL_MAGIC);
+        header[ORIGINAL_PAGE_COUNT_OFFSET..ORIGINAL_PAGE_COUNT_OFFSET + ORIGINAL_PAGE_COUNT_SIZE]
+            .copy_from_slice(&original_page_count.to_le_bytes());
+        file.write_all(&header)?;
+
+        Ok(Self {
+            file,
+            path,
+            original_page_count,
+            pages: HashSet::new(),
+            synced: false,
+        })
+    }
+
+    /// Pages in the database file when the journal was started.
+    pub fn original_page_count(&self) -> u32 {
+        self.original_page_count
+    }
+
+    pub fn contains(&self, page_num: u32) -> bool {
+        self.pages.contains(&page_num)
+    }
+
+    /// Save the original image of a page before it is first changed.
+    pub fn append(&mut self, page_num: u32, page: &[u8; PAGE_SIZE]) -> io::Result<()> {
+        let mut record = vec![0; JOURNAL_RECORD_SIZE];
+        record[RECORD_PAGE_NUM_OFFSET..RECORD_PAGE_NUM_OFFSET + RECORD_PAGE_NUM_SIZE]
+            .copy_from_slice(&page_num.to_le_bytes());
+        record[RECORD_PAGE_OFFSET..RECORD_PAGE_OFFSET + PAGE_SIZE].copy_from_slice(page);
+        let checksum = checksum(&record[..RECORD_CHECKSUM_OFFSET]);
+        record[RECORD_CHECKSUM_OFFSET..].copy_from_slice(&checksum.to_le_bytes());
+
+        self.file.write_all(&record)?;
+        self.pages.insert(page_num);
+        self.synced = false;
+        Ok(())
+    }
+
+    /// Make sure every original image is on disk. Must be called before
+    /// the page it saves is overwritten in the database file.
+    pub fn sync(&mut self) -> io::Result<()> {
+        if !self.synced {
+            self.file.sync_all()?;
+            self.synced = true;
+        }
+        Ok(())
+    }
+
+    /// Undo the changes written to the database file since the journal was
+    /// started, the same way a hot journal is rolled back, and delete it.
+    pub fn roll_back(self, file: &mut File) -> io::Result<()> {
+        roll_back_hot_journal(&self.path, file)
+    }
+
+    /// Delete the journal once the database file holds the committed pages.
+    /// This is the commit point: a crash before it rolls the changes back.
+    pub fn delete(self) -> io::Result<()> {
+        std::fs::remove_file(&self.path)
+    }
+}
+
+/// Undo an unfinished write left behind by a crash: copy the original page
+/// images in the journal back into the database file and cut the file back
+/// to its original length. A journal without a valid header was never
+/// synced, so the database file was not written and the journal is dropped.
+/// A record cut short or with a bad checksum ends the journal.
+pub fn roll_back_hot_journal(path: &str, file: &mut File) -> io::Result<()> {
+    let mut journal = match File::open(path) {
+        Ok(journal) => journal,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(err) => return Err(err),
+    };
+
+    let mut bytes = Vec::new();
+    journal.read_to_end(&mut bytes)?;
+
+    let valid_header = bytes.len() >= JOURNAL_HEADER_SIZE
+        && &bytes[JOURNAL_MAGIC_OFFSET..JOURNAL_MAGIC_OFFSET + JOURNAL_MAGIC_SIZE] == JOURNAL_MAGIC;
+    if valid_header {
+        for record in bytes[JOURNAL_HEADER_SIZE..].chunks_exact(JOURNAL_RECORD_SIZE) {
+            let stored_checksum = read_u32(record, RECORD_CHECKSUM_OFFSET);
+            if checksum(&record[..RECORD_CHECKSUM_OFFSET]) != stored_checksum {
+                break;
+            }
+
+            let page_num = read_u32(record, RECORD_PAGE_NUM_OFFSET);
+            file.seek(io::SeekFrom::Start(page_num as u64 * PAGE_SIZE as u64))?;
+            file.write_all(&record[RECORD_PAGE_OFFSET..RECORD_PAGE_OFFSET + PAGE_SIZE])?;
+        }
+
+        let original_page_count = read_u32(&bytes, ORIGINAL_PAGE_COUNT_OFFSET);
+        file.set_len(original_page_count as u64 * PAGE_SIZE as u64)?;
+        file.sync_all()?;
+    }
+
+    std::fs::remove_file(path)
+}
+
+/// Checksum of a journal record, two running sums over its 32-bit words.
+pub fn checksum(bytes: &[u8]) -> u32 {
+    let (mut s1, mut s2) = (0u32, 0u32);
+    for word in bytes.chunks(std::mem::size_of::<u32>()) {
+        let mut word_bytes = [0; std::mem::size_of::<u32>()];
+        word_bytes[..word.len()].copy_from_slice(word);
+        s1 = s1
+            .wrapping_add(u32::from_le_bytes(word_bytes))
+            .wrapping_add(s2);
+        s2 = s2.wrapping_add(s1);
+    }
+    s1 ^ s2
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    let mut u32_bytes = [0; std::mem::size_of::<u32>()];
+    u32_bytes.copy_from_slice(&bytes[offset..offset + std::mem::size_of::<u32>()]);
+    u32::from_le_bytes(u32_bytes)
+}
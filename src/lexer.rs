@@ -0,0 +1,479 @@
+use std::fmt;
+use std::iter::Peekable;
+use std::str::Chars;
+
+use crate::parser::ParseError;
+
+/// Position of a token in the input, both 1-based.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Span {
+    pub line: usize,
+    pub column: usize,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Keyword {
+    Action,
+    And,
+    Asc,
+    Autoincrement,
+    Begin,
+    Between,
+    By,
+    Cascade,
+    Check,
+    Commit,
+    Create,
+    Default,
+    Deferrable,
+    Deferred,
+    Delete,
+    Desc,
+    Drop,
+    Exists,
+    From,
+    If,
+    Immediate,
+    In,
+    Index,
+    Initially,
+    Insert,
+    Into,
+    Is,
+    Key,
+    Like,
+    Limit,
+    No,
+    Not,
+    Null,
+    Offset,
+    On,
+    Or,
+    Order,
+    Pragma,
+    Primary,
+    References,
+    Release,
+    Restrict,
+    Rollback,
+    Savepoint,
+    Select,
+    Set,
+    Table,
+    To,
+    Transaction,
+    Unique,
+    Update,
+    Vacuum,
+    Values,
+    Where,
+}
+
+impl Keyword {
+    fn from_identifier(identifier: &str) -> Option<Self> {
+        let keyword = match identifier.to_uppercase().as_str() {
+            "ACTION" => Keyword::Action,
+            "AND" => Keyword::And,
+            "ASC" => Keyword::Asc,
+            "AUTOINCREMENT" => Keyword::Autoincrement,
+            "BEGIN" => Keyword::Begin,
+            "BETWEEN" => Keyword::Between,
+            "BY" => Keyword::By,
+            "CASCADE" => Keyword::Cascade,
+            "CHECK" => Keyword::Check,
+            "COMMIT" => Keyword::Commit,
+            "CREATE" => Keyword::Create,
+            "DEFAULT" => Keyword::Default,
+            "DEFERRABLE" => Keyword::Deferrable,
+            "DEFERRED" => Keyword::Deferred,
+            "DELETE" => Keyword::Delete,
+            "DESC" => Keyword::Desc,
+            "DROP" => Keyword::Drop,
+            "EXISTS" => Keyword::Exists,
+            "FROM" => Keyword::From,
+            "IF" => Keyword::If,
+            "IMMEDIATE" => Keyword::Immediate,
+            "IN" => Keyword::In,
+            "INDEX" => Keyword::Index,
+            "INITIALLY" => Keyword::Initially,
+            "INSERT" => Keyword::Insert,
+            "INTO" => Keyword::Into,
+            "IS" => Keyword::Is,
+            "KEY" => Keyword::Key,
+            "LIKE" => Keyword::Like,
+            "LIMIT" => Keyword::Limit,
+            "NO" => Keyword::No,
+            "NOT" => Keyword::Not,
+            "NULL" => Keyword::Null,
+            "OFFSET" => Keyword::Offset,
+            "ON" => Keyword::On,
+            "OR" => Keyword::Or,
+            "ORDER" => Keyword::Order,
+            "PRAGMA" => Keyword::Pragma,
+            "PRIMARY" => Keyword::Primary,
+            "REFERENCES" => Keyword::References,
+            "RELEASE" => Keyword::Release,
+            "RESTRICT" => Keyword::Restrict,
+            "ROLLBACK" => Keyword::Rollback,
+            "SAVEPOINT" => Keyword::Savepoint,
+            "SELECT" => Keyword::Select,
+            "SET" => Keyword::Set,
+            "TABLE" => Keyword::Table,
+            "TO" => Keyword::To,
+            "TRANSACTION" => Keyword::Transaction,
+            "UNIQUE" => Keyword::Unique,
+            "UPDATE" => Keyword::Update,
+            "VACUUM" => Keyword::Vacuum,
+            "VALUES" => Keyword::Values,
+            "WHERE" => Keyword::Where,
+            _ => return None,
+        };
+        Some(keyword)
+    }
+}
+
+impl fmt::Display for Keyword {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let keyword = format!("{:?}", self).to_uppercase();
+        write!(f, "{}", keyword)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Token {
+    Keyword(Keyword),
+    Identifier(String),
+    String(String),
+    /// Integer literal. A minus sign before it is a separate token, so it
+    /// can be one more than the largest `i64`.
+    Integer(u64),
+    Float(f64),
+    /// `X'hex'` blob literal
+    Blob(Vec<u8>),
+    /// `?`, `?NNN` or `:name`, as written
+    Parameter(String),
+    LeftParen,
+    RightParen,
+    Comma,
+    Semicolon,
+    Dot,
+    Star,
+    Plus,
+    Minus,
+    Slash,
+    Percent,
+    Concat,
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    Eof,
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Token::Keyword(keyword) => write!(f, "{}", keyword),
+            Token::Identifier(identifier) => write!(f, "{}", identifier),
+            Token::String(string) => write!(f, "'{}'", string.replace('\'', "''")),
+            Token::Integer(integer) => write!(f, "{}", integer),
+            Token::Float(float) => write!(f, "{}", float),
+            Token::Blob(blob) => {
+                write!(f, "X'")?;
+                for byte in blob {
+                    write!(f, "{:02X}", byte)?;
+                }
+                write!(f, "'")
+            }
+            Token::Parameter(parameter) => write!(f, "{}", parameter),
+            Token::LeftParen => write!(f, "("),
+            Token::RightParen => write!(f, ")"),
+            Token::Comma => write!(f, ","),
+            Token::Semicolon => write!(f, ";"),
+            Token::Dot => write!(f, "."),
+            Token::Star => write!(f, "*"),
+            Token::Plus => write!(f, "+"),
+            Token::Minus => write!(f, "-"),
+            Token::Slash => write!(f, "/"),
+            Token::Percent => write!(f, "%"),
+            Token::Concat => write!(f, "||"),
+            Token::Equal => write!(f, "="),
+            Token::NotEqual => write!(f, "!="),
+            Token::Less => write!(f, "<"),
+            Token::LessEqual => write!(f, "<="),
+            Token::Greater => write!(f, ">"),
+            Token::GreaterEqual => write!(f, ">="),
+            Token::Eof => write!(f, "end of input"),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct SpannedToken {
+    pub token: Token,
+    pub span: Span,
+}
+
+pub struct Lexer<'a> {
+    chars: Peekable<Chars<'a>>,
+    line: usize,
+    column: usize,
+}
+
+impl<'a> Lexer<'a> {
+    pub fn new(input: &'a str) -> Self {
+        Self {
+            chars: input.chars().peekable(),
+            line: 1,
+            column: 1,
+        }
+    }
+
+    /// Split the whole input into tokens. The last token is always `Token::Eof`.
+    pub fn tokenize(mut self) -> Result<Vec<SpannedToken>, ParseError> {
+        let mut tokens = Vec::new();
+        loop {
+            let token = self.next_token()?;
+            let end_of_input = token.token == Token::Eof;
+            tokens.push(token);
+            if end_of_input {
+                return Ok(tokens);
+            }
+        }
+    }
+
+    fn span(&self) -> Span {
+        Span {
+            line: self.line,
+            column: self.column,
+        }
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.chars.next()?;
+        if c == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+        Some(c)
+    }
+
+    fn bump_if(&mut self, expected: char) -> bool {
+        if self.chars.peek() == Some(&expected) {
+            self.bump();
+            true
+        } else {
+            false
+        }
+    }
+
+    // Skip whitespace, `-- line` comments and `/* block */` comments
+    fn skip_trivia(&mut self) -> Result<(), ParseError> {
+        loop {
+            match self.chars.peek() {
+                Some(c) if c.is_whitespace() => {
+                    self.bump();
+                }
+                Some('-') => {
+                    let mut lookahead = self.chars.clone();
+                    lookahead.next();
+                    if lookahead.peek() != Some(&'-') {
+                        return Ok(());
+                    }
+                    while let Some(c) = self.bump() {
+                        if c == '\n' {
+                            break;
+                        }
+                    }
+                }
+                Some('/') => {
+                    let mut lookahead = self.chars.clone();
+                    lookahead.next();
+                    if lookahead.peek() != Some(&'*') {
+                        return Ok(());
+                    }
+                    let span = self.span();
+                    self.bump();
+                    self.bump();
+                    loop {
+                        match self.bump() {
+                            Some('*') if self.bump_if('/') => break,
+                            Some(_) => {}
+                            None => return Err(ParseError::new("unterminated comment", span)),
+                        }
+                    }
+                }
+                _ => return Ok(()),
+            }
+        }
+    }
+
+    fn next_token(&mut self) -> Result<SpannedToken, ParseError> {
+        self.skip_trivia()?;
+
+        let span = self.span();
+        let Some(c) = self.bump() else {
+            return Ok(SpannedToken {
+                token: Token::Eof,
+                span,
+            });
+        };
+
+        let token = match c {
+            '(' => Token::LeftParen,
+            ')' => Token::RightParen,
+            ',' => Token::Comma,
+            ';' => Token::Semicolon,
+            '*' => Token::Star,
+            '+' => Token::Plus,
+            '-' => Token::Minus,
+            '/' => Token::Slash,
+            '%' => Token::Percent,
+            '=' => {
+                self.bump_if('=');
+                Token::Equal
+            }
+            '!' if self.bump_if('=') => Token::NotEqual,
+            '<' if self.bump_if('=') => Token::LessEqual,
+            '<' if self.bump_if('>') => Token::NotEqual,
+            '<' => Token::Less,
+            '>' if self.bump_if('=') => Token::GreaterEqual,
+            '>' => Token::Greater,
+            '|' if self.bump_if('|') => Token::Concat,
+            '\'' => Token::String(self.quoted('\'', span)?),
+            '"' | '`' => Token::Identifier(self.quoted(c, span)?),
+            '[' => Token::Identifier(self.quoted(']', span)?),
+            '.' if self.chars.peek().is_some_and(char::is_ascii_digit) => self.number(c, span)?,
+            '.' => Token::Dot,
+            c if c.is_ascii_digit() => self.number(c, span)?,
+            'x' | 'X' if self.bump_if('\'') => self.blob(span)?,
+            '?' => {
+                let mut parameter = String::from(c);
+                while let Some(&c) = self.chars.peek().filter(|c| c.is_ascii_digit()) {
+                    parameter.push(c);
+                    self.bump();
+                }
+                Token::Parameter(parameter)
+            }
+            ':' if self
+                .chars
+                .peek()
+                .is_some_and(|c| c.is_alphanumeric() || *c == '_') =>
+            {
+                Token::Parameter(self.identifier(c))
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                let identifier = self.identifier(c);
+                match Keyword::from_identifier(&identifier) {
+                    Some(keyword) => Token::Keyword(keyword),
+                    None => Token::Identifier(identifier),
+                }
+            }
+            c => {
+                return Err(ParseError::new(
+                    format!("unexpected character '{}'", c),
+                    span,
+                ))
+            }
+        };
+
+        Ok(SpannedToken { token, span })
+    }
+
+    fn identifier(&mut self, first: char) -> String {
+        let mut identifier = String::from(first);
+        while let Some(&c) = self.chars.peek() {
+            if !(c.is_alphanumeric() || c == '_' || c == '$') {
+                break;
+            }
+            identifier.push(c);
+            self.bump();
+        }
+        identifier
+    }
+
+    // The hex digits of a blob literal, after the opening quote
+    fn blob(&mut self, span: Span) -> Result<Token, ParseError> {
+        let digits = self.quoted('\'', span)?;
+        let malformed = || ParseError::new(format!("malformed blob X'{}'", digits), span);
+        if digits.len() % 2 != 0 || !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(malformed());
+        }
+        let blob = (0..digits.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&digits[i..i + 2], 16).map_err(|_| malformed()))
+            .collect::<Result<_, _>>()?;
+        Ok(Token::Blob(blob))
+    }
+
+    // Read up to the closing quote. A doubled quote stands for the quote itself.
+    fn quoted(&mut self, quote: char, span: Span) -> Result<String, ParseError> {
+        let mut string = String::new();
+        loop {
+            match self.bump() {
+                Some(c) if c == quote => {
+                    if !self.bump_if(quote) {
+                        return Ok(string);
+                    }
+                    string.push(quote);
+                }
+                Some(c) => string.push(c),
+                None => return Err(ParseError::new("unterminated quoted string", span)),
+            }
+        }
+    }
+
+    fn number(&mut self, first: char, span: Span) -> Result<Token, ParseError> {
+        let mut number = String::from(first);
+        let mut is_float = first == '.';
+
+        while let Some(&c) = self.chars.peek() {
+            if c.is_ascii_digit() {
+                number.push(c);
+            } else if c == '.' && !is_float {
+                is_float = true;
+                number.push(c);
+            } else if c == 'e' || c == 'E' {
+                is_float = true;
+                number.push(c);
+                self.bump();
+                if let Some(&sign) = self.chars.peek() {
+                    if sign == '+' || sign == '-' {
+                        number.push(sign);
+                        self.bump();
+                    }
+                }
+                continue;
+            } else {
+                break;
+            }
+            self.bump();
+        }
+
+        if self
+            .chars
+            .peek()
+            .is_some_and(|c| c.is_alphabetic() || *c == '_')
+        {
+            return Err(ParseError::new(
+                format!("malformed number '{}'", number),
+                span,
+            ));
+        }
+
+        if !is_float {
+            if let Ok(integer) = number.parse::<u64>() {
+                return Ok(Token::Integer(integer));
+            }
+        }
+
+        number
+            .parse::<f64>()
+            .map(Token::Float)
+            .map_err(|_| ParseError::new(format!("malformed number '{}'", number), span))
+    }
+}
@@ -0,0 +1,34 @@
+//! A small SQLite-like database engine.
+//!
+//! Open a database file with [`Connection::open`], then run SQL with
+//! [`Connection::execute`], or [`Connection::prepare`] a statement to run
+//! it several times and read the [`Rows`] it returns.
+
+pub mod ast;
+pub mod catalog;
+pub mod connection;
+pub mod cursor;
+pub mod database;
+pub mod error;
+pub mod eval;
+pub mod header;
+pub mod index;
+pub mod journal;
+pub mod lexer;
+pub mod node;
+pub mod pager;
+pub mod parser;
+pub mod row;
+pub mod rows;
+pub mod schema;
+pub mod sorter;
+pub mod statement;
+pub mod table;
+pub mod value;
+pub mod varint;
+pub mod wal;
+
+pub use connection::{Connection, Statement};
+pub use error::Error;
+pub use rows::Rows;
+pub use value::Value;
@@ -1,20 +1,12 @@
 use std::io::{self, Write};
 
-use node::{
-    COMMON_NODE_HEADER_SIZE, LEAF_NODE_CELL_SIZE, LEAF_NODE_HEADER_SIZE, LEAF_NODE_MAX_CELLS,
+use rust_sqlite::header::FORMAT_VERSION;
+use rust_sqlite::node::{
+    COMMON_NODE_HEADER_SIZE, LEAF_NODE_HEADER_SIZE, LEAF_NODE_MAX_VALUE_SIZE,
     LEAF_NODE_SPACE_FOR_CELLS,
 };
-use pager::PagerProxy;
-use row::ROW_SIZE;
-use statement::Statement;
-use table::Table;
-
-pub mod cursor;
-pub mod node;
-pub mod pager;
-pub mod row;
-pub mod statement;
-pub mod table;
+use rust_sqlite::pager::PagerProxy;
+use rust_sqlite::{Connection, Value};
 
 type InputBuffer = String;
 
@@ -32,23 +24,31 @@ pub struct ExitSuccess;
 fn main() {
     let args: Vec<String> = std::env::args().collect();
     if args.len() < 2 {
-        panic!("Must supply a database filename.");
+        eprintln!("Usage: {} DATABASE", args[0]);
+        std::process::exit(1);
     }
 
-    let mut table = Table::db_open(&args[1]);
+    let connection = match Connection::open(&args[1]) {
+        Ok(connection) => connection,
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+    };
 
     let mut input_buffer = InputBuffer::new();
     loop {
         print_prompt();
         read_input(&mut input_buffer);
 
-        // TODO: implement parser combinator here, or regex instead of doing this
-        let buffer: Vec<char> = input_buffer.chars().collect();
+        if input_buffer.is_empty() {
+            continue;
+        }
 
-        if buffer[0] == '.' {
-            match do_meta_command(&input_buffer, &mut table) {
+        if input_buffer.starts_with('.') {
+            match do_meta_command(&input_buffer, &connection) {
                 Ok(MetaCommandOk::ExitSuccess) => {
-                    drop(table);
+                    drop(connection);
                     break;
                 }
                 Ok(MetaCommandOk::CommandSuccess) => {
@@ -61,21 +61,32 @@ fn main() {
             }
         }
 
-        let statement = match Statement::prepare_statement(&input_buffer) {
-            Ok(statement) => statement,
-            Err(err) => {
-                println!("{}", err);
-                continue;
-            }
-        };
-
-        match statement.execute_statement(&mut table) {
-            Ok(()) => println!("Executed."),
-            Err(err) => println!("{}", err),
+        if let Err(err) = run_statement(&input_buffer, &connection) {
+            println!("{}", err);
         }
     }
 }
 
+// Print the rows of a statement as they are read
+fn run_statement(sql: &str, connection: &Connection) -> Result<(), rust_sqlite::Error> {
+    let mut statement = connection.prepare(sql)?;
+    let mut rows = statement.query(&[])?;
+    for values in rows.by_ref() {
+        print_values(&values?);
+    }
+
+    match rows.rows_changed() {
+        Some(rows_changed) => println!("Executed. Rows changed: {}.", rows_changed),
+        None => println!("Executed."),
+    }
+    Ok(())
+}
+
+fn print_values(values: &[Value]) {
+    let values: Vec<_> = values.iter().map(Value::to_string).collect();
+    println!("({})", values.join(", "));
+}
+
 fn print_prompt() {
     print!("db > ");
     let _ = io::stdout().flush();
@@ -83,8 +94,15 @@ fn print_prompt() {
 
 fn read_input(input_buffer: &mut InputBuffer) {
     input_buffer.clear();
-    if let Err(_) = io::stdin().read_line(input_buffer) {
-        panic!("Error while reading input");
+    match io::stdin().read_line(input_buffer) {
+        // End of input, exit as if .exit was typed
+        Ok(0) => input_buffer.push_str(".exit"),
+        Ok(_) => {}
+        Err(err) => {
+            eprintln!("Error while reading input: {}.", err);
+            input_buffer.clear();
+            input_buffer.push_str(".exit");
+        }
     }
 
     // Ignore trailing newline
@@ -93,19 +111,81 @@ fn read_input(input_buffer: &mut InputBuffer) {
 
 fn do_meta_command(
     input_buffer: &InputBuffer,
-    table: &mut Table,
+    connection: &Connection,
 ) -> Result<MetaCommandOk, MetaCommandErr> {
+    let mut database = connection.database();
     if input_buffer == ".exit" {
         Ok(MetaCommandOk::ExitSuccess)
     } else if input_buffer == ".constants" {
         println!("Constants:");
         print_constants();
         Ok(MetaCommandOk::CommandSuccess)
-    } else if input_buffer == ".btree" {
+    } else if let Some(name) = input_buffer
+        .strip_prefix(".btree")
+        .filter(|name| name.is_empty() || name.starts_with(' '))
+    {
+        // `.btree NAME` prints the tree of a table or index, `.btree` the
+        // trees of every table
+        let name = name.trim();
+        let root_page_nums: Vec<_> = if name.is_empty() {
+            database
+                .tables()
+                .iter()
+                .map(|table| table.root_page_num)
+                .collect()
+        } else {
+            let index = database.find_index(name).map(|(_, index)| index);
+            match (database.find_table(name), index) {
+                (Some(table), _) => vec![table.root_page_num],
+                (None, Some(index)) => vec![index.root_page_num],
+                (None, None) => {
+                    println!("No such table or index: {}.", name);
+                    return Ok(MetaCommandOk::CommandSuccess);
+                }
+            }
+        };
+
         println!("Tree:");
-        let pager = &mut table.pager;
-        let pager_proxy = PagerProxy::new(pager);
-        print!("{pager_proxy}");
+        for root_page_num in root_page_nums {
+            let pager = &mut database.pager;
+            let pager_proxy = PagerProxy::new(pager, root_page_num);
+            print!("{pager_proxy}");
+        }
+        Ok(MetaCommandOk::CommandSuccess)
+    } else if input_buffer == ".dbinfo" {
+        let pager = &mut database.pager;
+        println!("database page size: {}", pager.header.page_size);
+        println!("format version: {}", FORMAT_VERSION);
+        println!("page count: {}", pager.num_pages);
+        println!("freelist head: {}", pager.header.freelist_head);
+        match pager.freelist_count() {
+            Ok(freelist_count) => println!("freelist count: {}", freelist_count),
+            Err(err) => println!("{}", err),
+        }
+        println!("schema cookie: {}", pager.header.schema_cookie);
+        println!("journal mode: {}", pager.journal_mode().name());
+        Ok(MetaCommandOk::CommandSuccess)
+    } else if input_buffer == ".stats" {
+        let pager = &database.pager;
+        println!("cache size: {}", pager.cache_size());
+        println!("cached pages: {}", pager.cached_pages());
+        println!("cache hits: {}", pager.stats.hits);
+        println!("cache misses: {}", pager.stats.misses);
+        println!("evictions: {}", pager.stats.evictions);
+        println!("writebacks: {}", pager.stats.writebacks);
+        Ok(MetaCommandOk::CommandSuccess)
+    } else if input_buffer == ".schema" {
+        for table in database.tables() {
+            println!("{};", table.schema.to_sql());
+            // UNIQUE constraints are part of the table's schema
+            for index in table
+                .indexes
+                .iter()
+                .filter(|index| !index.schema.is_automatic())
+            {
+                println!("{};", index.schema.to_sql(&table.schema));
+            }
+        }
         Ok(MetaCommandOk::CommandSuccess)
     } else {
         Err(MetaCommandErr::UnrecognizedCommand)
@@ -113,10 +193,8 @@ fn do_meta_command(
 }
 
 fn print_constants() {
-    println!("ROW_SIZE: {}", ROW_SIZE);
     println!("COMMON_NODE_HEADER_SIZE: {}", COMMON_NODE_HEADER_SIZE);
     println!("LEAF_NODE_HEADER_SIZE: {}", LEAF_NODE_HEADER_SIZE);
-    println!("LEAF_NODE_CELL_SIZE: {}", LEAF_NODE_CELL_SIZE);
     println!("LEAF_NODE_SPACE_FOR_CELLS: {}", LEAF_NODE_SPACE_FOR_CELLS);
-    println!("LEAF_NODE_MAX_CELLS: {}", LEAF_NODE_MAX_CELLS);
+    println!("LEAF_NODE_MAX_VALUE_SIZE: {}", LEAF_NODE_MAX_VALUE_SIZE);
 }
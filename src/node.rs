@@ -1,7 +1,6 @@
-use crate::{
-    pager::{INVALID_PAGE_NUM, PAGE_SIZE},
-    row::{Row, ROW_SIZE},
-};
+use crate::error::CorruptErr;
+use crate::pager::{INVALID_PAGE_NUM, PAGE_SIZE};
+use crate::varint::{put_varint, read_varint, varint_size, VARINT_MAX_SIZE};
 
 // Common Node Header Layout
 pub const NODE_TYPE_SIZE: usize = std::mem::size_of::<u8>();
@@ -17,21 +16,77 @@ pub const LEAF_NODE_NUM_CELLS_SIZE: usize = std::mem::size_of::<u32>();
 pub const LEAF_NODE_NUM_CELLS_OFFSET: usize = COMMON_NODE_HEADER_SIZE;
 pub const LEAF_NODE_NEXT_LEAF_SIZE: usize = std::mem::size_of::<u32>();
 pub const LEAF_NODE_NEXT_LEAF_OFFSET: usize = LEAF_NODE_NUM_CELLS_OFFSET + LEAF_NODE_NUM_CELLS_SIZE;
-pub const LEAF_NODE_HEADER_SIZE: usize =
-    COMMON_NODE_HEADER_SIZE + LEAF_NODE_NUM_CELLS_SIZE + LEAF_NODE_NEXT_LEAF_SIZE;
+pub const LEAF_NODE_CELL_CONTENT_START_SIZE: usize = std::mem::size_of::<u16>();
+pub const LEAF_NODE_CELL_CONTENT_START_OFFSET: usize =
+    LEAF_NODE_NEXT_LEAF_OFFSET + LEAF_NODE_NEXT_LEAF_SIZE;
+pub const LEAF_NODE_FRAGMENTED_BYTES_SIZE: usize = std::mem::size_of::<u16>();
+pub const LEAF_NODE_FRAGMENTED_BYTES_OFFSET: usize =
+    LEAF_NODE_CELL_CONTENT_START_OFFSET + LEAF_NODE_CELL_CONTENT_START_SIZE;
+pub const LEAF_NODE_HEADER_SIZE: usize = COMMON_NODE_HEADER_SIZE
+    + LEAF_NODE_NUM_CELLS_SIZE
+    + LEAF_NODE_NEXT_LEAF_SIZE
+    + LEAF_NODE_CELL_CONTENT_START_SIZE
+    + LEAF_NODE_FRAGMENTED_BYTES_SIZE;
 
 // Leaf Node Body Layout
-pub const LEAF_NODE_KEY_SIZE: usize = std::mem::size_of::<u32>();
-pub const LEAF_NODE_KEY_OFFSET: usize = 0;
-pub const LEAF_NODE_VALUE_SIZE: usize = ROW_SIZE;
-pub const LEAF_NODE_VALUE_OFFSET: usize = LEAF_NODE_KEY_OFFSET + LEAF_NODE_KEY_SIZE;
-pub const LEAF_NODE_CELL_SIZE: usize = LEAF_NODE_KEY_SIZE + LEAF_NODE_VALUE_SIZE;
+// The cell pointer array grows up from the header, the cells it points to
+// are allocated down from the end of the page
+pub const LEAF_NODE_CELL_POINTER_SIZE: usize = std::mem::size_of::<u16>();
 pub const LEAF_NODE_SPACE_FOR_CELLS: usize = PAGE_SIZE - LEAF_NODE_HEADER_SIZE;
-pub const LEAF_NODE_MAX_CELLS: usize = LEAF_NODE_SPACE_FOR_CELLS / LEAF_NODE_CELL_SIZE;
 
-pub const LEAF_NODE_RIGHT_SPLIT_COUNT: usize = (LEAF_NODE_MAX_CELLS + 1) / 2;
-pub const LEAF_NODE_LEFT_SPLIT_COUNT: usize =
-    (LEAF_NODE_MAX_CELLS + 1) - LEAF_NODE_RIGHT_SPLIT_COUNT;
+// Besides its value a cell takes up its pointer, the length of the value,
+// which is at most 2 bytes as a varint, and the key, at most 9 bytes
+pub const LEAF_NODE_MAX_CELL_OVERHEAD: usize = LEAF_NODE_CELL_POINTER_SIZE + 2 + VARINT_MAX_SIZE;
+
+// A leaf must hold at least three cells for splitting and merging to work
+pub const LEAF_NODE_MAX_VALUE_SIZE: usize =
+    LEAF_NODE_SPACE_FOR_CELLS / 3 - LEAF_NODE_MAX_CELL_OVERHEAD;
+
+// A non-root leaf whose cells take up less space than this borrows from or
+// merges with a sibling. When neither sibling can spare a cell, the two
+// leaves together take up less than a page and can always be merged.
+pub const LEAF_NODE_MIN_USED_SPACE: usize = LEAF_NODE_SPACE_FOR_CELLS / 4;
+
+// A value longer than LEAF_NODE_MAX_VALUE_SIZE keeps only a prefix in its
+// cell, followed by the page number of the first overflow page holding the rest
+pub const LEAF_NODE_OVERFLOW_PAGE_SIZE: usize = std::mem::size_of::<u32>();
+
+// The length of such a value takes up to 5 bytes as a varint rather than 2
+pub const LEAF_NODE_MAX_LOCAL_SIZE: usize =
+    LEAF_NODE_MAX_VALUE_SIZE - LEAF_NODE_OVERFLOW_PAGE_SIZE - 3;
+pub const LEAF_NODE_MIN_LOCAL_SIZE: usize = LEAF_NODE_SPACE_FOR_CELLS / 8;
+
+/// Bytes of a value of the given length kept in its cell. As in SQLite, the
+/// prefix of a value that overflows is sized so that the last of its
+/// overflow pages is as full as possible.
+pub fn leaf_node_local_size(value_len: usize) -> usize {
+    if value_len <= LEAF_NODE_MAX_VALUE_SIZE {
+        return value_len;
+    }
+    let local_size = LEAF_NODE_MIN_LOCAL_SIZE
+        + (value_len - LEAF_NODE_MIN_LOCAL_SIZE) % OVERFLOW_PAGE_SPACE_FOR_DATA;
+    if local_size <= LEAF_NODE_MAX_LOCAL_SIZE {
+        local_size
+    } else {
+        LEAF_NODE_MIN_LOCAL_SIZE
+    }
+}
+
+/// Space a cell with the given key and value length takes up in a leaf,
+/// its pointer included.
+pub fn leaf_node_cell_size(key: i64, value_len: usize) -> usize {
+    let local_size = leaf_node_local_size(value_len);
+    let overflow_page_size = if local_size < value_len {
+        LEAF_NODE_OVERFLOW_PAGE_SIZE
+    } else {
+        0
+    };
+    LEAF_NODE_CELL_POINTER_SIZE
+        + varint_size(value_len as u64)
+        + varint_size(key as u64)
+        + local_size
+        + overflow_page_size
+}
 
 // Internal Node Header Layout
 pub const INTERNAL_NODE_NUM_KEYS_SIZE: usize = std::mem::size_of::<u32>();
@@ -43,7 +98,7 @@ pub const INTERNAL_NODE_HEADER_SIZE: usize =
     COMMON_NODE_HEADER_SIZE + INTERNAL_NODE_NUM_KEYS_SIZE + INTERNAL_NODE_RIGHT_CHILD_SIZE;
 
 // Internal Node Header Layout
-pub const INTERNAL_NODE_KEY_SIZE: usize = std::mem::size_of::<u32>();
+pub const INTERNAL_NODE_KEY_SIZE: usize = std::mem::size_of::<i64>();
 pub const INTERNAL_NODE_CHILD_SIZE: usize = std::mem::size_of::<u32>();
 pub const INTERNAL_NODE_CELL_SIZE: usize = INTERNAL_NODE_CHILD_SIZE + INTERNAL_NODE_KEY_SIZE;
 #[cfg(debug_assertions)]
@@ -51,25 +106,96 @@ pub const INTERNAL_NODE_MAX_CELLS: usize = 3; // Kept small for testing
 #[cfg(not(debug_assertions))]
 pub const INTERNAL_NODE_MAX_CELLS: usize = LEAF_NODE_SPACE_FOR_CELLS / INTERNAL_NODE_CELL_SIZE;
 
-// Leaf Node Format
-// |-------------+----------------+----------------+-----------+---------------------|
-// | byte 0      | byte 1         | bytes 2-5      | bytes 6-9 | bytes 10-13         |
-// | node_type   | is_root        | parent_pointer | num_cells | next_leaf_pointer   |
-// |-------------+----------------+----------------+-----------+---------------------|
-// | bytes 14-17                  | bytes 18-308                                     |
-// | key 0                        | value 0                                          |
-// |------------------------------+--------------------------------------------------|
-// | bytes 309-312                | bytes 313-603                                    |
-// | key 1                        | value 1                                          |
-// |------------------------------+--------------------------------------------------|
-// |             ...              |          ...                                     |
-// |------------------------------+--------------------------------------------------|
-// | bytes 3554-3557              | bytes 3558-3848                                  |
-// | key 12                       | value 12                                         |
-// |------------------------------+--------------------------------------------------|
-// |                                 bytes 3849-4095                                 |
-// |                                  wasted space                                   |
-// |---------------------------------------------------------------------------------|
+// A non-root internal node holding fewer keys than this borrows from or merges with a sibling
+pub const INTERNAL_NODE_MIN_KEYS: usize = INTERNAL_NODE_MAX_CELLS / 2;
+
+// Freelist Trunk Header Layout
+pub const FREELIST_TRUNK_NEXT_SIZE: usize = std::mem::size_of::<u32>();
+pub const FREELIST_TRUNK_NEXT_OFFSET: usize = COMMON_NODE_HEADER_SIZE;
+pub const FREELIST_TRUNK_NUM_LEAVES_SIZE: usize = std::mem::size_of::<u32>();
+pub const FREELIST_TRUNK_NUM_LEAVES_OFFSET: usize =
+    FREELIST_TRUNK_NEXT_OFFSET + FREELIST_TRUNK_NEXT_SIZE;
+pub const FREELIST_TRUNK_HEADER_SIZE: usize =
+    COMMON_NODE_HEADER_SIZE + FREELIST_TRUNK_NEXT_SIZE + FREELIST_TRUNK_NUM_LEAVES_SIZE;
+
+// Freelist Trunk Body Layout
+pub const FREELIST_LEAF_PAGE_NUM_SIZE: usize = std::mem::size_of::<u32>();
+#[cfg(debug_assertions)]
+pub const FREELIST_TRUNK_MAX_LEAVES: usize = 3; // Kept small for testing
+#[cfg(not(debug_assertions))]
+pub const FREELIST_TRUNK_MAX_LEAVES: usize =
+    (PAGE_SIZE - FREELIST_TRUNK_HEADER_SIZE) / FREELIST_LEAF_PAGE_NUM_SIZE;
+
+// Overflow Page Header Layout
+pub const OVERFLOW_PAGE_NEXT_SIZE: usize = std::mem::size_of::<u32>();
+pub const OVERFLOW_PAGE_NEXT_OFFSET: usize = COMMON_NODE_HEADER_SIZE;
+pub const OVERFLOW_PAGE_DATA_LEN_SIZE: usize = std::mem::size_of::<u16>();
+pub const OVERFLOW_PAGE_DATA_LEN_OFFSET: usize =
+    OVERFLOW_PAGE_NEXT_OFFSET + OVERFLOW_PAGE_NEXT_SIZE;
+pub const OVERFLOW_PAGE_HEADER_SIZE: usize =
+    COMMON_NODE_HEADER_SIZE + OVERFLOW_PAGE_NEXT_SIZE + OVERFLOW_PAGE_DATA_LEN_SIZE;
+pub const OVERFLOW_PAGE_SPACE_FOR_DATA: usize = PAGE_SIZE - OVERFLOW_PAGE_HEADER_SIZE;
+
+// Index Node Header Layout
+pub const INDEX_NODE_NUM_KEYS_SIZE: usize = std::mem::size_of::<u32>();
+pub const INDEX_NODE_NUM_KEYS_OFFSET: usize = COMMON_NODE_HEADER_SIZE;
+pub const INDEX_NODE_POINTER_SIZE: usize = std::mem::size_of::<u32>();
+pub const INDEX_NODE_POINTER_OFFSET: usize = INDEX_NODE_NUM_KEYS_OFFSET + INDEX_NODE_NUM_KEYS_SIZE;
+pub const INDEX_NODE_HEADER_SIZE: usize =
+    COMMON_NODE_HEADER_SIZE + INDEX_NODE_NUM_KEYS_SIZE + INDEX_NODE_POINTER_SIZE;
+
+// Index Node Body Layout
+pub const INDEX_NODE_CHILD_SIZE: usize = std::mem::size_of::<u32>();
+pub const INDEX_NODE_SPACE_FOR_CELLS: usize = PAGE_SIZE - INDEX_NODE_HEADER_SIZE;
+
+// Besides its key a cell takes up the length of the key, at most 2 bytes
+// as a varint, and in an internal node the child pointer
+pub const INDEX_NODE_MAX_CELL_OVERHEAD: usize = INDEX_NODE_CHILD_SIZE + 2;
+
+// A node must hold at least four keys for splitting and merging to work.
+// Longer entries have nowhere to go, so an index whose entries can be
+// longer than this cannot be created.
+pub const INDEX_MAX_KEY_SIZE: usize = INDEX_NODE_SPACE_FOR_CELLS / 4 - INDEX_NODE_MAX_CELL_OVERHEAD;
+
+// A non-root index node whose cells take up less space than this merges
+// with a sibling, or shares the cells of both evenly if they do not fit in one
+pub const INDEX_NODE_MIN_USED_SPACE: usize = INDEX_NODE_SPACE_FOR_CELLS / 4;
+
+#[cfg(debug_assertions)]
+pub const INDEX_NODE_MAX_CELLS: usize = 3; // Kept small for testing
+#[cfg(not(debug_assertions))]
+pub const INDEX_NODE_MAX_CELLS: usize = INDEX_NODE_SPACE_FOR_CELLS;
+
+/// Space a key takes up in an index node, its child pointer included in
+/// an internal node.
+pub fn index_node_cell_size(key: &[u8], is_leaf: bool) -> usize {
+    let child_size = if is_leaf { 0 } else { INDEX_NODE_CHILD_SIZE };
+    child_size + varint_size(key.len() as u64) + key.len()
+}
+
+// Leaf Node Format, for n cells
+// |-----------+-----------+----------------+-----------+-------------+--------------------+--------------------|
+// | byte 0    | byte 1    | bytes 2-5      | bytes 6-9 | bytes 10-13 | bytes 14-15        | bytes 16-17        |
+// | node_type | is_root   | parent_pointer | num_cells | next_leaf   | cell_content_start | fragmented_bytes   |
+// |-----------+-----------+----------------+-----------+-------------+--------------------+--------------------|
+// | bytes 18-19           | bytes 20-21    | ...       | bytes (16+2n)-(17+2n)                                 |
+// | cell pointer 0        | cell pointer 1 | ...       | cell pointer n-1                                      |
+// |-----------------------+----------------+-----------+-------------------------------------------------------|
+// |                                   unallocated space                                                      |
+// |----------------------------------------------------------------------------------------------------------|
+// | bytes cell_content_start-4095                                                                            |
+// | cells, in any order, with the space of removed cells between them                                        |
+// |----------------------------------------------------------------------------------------------------------|
+//
+// The cell pointers are in key order and hold the offset of each cell in
+// the page. A cell is the length of its value and its key, both varints,
+// the key as the bits of the signed integer so that a negative key takes
+// up 9 bytes, then the value, a record of the table. A value that does not fit in the
+// cell is cut short after its local size and followed by the page number of
+// its first overflow page. New cells are allocated from the
+// unallocated space. The space of a removed cell counts towards
+// fragmented_bytes until a cell does not fit in the unallocated space, when
+// the cells are moved to the end of the page to join the two.
 //
 //
 // Internal Node Format
@@ -77,29 +203,84 @@ pub const INTERNAL_NODE_MAX_CELLS: usize = LEAF_NODE_SPACE_FOR_CELLS / INTERNAL_
 // | byte 0    | byte 1           | bytes 2-5      | bytes 6-9 | bytes 10-13         |
 // | node_type | is_root          | parent_pointer | num_keys  | right_child_pointer |
 // |-----------+------------------+----------------+-----------+---------------------|
-// | bytes 14-17                             | bytes 18-21                           |
+// | bytes 14-17                             | bytes 18-25                           |
 // | child pointer 0                         | key 0                                 |
 // |-----------------------------------------+---------------------------------------|
-// | bytes 22-25                             | bytes 26-29                           |
+// | bytes 26-29                             | bytes 30-37                           |
 // | child pointer 1                         | key 1                                 |
 // |-----------------------------------------+---------------------------------------|
 // |                 ...                     |             ...                       |
 // |-----------------------------------------+---------------------------------------|
-// | bytes 4086-4089                         | bytes 4090-4093                       |
-// | child pointer 509                       | key 509                               |
+// | bytes 4070-4073                         | bytes 4074-4081                       |
+// | child pointer 338                       | key 338                               |
 // |-----------------------------------------+---------------------------------------|
-// |                                  bytes 4094-4095                                |
+// |                                  bytes 4082-4095                                |
 // |                                    wasted space                                 |
 // |---------------------------------------------------------------------------------|
 //
-// |------------------------+-----------------------+------------------------|
-// | # internal node layers | max # leaf nodes      | Size of all leaf nodes |
-// |------------------------+-----------------------+------------------------|
-// | 0                      | 510 ^ 0 = 1           | 4 KB                   |
-// | 1                      | 510 ^ 1 = 510         | ~2 MB                  |
-// | 2                      | 510 ^ 2 = 260,100     | ~1 GB                  |
-// | 3                      | 510 ^ 3 = 132,651,000 | ~550 GB                |
-// |------------------------+-----------------------+------------------------|
+// Keys are signed 64-bit integers, little-endian.
+//
+// |------------------------+----------------------+------------------------|
+// | # internal node layers | max # leaf nodes     | Size of all leaf nodes |
+// |------------------------+----------------------+------------------------|
+// | 0                      | 340 ^ 0 = 1          | 4 KB                   |
+// | 1                      | 340 ^ 1 = 340        | ~1.4 MB                |
+// | 2                      | 340 ^ 2 = 115,600    | ~450 MB                |
+// | 3                      | 340 ^ 3 = 39,304,000 | ~150 GB                |
+// |------------------------+----------------------+------------------------|
+//
+//
+// Freelist Trunk Format
+// |-----------+-----------+-----------+-------------+---------------|
+// | byte 0    | byte 1    | bytes 2-5 | bytes 6-9   | bytes 10-13   |
+// | node_type | unused    | unused    | next_trunk  | num_leaves    |
+// |-----------+-----------+-----------+-------------+---------------|
+// | bytes 14-17           | bytes 18-21             | ...           |
+// | leaf page 0           | leaf page 1             | ...           |
+// |-----------------------+-------------------------+---------------|
+//
+// Free pages are listed on a chain of trunk pages starting at the freelist
+// head in the file header. A trunk is a free page itself.
+//
+//
+// Overflow Page Format
+// |-----------+-----------+-----------+-------------+---------------|
+// | byte 0    | byte 1    | bytes 2-5 | bytes 6-9   | bytes 10-11   |
+// | node_type | unused    | unused    | next_page   | data_len      |
+// |-----------+-----------+-----------+-------------+---------------|
+// | bytes 12-(11+data_len)                                          |
+// | the next part of the value                                      |
+// |-----------------------------------------------------------------|
+//
+// The part of a value past the prefix in its cell is spread over a chain
+// of overflow pages, the last of which has a next_page of 0.
+//
+//
+// Index Leaf Format
+// |-----------+-----------+-----------+-------------+---------------|
+// | byte 0    | byte 1    | bytes 2-5 | bytes 6-9   | bytes 10-13   |
+// | node_type | unused    | unused    | num_keys    | next_leaf     |
+// |-----------+-----------+-----------+-------------+---------------|
+// | bytes 14-                                                       |
+// | key 0 length, key 0, key 1 length, key 1, ...                   |
+// |-----------------------------------------------------------------|
+//
+// Index Internal Node Format
+// |-----------+-----------+-----------+-------------+---------------|
+// | byte 0    | byte 1    | bytes 2-5 | bytes 6-9   | bytes 10-13   |
+// | node_type | unused    | unused    | num_keys    | right_child   |
+// |-----------+-----------+-----------+-------------+---------------|
+// | bytes 14-                                                       |
+// | child 0, key 0 length, key 0, child 1, key 1 length, key 1, ... |
+// |-----------------------------------------------------------------|
+//
+// The keys of an index are records of the indexed values followed by the
+// key of the row in its table, ordered by those values in turn. Lengths
+// are varints and the cells are packed one after another in key order.
+// As in a table B-tree, key i of an internal node is at least the largest
+// key under child i and less than every key under the children after it.
+// Index nodes keep no parent pointer; the path down from the root is
+// remembered instead.
 
 pub enum Node {
     Leaf {
@@ -107,6 +288,8 @@ pub enum Node {
         parent_pointer: u32,
         num_cells: u32,
         next_leaf_pointer: u32,
+        cell_content_start: u16,
+        fragmented_bytes: u16,
         cells: Vec<LeafNodeCell>,
     },
     Internal {
@@ -116,26 +299,102 @@ pub enum Node {
         right_child_pointer: u32,
         cells: Vec<InternalNodeCell>,
     },
+    FreelistTrunk {
+        next_trunk: u32,
+        leaf_page_nums: Vec<u32>,
+    },
+    Overflow {
+        next_page: u32,
+        data: Vec<u8>,
+    },
+    IndexLeaf {
+        next_leaf_pointer: u32,
+        keys: Vec<Vec<u8>>,
+    },
+    /// An internal node of an index, with one more child than keys.
+    /// The last child is the right child.
+    IndexInternal {
+        keys: Vec<Vec<u8>>,
+        children: Vec<u32>,
+    },
 }
 
 pub struct LeafNodeCell {
-    key: u32,
-    value: Row,
+    key: i64,
+    // The whole value, or its prefix if the rest is on overflow pages
+    value: Vec<u8>,
+    value_len: usize,
+    // First overflow page, 0 if the value is all in the cell
+    overflow_page: u32,
+    // Offset of the cell in the page, given when it is inserted into a leaf
+    offset: u16,
 }
 
 impl LeafNodeCell {
-    pub fn new() -> Self {
-        let row = [0; ROW_SIZE];
+    /// A cell holding the whole value, which must fit in it.
+    pub fn new(key: i64, value: Vec<u8>) -> Self {
+        assert!(
+            value.len() <= LEAF_NODE_MAX_VALUE_SIZE,
+            "LeafNodeCell::new: value needs overflow pages"
+        );
         Self {
-            key: 0,
-            value: Row::deserialize(&row),
+            key,
+            value_len: value.len(),
+            value,
+            overflow_page: 0,
+            offset: 0,
+        }
+    }
+
+    /// A cell holding the local prefix of a value of `value_len` bytes,
+    /// the rest of which is on the chain of overflow pages starting at `overflow_page`.
+    pub fn with_overflow(key: i64, prefix: Vec<u8>, value_len: usize, overflow_page: u32) -> Self {
+        assert_eq!(
+            prefix.len(),
+            leaf_node_local_size(value_len),
+            "LeafNodeCell::with_overflow: prefix has the wrong length"
+        );
+        Self {
+            key,
+            value: prefix,
+            value_len,
+            overflow_page,
+            offset: 0,
         }
     }
+
+    pub fn key(&self) -> i64 {
+        self.key
+    }
+
+    /// The part of the value kept in the cell.
+    pub fn local_value(&self) -> &[u8] {
+        &self.value
+    }
+
+    pub fn value_len(&self) -> usize {
+        self.value_len
+    }
+
+    /// First overflow page of the value, if it does not fit in the cell.
+    pub fn overflow_page(&self) -> Option<u32> {
+        (self.overflow_page != 0).then_some(self.overflow_page)
+    }
+
+    /// Space the cell takes up in a leaf, its pointer included.
+    pub fn size(&self) -> usize {
+        leaf_node_cell_size(self.key, self.value_len)
+    }
+
+    // Bytes of the cell past its pointer
+    fn content_size(&self) -> u16 {
+        (self.size() - LEAF_NODE_CELL_POINTER_SIZE) as u16
+    }
 }
 
 pub struct InternalNodeCell {
     child_pointer: u32,
-    key: u32,
+    key: i64,
 }
 
 impl InternalNodeCell {
@@ -147,18 +406,29 @@ impl InternalNodeCell {
     }
 }
 
+impl Default for InternalNodeCell {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Errors for a page of another type than an accessor expects
+const NOT_A_TABLE_LEAF: CorruptErr = CorruptErr::Invalid("Page is not a table leaf");
+const NOT_A_TABLE_INTERNAL_NODE: CorruptErr =
+    CorruptErr::Invalid("Page is not an internal node of a table");
+const NOT_A_TABLE_NODE: CorruptErr = CorruptErr::Invalid("Page is not part of a table B-tree");
+const NOT_AN_INDEX_NODE: CorruptErr = CorruptErr::Invalid("Page is not part of an index");
+
 impl Node {
     pub fn initialize_leaf_node() -> Self {
-        let mut cells = Vec::new();
-        for _ in 0..LEAF_NODE_MAX_CELLS {
-            cells.push(LeafNodeCell::new())
-        }
         Node::Leaf {
             is_root: false,
             parent_pointer: 0,
             num_cells: 0,
             next_leaf_pointer: 0,
-            cells,
+            cell_content_start: PAGE_SIZE as u16,
+            fragmented_bytes: 0,
+            cells: Vec::new(),
         }
     }
 
@@ -176,40 +446,183 @@ impl Node {
         }
     }
 
-    pub fn leaf_node_num_cells(&mut self) -> &mut u32 {
+    pub fn leaf_node_num_cells(&mut self) -> Result<&mut u32, CorruptErr> {
         match *self {
             Node::Leaf {
                 ref mut num_cells, ..
-            } => num_cells,
-            Node::Internal { .. } => panic!("leaf_node_num_cells: Not a leaf node"),
+            } => Ok(num_cells),
+            Node::Internal { .. }
+            | Node::FreelistTrunk { .. }
+            | Node::Overflow { .. }
+            | Node::IndexLeaf { .. }
+            | Node::IndexInternal { .. } => Err(NOT_A_TABLE_LEAF),
         }
     }
 
-    pub fn leaf_node_cell(&mut self, cell_num: u32) -> &mut LeafNodeCell {
+    pub fn leaf_node_cell(&mut self, cell_num: u32) -> Result<&mut LeafNodeCell, CorruptErr> {
         match *self {
-            Node::Leaf { ref mut cells, .. } => &mut cells[cell_num as usize],
-            Node::Internal { .. } => panic!("leaf_node_cell: Not a leaf node"),
+            Node::Leaf { ref mut cells, .. } => cells.get_mut(cell_num as usize).ok_or(
+                CorruptErr::Invalid("Cell number is past the end of the leaf"),
+            ),
+            Node::Internal { .. }
+            | Node::FreelistTrunk { .. }
+            | Node::Overflow { .. }
+            | Node::IndexLeaf { .. }
+            | Node::IndexInternal { .. } => Err(NOT_A_TABLE_LEAF),
+        }
+    }
+
+    pub fn leaf_node_key(&mut self, cell_num: u32) -> Result<&mut i64, CorruptErr> {
+        let leaf_node_cell = self.leaf_node_cell(cell_num)?;
+        Ok(&mut leaf_node_cell.key)
+    }
+
+    /// Space taken up by the cells of a leaf and their pointers.
+    pub fn leaf_node_used_space(&self) -> Result<usize, CorruptErr> {
+        match self {
+            Node::Leaf { cells, .. } => Ok(cells.iter().map(LeafNodeCell::size).sum()),
+            Node::Internal { .. }
+            | Node::FreelistTrunk { .. }
+            | Node::Overflow { .. }
+            | Node::IndexLeaf { .. }
+            | Node::IndexInternal { .. } => Err(NOT_A_TABLE_LEAF),
         }
     }
 
-    pub fn leaf_node_key(&mut self, cell_num: u32) -> &mut u32 {
-        let leaf_node_cell = self.leaf_node_cell(cell_num);
-        &mut leaf_node_cell.key
+    /// Whether a cell fits in the leaf, counting the space of removed cells as free.
+    pub fn leaf_node_fits(&self, cell: &LeafNodeCell) -> Result<bool, CorruptErr> {
+        Ok(self.leaf_node_used_space()? + cell.size() <= LEAF_NODE_SPACE_FOR_CELLS)
     }
 
-    pub fn leaf_node_value(&mut self, cell_num: u32) -> &mut Row {
-        let leaf_node_cell = self.leaf_node_cell(cell_num);
-        &mut leaf_node_cell.value
+    /// Whether the leaf stays at least at its minimum used space without the given cell.
+    pub fn leaf_node_can_spare(&mut self, cell_num: u32) -> Result<bool, CorruptErr> {
+        let cell_size = self.leaf_node_cell(cell_num)?.size();
+        Ok(self.leaf_node_used_space()? - cell_size >= LEAF_NODE_MIN_USED_SPACE)
+    }
+
+    /// Move the cells of a leaf to the end of the page, in key order, so that
+    /// the space of removed cells joins the unallocated space.
+    pub fn leaf_node_defragment(&mut self) -> Result<(), CorruptErr> {
+        match *self {
+            Node::Leaf {
+                ref mut cell_content_start,
+                ref mut fragmented_bytes,
+                ref mut cells,
+                ..
+            } => {
+                let mut offset = PAGE_SIZE as u16;
+                for cell in cells.iter_mut() {
+                    offset -= cell.content_size();
+                    cell.offset = offset;
+                }
+                *cell_content_start = offset;
+                *fragmented_bytes = 0;
+                Ok(())
+            }
+            Node::Internal { .. }
+            | Node::FreelistTrunk { .. }
+            | Node::Overflow { .. }
+            | Node::IndexLeaf { .. }
+            | Node::IndexInternal { .. } => Err(NOT_A_TABLE_LEAF),
+        }
+    }
+
+    // Space between the cell pointers and the cell content
+    fn leaf_node_unallocated_space(&self) -> Result<usize, CorruptErr> {
+        match self {
+            Node::Leaf {
+                cell_content_start,
+                cells,
+                ..
+            } => Ok(*cell_content_start as usize
+                - LEAF_NODE_HEADER_SIZE
+                - LEAF_NODE_CELL_POINTER_SIZE * cells.len()),
+            Node::Internal { .. }
+            | Node::FreelistTrunk { .. }
+            | Node::Overflow { .. }
+            | Node::IndexLeaf { .. }
+            | Node::IndexInternal { .. } => Err(NOT_A_TABLE_LEAF),
+        }
+    }
+
+    /// Remove every cell of a leaf, leaving it empty.
+    pub fn leaf_node_take_cells(&mut self) -> Result<Vec<LeafNodeCell>, CorruptErr> {
+        match *self {
+            Node::Leaf {
+                ref mut num_cells,
+                ref mut cell_content_start,
+                ref mut fragmented_bytes,
+                ref mut cells,
+                ..
+            } => {
+                *num_cells = 0;
+                *cell_content_start = PAGE_SIZE as u16;
+                *fragmented_bytes = 0;
+                Ok(std::mem::take(cells))
+            }
+            Node::Internal { .. }
+            | Node::FreelistTrunk { .. }
+            | Node::Overflow { .. }
+            | Node::IndexLeaf { .. }
+            | Node::IndexInternal { .. } => Err(NOT_A_TABLE_LEAF),
+        }
+    }
+
+    /// Keys of an index node, in order.
+    pub fn index_node_keys(&mut self) -> Result<&mut Vec<Vec<u8>>, CorruptErr> {
+        match *self {
+            Node::IndexLeaf { ref mut keys, .. } | Node::IndexInternal { ref mut keys, .. } => {
+                Ok(keys)
+            }
+            Node::Leaf { .. }
+            | Node::Internal { .. }
+            | Node::FreelistTrunk { .. }
+            | Node::Overflow { .. } => Err(NOT_AN_INDEX_NODE),
+        }
+    }
+
+    /// Space taken up by the cells of an index node.
+    pub fn index_node_used_space(&self) -> Result<usize, CorruptErr> {
+        match self {
+            Node::IndexLeaf { keys, .. } => {
+                Ok(keys.iter().map(|key| index_node_cell_size(key, true)).sum())
+            }
+            Node::IndexInternal { keys, .. } => Ok(keys
+                .iter()
+                .map(|key| index_node_cell_size(key, false))
+                .sum()),
+            Node::Leaf { .. }
+            | Node::Internal { .. }
+            | Node::FreelistTrunk { .. }
+            | Node::Overflow { .. } => Err(NOT_AN_INDEX_NODE),
+        }
+    }
+
+    /// Whether the cells of an index node fit in its page.
+    pub fn index_node_fits(&self) -> Result<bool, CorruptErr> {
+        let num_keys = match self {
+            Node::IndexLeaf { keys, .. } | Node::IndexInternal { keys, .. } => keys.len(),
+            Node::Leaf { .. }
+            | Node::Internal { .. }
+            | Node::FreelistTrunk { .. }
+            | Node::Overflow { .. } => return Err(NOT_AN_INDEX_NODE),
+        };
+        Ok(self.index_node_used_space()? <= INDEX_NODE_SPACE_FOR_CELLS
+            && num_keys <= INDEX_NODE_MAX_CELLS)
     }
 
     pub fn is_node_root(&self) -> bool {
         match *self {
             Node::Leaf { is_root, .. } => is_root,
             Node::Internal { is_root, .. } => is_root,
+            Node::FreelistTrunk { .. }
+            | Node::Overflow { .. }
+            | Node::IndexLeaf { .. }
+            | Node::IndexInternal { .. } => false,
         }
     }
 
-    pub fn set_node_root(&mut self, is_root: bool) {
+    pub fn set_node_root(&mut self, is_root: bool) -> Result<(), CorruptErr> {
         let is_root_curr = match *self {
             Node::Leaf {
                 ref mut is_root, ..
@@ -217,124 +630,290 @@ impl Node {
             Node::Internal {
                 ref mut is_root, ..
             } => is_root,
+            Node::FreelistTrunk { .. }
+            | Node::Overflow { .. }
+            | Node::IndexLeaf { .. }
+            | Node::IndexInternal { .. } => return Err(NOT_A_TABLE_NODE),
         };
 
         *is_root_curr = is_root;
+        Ok(())
     }
 
-    pub fn internal_node_num_keys(&mut self) -> &mut u32 {
+    pub fn internal_node_num_keys(&mut self) -> Result<&mut u32, CorruptErr> {
         match *self {
-            Node::Leaf { .. } => {
-                panic!("internal_node_num_keys: Not an internal node")
-            }
+            Node::Leaf { .. }
+            | Node::FreelistTrunk { .. }
+            | Node::Overflow { .. }
+            | Node::IndexLeaf { .. }
+            | Node::IndexInternal { .. } => Err(NOT_A_TABLE_INTERNAL_NODE),
             Node::Internal {
                 ref mut num_keys, ..
-            } => num_keys,
+            } => Ok(num_keys),
         }
     }
 
-    pub fn internal_node_right_child(&mut self) -> &mut u32 {
+    pub fn internal_node_right_child(&mut self) -> Result<&mut u32, CorruptErr> {
         match *self {
-            Node::Leaf { .. } => {
-                panic!("internal_node_right_child: Not an internal node")
-            }
+            Node::Leaf { .. }
+            | Node::FreelistTrunk { .. }
+            | Node::Overflow { .. }
+            | Node::IndexLeaf { .. }
+            | Node::IndexInternal { .. } => Err(NOT_A_TABLE_INTERNAL_NODE),
             Node::Internal {
                 ref mut right_child_pointer,
                 ..
-            } => right_child_pointer,
+            } => Ok(right_child_pointer),
         }
     }
 
-    pub fn internal_node_cell(&mut self, key_num: u32) -> &mut InternalNodeCell {
+    pub fn internal_node_cell(
+        &mut self,
+        key_num: u32,
+    ) -> Result<&mut InternalNodeCell, CorruptErr> {
         match *self {
-            Node::Leaf { .. } => {
-                panic!("internal_node_right_child: Not an internal node")
-            }
-            Node::Internal { ref mut cells, .. } => &mut cells[key_num as usize],
+            Node::Leaf { .. }
+            | Node::FreelistTrunk { .. }
+            | Node::Overflow { .. }
+            | Node::IndexLeaf { .. }
+            | Node::IndexInternal { .. } => Err(NOT_A_TABLE_INTERNAL_NODE),
+            Node::Internal { ref mut cells, .. } => cells.get_mut(key_num as usize).ok_or(
+                CorruptErr::Invalid("Key number is past the end of the node"),
+            ),
+        }
+    }
+
+    pub fn internal_node_child(&mut self, child_num: u32) -> Result<&mut u32, CorruptErr> {
+        let num_keys = *self.internal_node_num_keys()?;
+
+        let child = if child_num > num_keys {
+            return Err(CorruptErr::Invalid("Child number is past the last child"));
+        } else if child_num == num_keys {
+            self.internal_node_right_child()?
+        } else {
+            &mut self.internal_node_cell(child_num)?.child_pointer
+        };
+        if *child == INVALID_PAGE_NUM {
+            return Err(CorruptErr::Invalid("Internal node has an invalid child"));
         }
+        Ok(child)
     }
 
-    pub fn internal_node_child(&mut self, child_num: u32) -> &mut u32 {
-        let num_keys = self.internal_node_num_keys();
+    pub fn internal_node_key(&mut self, key_num: u32) -> Result<&mut i64, CorruptErr> {
+        let internal_node_cell = self.internal_node_cell(key_num)?;
+        Ok(&mut internal_node_cell.key)
+    }
 
-        if child_num > *num_keys {
-            panic!(
-                "Tried to access child_num {} > num_keys {}",
-                child_num, num_keys
+    /// Insert a cell at the given position, shifting the following pointers right.
+    /// The cell is allocated from the unallocated space, which the leaf is
+    /// defragmented to grow if needed. The caller must make sure the cell fits.
+    pub fn leaf_node_insert_cell(
+        &mut self,
+        cell_num: u32,
+        mut cell: LeafNodeCell,
+    ) -> Result<(), CorruptErr> {
+        let content_size = cell.content_size();
+        if self.leaf_node_unallocated_space()? < cell.size() {
+            self.leaf_node_defragment()?;
+            assert!(
+                self.leaf_node_unallocated_space()? >= cell.size(),
+                "leaf_node_insert_cell: The cell does not fit in the leaf"
             );
-        } else if child_num == *num_keys {
-            let right_child = self.internal_node_right_child();
-            if *right_child == INVALID_PAGE_NUM {
-                panic!("Tried to access right child of node, but was invalid page");
+        }
+
+        match *self {
+            Node::Leaf {
+                ref mut num_cells,
+                ref mut cell_content_start,
+                ref mut cells,
+                ..
+            } => {
+                *cell_content_start -= content_size;
+                cell.offset = *cell_content_start;
+                cells.insert(cell_num as usize, cell);
+                *num_cells += 1;
+                Ok(())
             }
-            right_child
-        } else {
-            let child = &mut self.internal_node_cell(child_num).child_pointer;
-            if *child == INVALID_PAGE_NUM {
-                panic!(
-                    "Tried to access child {} of node, but was invalid page",
-                    child_num
-                );
+            Node::Internal { .. }
+            | Node::FreelistTrunk { .. }
+            | Node::Overflow { .. }
+            | Node::IndexLeaf { .. }
+            | Node::IndexInternal { .. } => Err(NOT_A_TABLE_LEAF),
+        }
+    }
+
+    /// Remove the cell at the given position, shifting the following pointers left.
+    /// Its space joins the unallocated space if it was the first cell in the
+    /// page, otherwise it is counted as fragmented.
+    pub fn leaf_node_remove_cell(&mut self, cell_num: u32) -> Result<LeafNodeCell, CorruptErr> {
+        match *self {
+            Node::Leaf {
+                ref mut num_cells,
+                ref mut cell_content_start,
+                ref mut fragmented_bytes,
+                ref mut cells,
+                ..
+            } => {
+                if cell_num as usize >= cells.len() {
+                    return Err(CorruptErr::Invalid(
+                        "Cell number is past the end of the leaf",
+                    ));
+                }
+                let cell = cells.remove(cell_num as usize);
+                *num_cells -= 1;
+                if cells.is_empty() {
+                    *cell_content_start = PAGE_SIZE as u16;
+                    *fragmented_bytes = 0;
+                } else if cell.offset == *cell_content_start {
+                    *cell_content_start += cell.content_size();
+                } else {
+                    *fragmented_bytes += cell.content_size();
+                }
+                Ok(cell)
             }
-            child
+            Node::Internal { .. }
+            | Node::FreelistTrunk { .. }
+            | Node::Overflow { .. }
+            | Node::IndexLeaf { .. }
+            | Node::IndexInternal { .. } => Err(NOT_A_TABLE_LEAF),
         }
     }
 
-    pub fn internal_node_key(&mut self, key_num: u32) -> &mut u32 {
-        let internal_node_cell = self.internal_node_cell(key_num);
-        &mut internal_node_cell.key
+    /// Insert a key/child pair at the given position, shifting the following pairs right.
+    /// The caller must make sure the node is not full.
+    pub fn internal_node_insert_cell(
+        &mut self,
+        key_num: u32,
+        child_pointer: u32,
+        key: i64,
+    ) -> Result<(), CorruptErr> {
+        match *self {
+            Node::Leaf { .. }
+            | Node::FreelistTrunk { .. }
+            | Node::Overflow { .. }
+            | Node::IndexLeaf { .. }
+            | Node::IndexInternal { .. } => Err(NOT_A_TABLE_INTERNAL_NODE),
+            Node::Internal {
+                ref mut num_keys,
+                ref mut cells,
+                ..
+            } => {
+                cells.insert(key_num as usize, InternalNodeCell { child_pointer, key });
+                cells.pop();
+                *num_keys += 1;
+                Ok(())
+            }
+        }
     }
 
-    pub fn leaf_node_next_leaf(&mut self) -> &mut u32 {
+    /// Remove the key/child pair at the given position, shifting the following pairs left.
+    pub fn internal_node_remove_cell(&mut self, key_num: u32) -> Result<(), CorruptErr> {
+        match *self {
+            Node::Leaf { .. }
+            | Node::FreelistTrunk { .. }
+            | Node::Overflow { .. }
+            | Node::IndexLeaf { .. }
+            | Node::IndexInternal { .. } => Err(NOT_A_TABLE_INTERNAL_NODE),
+            Node::Internal {
+                ref mut num_keys,
+                ref mut cells,
+                ..
+            } => {
+                cells.remove(key_num as usize);
+                cells.push(InternalNodeCell::new());
+                *num_keys -= 1;
+                Ok(())
+            }
+        }
+    }
+
+    // Return the index of the given child page within this node.
+    // The right child has index num_keys.
+    pub fn internal_node_child_index(&mut self, child_page_num: u32) -> Result<u32, CorruptErr> {
+        let num_keys = *self.internal_node_num_keys()?;
+        for i in 0..num_keys {
+            if *self.internal_node_child(i)? == child_page_num {
+                return Ok(i);
+            }
+        }
+
+        if *self.internal_node_right_child()? != child_page_num {
+            return Err(CorruptErr::Invalid("Page is not a child of its parent"));
+        }
+        Ok(num_keys)
+    }
+
+    pub fn leaf_node_next_leaf(&mut self) -> Result<&mut u32, CorruptErr> {
         match *self {
             Node::Leaf {
                 ref mut next_leaf_pointer,
                 ..
-            } => next_leaf_pointer,
-            Node::Internal { .. } => panic!("leaf_node_next_leaf: Not a leaf node"),
+            } => Ok(next_leaf_pointer),
+            Node::Internal { .. }
+            | Node::FreelistTrunk { .. }
+            | Node::Overflow { .. }
+            | Node::IndexLeaf { .. }
+            | Node::IndexInternal { .. } => Err(NOT_A_TABLE_LEAF),
         }
     }
 
-    pub fn num_cell_or_keys(&mut self) -> &mut u32 {
+    pub fn num_cell_or_keys(&mut self) -> Result<&mut u32, CorruptErr> {
         match *self {
             Node::Leaf {
                 ref mut num_cells, ..
-            } => num_cells,
+            } => Ok(num_cells),
             Node::Internal {
                 ref mut num_keys, ..
-            } => num_keys,
+            } => Ok(num_keys),
+            Node::FreelistTrunk { .. }
+            | Node::Overflow { .. }
+            | Node::IndexLeaf { .. }
+            | Node::IndexInternal { .. } => Err(NOT_A_TABLE_NODE),
         }
     }
 
-    pub fn node_key(&mut self, cell_num: u32) -> &mut u32 {
+    pub fn node_key(&mut self, cell_num: u32) -> Result<&mut i64, CorruptErr> {
         match *self {
             Node::Leaf { .. } => self.leaf_node_key(cell_num),
             Node::Internal { .. } => self.internal_node_key(cell_num),
+            Node::FreelistTrunk { .. }
+            | Node::Overflow { .. }
+            | Node::IndexLeaf { .. }
+            | Node::IndexInternal { .. } => Err(NOT_A_TABLE_NODE),
         }
     }
 
-    pub fn parent(&mut self) -> &mut u32 {
+    pub fn parent(&mut self) -> Result<&mut u32, CorruptErr> {
         match *self {
             Node::Leaf {
                 ref mut parent_pointer,
                 ..
-            } => parent_pointer,
+            } => Ok(parent_pointer),
             Node::Internal {
                 ref mut parent_pointer,
                 ..
-            } => parent_pointer,
+            } => Ok(parent_pointer),
+            Node::FreelistTrunk { .. }
+            | Node::Overflow { .. }
+            | Node::IndexLeaf { .. }
+            | Node::IndexInternal { .. } => Err(NOT_A_TABLE_NODE),
         }
     }
 
-    pub fn update_internal_node_key(&mut self, old_key: u32, new_key: u32) {
-        let old_child_index = self.internal_node_find_child(old_key);
-        *self.internal_node_key(old_child_index) = new_key;
+    pub fn update_internal_node_key(
+        &mut self,
+        old_key: i64,
+        new_key: i64,
+    ) -> Result<(), CorruptErr> {
+        let old_child_index = self.internal_node_find_child(old_key)?;
+        *self.internal_node_key(old_child_index)? = new_key;
+        Ok(())
     }
 
     // Return the index of the child which should contain
     // the given key.
-    pub fn internal_node_find_child(&mut self, key: u32) -> u32 {
-        let num_keys = self.internal_node_num_keys();
+    pub fn internal_node_find_child(&mut self, key: i64) -> Result<u32, CorruptErr> {
+        let num_keys = self.internal_node_num_keys()?;
 
         // Binary search
         let mut min_index = 0;
@@ -342,7 +921,7 @@ impl Node {
 
         while min_index != max_index {
             let index = (min_index + max_index) / 2;
-            let key_to_right = self.internal_node_key(index);
+            let key_to_right = self.internal_node_key(index)?;
 
             if *key_to_right >= key {
                 max_index = index;
@@ -351,11 +930,128 @@ impl Node {
             }
         }
 
-        min_index
+        Ok(min_index)
     }
 
-    pub fn from_bytes(bytes: &[u8]) -> Self {
-        let node_type = bytes[0]; // 0 -> Leaf Node, 1 -> Internal Node
+    /// Decode a page read from the file. A page whose counts do not fit
+    /// in it, or of an unknown type, is corrupt.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, CorruptErr> {
+        // 0 -> Leaf Node, 1 -> Internal Node, 2 -> Freelist Trunk, 3 -> Overflow Page,
+        // 4 -> Index Leaf, 5 -> Index Internal Node
+        let node_type = bytes[0];
+        if node_type > 5 {
+            return Err(CorruptErr::Invalid("Unknown page type"));
+        }
+        if node_type == 4 || node_type == 5 {
+            let is_leaf = node_type == 4;
+
+            let start = INDEX_NODE_NUM_KEYS_OFFSET;
+            let end = start + INDEX_NODE_NUM_KEYS_SIZE;
+            let mut num_keys_bytes = [0; INDEX_NODE_NUM_KEYS_SIZE];
+            num_keys_bytes.copy_from_slice(&bytes[start..end]);
+            let num_keys = u32::from_le_bytes(num_keys_bytes);
+            if num_keys as usize > INDEX_NODE_MAX_CELLS {
+                return Err(CorruptErr::Invalid("Index node holds too many keys"));
+            }
+
+            let start = INDEX_NODE_POINTER_OFFSET;
+            let end = start + INDEX_NODE_POINTER_SIZE;
+            let mut pointer_bytes = [0; INDEX_NODE_POINTER_SIZE];
+            pointer_bytes.copy_from_slice(&bytes[start..end]);
+            let pointer = u32::from_le_bytes(pointer_bytes);
+
+            let mut keys = Vec::new();
+            let mut children = Vec::new();
+            let mut start = INDEX_NODE_HEADER_SIZE;
+            for _ in 0..num_keys {
+                if !is_leaf {
+                    let end = start + INDEX_NODE_CHILD_SIZE;
+                    if end > PAGE_SIZE {
+                        return Err(CorruptErr::Invalid("Index cells do not fit in the page"));
+                    }
+                    let mut child_bytes = [0; INDEX_NODE_CHILD_SIZE];
+                    child_bytes.copy_from_slice(&bytes[start..end]);
+                    children.push(u32::from_le_bytes(child_bytes));
+                    start = end;
+                }
+
+                let (key_len, key_len_size) = read_varint(&bytes[start..])
+                    .ok_or(CorruptErr::Invalid("Malformed index cell"))?;
+                start += key_len_size;
+                let end = usize::try_from(key_len)
+                    .ok()
+                    .and_then(|key_len| start.checked_add(key_len))
+                    .filter(|end| *end <= PAGE_SIZE)
+                    .ok_or(CorruptErr::Invalid("Index cells do not fit in the page"))?;
+                keys.push(bytes[start..end].to_vec());
+                start = end;
+            }
+
+            if is_leaf {
+                return Ok(Node::IndexLeaf {
+                    next_leaf_pointer: pointer,
+                    keys,
+                });
+            }
+            children.push(pointer);
+            return Ok(Node::IndexInternal { keys, children });
+        }
+        if node_type == 3 {
+            let start = OVERFLOW_PAGE_NEXT_OFFSET;
+            let end = start + OVERFLOW_PAGE_NEXT_SIZE;
+            let mut next_page_bytes = [0; OVERFLOW_PAGE_NEXT_SIZE];
+            next_page_bytes.copy_from_slice(&bytes[start..end]);
+            let next_page = u32::from_le_bytes(next_page_bytes);
+
+            let start = OVERFLOW_PAGE_DATA_LEN_OFFSET;
+            let end = start + OVERFLOW_PAGE_DATA_LEN_SIZE;
+            let mut data_len_bytes = [0; OVERFLOW_PAGE_DATA_LEN_SIZE];
+            data_len_bytes.copy_from_slice(&bytes[start..end]);
+            let data_len = u16::from_le_bytes(data_len_bytes) as usize;
+            if data_len > OVERFLOW_PAGE_SPACE_FOR_DATA {
+                return Err(CorruptErr::Invalid(
+                    "Overflow page data does not fit in the page",
+                ));
+            }
+
+            let start = OVERFLOW_PAGE_HEADER_SIZE;
+            return Ok(Node::Overflow {
+                next_page,
+                data: bytes[start..start + data_len].to_vec(),
+            });
+        }
+        if node_type == 2 {
+            let start = FREELIST_TRUNK_NEXT_OFFSET;
+            let end = start + FREELIST_TRUNK_NEXT_SIZE;
+            let mut next_trunk_bytes = [0; FREELIST_TRUNK_NEXT_SIZE];
+            next_trunk_bytes.copy_from_slice(&bytes[start..end]);
+            let next_trunk = u32::from_le_bytes(next_trunk_bytes);
+
+            let start = FREELIST_TRUNK_NUM_LEAVES_OFFSET;
+            let end = start + FREELIST_TRUNK_NUM_LEAVES_SIZE;
+            let mut num_leaves_bytes = [0; FREELIST_TRUNK_NUM_LEAVES_SIZE];
+            num_leaves_bytes.copy_from_slice(&bytes[start..end]);
+            let num_leaves = u32::from_le_bytes(num_leaves_bytes);
+            if num_leaves as usize > FREELIST_TRUNK_MAX_LEAVES {
+                return Err(CorruptErr::Invalid("Freelist trunk lists too many pages"));
+            }
+
+            let mut leaf_page_nums = Vec::new();
+            let mut start = FREELIST_TRUNK_HEADER_SIZE;
+            for _ in 0..num_leaves {
+                let end = start + FREELIST_LEAF_PAGE_NUM_SIZE;
+                let mut page_num_bytes = [0; FREELIST_LEAF_PAGE_NUM_SIZE];
+                page_num_bytes.copy_from_slice(&bytes[start..end]);
+                leaf_page_nums.push(u32::from_le_bytes(page_num_bytes));
+                start = end;
+            }
+
+            return Ok(Node::FreelistTrunk {
+                next_trunk,
+                leaf_page_nums,
+            });
+        }
+
         let is_root = bytes[1] == 1;
 
         let start = PARENT_POINTER_OFFSET;
@@ -377,47 +1073,104 @@ impl Node {
             next_leaf_pointer_bytes.copy_from_slice(&bytes[start..end]);
             let next_leaf_pointer = u32::from_le_bytes(next_leaf_pointer_bytes);
 
+            let start = LEAF_NODE_CELL_CONTENT_START_OFFSET;
+            let end = start + LEAF_NODE_CELL_CONTENT_START_SIZE;
+            let mut cell_content_start_bytes = [0; LEAF_NODE_CELL_CONTENT_START_SIZE];
+            cell_content_start_bytes.copy_from_slice(&bytes[start..end]);
+            let cell_content_start = u16::from_le_bytes(cell_content_start_bytes);
+
+            let start = LEAF_NODE_FRAGMENTED_BYTES_OFFSET;
+            let end = start + LEAF_NODE_FRAGMENTED_BYTES_SIZE;
+            let mut fragmented_bytes_bytes = [0; LEAF_NODE_FRAGMENTED_BYTES_SIZE];
+            fragmented_bytes_bytes.copy_from_slice(&bytes[start..end]);
+            let fragmented_bytes = u16::from_le_bytes(fragmented_bytes_bytes);
+
+            let pointers_end =
+                LEAF_NODE_HEADER_SIZE + LEAF_NODE_CELL_POINTER_SIZE * num_cells as usize;
+            if pointers_end > cell_content_start as usize || cell_content_start as usize > PAGE_SIZE
+            {
+                return Err(CorruptErr::Invalid("Leaf cells do not fit in the page"));
+            }
+
             let mut cells = Vec::new();
+            let mut content_size = 0;
 
             let mut start = LEAF_NODE_HEADER_SIZE;
-            let end = PAGE_SIZE;
+            for _ in 0..num_cells {
+                let end = start + LEAF_NODE_CELL_POINTER_SIZE;
+                let mut offset_bytes = [0; LEAF_NODE_CELL_POINTER_SIZE];
+                offset_bytes.copy_from_slice(&bytes[start..end]);
+                let offset = u16::from_le_bytes(offset_bytes);
+                start = end;
 
-            while start < end {
-                let end = start + LEAF_NODE_KEY_SIZE;
-                if end >= PAGE_SIZE {
-                    break;
+                if offset < cell_content_start || offset as usize >= PAGE_SIZE {
+                    return Err(CorruptErr::Invalid("Leaf cell pointer is out of the page"));
                 }
-
-                let mut key_bytes = [0; LEAF_NODE_KEY_SIZE];
-                key_bytes.copy_from_slice(&bytes[start..end]);
-                let key = u32::from_le_bytes(key_bytes);
-
-                start = end;
-                let end = start + ROW_SIZE;
-                if end >= PAGE_SIZE {
-                    break;
+                let cell = &bytes[offset as usize..PAGE_SIZE];
+                let (value_len, value_len_size) =
+                    read_varint(cell).ok_or(CorruptErr::Invalid("Malformed leaf cell"))?;
+                let (key, key_size) = read_varint(&cell[value_len_size..])
+                    .ok_or(CorruptErr::Invalid("Malformed leaf cell"))?;
+                let value_start = value_len_size + key_size;
+                let key = key as i64;
+                let value_len = usize::try_from(value_len)
+                    .map_err(|_| CorruptErr::Invalid("Malformed leaf cell"))?;
+                let local_size = leaf_node_local_size(value_len);
+                let overflow_page_size = if local_size < value_len {
+                    LEAF_NODE_OVERFLOW_PAGE_SIZE
+                } else {
+                    0
+                };
+                let value_end = value_start + local_size;
+                if value_end + overflow_page_size > cell.len() {
+                    return Err(CorruptErr::Invalid("Malformed leaf cell"));
+                }
+                let value = cell[value_start..value_end].to_vec();
+                let mut overflow_page = 0;
+                if overflow_page_size > 0 {
+                    let mut overflow_page_bytes = [0; LEAF_NODE_OVERFLOW_PAGE_SIZE];
+                    overflow_page_bytes
+                        .copy_from_slice(&cell[value_end..value_end + overflow_page_size]);
+                    overflow_page = u32::from_le_bytes(overflow_page_bytes);
+                    if overflow_page == 0 {
+                        return Err(CorruptErr::Invalid("Malformed leaf cell"));
+                    }
                 }
 
-                let value = Row::deserialize(&bytes[start..end]);
-                let leaf_node_cell = LeafNodeCell { key, value };
+                let cell = LeafNodeCell {
+                    key,
+                    value,
+                    value_len,
+                    overflow_page,
+                    offset,
+                };
+                content_size += cell.content_size() as usize;
+                cells.push(cell);
+            }
 
-                cells.push(leaf_node_cell);
-                start = end;
+            // Every byte past the start of the cell content is either in a cell or fragmented
+            if cell_content_start as usize + content_size + fragmented_bytes as usize != PAGE_SIZE {
+                return Err(CorruptErr::Invalid("Leaf cells overlap"));
             }
 
-            Node::Leaf {
+            Ok(Node::Leaf {
                 is_root,
                 parent_pointer,
                 num_cells,
                 next_leaf_pointer,
+                cell_content_start,
+                fragmented_bytes,
                 cells,
-            }
+            })
         } else {
             let start = INTERNAL_NODE_NUM_KEYS_OFFSET;
             let end = start + INTERNAL_NODE_NUM_KEYS_SIZE;
             let mut num_keys_bytes = [0; INTERNAL_NODE_NUM_KEYS_SIZE];
             num_keys_bytes.copy_from_slice(&bytes[start..end]);
             let num_keys = u32::from_le_bytes(num_keys_bytes);
+            if num_keys as usize > INTERNAL_NODE_MAX_CELLS {
+                return Err(CorruptErr::Invalid("Internal node holds too many keys"));
+            }
 
             let start = INTERNAL_NODE_RIGHT_CHILD_OFFSET;
             let end = start + INTERNAL_NODE_RIGHT_CHILD_SIZE;
@@ -447,7 +1200,7 @@ impl Node {
                 }
                 let mut key_bytes = [0; INTERNAL_NODE_KEY_SIZE];
                 key_bytes.copy_from_slice(&bytes[start..end]);
-                let key = u32::from_le_bytes(key_bytes);
+                let key = i64::from_le_bytes(key_bytes);
 
                 let internal_node_cell = InternalNodeCell { child_pointer, key };
 
@@ -455,13 +1208,13 @@ impl Node {
                 start = end;
             }
 
-            Node::Internal {
+            Ok(Node::Internal {
                 is_root,
                 parent_pointer,
                 num_keys,
                 right_child_pointer,
                 cells,
-            }
+            })
         }
     }
 
@@ -474,6 +1227,8 @@ impl Node {
                 parent_pointer,
                 num_cells,
                 next_leaf_pointer,
+                cell_content_start,
+                fragmented_bytes,
                 cells,
             } => {
                 node[0] = 0;
@@ -491,25 +1246,29 @@ impl Node {
                 let end = start + LEAF_NODE_NEXT_LEAF_SIZE;
                 node[start..end].copy_from_slice(&next_leaf_pointer.to_le_bytes());
 
+                let start = LEAF_NODE_CELL_CONTENT_START_OFFSET;
+                let end = start + LEAF_NODE_CELL_CONTENT_START_SIZE;
+                node[start..end].copy_from_slice(&cell_content_start.to_le_bytes());
+
+                let start = LEAF_NODE_FRAGMENTED_BYTES_OFFSET;
+                let end = start + LEAF_NODE_FRAGMENTED_BYTES_SIZE;
+                node[start..end].copy_from_slice(&fragmented_bytes.to_le_bytes());
+
                 let mut start = LEAF_NODE_HEADER_SIZE;
                 for cell in cells {
-                    let end = start + LEAF_NODE_KEY_SIZE;
-                    if end >= PAGE_SIZE {
-                        break;
-                    }
-                    node[start..end].copy_from_slice(&cell.key.to_le_bytes());
-
+                    let end = start + LEAF_NODE_CELL_POINTER_SIZE;
+                    node[start..end].copy_from_slice(&cell.offset.to_le_bytes());
                     start = end;
-                    let end = start + ROW_SIZE;
-                    if end >= PAGE_SIZE {
-                        break;
-                    }
-
-                    let mut value = [0; ROW_SIZE];
-                    cell.value.serialize(&mut value);
-                    node[start..end].copy_from_slice(&value);
 
-                    start = end;
+                    let mut content = Vec::with_capacity(cell.content_size() as usize);
+                    put_varint(&mut content, cell.value_len as u64);
+                    put_varint(&mut content, cell.key as u64);
+                    content.extend_from_slice(&cell.value);
+                    if let Some(overflow_page) = cell.overflow_page() {
+                        content.extend_from_slice(&overflow_page.to_le_bytes());
+                    }
+                    let offset = cell.offset as usize;
+                    node[offset..offset + content.len()].copy_from_slice(&content);
                 }
             }
             Node::Internal {
@@ -552,7 +1311,81 @@ impl Node {
                     start = end;
                 }
             }
+            Node::FreelistTrunk {
+                next_trunk,
+                leaf_page_nums,
+            } => {
+                node[0] = 2;
+
+                let start = FREELIST_TRUNK_NEXT_OFFSET;
+                let end = start + FREELIST_TRUNK_NEXT_SIZE;
+                node[start..end].copy_from_slice(&next_trunk.to_le_bytes());
+
+                let start = FREELIST_TRUNK_NUM_LEAVES_OFFSET;
+                let end = start + FREELIST_TRUNK_NUM_LEAVES_SIZE;
+                node[start..end].copy_from_slice(&(leaf_page_nums.len() as u32).to_le_bytes());
+
+                let mut start = FREELIST_TRUNK_HEADER_SIZE;
+                for page_num in leaf_page_nums {
+                    let end = start + FREELIST_LEAF_PAGE_NUM_SIZE;
+                    node[start..end].copy_from_slice(&page_num.to_le_bytes());
+                    start = end;
+                }
+            }
+            Node::Overflow { next_page, data } => {
+                node[0] = 3;
+
+                let start = OVERFLOW_PAGE_NEXT_OFFSET;
+                let end = start + OVERFLOW_PAGE_NEXT_SIZE;
+                node[start..end].copy_from_slice(&next_page.to_le_bytes());
+
+                let start = OVERFLOW_PAGE_DATA_LEN_OFFSET;
+                let end = start + OVERFLOW_PAGE_DATA_LEN_SIZE;
+                node[start..end].copy_from_slice(&(data.len() as u16).to_le_bytes());
+
+                let start = OVERFLOW_PAGE_HEADER_SIZE;
+                node[start..start + data.len()].copy_from_slice(data);
+            }
+            Node::IndexLeaf {
+                next_leaf_pointer,
+                keys,
+            } => {
+                node[0] = 4;
+                write_index_node(&mut node, *next_leaf_pointer, keys, &[]);
+            }
+            Node::IndexInternal { keys, children } => {
+                node[0] = 5;
+                write_index_node(
+                    &mut node,
+                    children[keys.len()],
+                    keys,
+                    &children[..keys.len()],
+                );
+            }
         }
         node
     }
 }
+
+// Write the header and cells of an index node. An internal node has a
+// child before each key, a leaf has none.
+fn write_index_node(node: &mut [u8; PAGE_SIZE], pointer: u32, keys: &[Vec<u8>], children: &[u32]) {
+    let start = INDEX_NODE_NUM_KEYS_OFFSET;
+    let end = start + INDEX_NODE_NUM_KEYS_SIZE;
+    node[start..end].copy_from_slice(&(keys.len() as u32).to_le_bytes());
+
+    let start = INDEX_NODE_POINTER_OFFSET;
+    let end = start + INDEX_NODE_POINTER_SIZE;
+    node[start..end].copy_from_slice(&pointer.to_le_bytes());
+
+    let mut cells = Vec::with_capacity(INDEX_NODE_SPACE_FOR_CELLS);
+    for (i, key) in keys.iter().enumerate() {
+        if let Some(child) = children.get(i) {
+            cells.extend_from_slice(&child.to_le_bytes());
+        }
+        put_varint(&mut cells, key.len() as u64);
+        cells.extend_from_slice(key);
+    }
+    let start = INDEX_NODE_HEADER_SIZE;
+    node[start..start + cells.len()].copy_from_slice(&cells);
+}
@@ -1,151 +1,726 @@
 use std::cell::RefCell;
+use std::collections::HashMap;
 use std::fmt;
 use std::fs::{File, OpenOptions};
 use std::io::{Read, Seek, Write};
 
-use crate::node::Node;
+use crate::error::{CorruptErr, Error};
+use crate::header::{Header, JournalMode, HEADER_PAGE_NUM};
+use crate::journal::{journal_path, roll_back_hot_journal, Journal};
+use crate::node::{Node, FREELIST_TRUNK_MAX_LEAVES};
+use crate::row::Row;
+use crate::value::Value;
+use crate::wal::{wal_path, Wal, WAL_AUTOCHECKPOINT};
 
 pub const PAGE_SIZE: usize = 4096;
-pub const TABLE_MAX_PAGES: usize = 100;
 pub const INVALID_PAGE_NUM: u32 = u32::MAX;
 
+// Pages kept in memory before the cache starts evicting them
+#[cfg(debug_assertions)]
+pub const DEFAULT_CACHE_SIZE: usize = 16; // Kept small for testing
+#[cfg(not(debug_assertions))]
+pub const DEFAULT_CACHE_SIZE: usize = 2000;
+
 pub struct Pager {
+    filename: String,
     file: File,
     file_length: u64,
-    // TODO: is this required? can be derived from pages.len()
     pub num_pages: u32,
-    pages: Vec<Option<Box<Node>>>,
+    pub header: Header,
+    // Bumped whenever a page may change, so a position kept in a tree
+    // can tell whether it still holds
+    pub change_counter: u64,
+    cache_size: usize,
+    frames: Vec<Frame>,
+    // Frame index of each cached page
+    page_frames: HashMap<u32, usize>,
+    clock_hand: usize,
+    pub stats: CacheStats,
+    journal_path: String,
+    // Open from the first change after a commit until the next commit
+    journal: Option<Journal>,
+    wal_path: String,
+    // Open while the database is in WAL mode
+    wal: Option<Wal>,
+    // Open savepoints, innermost last
+    savepoints: Vec<Savepoint>,
+}
+
+// The state of the database when a savepoint was set. Only the pages changed
+// since then are saved, as they were before their first change.
+struct Savepoint {
+    name: String,
+    pages: HashMap<u32, Box<[u8; PAGE_SIZE]>>,
+    header: Header,
+    num_pages: u32,
+}
+
+// A cached page
+struct Frame {
+    page_num: u32,
+    node: Box<Node>,
+    // Changed since it was read, so it must be written back before it is evicted
+    dirty: bool,
+    // Used since the clock hand last passed it
+    referenced: bool,
+}
+
+/// Page cache counters, for tuning the cache size.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub evictions: u64,
+    /// Dirty pages written to the file when they were evicted.
+    pub writebacks: u64,
 }
 
 impl Pager {
-    pub fn pager_open(filename: &str) -> Self {
-        let Ok(file) = OpenOptions::new()
+    /// Open a database file, or create it if it does not exist.
+    /// A journal left behind by a crash is rolled back first, and the
+    /// commits in a write-ahead log left behind are checkpointed.
+    /// An existing file must start with a valid header.
+    pub fn pager_open(filename: &str) -> Result<Self, Error> {
+        let mut file = OpenOptions::new()
             .read(true)
             .write(true)
             .create(true)
-            .open(filename)
-        else {
-            panic!("Unable to open file.");
-        };
+            .truncate(false)
+            .open(filename)?;
 
-        let Ok(metadata) = file.metadata() else {
-            panic!("Unable to get file metadata.");
-        };
+        let journal_path = journal_path(filename);
+        roll_back_hot_journal(&journal_path, &mut file)?;
 
-        let file_length = metadata.len();
+        let wal_path = wal_path(filename);
+        if std::path::Path::new(&wal_path).exists() {
+            let mut wal = Wal::open(wal_path.clone())?;
+            wal.checkpoint(&mut file)?;
+            wal.delete()?;
+        }
+
+        let file_length = file.metadata()?.len();
         let num_pages = (file_length / PAGE_SIZE as u64) as u32;
 
+        let mut pager = Self {
+            filename: filename.to_owned(),
+            file,
+            file_length,
+            num_pages,
+            header: Header::for_new_file(),
+            change_counter: 0,
+            cache_size: DEFAULT_CACHE_SIZE,
+            frames: Vec::new(),
+            page_frames: HashMap::new(),
+            clock_hand: 0,
+            stats: CacheStats::default(),
+            journal_path,
+            journal: None,
+            wal_path,
+            wal: None,
+            savepoints: Vec::new(),
+        };
+
+        if file_length == 0 {
+            // New database file, the header page is written when the file is closed
+            pager.num_pages = HEADER_PAGE_NUM + 1;
+            return Ok(pager);
+        }
+
+        if file_length < PAGE_SIZE as u64 {
+            return Err(CorruptErr::NotADatabase.into());
+        }
+        pager.header = Header::from_bytes(&pager.read_page_bytes(HEADER_PAGE_NUM)?)?;
+
         if file_length % PAGE_SIZE as u64 != 0 {
-            panic!("Db file is not a whole number of pages. Corrupt file.")
+            return Err(CorruptErr::Invalid("Db file is not a whole number of pages").into());
+        }
+        if pager.header.page_count != num_pages {
+            return Err(CorruptErr::Invalid(
+                "Page count in the header does not match the file length",
+            )
+            .into());
         }
 
-        let mut pages = Vec::new();
+        if pager.header.journal_mode == JournalMode::Wal {
+            pager.wal = Some(Wal::open(pager.wal_path.clone())?);
+        }
+
+        Ok(pager)
+    }
 
-        for _ in 0..TABLE_MAX_PAGES {
-            pages.push(None);
+    // The newest version of a page is in the write-ahead log, if it is there at all
+    fn read_page_bytes(&mut self, page_num: u32) -> Result<[u8; PAGE_SIZE], Error> {
+        if let Some(wal) = &mut self.wal {
+            if let Some(page) = wal.read_page(page_num)? {
+                return Ok(page);
+            }
         }
 
-        Self {
-            file,
-            file_length,
-            num_pages,
-            pages,
+        let offset = page_num as usize * PAGE_SIZE;
+        self.file.seek(std::io::SeekFrom::Start(offset as u64))?;
+
+        let mut buffer = [0; PAGE_SIZE];
+
+        self.file.read_exact(&mut buffer)?;
+
+        Ok(buffer)
+    }
+
+    // Pages are only overwritten once their original images are safely in the journal.
+    // In WAL mode they are appended to the log instead.
+    fn write_page_bytes(&mut self, page_num: u32, bytes: &[u8; PAGE_SIZE]) -> Result<(), Error> {
+        if let Some(wal) = &mut self.wal {
+            wal.write_page(page_num, bytes)?;
+            return Ok(());
         }
+        if let Some(journal) = &mut self.journal {
+            journal.sync()?;
+        }
+
+        let offset = page_num as usize * PAGE_SIZE;
+        self.file.seek(std::io::SeekFrom::Start(offset as u64))?;
+
+        self.file.write_all(bytes)?;
+
+        self.file_length = self.file_length.max((offset + PAGE_SIZE) as u64);
+        Ok(())
+    }
+
+    // Write the header page, recording the current page count
+    fn write_header(&mut self) -> Result<(), Error> {
+        self.journal_page(HEADER_PAGE_NUM)?;
+        self.header.page_count = self.num_pages;
+        let header = self.header.to_bytes();
+        self.write_page_bytes(HEADER_PAGE_NUM, &header)
+    }
+
+    pub fn filename(&self) -> &str {
+        &self.filename
     }
 
     pub fn file_length(&self) -> u64 {
         self.file_length
     }
 
-    fn validate_page_num(page_num: u32) {
-        if page_num as usize >= TABLE_MAX_PAGES {
-            panic!("Error: Table Full.");
+    pub fn cache_size(&self) -> usize {
+        self.cache_size
+    }
+
+    /// Number of pages currently in the cache.
+    pub fn cached_pages(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// Change the number of pages kept in memory, at least one.
+    /// Shrinking the cache writes back and drops every cached page.
+    pub fn set_cache_size(&mut self, cache_size: usize) -> Result<(), Error> {
+        let cache_size = cache_size.max(1);
+        if cache_size < self.frames.len() {
+            self.flush()?;
+            self.frames.clear();
+            self.page_frames.clear();
+            self.clock_hand = 0;
         }
+        self.cache_size = cache_size;
+        Ok(())
     }
 
-    pub fn get_page(&mut self, page_num: u32) -> &mut Node {
-        Self::validate_page_num(page_num);
+    /// Fetch a page to modify it. The page is written back to the file
+    /// before it is evicted from the cache. The page just past the end
+    /// of the database is created as an empty leaf.
+    pub fn get_page(&mut self, page_num: u32) -> Result<&mut Node, Error> {
+        if page_num > self.num_pages {
+            return Err(CorruptErr::Invalid("Page number is past the end of the file").into());
+        }
+        self.journal_page(page_num)?;
+        let frame = self.fetch(page_num)?;
+        self.change_counter += 1;
+
+        // Save the page as it was when the innermost savepoint was set
+        if let Some(savepoint) = self.savepoints.last_mut() {
+            if page_num < savepoint.num_pages && !savepoint.pages.contains_key(&page_num) {
+                let page = Box::new(self.frames[frame].node.to_bytes());
+                savepoint.pages.insert(page_num, page);
+            }
+        }
 
-        // Cache miss. Allocate memory and load from file.
-        if self.pages[page_num as usize].is_none() {
-            let mut page = Node::initialize_leaf_node();
+        let frame = &mut self.frames[frame];
+        frame.dirty = true;
+        Ok(&mut frame.node)
+    }
+
+    /// Fetch a page for lookups only. Changes made through the returned
+    /// node are lost when the page is evicted, use `get_page` for those.
+    pub fn read_page(&mut self, page_num: u32) -> Result<&mut Node, Error> {
+        if page_num >= self.num_pages {
+            return Err(CorruptErr::Invalid("Page number is past the end of the file").into());
+        }
+        let frame = self.fetch(page_num)?;
+        Ok(&mut self.frames[frame].node)
+    }
 
-            let mut num_pages = self.file_length / PAGE_SIZE as u64;
+    // Index of the frame holding the page, loading it on a cache miss.
+    // The header page is not a node, a tree pointing to it is corrupt.
+    fn fetch(&mut self, page_num: u32) -> Result<usize, Error> {
+        if page_num == HEADER_PAGE_NUM {
+            return Err(CorruptErr::Invalid("Page 0 is not a B-tree page").into());
+        }
+
+        if let Some(&frame) = self.page_frames.get(&page_num) {
+            self.stats.hits += 1;
+            self.frames[frame].referenced = true;
+            return Ok(frame);
+        }
+        self.stats.misses += 1;
+
+        // Cache miss. Load from file, or start a new page past the end of the database.
+        // A new page is dirty, it has to reach the file even if it is never changed.
+        // Pages past the end may still be in the file after a rollback to a savepoint.
+        let in_file = page_num < self.num_pages;
+        let node = if in_file {
+            Node::from_bytes(&self.read_page_bytes(page_num)?)?
+        } else {
+            Node::initialize_leaf_node()
+        };
+
+        if page_num >= self.num_pages {
+            self.num_pages = page_num + 1;
+        }
 
-            // We might have partial page at the end of the file
-            if self.file_length % PAGE_SIZE as u64 != 0 {
-                num_pages += 1;
+        if !in_file {
+            self.journal_page(page_num)?;
+        }
+        let frame = Frame {
+            page_num,
+            node: Box::new(node),
+            dirty: !in_file,
+            referenced: true,
+        };
+        let index = if self.frames.len() < self.cache_size {
+            self.frames.push(frame);
+            self.frames.len() - 1
+        } else {
+            let index = self.evict()?;
+            self.frames[index] = frame;
+            index
+        };
+        self.page_frames.insert(page_num, index);
+
+        Ok(index)
+    }
+
+    // Free up a frame with the CLOCK algorithm. The hand sweeps over the frames,
+    // giving pages used since its last pass a second chance, and evicts the first
+    // page that was not. Dirty pages are written back first.
+    fn evict(&mut self) -> Result<usize, Error> {
+        loop {
+            let index = self.clock_hand;
+            self.clock_hand = (self.clock_hand + 1) % self.frames.len();
+
+            let frame = &mut self.frames[index];
+            if frame.referenced {
+                frame.referenced = false;
+                continue;
             }
 
-            if page_num as u64 <= num_pages {
-                let offset = page_num as usize * PAGE_SIZE;
-                self.file
-                    .seek(std::io::SeekFrom::Start(offset as u64))
-                    .expect("Unable to seek file.");
+            let page_num = frame.page_num;
+            if frame.dirty {
+                let bytes = frame.node.to_bytes();
+                self.write_page_bytes(page_num, &bytes)?;
+                self.frames[index].dirty = false;
+                self.stats.writebacks += 1;
+            }
+            self.page_frames.remove(&page_num);
+            self.stats.evictions += 1;
 
-                let mut buffer = [0; PAGE_SIZE];
+            return Ok(index);
+        }
+    }
 
-                self.file
-                    .read(&mut buffer)
-                    .expect("Unable to read file to a buffer.");
+    // Save the original image of a page to the journal before its first change,
+    // starting the journal on the first change after a commit. Pages past the
+    // original end of the file have no image to save, they are cut off on rollback.
+    fn journal_page(&mut self, page_num: u32) -> Result<(), Error> {
+        if self.wal.is_some() {
+            return Ok(());
+        }
 
-                page = Node::from_bytes(&buffer);
+        if self.journal.is_none() {
+            let page_count = (self.file_length / PAGE_SIZE as u64) as u32;
+            self.journal = Some(Journal::create(self.journal_path.clone(), page_count)?);
+        }
+        let Some(journal) = &self.journal else {
+            unreachable!();
+        };
+        if page_num >= journal.original_page_count() || journal.contains(page_num) {
+            return Ok(());
+        }
+
+        let original = self.read_page_bytes(page_num)?;
+        if let Some(journal) = &mut self.journal {
+            journal.append(page_num, &original)?;
+        }
+        Ok(())
+    }
+
+    /// Make every change since the last commit durable: write the changed pages
+    /// and the header to the file, sync it and delete the journal. In WAL mode
+    /// the pages are appended to the log instead, ending with a commit frame.
+    pub fn commit(&mut self) -> Result<(), Error> {
+        self.savepoints.clear();
+        if self.wal.is_some() {
+            return self.commit_wal();
+        }
+        if self.journal.is_none() {
+            return Ok(());
+        }
+
+        self.flush()?;
+        // Pages past the end are left over from a rollback to a savepoint
+        let length = self.num_pages as u64 * PAGE_SIZE as u64;
+        if self.file_length > length {
+            self.file.set_len(length)?;
+            self.file_length = length;
+        }
+        self.write_header()?;
+        self.file.sync_all()?;
+
+        if let Some(journal) = self.journal.take() {
+            journal.delete()?;
+        }
+        Ok(())
+    }
+
+    /// Set a savepoint within the current transaction.
+    pub fn savepoint(&mut self, name: String) {
+        self.savepoints.push(Savepoint {
+            name,
+            pages: HashMap::new(),
+            header: self.header,
+            num_pages: self.num_pages,
+        });
+    }
+
+    /// Position of the innermost savepoint with the given name, counting from the outermost.
+    pub fn find_savepoint(&self, name: &str) -> Option<usize> {
+        self.savepoints
+            .iter()
+            .rposition(|savepoint| savepoint.name.eq_ignore_ascii_case(name))
+    }
+
+    pub fn has_savepoint(&self, name: &str) -> bool {
+        self.find_savepoint(name).is_some()
+    }
+
+    /// Number of open savepoints.
+    pub fn savepoint_depth(&self) -> usize {
+        self.savepoints.len()
+    }
+
+    /// Forget the named savepoint and the ones set after it, keeping their
+    /// changes. The enclosing savepoint takes over the saved pages it lacks.
+    pub fn release_savepoint(&mut self, name: &str) {
+        let Some(position) = self.find_savepoint(name) else {
+            return;
+        };
+
+        let released = self.savepoints.split_off(position);
+        if let Some(enclosing) = self.savepoints.last_mut() {
+            // Outer savepoints saved their pages earlier, so the first image kept wins
+            for savepoint in released {
+                for (page_num, page) in savepoint.pages {
+                    if page_num < enclosing.num_pages {
+                        enclosing.pages.entry(page_num).or_insert(page);
+                    }
+                }
             }
+        }
+    }
+
+    /// Undo the changes made since the named savepoint was set, restoring just the
+    /// pages changed since then. The savepoint stays open, the ones after it are released.
+    pub fn roll_back_to_savepoint(&mut self, name: &str) -> Result<(), Error> {
+        let Some(position) = self.find_savepoint(name) else {
+            return Ok(());
+        };
 
-            if page_num as u32 >= self.num_pages {
-                self.num_pages = page_num + 1;
+        let mut released = self.savepoints.split_off(position + 1);
+        let savepoint = &mut self.savepoints[position];
+        let pages = std::mem::take(&mut savepoint.pages);
+        self.header = savepoint.header;
+        // Pages created since the savepoint are gone
+        let num_pages = savepoint.num_pages;
+        self.truncate(num_pages);
+
+        // Restore the innermost images first, so older images overwrite newer ones
+        released.reverse();
+        for pages in released
+            .into_iter()
+            .map(|savepoint| savepoint.pages)
+            .chain(std::iter::once(pages))
+        {
+            for (page_num, page) in pages {
+                if page_num < self.num_pages {
+                    self.journal_page(page_num)?;
+                    let frame = self.fetch(page_num)?;
+                    let frame = &mut self.frames[frame];
+                    *frame.node = Node::from_bytes(&*page)?;
+                    frame.dirty = true;
+                }
             }
-            self.pages[page_num as usize] = Some(Box::new(page));
         }
+        Ok(())
+    }
 
-        self.pages[page_num as usize]
-            .as_mut()
-            .expect("Node is already initialized. This should not happen")
+    /// Replace every page of the database with the pages of another database
+    /// file. The pages are changed through the journal like any other change,
+    /// so the replacement is committed or rolled back as a whole.
+    pub fn copy_pages_from(&mut self, source: &mut Pager) -> Result<(), Error> {
+        for page_num in HEADER_PAGE_NUM + 1..source.num_pages {
+            let page = source.read_page(page_num)?.to_bytes();
+            *self.get_page(page_num)? = Node::from_bytes(&page)?;
+        }
+        self.truncate(source.num_pages);
+        self.header.freelist_head = source.header.freelist_head;
+        Ok(())
     }
 
-    pub fn pager_flush(&mut self, page_num: u32) {
-        Self::validate_page_num(page_num);
+    /// Drop every page from `num_pages` on. The file is cut
+    /// to the new length when the change is committed.
+    pub fn truncate(&mut self, num_pages: u32) {
+        self.change_counter += 1;
+        self.num_pages = num_pages;
+        self.frames.retain(|frame| frame.page_num < num_pages);
+        self.page_frames = self
+            .frames
+            .iter()
+            .enumerate()
+            .map(|(index, frame)| (frame.page_num, index))
+            .collect();
+        self.clock_hand = 0;
+    }
 
-        self.pages[page_num as usize]
-            .as_ref()
-            .expect("Tried to flush a null page.");
+    /// Undo every change since the last commit. Cached pages are dropped, and
+    /// pages already written back are restored from the journal, or dropped
+    /// from the write-ahead log.
+    pub fn roll_back(&mut self) -> Result<(), Error> {
+        self.change_counter += 1;
+        self.frames.clear();
+        self.page_frames.clear();
+        self.clock_hand = 0;
+        self.savepoints.clear();
+
+        if let Some(wal) = &mut self.wal {
+            wal.roll_back()?;
+        } else if let Some(journal) = self.journal.take() {
+            journal.roll_back(&mut self.file)?;
+        }
+
+        self.file_length = self.file.metadata()?.len();
+        if self.file_length == 0 {
+            self.header = Header::for_new_file();
+        } else {
+            self.header = Header::from_bytes(&self.read_page_bytes(HEADER_PAGE_NUM)?)?;
+        }
+        self.num_pages = self.header.page_count.max(HEADER_PAGE_NUM + 1);
+        Ok(())
+    }
 
-        let page = self.get_page(page_num).to_bytes();
+    // The header page goes last, as the commit frame. The log is checkpointed
+    // once it grows past WAL_AUTOCHECKPOINT frames.
+    fn commit_wal(&mut self) -> Result<(), Error> {
+        self.flush()?;
 
-        let offset = page_num as usize * PAGE_SIZE;
-        self.file
-            .seek(std::io::SeekFrom::Start(offset as u64))
-            .expect("Unable to seek file.");
+        let Some(wal) = &mut self.wal else {
+            return Ok(());
+        };
+        if !wal.has_pending() {
+            return Ok(());
+        }
+        self.header.page_count = self.num_pages;
+        wal.commit(HEADER_PAGE_NUM, &self.header.to_bytes(), self.num_pages)?;
+
+        if wal.frame_count() >= WAL_AUTOCHECKPOINT {
+            self.checkpoint()?;
+        }
+        Ok(())
+    }
+
+    /// Copy the pages committed to the write-ahead log into the database file.
+    /// Returns the number of pages copied, 0 when not in WAL mode.
+    pub fn checkpoint(&mut self) -> Result<usize, Error> {
+        let Some(wal) = &mut self.wal else {
+            return Ok(0);
+        };
+
+        let pages_copied = wal.checkpoint(&mut self.file)?;
+        self.file_length = self.file.metadata()?.len();
+
+        Ok(pages_copied)
+    }
+
+    /// Commit, and in WAL mode checkpoint and delete the log.
+    pub fn close(&mut self) -> Result<(), Error> {
+        self.commit()?;
+        self.close_wal()
+    }
+
+    fn close_wal(&mut self) -> Result<(), Error> {
+        self.checkpoint()?;
+        if let Some(wal) = self.wal.take() {
+            wal.delete()?;
+        }
+        Ok(())
+    }
+
+    pub fn journal_mode(&self) -> JournalMode {
+        self.header.journal_mode
+    }
+
+    /// Switch between a rollback journal and a write-ahead log.
+    /// Changes so far are committed first.
+    pub fn set_journal_mode(&mut self, journal_mode: JournalMode) -> Result<(), Error> {
+        if journal_mode == self.header.journal_mode {
+            return Ok(());
+        }
+
+        self.commit()?;
+        self.close_wal()?;
+
+        // The header records the mode, it is written through the rollback journal
+        self.header.journal_mode = journal_mode;
+        self.journal_page(HEADER_PAGE_NUM)?;
+        self.commit()?;
+
+        if journal_mode == JournalMode::Wal {
+            self.wal = Some(Wal::open(self.wal_path.clone())?);
+        }
+        Ok(())
+    }
+
+    /// Write every dirty page in the cache to the file.
+    pub fn flush(&mut self) -> Result<(), Error> {
+        for index in 0..self.frames.len() {
+            let frame = &self.frames[index];
+            if !frame.dirty {
+                continue;
+            }
+
+            let page_num = frame.page_num;
+            let bytes = frame.node.to_bytes();
+            self.write_page_bytes(page_num, &bytes)?;
+            self.frames[index].dirty = false;
+        }
+        Ok(())
+    }
 
-        self.file.write(&page).expect("Unable to write to file.");
+    // Reuse a page from the freelist if there is one, otherwise
+    // new pages go onto the end of the database file.
+    // The page is handed out as an empty leaf.
+    pub fn get_unused_page_num(&mut self) -> Result<u32, Error> {
+        let head = self.header.freelist_head;
+        if head == 0 {
+            // The last page number is kept free as INVALID_PAGE_NUM
+            if self.num_pages == INVALID_PAGE_NUM {
+                return Err(Error::Full);
+            }
+            return Ok(self.num_pages);
+        }
+
+        let Node::FreelistTrunk {
+            next_trunk,
+            leaf_page_nums,
+        } = self.get_page(head)?
+        else {
+            return Err(CorruptErr::Invalid("Freelist head is not a trunk page").into());
+        };
+        // Hand out the leaves of the head trunk first, then the trunk itself
+        let page_num = match leaf_page_nums.pop() {
+            Some(page_num) => page_num,
+            None => {
+                self.header.freelist_head = *next_trunk;
+                head
+            }
+        };
+
+        *self.get_page(page_num)? = Node::initialize_leaf_node();
+        Ok(page_num)
     }
 
-    // Until we start recycling free pages, new pages will always
-    // go onto the end of the database file
-    pub fn get_unused_page_num(&self) -> u32 {
-        self.num_pages
+    /// Number of pages on the freelist, trunks included.
+    pub fn freelist_count(&mut self) -> Result<u32, Error> {
+        let mut count = 0;
+        let mut trunk_page_num = self.header.freelist_head;
+        while trunk_page_num != 0 {
+            if count >= self.num_pages {
+                return Err(CorruptErr::Invalid("Freelist trunks link up in a loop").into());
+            }
+            let Node::FreelistTrunk {
+                next_trunk,
+                leaf_page_nums,
+            } = self.read_page(trunk_page_num)?
+            else {
+                return Err(CorruptErr::Invalid("Freelist page is not a trunk page").into());
+            };
+            count += 1 + leaf_page_nums.len() as u32;
+            trunk_page_num = *next_trunk;
+        }
+        Ok(count)
     }
 
-    pub fn get_node_max_key(&mut self, page_num: u32) -> u32 {
-        let node = self.get_page(page_num);
+    /// Put a page that is no longer part of any tree on the freelist. It
+    /// becomes a leaf of the head trunk, or the new head trunk if that is full.
+    pub fn free_page(&mut self, page_num: u32) -> Result<(), Error> {
+        let head = self.header.freelist_head;
+        if head != 0 {
+            let Node::FreelistTrunk { leaf_page_nums, .. } = self.get_page(head)? else {
+                return Err(CorruptErr::Invalid("Freelist head is not a trunk page").into());
+            };
+            if leaf_page_nums.len() < FREELIST_TRUNK_MAX_LEAVES {
+                leaf_page_nums.push(page_num);
+                *self.get_page(page_num)? = Node::initialize_leaf_node();
+                return Ok(());
+            }
+        }
+
+        *self.get_page(page_num)? = Node::FreelistTrunk {
+            next_trunk: head,
+            leaf_page_nums: Vec::new(),
+        };
+        self.header.freelist_head = page_num;
+        Ok(())
+    }
+
+    pub fn get_node_max_key(&mut self, page_num: u32) -> Result<i64, Error> {
+        let node = self.read_page(page_num)?;
         match node {
             Node::Leaf { .. } => {
-                let num_cells = *node.leaf_node_num_cells() - 1;
-                *node.leaf_node_key(num_cells)
+                let num_cells = *node.leaf_node_num_cells()? - 1;
+                Ok(*node.leaf_node_key(num_cells)?)
             }
             Node::Internal { .. } => {
-                let right_child_page_num = *node.internal_node_right_child();
+                let right_child_page_num = *node.internal_node_right_child()?;
                 self.get_node_max_key(right_child_page_num)
             }
+            Node::FreelistTrunk { .. } => {
+                Err(CorruptErr::Invalid("Freelist page is part of a B-tree").into())
+            }
+            Node::Overflow { .. } => {
+                Err(CorruptErr::Invalid("Overflow page is part of a B-tree").into())
+            }
+            Node::IndexLeaf { .. } | Node::IndexInternal { .. } => {
+                Err(CorruptErr::Invalid("Index page is part of a table B-tree").into())
+            }
         }
     }
 }
 
-pub struct PagerProxy<'a>(RefCell<&'a mut Pager>);
+/// Prints the tree rooted at the given page.
+pub struct PagerProxy<'a>(RefCell<&'a mut Pager>, u32);
 
 impl<'a> PagerProxy<'a> {
-    pub fn new(node: &'a mut Pager) -> Self {
-        Self(RefCell::new(node))
+    pub fn new(node: &'a mut Pager, root_page_num: u32) -> Self {
+        Self(RefCell::new(node), root_page_num)
     }
 }
 
@@ -158,23 +733,32 @@ impl<'a> fmt::Display for PagerProxy<'a> {
             Ok(())
         }
 
-        fn print_tree<'a>(
+        fn print_tree(
             f: &mut fmt::Formatter,
-            pager: &'a mut Pager,
+            pager: &mut Pager,
             page_num: u32,
             indentation_level: usize,
         ) -> fmt::Result {
-            let node = pager.get_page(page_num);
+            let node = match pager.read_page(page_num) {
+                Ok(node) => node,
+                Err(err) => {
+                    indent(f, indentation_level)?;
+                    return writeln!(f, "- {err}");
+                }
+            };
 
             match *node {
-                Node::Leaf { num_cells, .. } => {
+                Node::Leaf {
+                    num_cells,
+                    ref cells,
+                    ..
+                } => {
                     indent(f, indentation_level)?;
                     writeln!(f, "- leaf (size {num_cells})")?;
 
-                    for i in 0..num_cells {
+                    for cell in cells {
                         indent(f, indentation_level + 1)?;
-                        let leaf_node_key = node.leaf_node_key(i);
-                        writeln!(f, "- {leaf_node_key}")?;
+                        writeln!(f, "- {}", cell.key())?;
                     }
                 }
                 Node::Internal {
@@ -186,27 +770,81 @@ impl<'a> fmt::Display for PagerProxy<'a> {
                     writeln!(f, "- internal (size {num_keys})")?;
 
                     if num_keys > 0 {
-                        for i in 0..num_keys {
-                            let node = pager.get_page(page_num);
-                            let child_page_num = *node.internal_node_child(i);
+                        let children: Result<Vec<(u32, i64)>, CorruptErr> = (0..num_keys)
+                            .map(|i| {
+                                Ok((*node.internal_node_child(i)?, *node.internal_node_key(i)?))
+                            })
+                            .collect();
+                        let children = match children {
+                            Ok(children) => children,
+                            Err(err) => {
+                                indent(f, indentation_level + 1)?;
+                                return writeln!(f, "- {}", Error::from(err));
+                            }
+                        };
+                        for (child_page_num, internal_node_key) in children {
                             print_tree(f, pager, child_page_num, indentation_level + 1)?;
 
                             indent(f, indentation_level + 1)?;
-
-                            let node = pager.get_page(page_num);
-                            let internal_node_key = node.internal_node_key(i);
                             writeln!(f, "- key {}", internal_node_key)?;
                         }
                         print_tree(f, pager, right_child_pointer, indentation_level + 1)?;
                     }
                 }
+                Node::FreelistTrunk { .. } => {
+                    indent(f, indentation_level)?;
+                    writeln!(f, "- freelist trunk")?;
+                }
+                Node::Overflow { .. } => {
+                    indent(f, indentation_level)?;
+                    writeln!(f, "- overflow page")?;
+                }
+                Node::IndexLeaf { ref keys, .. } => {
+                    indent(f, indentation_level)?;
+                    writeln!(f, "- index leaf (size {})", keys.len())?;
+
+                    for key in keys {
+                        indent(f, indentation_level + 1)?;
+                        writeln!(f, "- {}", index_key(key))?;
+                    }
+                }
+                Node::IndexInternal {
+                    ref keys,
+                    ref children,
+                } => {
+                    indent(f, indentation_level)?;
+                    writeln!(f, "- index internal (size {})", keys.len())?;
+
+                    let keys: Vec<String> = keys.iter().map(|key| index_key(key)).collect();
+                    let children = children.clone();
+                    for (child_page_num, key) in children.iter().zip(keys) {
+                        print_tree(f, pager, *child_page_num, indentation_level + 1)?;
+
+                        indent(f, indentation_level + 1)?;
+                        writeln!(f, "- key {}", key)?;
+                    }
+                    if let Some(right_child_page_num) = children.last() {
+                        print_tree(f, pager, *right_child_page_num, indentation_level + 1)?;
+                    }
+                }
             }
 
             Ok(())
         }
 
+        // The values of an index key, in parentheses
+        fn index_key(key: &[u8]) -> String {
+            match Row::from_record(key) {
+                Ok(row) => {
+                    let values: Vec<_> = row.values.iter().map(Value::to_string).collect();
+                    format!("({})", values.join(", "))
+                }
+                Err(err) => err.to_string(),
+            }
+        }
+
         let mut pager = self.0.borrow_mut();
-        print_tree(f, &mut pager, 0, 0)?;
+        print_tree(f, &mut pager, self.1, 0)?;
 
         Ok(())
     }
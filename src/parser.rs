@@ -0,0 +1,816 @@
+use std::fmt;
+
+use crate::ast::{
+    BinaryOperator, ColumnDef, CreateIndex, CreateTable, Delete, DropIndex, DropTable, Expr,
+    ForeignKey, ForeignKeyAction, Insert, Literal, OrderingTerm, Pragma, ResultColumn, Select,
+    Statement, TypeName, UnaryOperator, Update,
+};
+use crate::lexer::{Keyword, Lexer, Span, SpannedToken, Token};
+
+#[derive(Debug, PartialEq)]
+pub struct ParseError {
+    pub message: String,
+    pub span: Span,
+}
+
+impl ParseError {
+    pub fn new(message: impl Into<String>, span: Span) -> Self {
+        Self {
+            message: message.into(),
+            span,
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "line {}, column {}: {}",
+            self.span.line, self.span.column, self.message
+        )
+    }
+}
+
+/// Largest number a `?NNN` parameter may have.
+pub const MAX_PARAMETER_NUMBER: usize = 32766;
+
+/// Recursive descent parser over the tokens of a single statement.
+pub struct Parser {
+    tokens: Vec<SpannedToken>,
+    position: usize,
+    // Name of each parameter seen so far, by number. `?` and `?NNN` have none.
+    parameters: Vec<Option<String>>,
+}
+
+impl Parser {
+    pub fn new(input: &str) -> Result<Self, ParseError> {
+        let tokens = Lexer::new(input).tokenize()?;
+        Ok(Self {
+            tokens,
+            position: 0,
+            parameters: Vec::new(),
+        })
+    }
+
+    /// Names of the parameters of the parsed statement, where the name of
+    /// parameter `n` is at index `n - 1`.
+    pub fn parameters(&self) -> &[Option<String>] {
+        &self.parameters
+    }
+
+    /// Parse one statement, optionally followed by a semicolon.
+    pub fn parse_statement(&mut self) -> Result<Statement, ParseError> {
+        let statement = match self.peek() {
+            Token::Keyword(Keyword::Select) => Statement::Select(self.parse_select()?),
+            Token::Keyword(Keyword::Insert) => Statement::Insert(self.parse_insert()?),
+            Token::Keyword(Keyword::Update) => Statement::Update(self.parse_update()?),
+            Token::Keyword(Keyword::Delete) => Statement::Delete(self.parse_delete()?),
+            Token::Keyword(Keyword::Create) => self.parse_create()?,
+            Token::Keyword(Keyword::Drop) => self.parse_drop()?,
+            Token::Keyword(Keyword::Pragma) => Statement::Pragma(self.parse_pragma()?),
+            Token::Keyword(Keyword::Vacuum) => Statement::Vacuum(self.parse_vacuum()?),
+            Token::Keyword(
+                Keyword::Begin
+                | Keyword::Commit
+                | Keyword::Rollback
+                | Keyword::Savepoint
+                | Keyword::Release,
+            ) => self.parse_transaction()?,
+            _ => return Err(self.unexpected("a statement")),
+        };
+
+        self.eat(&Token::Semicolon);
+        if *self.peek() != Token::Eof {
+            return Err(self.unexpected("end of statement"));
+        }
+
+        Ok(statement)
+    }
+
+    fn peek(&self) -> &Token {
+        &self.tokens[self.position].token
+    }
+
+    fn span(&self) -> Span {
+        self.tokens[self.position].span
+    }
+
+    fn advance(&mut self) -> Token {
+        let token = self.tokens[self.position].token.clone();
+        // Eof is never consumed, so peeking past the end is always safe
+        if token != Token::Eof {
+            self.position += 1;
+        }
+        token
+    }
+
+    fn unexpected(&self, expected: &str) -> ParseError {
+        ParseError::new(
+            format!("expected {}, found {}", expected, self.peek()),
+            self.span(),
+        )
+    }
+
+    // Consume the token if it is the expected one
+    fn eat(&mut self, expected: &Token) -> bool {
+        if self.peek() == expected {
+            self.advance();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn eat_keyword(&mut self, keyword: Keyword) -> bool {
+        self.eat(&Token::Keyword(keyword))
+    }
+
+    fn expect(&mut self, expected: &Token) -> Result<(), ParseError> {
+        if self.eat(expected) {
+            Ok(())
+        } else {
+            Err(self.unexpected(&expected.to_string()))
+        }
+    }
+
+    fn expect_keyword(&mut self, keyword: Keyword) -> Result<(), ParseError> {
+        self.expect(&Token::Keyword(keyword))
+    }
+
+    fn expect_identifier(&mut self) -> Result<String, ParseError> {
+        match self.peek() {
+            Token::Identifier(_) => match self.advance() {
+                Token::Identifier(identifier) => Ok(identifier),
+                _ => unreachable!(),
+            },
+            _ => Err(self.unexpected("an identifier")),
+        }
+    }
+
+    // Parse `item (, item)*`
+    fn parse_list<T>(
+        &mut self,
+        mut parse_item: impl FnMut(&mut Self) -> Result<T, ParseError>,
+    ) -> Result<Vec<T>, ParseError> {
+        let mut items = vec![parse_item(self)?];
+        while self.eat(&Token::Comma) {
+            items.push(parse_item(self)?);
+        }
+        Ok(items)
+    }
+
+    fn parse_select(&mut self) -> Result<Select, ParseError> {
+        self.expect_keyword(Keyword::Select)?;
+        let columns = self.parse_list(Self::parse_result_column)?;
+        // Without FROM there is one row of values, and nothing to filter,
+        // sort or limit
+        if !self.eat_keyword(Keyword::From) {
+            return Ok(Select {
+                columns,
+                table: None,
+                where_clause: None,
+                order_by: Vec::new(),
+                limit: None,
+                offset: None,
+            });
+        }
+        let table = Some(self.expect_identifier()?);
+        let where_clause = self.parse_where()?;
+
+        let order_by = if self.eat_keyword(Keyword::Order) {
+            self.expect_keyword(Keyword::By)?;
+            self.parse_list(Self::parse_ordering_term)?
+        } else {
+            Vec::new()
+        };
+
+        let (limit, offset) = if self.eat_keyword(Keyword::Limit) {
+            let limit = self.parse_expr()?;
+            let offset = if self.eat_keyword(Keyword::Offset) {
+                Some(self.parse_expr()?)
+            } else {
+                None
+            };
+            (Some(limit), offset)
+        } else {
+            (None, None)
+        };
+
+        Ok(Select {
+            columns,
+            table,
+            where_clause,
+            order_by,
+            limit,
+            offset,
+        })
+    }
+
+    fn parse_result_column(&mut self) -> Result<ResultColumn, ParseError> {
+        if self.eat(&Token::Star) {
+            Ok(ResultColumn::Wildcard)
+        } else {
+            Ok(ResultColumn::Expr(self.parse_expr()?))
+        }
+    }
+
+    fn parse_ordering_term(&mut self) -> Result<OrderingTerm, ParseError> {
+        let expr = self.parse_expr()?;
+        let descending = if self.eat_keyword(Keyword::Desc) {
+            true
+        } else {
+            self.eat_keyword(Keyword::Asc);
+            false
+        };
+        Ok(OrderingTerm { expr, descending })
+    }
+
+    fn parse_insert(&mut self) -> Result<Insert, ParseError> {
+        self.expect_keyword(Keyword::Insert)?;
+        self.expect_keyword(Keyword::Into)?;
+        let table = self.expect_identifier()?;
+
+        let columns = if self.eat(&Token::LeftParen) {
+            let columns = self.parse_list(Self::expect_identifier)?;
+            self.expect(&Token::RightParen)?;
+            Some(columns)
+        } else {
+            None
+        };
+
+        self.expect_keyword(Keyword::Values)?;
+        let rows = self.parse_list(|parser| {
+            parser.expect(&Token::LeftParen)?;
+            let values = parser.parse_list(Self::parse_expr)?;
+            parser.expect(&Token::RightParen)?;
+            Ok(values)
+        })?;
+
+        Ok(Insert {
+            table,
+            columns,
+            rows,
+        })
+    }
+
+    fn parse_update(&mut self) -> Result<Update, ParseError> {
+        self.expect_keyword(Keyword::Update)?;
+        let table = self.expect_identifier()?;
+        self.expect_keyword(Keyword::Set)?;
+
+        let assignments = self.parse_list(|parser| {
+            let column = parser.expect_identifier()?;
+            parser.expect(&Token::Equal)?;
+            let value = parser.parse_expr()?;
+            Ok((column, value))
+        })?;
+
+        let where_clause = self.parse_where()?;
+
+        Ok(Update {
+            table,
+            assignments,
+            where_clause,
+        })
+    }
+
+    fn parse_delete(&mut self) -> Result<Delete, ParseError> {
+        self.expect_keyword(Keyword::Delete)?;
+        self.expect_keyword(Keyword::From)?;
+        let table = self.expect_identifier()?;
+        let where_clause = self.parse_where()?;
+
+        Ok(Delete {
+            table,
+            where_clause,
+        })
+    }
+
+    // `CREATE TABLE name (column, ...)` or
+    // `CREATE [UNIQUE] INDEX [name] ON table (column, ...)`
+    fn parse_create(&mut self) -> Result<Statement, ParseError> {
+        self.expect_keyword(Keyword::Create)?;
+        let unique = self.eat_keyword(Keyword::Unique);
+        if unique || self.eat_keyword(Keyword::Index) {
+            if unique {
+                self.expect_keyword(Keyword::Index)?;
+            }
+            let name = match self.peek() {
+                Token::Identifier(_) => Some(self.expect_identifier()?),
+                _ => None,
+            };
+            self.expect_keyword(Keyword::On)?;
+            let table = self.expect_identifier()?;
+
+            self.expect(&Token::LeftParen)?;
+            let columns = self.parse_list(Self::expect_identifier)?;
+            self.expect(&Token::RightParen)?;
+
+            return Ok(Statement::CreateIndex(CreateIndex {
+                name,
+                table,
+                columns,
+                unique,
+            }));
+        }
+
+        self.expect_keyword(Keyword::Table)?;
+        let name = self.expect_identifier()?;
+
+        self.expect(&Token::LeftParen)?;
+        let columns = self.parse_list(Self::parse_column_def)?;
+        self.expect(&Token::RightParen)?;
+
+        Ok(Statement::CreateTable(CreateTable { name, columns }))
+    }
+
+    // `DROP TABLE [IF EXISTS] name` or `DROP INDEX [IF EXISTS] name`
+    fn parse_drop(&mut self) -> Result<Statement, ParseError> {
+        self.expect_keyword(Keyword::Drop)?;
+        let index = self.eat_keyword(Keyword::Index);
+        if !index {
+            self.expect_keyword(Keyword::Table)?;
+        }
+        let if_exists = self.eat_keyword(Keyword::If);
+        if if_exists {
+            self.expect_keyword(Keyword::Exists)?;
+        }
+        let name = self.expect_identifier()?;
+
+        if index {
+            Ok(Statement::DropIndex(DropIndex { name, if_exists }))
+        } else {
+            Ok(Statement::DropTable(DropTable { name, if_exists }))
+        }
+    }
+
+    // `BEGIN [TRANSACTION]`, `COMMIT [TRANSACTION]`,
+    // `ROLLBACK [TRANSACTION] [TO [SAVEPOINT] name]`,
+    // `SAVEPOINT name` or `RELEASE [SAVEPOINT] name`
+    fn parse_transaction(&mut self) -> Result<Statement, ParseError> {
+        let statement = match self.advance() {
+            Token::Keyword(Keyword::Begin) => {
+                self.eat_keyword(Keyword::Transaction);
+                Statement::Begin
+            }
+            Token::Keyword(Keyword::Commit) => {
+                self.eat_keyword(Keyword::Transaction);
+                Statement::Commit
+            }
+            Token::Keyword(Keyword::Rollback) => {
+                self.eat_keyword(Keyword::Transaction);
+                if self.eat_keyword(Keyword::To) {
+                    self.eat_keyword(Keyword::Savepoint);
+                    Statement::Rollback(Some(self.expect_identifier()?))
+                } else {
+                    Statement::Rollback(None)
+                }
+            }
+            Token::Keyword(Keyword::Savepoint) => Statement::Savepoint(self.expect_identifier()?),
+            Token::Keyword(Keyword::Release) => {
+                self.eat_keyword(Keyword::Savepoint);
+                Statement::Release(self.expect_identifier()?)
+            }
+            _ => unreachable!(),
+        };
+
+        Ok(statement)
+    }
+
+    fn parse_pragma(&mut self) -> Result<Pragma, ParseError> {
+        self.expect_keyword(Keyword::Pragma)?;
+        let name = self.expect_identifier()?;
+        // `PRAGMA name(value)` is the same as `PRAGMA name = value`
+        if self.eat(&Token::LeftParen) {
+            let value = self.parse_pragma_value()?;
+            self.expect(&Token::RightParen)?;
+            return Ok(Pragma {
+                name,
+                value: Some(value),
+            });
+        }
+        let value = if self.eat(&Token::Equal) {
+            Some(self.parse_pragma_value()?)
+        } else {
+            None
+        };
+
+        Ok(Pragma { name, value })
+    }
+
+    fn parse_pragma_value(&mut self) -> Result<Expr, ParseError> {
+        if let Token::Keyword(keyword) = self.peek() {
+            // Setting values such as DELETE may be keywords, read them as names
+            let name = keyword.to_string().to_lowercase();
+            self.advance();
+            Ok(Expr::Column(name))
+        } else {
+            self.parse_expr()
+        }
+    }
+
+    // `VACUUM [INTO 'filename']`
+    fn parse_vacuum(&mut self) -> Result<Option<String>, ParseError> {
+        self.expect_keyword(Keyword::Vacuum)?;
+        if !self.eat_keyword(Keyword::Into) {
+            return Ok(None);
+        }
+
+        match self.peek() {
+            Token::String(_) => match self.advance() {
+                Token::String(filename) => Ok(Some(filename)),
+                _ => unreachable!(),
+            },
+            _ => Err(self.unexpected("a file name")),
+        }
+    }
+
+    fn parse_column_def(&mut self) -> Result<ColumnDef, ParseError> {
+        let name = self.expect_identifier()?;
+
+        let type_name = if let Token::Identifier(_) = self.peek() {
+            let name = self.expect_identifier()?;
+            let mut arguments = Vec::new();
+            if self.eat(&Token::LeftParen) {
+                arguments = self.parse_list(|parser| match *parser.peek() {
+                    Token::Integer(integer) if integer <= i64::MAX as u64 => {
+                        parser.advance();
+                        Ok(integer as i64)
+                    }
+                    _ => Err(parser.unexpected("an integer")),
+                })?;
+                self.expect(&Token::RightParen)?;
+            }
+            Some(TypeName { name, arguments })
+        } else {
+            None
+        };
+
+        let mut column_def = ColumnDef {
+            name,
+            type_name,
+            primary_key: false,
+            autoincrement: false,
+            not_null: false,
+            unique: false,
+            default: None,
+            checks: Vec::new(),
+            references: None,
+        };
+
+        // Column constraints, in any order
+        loop {
+            match self.peek() {
+                Token::Keyword(Keyword::Primary) => {
+                    self.advance();
+                    self.expect_keyword(Keyword::Key)?;
+                    column_def.primary_key = true;
+                    column_def.autoincrement = self.eat_keyword(Keyword::Autoincrement);
+                }
+                Token::Keyword(Keyword::Not) => {
+                    self.advance();
+                    self.expect_keyword(Keyword::Null)?;
+                    column_def.not_null = true;
+                }
+                Token::Keyword(Keyword::Unique) => {
+                    self.advance();
+                    column_def.unique = true;
+                }
+                // `DEFAULT (expression)`, or a literal with an optional sign
+                Token::Keyword(Keyword::Default) => {
+                    self.advance();
+                    column_def.default = Some(self.parse_unary()?);
+                }
+                Token::Keyword(Keyword::Check) => {
+                    self.advance();
+                    self.expect(&Token::LeftParen)?;
+                    column_def.checks.push(self.parse_expr()?);
+                    self.expect(&Token::RightParen)?;
+                }
+                Token::Keyword(Keyword::References) => {
+                    column_def.references = Some(self.parse_foreign_key()?);
+                }
+                _ => return Ok(column_def),
+            }
+        }
+    }
+
+    // `REFERENCES table [(column)]` followed by `ON DELETE action`,
+    // `ON UPDATE action` and `DEFERRABLE [INITIALLY DEFERRED | IMMEDIATE]`
+    // in any order
+    fn parse_foreign_key(&mut self) -> Result<ForeignKey, ParseError> {
+        self.expect_keyword(Keyword::References)?;
+        let table = self.expect_identifier()?;
+        let mut column = None;
+        if self.eat(&Token::LeftParen) {
+            column = Some(self.expect_identifier()?);
+            self.expect(&Token::RightParen)?;
+        }
+
+        let mut foreign_key = ForeignKey {
+            table,
+            column,
+            on_delete: ForeignKeyAction::NoAction,
+            on_update: ForeignKeyAction::NoAction,
+            deferred: false,
+        };
+        loop {
+            if self.eat_keyword(Keyword::On) {
+                if self.eat_keyword(Keyword::Delete) {
+                    foreign_key.on_delete = self.parse_foreign_key_action()?;
+                } else if self.eat_keyword(Keyword::Update) {
+                    foreign_key.on_update = self.parse_foreign_key_action()?;
+                } else {
+                    return Err(self.unexpected("DELETE or UPDATE"));
+                }
+            } else if self.eat_keyword(Keyword::Deferrable) {
+                foreign_key.deferred = false;
+                if self.eat_keyword(Keyword::Initially) {
+                    if self.eat_keyword(Keyword::Deferred) {
+                        foreign_key.deferred = true;
+                    } else if !self.eat_keyword(Keyword::Immediate) {
+                        return Err(self.unexpected("DEFERRED or IMMEDIATE"));
+                    }
+                }
+            } else {
+                return Ok(foreign_key);
+            }
+        }
+    }
+
+    fn parse_foreign_key_action(&mut self) -> Result<ForeignKeyAction, ParseError> {
+        let action = match self.peek() {
+            Token::Keyword(Keyword::Set) => {
+                self.advance();
+                if self.eat_keyword(Keyword::Null) {
+                    ForeignKeyAction::SetNull
+                } else {
+                    self.expect_keyword(Keyword::Default)?;
+                    ForeignKeyAction::SetDefault
+                }
+            }
+            Token::Keyword(Keyword::Cascade) => {
+                self.advance();
+                ForeignKeyAction::Cascade
+            }
+            Token::Keyword(Keyword::Restrict) => {
+                self.advance();
+                ForeignKeyAction::Restrict
+            }
+            Token::Keyword(Keyword::No) => {
+                self.advance();
+                self.expect_keyword(Keyword::Action)?;
+                ForeignKeyAction::NoAction
+            }
+            _ => return Err(self.unexpected("a foreign key action")),
+        };
+        Ok(action)
+    }
+
+    fn parse_where(&mut self) -> Result<Option<Expr>, ParseError> {
+        if self.eat_keyword(Keyword::Where) {
+            Ok(Some(self.parse_expr()?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    // Expressions, from the loosest binding operator to the tightest:
+    // OR, AND, NOT, comparisons, + - ||, * / %, unary - +
+    pub fn parse_expr(&mut self) -> Result<Expr, ParseError> {
+        self.parse_or()
+    }
+
+    fn parse_or(&mut self) -> Result<Expr, ParseError> {
+        let mut expr = self.parse_and()?;
+        while self.eat_keyword(Keyword::Or) {
+            let right = self.parse_and()?;
+            expr = Expr::Binary(Box::new(expr), BinaryOperator::Or, Box::new(right));
+        }
+        Ok(expr)
+    }
+
+    fn parse_and(&mut self) -> Result<Expr, ParseError> {
+        let mut expr = self.parse_not()?;
+        while self.eat_keyword(Keyword::And) {
+            let right = self.parse_not()?;
+            expr = Expr::Binary(Box::new(expr), BinaryOperator::And, Box::new(right));
+        }
+        Ok(expr)
+    }
+
+    fn parse_not(&mut self) -> Result<Expr, ParseError> {
+        if self.eat_keyword(Keyword::Not) {
+            let expr = self.parse_not()?;
+            return Ok(Expr::Unary(UnaryOperator::Not, Box::new(expr)));
+        }
+        self.parse_comparison()
+    }
+
+    fn parse_comparison(&mut self) -> Result<Expr, ParseError> {
+        let mut expr = self.parse_additive()?;
+
+        loop {
+            let operator = match self.peek() {
+                Token::Equal => BinaryOperator::Equal,
+                Token::NotEqual => BinaryOperator::NotEqual,
+                Token::Less => BinaryOperator::Less,
+                Token::LessEqual => BinaryOperator::LessEqual,
+                Token::Greater => BinaryOperator::Greater,
+                Token::GreaterEqual => BinaryOperator::GreaterEqual,
+                Token::Keyword(Keyword::Is) => {
+                    self.advance();
+                    let negated = self.eat_keyword(Keyword::Not);
+                    self.expect_keyword(Keyword::Null)?;
+                    expr = Expr::IsNull {
+                        expr: Box::new(expr),
+                        negated,
+                    };
+                    continue;
+                }
+                Token::Keyword(Keyword::Not)
+                | Token::Keyword(Keyword::Like)
+                | Token::Keyword(Keyword::In)
+                | Token::Keyword(Keyword::Between) => {
+                    let negated = self.eat_keyword(Keyword::Not);
+                    expr = self.parse_postfix_predicate(expr, negated)?;
+                    continue;
+                }
+                _ => return Ok(expr),
+            };
+
+            self.advance();
+            let right = self.parse_additive()?;
+            expr = Expr::Binary(Box::new(expr), operator, Box::new(right));
+        }
+    }
+
+    // Parse the rest of `expr [NOT] LIKE | IN | BETWEEN ...` after the optional NOT
+    fn parse_postfix_predicate(&mut self, expr: Expr, negated: bool) -> Result<Expr, ParseError> {
+        let expr = Box::new(expr);
+
+        if self.eat_keyword(Keyword::Like) {
+            let pattern = Box::new(self.parse_additive()?);
+            Ok(Expr::Like {
+                expr,
+                pattern,
+                negated,
+            })
+        } else if self.eat_keyword(Keyword::In) {
+            self.expect(&Token::LeftParen)?;
+            let list = self.parse_list(Self::parse_expr)?;
+            self.expect(&Token::RightParen)?;
+            Ok(Expr::InList {
+                expr,
+                list,
+                negated,
+            })
+        } else if self.eat_keyword(Keyword::Between) {
+            let low = Box::new(self.parse_additive()?);
+            self.expect_keyword(Keyword::And)?;
+            let high = Box::new(self.parse_additive()?);
+            Ok(Expr::Between {
+                expr,
+                low,
+                high,
+                negated,
+            })
+        } else {
+            Err(self.unexpected("LIKE, IN or BETWEEN"))
+        }
+    }
+
+    fn parse_additive(&mut self) -> Result<Expr, ParseError> {
+        let mut expr = self.parse_multiplicative()?;
+        loop {
+            let operator = match self.peek() {
+                Token::Plus => BinaryOperator::Add,
+                Token::Minus => BinaryOperator::Subtract,
+                Token::Concat => BinaryOperator::Concat,
+                _ => return Ok(expr),
+            };
+            self.advance();
+            let right = self.parse_multiplicative()?;
+            expr = Expr::Binary(Box::new(expr), operator, Box::new(right));
+        }
+    }
+
+    fn parse_multiplicative(&mut self) -> Result<Expr, ParseError> {
+        let mut expr = self.parse_unary()?;
+        loop {
+            let operator = match self.peek() {
+                Token::Star => BinaryOperator::Multiply,
+                Token::Slash => BinaryOperator::Divide,
+                Token::Percent => BinaryOperator::Modulo,
+                _ => return Ok(expr),
+            };
+            self.advance();
+            let right = self.parse_unary()?;
+            expr = Expr::Binary(Box::new(expr), operator, Box::new(right));
+        }
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr, ParseError> {
+        if self.eat(&Token::Minus) {
+            // A negated integer literal is folded into a negative literal,
+            // which lets -9223372036854775808 be an integer
+            if let Token::Integer(integer) = *self.peek() {
+                if let Some(negated) = 0i64.checked_sub_unsigned(integer) {
+                    self.advance();
+                    return Ok(Expr::Literal(Literal::Integer(negated)));
+                }
+            }
+            let expr = self.parse_unary()?;
+            return Ok(Expr::Unary(UnaryOperator::Negate, Box::new(expr)));
+        }
+        if self.eat(&Token::Plus) {
+            let expr = self.parse_unary()?;
+            return Ok(Expr::Unary(UnaryOperator::Plus, Box::new(expr)));
+        }
+        self.parse_primary()
+    }
+
+    fn parse_primary(&mut self) -> Result<Expr, ParseError> {
+        let expr = match self.peek() {
+            Token::Integer(integer) => match i64::try_from(*integer) {
+                Ok(integer) => Expr::Literal(Literal::Integer(integer)),
+                Err(_) => Expr::Literal(Literal::Float(*integer as f64)),
+            },
+            Token::Float(float) => Expr::Literal(Literal::Float(*float)),
+            Token::String(string) => Expr::Literal(Literal::String(string.clone())),
+            Token::Blob(blob) => Expr::Literal(Literal::Blob(blob.clone())),
+            Token::Parameter(parameter) => {
+                let parameter = parameter.clone();
+                Expr::Parameter(self.parameter_number(&parameter)?)
+            }
+            Token::Keyword(Keyword::Null) => Expr::Literal(Literal::Null),
+            Token::Identifier(identifier) => {
+                let identifier = identifier.clone();
+                self.advance();
+                if !self.eat(&Token::LeftParen) {
+                    return Ok(Expr::Column(identifier));
+                }
+                let args = if self.eat(&Token::RightParen) {
+                    Vec::new()
+                } else {
+                    let args = self.parse_list(Self::parse_expr)?;
+                    self.expect(&Token::RightParen)?;
+                    args
+                };
+                return Ok(Expr::Function {
+                    name: identifier,
+                    args,
+                });
+            }
+            Token::LeftParen => {
+                self.advance();
+                let expr = self.parse_expr()?;
+                self.expect(&Token::RightParen)?;
+                return Ok(expr);
+            }
+            _ => return Err(self.unexpected("an expression")),
+        };
+        self.advance();
+        Ok(expr)
+    }
+
+    // `?` is numbered one past the largest number so far, `?NNN` is NNN,
+    // and a `:name` used again keeps the number it had the first time
+    fn parameter_number(&mut self, parameter: &str) -> Result<usize, ParseError> {
+        if let Some(digits) = parameter
+            .strip_prefix('?')
+            .filter(|digits| !digits.is_empty())
+        {
+            let number = digits
+                .parse::<usize>()
+                .ok()
+                .filter(|number| (1..=MAX_PARAMETER_NUMBER).contains(number))
+                .ok_or_else(|| {
+                    ParseError::new(
+                        format!(
+                            "parameter {} is not between 1 and {}",
+                            parameter, MAX_PARAMETER_NUMBER
+                        ),
+                        self.span(),
+                    )
+                })?;
+            if number > self.parameters.len() {
+                self.parameters.resize(number, None);
+            }
+            return Ok(number);
+        }
+
+        let name = (parameter != "?").then(|| parameter.to_owned());
+        if name.is_some() {
+            if let Some(index) = self.parameters.iter().position(|known| *known == name) {
+                return Ok(index + 1);
+            }
+        }
+        if self.parameters.len() == MAX_PARAMETER_NUMBER {
+            return Err(ParseError::new("too many parameters", self.span()));
+        }
+        self.parameters.push(name);
+        Ok(self.parameters.len())
+    }
+}
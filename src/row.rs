@@ -1,117 +1,150 @@
-use std::{fmt, str::FromStr};
+use crate::error::{CorruptErr, Error};
+use crate::schema::TableSchema;
+use crate::value::Value;
+use crate::varint::{put_varint, read_varint, varint_size};
 
-use crate::statement::PrepareStatementErr;
-
-pub const ID_SIZE: usize = size_of::<u32>();
-pub const USERNAME_SIZE: usize = 32;
-pub const EMAIL_SIZE: usize = 255;
-pub const ROW_SIZE: usize = ID_SIZE + USERNAME_SIZE + EMAIL_SIZE;
-
-#[derive(Clone)]
+/// Values of a row, one per column of its table's schema.
+#[derive(Clone, Debug, PartialEq)]
 pub struct Row {
-    pub id: u32,
-    username: [u8; USERNAME_SIZE],
-    email: [u8; EMAIL_SIZE],
+    pub values: Vec<Value>,
 }
 
 impl Row {
-    pub fn new<'a>(id: &str, username: &str, email: &str) -> Result<Self, PrepareStatementErr<'a>> {
-        let id = id
-            .parse::<u32>()
-            .map_err(|_| PrepareStatementErr::InvalidID)?;
-
-        let username_bytes = username.as_bytes();
-        let mut username = [0; USERNAME_SIZE];
-        username[..username_bytes.len()].copy_from_slice(username_bytes);
-
-        let email_bytes = email.as_bytes();
-        let mut email = [0; EMAIL_SIZE];
-        email[..email_bytes.len()].copy_from_slice(email_bytes);
-
-        Ok(Self {
-            id,
-            username,
-            email,
-        })
+    pub fn new(values: Vec<Value>) -> Self {
+        Self { values }
     }
 
-    pub fn username(&self) -> String {
-        String::from_utf8_lossy(&self.username)
-            .trim_end_matches(char::from(0))
-            .to_string()
+    /// The row's key in the table B-tree, the value of its primary key column.
+    pub fn key(&self, schema: &TableSchema) -> i64 {
+        match self.values[schema.key_column()] {
+            Value::Integer(key) => key,
+            _ => panic!("key: primary key is not an integer"),
+        }
     }
 
-    pub fn email(&self) -> String {
-        String::from_utf8_lossy(&self.email)
-            .trim_end_matches(char::from(0))
-            .to_string()
+    /// Value of a column by name, `None` if there is no such column.
+    pub fn column_value(&self, schema: &TableSchema, name: &str) -> Option<Value> {
+        let index = schema.column_index(name)?;
+        Some(self.values[index].clone())
     }
 
-    pub fn serialize(&self, row: &mut [u8]) {
-        let id_bytes = self.id.to_le_bytes();
-
-        let start = 0;
-        let end = ID_SIZE;
-        row[start..end].copy_from_slice(&id_bytes);
+    /// Encode the row as a record: a header of the record's size and the
+    /// serial type of every value, all varints, then the values themselves.
+    pub fn serialize(&self) -> Vec<u8> {
+        let serial_types: Vec<u64> = self.values.iter().map(serial_type).collect();
+        let types_size: usize = serial_types.iter().map(|&t| varint_size(t)).sum();
+        // The header size counts the varint holding it
+        let mut header_size = types_size + 1;
+        while types_size + varint_size(header_size as u64) != header_size {
+            header_size = types_size + varint_size(header_size as u64);
+        }
 
-        let start = end;
-        let end = start + USERNAME_SIZE;
-        row[start..end].copy_from_slice(&self.username);
+        let mut record = Vec::new();
+        put_varint(&mut record, header_size as u64);
+        for &serial_type in serial_types.iter() {
+            put_varint(&mut record, serial_type);
+        }
+        for (value, serial_type) in self.values.iter().zip(serial_types) {
+            match value {
+                Value::Null => {}
+                Value::Integer(integer) => {
+                    let size = serial_type_size(serial_type);
+                    record.extend_from_slice(&integer.to_be_bytes()[8 - size..]);
+                }
+                Value::Real(real) => record.extend_from_slice(&real.to_be_bytes()),
+                Value::Text(text) => record.extend_from_slice(text.as_bytes()),
+                Value::Blob(blob) => record.extend_from_slice(blob),
+            }
+        }
 
-        let start = end;
-        let end = start + EMAIL_SIZE;
-        row[start..end].copy_from_slice(&self.email);
+        record
     }
 
-    pub fn deserialize(row: &[u8]) -> Self {
-        let start = 0;
-        let end = ID_SIZE;
-        let mut id_bytes = [0; ID_SIZE];
-        id_bytes.copy_from_slice(&row[start..end]);
-        let id = u32::from_le_bytes(id_bytes);
-
-        let start = end;
-        let end = start + USERNAME_SIZE;
-        let mut username = [0; USERNAME_SIZE];
-        username.copy_from_slice(&row[start..end]);
-
-        let start = end;
-        let end = start + EMAIL_SIZE;
-        let mut email = [0; EMAIL_SIZE];
-        email.copy_from_slice(&row[start..end]);
-
-        Self {
-            id,
-            username,
-            email,
+    pub fn deserialize(schema: &TableSchema, record: &[u8]) -> Result<Self, Error> {
+        let row = Self::from_record(record)?;
+        if row.values.len() != schema.columns.len() {
+            return Err(CorruptErr::Invalid("Record has the wrong number of columns").into());
         }
+        Ok(row)
     }
-}
 
-impl FromStr for Row {
-    type Err = PrepareStatementErr<'static>;
-
-    fn from_str(row: &str) -> Result<Self, Self::Err> {
-        let columns: Vec<&str> = row.split_whitespace().collect();
-        match columns[..] {
-            [id, username, email] => {
-                if username.len() > USERNAME_SIZE {
-                    return Err(PrepareStatementErr::StringTooLong);
+    /// Decode a record holding any number of values, such as an index key.
+    pub fn from_record(record: &[u8]) -> Result<Self, Error> {
+        let corrupt = || CorruptErr::Invalid("Malformed record");
+        let (header_size, mut header_start) = read_varint(record).ok_or_else(corrupt)?;
+        let header_size = usize::try_from(header_size)
+            .ok()
+            .filter(|size| *size <= record.len())
+            .ok_or_else(corrupt)?;
+
+        let mut values = Vec::new();
+        let mut body_start = header_size;
+        while header_start < header_size {
+            let (serial_type, size) =
+                read_varint(&record[header_start..header_size]).ok_or_else(corrupt)?;
+            header_start += size;
+
+            let body_end = body_start
+                .checked_add(serial_type_size(serial_type))
+                .filter(|end| *end <= record.len())
+                .ok_or_else(corrupt)?;
+            let body = &record[body_start..body_end];
+            body_start = body_end;
+
+            let value = match serial_type {
+                0 => Value::Null,
+                1..=6 => {
+                    // Sign-extend the big-endian integer to eight bytes
+                    let fill = if body[0] & 0x80 != 0 { 0xff } else { 0 };
+                    let mut bytes = [fill; 8];
+                    bytes[8 - body.len()..].copy_from_slice(body);
+                    Value::Integer(i64::from_be_bytes(bytes))
                 }
+                7 => Value::Real(f64::from_be_bytes(body.try_into().map_err(|_| corrupt())?)),
+                8 => Value::Integer(0),
+                9 => Value::Integer(1),
+                10 | 11 => return Err(corrupt().into()),
+                _ if serial_type % 2 == 0 => Value::Blob(body.to_vec()),
+                _ => Value::Text(String::from_utf8_lossy(body).into_owned()),
+            };
+            values.push(value);
+        }
 
-                if email.len() > EMAIL_SIZE {
-                    return Err(PrepareStatementErr::StringTooLong);
-                }
+        Ok(Self { values })
+    }
+}
 
-                Self::new(id, username, email)
+// Serial types give the type of a value and its size in the record body:
+// 0 is NULL; 1 to 6 integers of 1, 2, 3, 4, 6 and 8 bytes; 7 a real; 8 and
+// 9 the integers 0 and 1, which take no bytes; an even number from 12 a
+// blob of (n - 12) / 2 bytes; and an odd number from 13 text of (n - 13) / 2 bytes.
+fn serial_type(value: &Value) -> u64 {
+    match value {
+        Value::Null => 0,
+        Value::Integer(0) => 8,
+        Value::Integer(1) => 9,
+        Value::Integer(integer) => {
+            let fits = |bytes: u32| {
+                let bound = 1i64 << (8 * bytes - 1);
+                (-bound..bound).contains(integer)
+            };
+            match [1, 2, 3, 4, 6].iter().position(|&bytes| fits(bytes)) {
+                Some(index) => index as u64 + 1,
+                None => 6,
             }
-            _ => Err(PrepareStatementErr::SyntaxError),
         }
+        Value::Real(_) => 7,
+        Value::Text(text) => 13 + 2 * text.len() as u64,
+        Value::Blob(blob) => 12 + 2 * blob.len() as u64,
     }
 }
 
-impl fmt::Display for Row {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "({}, {}, {})", self.id, self.username(), self.email())
+fn serial_type_size(serial_type: u64) -> usize {
+    match serial_type {
+        0 | 8 | 9 | 10 | 11 => 0,
+        1..=4 => serial_type as usize,
+        5 => 6,
+        6 | 7 => 8,
+        _ => ((serial_type - 12) / 2) as usize,
     }
 }
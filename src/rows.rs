@@ -0,0 +1,273 @@
+use std::cell::RefCell;
+use std::iter::{Skip, Take};
+use std::ops::Bound;
+
+use crate::cursor::{Cursor, CursorPosition};
+use crate::database::Database;
+use crate::error::{CorruptErr, Error};
+use crate::eval::evaluate;
+use crate::index::is_before;
+use crate::row::Row;
+use crate::sorter::SortedRows;
+use crate::statement::{ExecuteOk, Filter, Select};
+use crate::value::Value;
+
+/// Position of a scan over the rows of a table matched by a filter, in key
+/// order, or in the order of the index the filter looks rows up in. The
+/// table can change between rows: the scan then seeks the next key to look
+/// at again, instead of picking up its cursor where it left off.
+pub struct Scan {
+    next_key: Option<i64>,
+    // Cursor at the last row returned, while the database is unchanged
+    position: Option<ScanPosition>,
+    // Start of the index keys left to look at
+    next_index_key: Option<Bound<Vec<Value>>>,
+}
+
+struct ScanPosition {
+    cursor: CursorPosition,
+    schema_cookie: u32,
+    change_counter: u64,
+}
+
+impl Scan {
+    pub fn new(filter: &Filter) -> Self {
+        Self {
+            next_key: filter.key_range.first_key(),
+            position: None,
+            next_index_key: filter
+                .index_range
+                .as_ref()
+                .map(|index_range| index_range.start.clone()),
+        }
+    }
+
+    pub fn next_row(
+        &mut self,
+        filter: &Filter,
+        database: &mut Database,
+    ) -> Result<Option<Row>, Error> {
+        if filter.index_range.is_some() {
+            return self.next_index_row(filter, database);
+        }
+        let Some(next_key) = self.next_key else {
+            return Ok(None);
+        };
+
+        let schema_cookie = database.pager.header.schema_cookie;
+        let change_counter = database.pager.change_counter;
+        let position = self.position.take().filter(|position| {
+            position.schema_cookie == schema_cookie && position.change_counter == change_counter
+        });
+
+        let context = database.context();
+        let table = database.table(filter.table.root_page_num);
+        let mut cursor = match position {
+            Some(position) => {
+                let mut cursor = Cursor::table_at(table, position.cursor);
+                cursor.advance()?;
+                cursor
+            }
+            None => Cursor::table_seek(table, next_key)?,
+        };
+        while !cursor.end_of_table {
+            let key = cursor.key()?;
+            if !filter.key_range.contains(key) {
+                break;
+            }
+
+            let row = Row::deserialize(&filter.table.schema, &cursor.value()?)?;
+            self.next_key = key.checked_add(1);
+            if filter.matches(&row, context) {
+                self.position = Some(ScanPosition {
+                    cursor: cursor.position(),
+                    schema_cookie,
+                    change_counter,
+                });
+                return Ok(Some(row));
+            }
+            if self.next_key.is_none() {
+                return Ok(None);
+            }
+            cursor.advance()?;
+        }
+
+        self.next_key = None;
+        Ok(None)
+    }
+
+    // The next row found through the filter's index. The last value of an
+    // index key is the key of its row.
+    fn next_index_row(
+        &mut self,
+        filter: &Filter,
+        database: &mut Database,
+    ) -> Result<Option<Row>, Error> {
+        let Some(index_range) = &filter.index_range else {
+            return Ok(None);
+        };
+        while let Some(start) = self.next_index_key.take() {
+            let mut index = database.index(index_range.index.root_page_num);
+            let Some(index_key) = index.seek(start.as_ref().map(Vec::as_slice))? else {
+                return Ok(None);
+            };
+            if !is_before(&index_key, index_range.end.as_ref().map(Vec::as_slice)) {
+                return Ok(None);
+            }
+
+            let Some(&Value::Integer(key)) = index_key.last() else {
+                return Err(CorruptErr::Invalid("Index key has an invalid row key").into());
+            };
+            let table = database.table(filter.table.root_page_num);
+            let mut cursor = Cursor::table_find(table, key)?;
+            if !cursor.is_at_key(key)? {
+                return Err(CorruptErr::Invalid("Index has the key of a missing row").into());
+            }
+
+            let row = Row::deserialize(&filter.table.schema, &cursor.value()?)?;
+            self.next_index_key = Some(Bound::Excluded(index_key));
+            if filter.matches(&row, database.context()) {
+                return Ok(Some(row));
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// Position in the rows of a select without an ORDER BY. The rows come
+/// out of the table in order, so the scan stops once the limit is reached.
+pub struct SelectScan {
+    scan: Scan,
+    to_skip: usize,
+    remaining: Option<usize>,
+}
+
+impl SelectScan {
+    pub fn new(select: &Select) -> Self {
+        Self {
+            scan: Scan::new(&select.filter),
+            to_skip: select.offset,
+            remaining: select.limit,
+        }
+    }
+
+    pub fn next_row(
+        &mut self,
+        select: &Select,
+        database: &mut Database,
+    ) -> Result<Option<Vec<Value>>, Error> {
+        if self.remaining == Some(0) {
+            return Ok(None);
+        }
+        while let Some(row) = self.scan.next_row(&select.filter, database)? {
+            if self.to_skip > 0 {
+                self.to_skip -= 1;
+                continue;
+            }
+            self.remaining = self.remaining.map(|remaining| remaining - 1);
+            return Ok(Some(select.project(&row, database.context())));
+        }
+        Ok(None)
+    }
+}
+
+/// Sort the rows of a select with an ORDER BY, skipping the offset and
+/// stopping at the limit.
+pub fn sort_rows(
+    select: &Select,
+    database: &mut Database,
+) -> Result<Take<Skip<SortedRows>>, Error> {
+    let mut sorter = select.sorter(database.pager.filename());
+    let mut scan = Scan::new(&select.filter);
+    while let Some(row) = scan.next_row(&select.filter, database)? {
+        let context = database.context();
+        let keys = select
+            .order_by
+            .iter()
+            .map(|(expr, _)| evaluate(expr, &select.filter.table.schema, &row, context))
+            .collect();
+        sorter.add(keys, select.project(&row, context))?;
+    }
+
+    let limit = select.limit.unwrap_or(usize::MAX);
+    Ok(sorter.finish()?.skip(select.offset).take(limit))
+}
+
+/// Rows returned by a statement, each a list of values for the result columns.
+/// The rows of a select without an ORDER BY are read from the table as
+/// they are iterated; sorted rows are read back from the sorter.
+pub struct Rows<'a> {
+    source: Source<'a>,
+    rows_changed: Option<usize>,
+}
+
+enum Source<'a> {
+    Scan {
+        select: &'a Select,
+        database: &'a RefCell<Database>,
+        scan: SelectScan,
+    },
+    Sorted(Take<Skip<SortedRows>>),
+    Values(std::vec::IntoIter<Vec<Value>>),
+}
+
+impl<'a> Rows<'a> {
+    /// Run a select, sorting its rows first if it has an ORDER BY.
+    pub fn select(select: &'a Select, database: &'a RefCell<Database>) -> Result<Self, Error> {
+        let source = if select.order_by.is_empty() {
+            Source::Scan {
+                select,
+                database,
+                scan: SelectScan::new(select),
+            }
+        } else {
+            Source::Sorted(sort_rows(select, &mut database.borrow_mut())?)
+        };
+        Ok(Self {
+            source,
+            rows_changed: None,
+        })
+    }
+
+    /// Number of rows an UPDATE or DELETE changed, `None` for other statements.
+    pub fn rows_changed(&self) -> Option<usize> {
+        self.rows_changed
+    }
+}
+
+impl From<ExecuteOk> for Rows<'_> {
+    fn from(ok: ExecuteOk) -> Self {
+        let (rows, rows_changed) = match ok {
+            ExecuteOk::Executed => (Vec::new(), None),
+            ExecuteOk::RowsChanged(rows_changed) => (Vec::new(), Some(rows_changed)),
+            ExecuteOk::Rows(rows) => (rows, None),
+        };
+        Self {
+            source: Source::Values(rows.into_iter()),
+            rows_changed,
+        }
+    }
+}
+
+impl Iterator for Rows<'_> {
+    type Item = Result<Vec<Value>, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let row = match &mut self.source {
+            Source::Scan {
+                select,
+                database,
+                scan,
+            } => scan
+                .next_row(select, &mut database.borrow_mut())
+                .transpose(),
+            Source::Sorted(rows) => rows.next().map(|values| values.map_err(Error::from)),
+            Source::Values(rows) => rows.next().map(Ok),
+        };
+        // No rows follow an error
+        if let Some(Err(_)) = row {
+            self.source = Source::Values(Vec::new().into_iter());
+        }
+        row
+    }
+}
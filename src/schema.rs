@@ -0,0 +1,522 @@
+use crate::ast::{self, Expr, ForeignKey, ForeignKeyAction};
+use crate::catalog::{CATALOG_NAME_MAX_SIZE, CATALOG_SQL_MAX_SIZE};
+use crate::error::ConstraintErr;
+use crate::eval::{evaluate, Context};
+use crate::node::INDEX_MAX_KEY_SIZE;
+use crate::parser::Parser;
+use crate::row::Row;
+use crate::statement::{check_columns, PrepareStatementErr};
+use crate::value::Value;
+use crate::varint::{varint_size, VARINT_MAX_SIZE};
+
+// Longest record of a row, SQLite's default limit on the length of a text
+// or blob, and so the longest text or blob of a column declared without a
+// length. The part of a record that does not fit in its leaf cell is kept
+// on overflow pages.
+pub const MAX_RECORD_SIZE: usize = 1_000_000_000;
+
+// Name of the index backing the UNIQUE constraints of a table, followed
+// by the table's name and a number counting them from 1, as in SQLite
+const AUTOINDEX_PREFIX: &str = "sqlite_autoindex_";
+
+/// Type a column prefers for the values stored in it, picked from its
+/// declared type the way SQLite does.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Affinity {
+    /// Text that spells a number is stored as that number, integral reals as integers
+    Integer,
+    /// Text that spells a number is stored as that number, integers as reals
+    Real,
+    /// Text that spells a number is stored as that number
+    Numeric,
+    /// Numbers are stored as text
+    Text,
+    /// Values are stored as they are
+    Blob,
+}
+
+impl Affinity {
+    /// Affinity of a declared type: names containing INT are integers;
+    /// CHAR, CLOB or TEXT text; BLOB or no type blobs; REAL, FLOA or DOUB
+    /// reals; and anything else numeric.
+    pub fn from_type_name(name: &str) -> Self {
+        let name = name.to_uppercase();
+        let contains = |parts: &[&str]| parts.iter().any(|part| name.contains(part));
+        if contains(&["INT"]) {
+            Affinity::Integer
+        } else if contains(&["CHAR", "CLOB", "TEXT"]) {
+            Affinity::Text
+        } else if name.is_empty() || contains(&["BLOB"]) {
+            Affinity::Blob
+        } else if contains(&["REAL", "FLOA", "DOUB"]) {
+            Affinity::Real
+        } else {
+            Affinity::Numeric
+        }
+    }
+
+    pub fn is_numeric(self) -> bool {
+        matches!(self, Affinity::Integer | Affinity::Real | Affinity::Numeric)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Column {
+    pub name: String,
+    /// Type as written in CREATE TABLE, e.g. `VARCHAR(32)`, empty if none was given
+    pub declared_type: String,
+    pub affinity: Affinity,
+    /// Longest text or blob the column holds, in bytes
+    pub max_size: usize,
+    pub primary_key: bool,
+    /// Keys of deleted rows are never used again, see `Database::next_key`
+    pub autoincrement: bool,
+    pub not_null: bool,
+    /// No two rows may have the same value, see `TableSchema::unique_indexes`
+    pub unique: bool,
+    /// Value of the column when an insert leaves it out, NULL if there is none
+    pub default: Option<Expr>,
+    /// Conditions a row must not make false
+    pub checks: Vec<Expr>,
+    /// Parent table and column the values of the column must be found in
+    pub references: Option<ForeignKey>,
+}
+
+impl Column {
+    // Largest serial type of a value of the column, that of its longest text
+    fn max_serial_type(&self) -> u64 {
+        (self.max_size as u64).saturating_mul(2).saturating_add(13)
+    }
+
+    /// Whether the column's text and blobs are only limited by the size of
+    /// a record, as when it is declared without a length.
+    pub fn is_unbounded(&self) -> bool {
+        self.max_size == MAX_RECORD_SIZE
+    }
+}
+
+/// Columns of a table. Rows are stored as records with the columns in
+/// order, see `Row::serialize`, keyed by the `INTEGER PRIMARY KEY` column.
+#[derive(Clone, Debug)]
+pub struct TableSchema {
+    pub name: String,
+    pub columns: Vec<Column>,
+}
+
+impl TableSchema {
+    /// A schema without columns, for expressions evaluated outside of a table.
+    pub fn empty() -> Self {
+        Self {
+            name: String::new(),
+            columns: Vec::new(),
+        }
+    }
+
+    pub fn from_create_table(create: ast::CreateTable) -> Result<Self, PrepareStatementErr> {
+        if create.name.len() > CATALOG_NAME_MAX_SIZE {
+            return Err(PrepareStatementErr::NameTooLong(create.name));
+        }
+
+        let mut columns: Vec<Column> = Vec::new();
+
+        for column_def in create.columns {
+            if columns
+                .iter()
+                .any(|column| column.name.eq_ignore_ascii_case(&column_def.name))
+            {
+                return Err(PrepareStatementErr::DuplicateColumn(column_def.name));
+            }
+
+            let (declared_type, affinity, max_size) = match column_def.type_name {
+                Some(type_name) => {
+                    let declared_type = declared_type(&type_name);
+                    let affinity = Affinity::from_type_name(&type_name.name);
+                    let Some(max_size) = max_size(&type_name, affinity) else {
+                        return Err(PrepareStatementErr::UnsupportedType(declared_type));
+                    };
+                    (declared_type, affinity, max_size)
+                }
+                None => (String::new(), Affinity::Blob, MAX_RECORD_SIZE),
+            };
+
+            if column_def.primary_key {
+                if affinity != Affinity::Integer {
+                    return Err(PrepareStatementErr::InvalidPrimaryKey(column_def.name));
+                }
+                if columns.iter().any(|column| column.primary_key) {
+                    return Err(PrepareStatementErr::MultiplePrimaryKeys);
+                }
+            }
+
+            if let Some(default) = &column_def.default {
+                if !is_constant(default) {
+                    return Err(PrepareStatementErr::NotConstant(column_def.name));
+                }
+            }
+
+            columns.push(Column {
+                name: column_def.name,
+                declared_type,
+                affinity,
+                max_size,
+                primary_key: column_def.primary_key,
+                autoincrement: column_def.autoincrement,
+                not_null: column_def.not_null,
+                unique: column_def.unique,
+                default: column_def.default,
+                checks: column_def.checks,
+                references: column_def.references,
+            });
+        }
+
+        if !columns.iter().any(|column| column.primary_key) {
+            return Err(PrepareStatementErr::MissingPrimaryKey);
+        }
+
+        let schema = Self {
+            name: create.name,
+            columns,
+        };
+        // A check can refer to any column of the table
+        for column in schema.columns.iter() {
+            for check in column.checks.iter() {
+                check_columns(&schema, check)?;
+            }
+        }
+        if schema.max_record_size() > MAX_RECORD_SIZE {
+            return Err(PrepareStatementErr::RowTooLarge(schema.max_record_size()));
+        }
+        if schema.to_sql().len() > CATALOG_SQL_MAX_SIZE {
+            return Err(PrepareStatementErr::SchemaTooLarge);
+        }
+        for index in schema.unique_indexes() {
+            index.validate(&schema)?;
+        }
+
+        Ok(schema)
+    }
+
+    /// Parse a schema stored as its CREATE TABLE statement.
+    /// `None` if the statement is not a valid table definition.
+    pub fn from_sql(sql: &str) -> Option<Self> {
+        let statement = Parser::new(sql).and_then(|mut parser| parser.parse_statement());
+        let Ok(ast::Statement::CreateTable(create)) = statement else {
+            return None;
+        };
+        Self::from_create_table(create).ok()
+    }
+
+    pub fn column_index(&self, name: &str) -> Option<usize> {
+        self.columns
+            .iter()
+            .position(|column| column.name.eq_ignore_ascii_case(name))
+    }
+
+    /// Index of the column whose values are the keys of the table's B-tree.
+    pub fn key_column(&self) -> usize {
+        self.columns
+            .iter()
+            .position(|column| column.primary_key)
+            .expect("key_column: a table always has a primary key")
+    }
+
+    pub fn key_column_name(&self) -> String {
+        self.columns[self.key_column()].name.clone()
+    }
+
+    /// The columns with a foreign key, by position, along with it.
+    pub fn foreign_keys(&self) -> impl Iterator<Item = (usize, &ForeignKey)> {
+        self.columns
+            .iter()
+            .enumerate()
+            .filter_map(|(index, column)| Some((index, column.references.as_ref()?)))
+    }
+
+    /// Column of this table a foreign key references, the primary key
+    /// unless it names another. `None` if there is no such column.
+    pub fn referenced_column(&self, foreign_key: &ForeignKey) -> Option<usize> {
+        match &foreign_key.column {
+            Some(name) => self.column_index(name),
+            None => Some(self.key_column()),
+        }
+    }
+
+    /// Check the NOT NULL and CHECK constraints of the columns against a row.
+    pub fn check_row(&self, row: &Row, context: Context) -> Result<(), ConstraintErr> {
+        for (column, value) in self.columns.iter().zip(row.values.iter()) {
+            if column.not_null && value.is_null() {
+                return Err(ConstraintErr::NotNull(self.column_name(column)));
+            }
+        }
+        // A check that is NULL passes, only false fails
+        for column in self.columns.iter() {
+            for check in column.checks.iter() {
+                if evaluate(check, self, row, context).to_bool() == Some(false) {
+                    return Err(ConstraintErr::Check(self.column_name(column)));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The indexes that enforce the UNIQUE constraints of the columns,
+    /// created along with the table. The key column needs none.
+    pub fn unique_indexes(&self) -> Vec<IndexSchema> {
+        self.columns
+            .iter()
+            .enumerate()
+            .filter(|(_, column)| column.unique && !column.primary_key)
+            .enumerate()
+            .map(|(n, (index, _))| IndexSchema {
+                name: format!("{}{}_{}", AUTOINDEX_PREFIX, self.name, n + 1),
+                table_name: self.name.clone(),
+                columns: vec![index],
+                unique: true,
+            })
+            .collect()
+    }
+
+    /// Name of a column after the table's name, as in `users.email`.
+    pub fn column_name(&self, column: &Column) -> String {
+        format!("{}.{}", self.name, column.name)
+    }
+
+    /// Size of the record of a row with the longest values the columns of
+    /// a declared length hold, see `max_record_size`.
+    pub fn max_record_size(&self) -> usize {
+        max_record_size(self.columns.iter())
+    }
+
+    /// The CREATE TABLE statement that recreates this schema.
+    pub fn to_sql(&self) -> String {
+        let columns: Vec<_> = self
+            .columns
+            .iter()
+            .map(|column| {
+                let mut definition = column.name.clone();
+                if !column.declared_type.is_empty() {
+                    definition.push(' ');
+                    definition.push_str(&column.declared_type);
+                }
+                if column.primary_key {
+                    definition.push_str(" PRIMARY KEY");
+                }
+                if column.autoincrement {
+                    definition.push_str(" AUTOINCREMENT");
+                }
+                if column.not_null {
+                    definition.push_str(" NOT NULL");
+                }
+                if column.unique {
+                    definition.push_str(" UNIQUE");
+                }
+                if let Some(default) = &column.default {
+                    definition.push_str(&format!(" DEFAULT ({})", default));
+                }
+                for check in column.checks.iter() {
+                    definition.push_str(&format!(" CHECK ({})", check));
+                }
+                if let Some(foreign_key) = &column.references {
+                    definition.push_str(&format!(" REFERENCES {}", foreign_key.table));
+                    if let Some(parent_column) = &foreign_key.column {
+                        definition.push_str(&format!(" ({})", parent_column));
+                    }
+                    if foreign_key.on_delete != ForeignKeyAction::NoAction {
+                        definition.push_str(&format!(" ON DELETE {}", foreign_key.on_delete));
+                    }
+                    if foreign_key.on_update != ForeignKeyAction::NoAction {
+                        definition.push_str(&format!(" ON UPDATE {}", foreign_key.on_update));
+                    }
+                    if foreign_key.deferred {
+                        definition.push_str(" DEFERRABLE INITIALLY DEFERRED");
+                    }
+                }
+                definition
+            })
+            .collect();
+        format!("CREATE TABLE {} ({})", self.name, columns.join(", "))
+    }
+}
+
+/// An index of a table. Its keys are records of the values of its columns,
+/// given by their position in the table, followed by the key of the row.
+#[derive(Clone, Debug)]
+pub struct IndexSchema {
+    pub name: String,
+    pub table_name: String,
+    pub columns: Vec<usize>,
+    /// No two rows may have the same values in the columns, unless one of them is NULL
+    pub unique: bool,
+}
+
+impl IndexSchema {
+    pub fn from_create_index(
+        create: ast::CreateIndex,
+        table: &TableSchema,
+    ) -> Result<Self, PrepareStatementErr> {
+        let mut columns = Vec::new();
+        for name in create.columns.iter() {
+            match table.column_index(name) {
+                Some(index) => columns.push(index),
+                None => return Err(PrepareStatementErr::NoSuchColumn(name.clone())),
+            }
+        }
+
+        // An index without a name is named after its table and columns
+        let name = create.name.unwrap_or_else(|| {
+            let names: Vec<_> = columns
+                .iter()
+                .map(|&index| table.columns[index].name.as_str())
+                .collect();
+            format!("{}_{}_index", table.name, names.join("_"))
+        });
+        let schema = Self {
+            name,
+            table_name: table.name.clone(),
+            columns,
+            unique: create.unique,
+        };
+        schema.validate(table)?;
+        Ok(schema)
+    }
+
+    // The index must fit in the catalog, and its keys in its B-tree
+    fn validate(&self, table: &TableSchema) -> Result<(), PrepareStatementErr> {
+        if self.name.len() > CATALOG_NAME_MAX_SIZE {
+            return Err(PrepareStatementErr::NameTooLong(self.name.clone()));
+        }
+        if self.max_key_size(table) > INDEX_MAX_KEY_SIZE {
+            return Err(PrepareStatementErr::IndexKeyTooLarge(
+                self.max_key_size(table),
+            ));
+        }
+        if self.to_sql(table).len() > CATALOG_SQL_MAX_SIZE {
+            return Err(PrepareStatementErr::SchemaTooLarge);
+        }
+        Ok(())
+    }
+
+    /// Parse an index of the table stored as its CREATE INDEX statement.
+    /// `None` if the statement is not a valid index definition.
+    pub fn from_sql(sql: &str, table: &TableSchema) -> Option<Self> {
+        let statement = Parser::new(sql).and_then(|mut parser| parser.parse_statement());
+        let Ok(ast::Statement::CreateIndex(create)) = statement else {
+            return None;
+        };
+        Self::from_create_index(create, table).ok()
+    }
+
+    /// Whether the index backs a UNIQUE constraint of its table, rather
+    /// than having been created with CREATE INDEX.
+    pub fn is_automatic(&self) -> bool {
+        self.name.starts_with(AUTOINDEX_PREFIX)
+    }
+
+    /// Values of the index key of a row of the table: the values of the
+    /// index's columns, then the row's key.
+    pub fn key(&self, table: &TableSchema, row: &Row) -> Vec<Value> {
+        self.columns
+            .iter()
+            .chain([table.key_column()].iter())
+            .map(|&index| row.values[index].clone())
+            .collect()
+    }
+
+    /// Names of the index's columns, each after its table's name, as in `users.email, users.id`.
+    pub fn column_names(&self, table: &TableSchema) -> String {
+        let names: Vec<_> = self
+            .columns
+            .iter()
+            .map(|&index| format!("{}.{}", table.name, table.columns[index].name))
+            .collect();
+        names.join(", ")
+    }
+
+    /// Size of the longest key of the index, counting the values of
+    /// unbounded columns as numbers.
+    pub fn max_key_size(&self, table: &TableSchema) -> usize {
+        let key_column = [table.key_column()];
+        let columns = self
+            .columns
+            .iter()
+            .chain(key_column.iter())
+            .map(|&index| &table.columns[index]);
+        max_record_size(columns)
+    }
+
+    /// The CREATE INDEX statement that recreates this index.
+    pub fn to_sql(&self, table: &TableSchema) -> String {
+        let columns: Vec<_> = self
+            .columns
+            .iter()
+            .map(|&index| table.columns[index].name.as_str())
+            .collect();
+        format!(
+            "CREATE {}INDEX {} ON {} ({})",
+            if self.unique { "UNIQUE " } else { "" },
+            self.name,
+            table.name,
+            columns.join(", ")
+        )
+    }
+}
+
+// Size of a record with the longest values the columns hold. A value of
+// an unbounded column counts as a number, the length of its text and blobs
+// is checked as rows are written instead.
+fn max_record_size<'a>(columns: impl Iterator<Item = &'a Column> + Clone) -> usize {
+    let types_size: usize = columns
+        .clone()
+        .map(|column| varint_size(column.max_serial_type()))
+        .sum();
+    let header_size = types_size + varint_size((types_size + VARINT_MAX_SIZE) as u64);
+    // Saturating, as a declared length can be as large as a usize
+    columns.fold(header_size, |size, column| {
+        let max_size = if column.is_unbounded() {
+            0
+        } else {
+            column.max_size
+        };
+        size.saturating_add(max_size.max(size_of::<i64>()))
+    })
+}
+
+// Whether an expression has the same value for every row
+fn is_constant(expr: &Expr) -> bool {
+    match expr {
+        Expr::Literal(_) | Expr::Parameter(_) => true,
+        Expr::Column(_) | Expr::Function { .. } => false,
+        Expr::Unary(_, expr) | Expr::IsNull { expr, .. } => is_constant(expr),
+        Expr::Binary(left, _, right)
+        | Expr::Like {
+            expr: left,
+            pattern: right,
+            ..
+        } => is_constant(left) && is_constant(right),
+        Expr::InList { expr, list, .. } => is_constant(expr) && list.iter().all(is_constant),
+        Expr::Between {
+            expr, low, high, ..
+        } => is_constant(expr) && is_constant(low) && is_constant(high),
+    }
+}
+
+fn declared_type(type_name: &ast::TypeName) -> String {
+    let name = type_name.name.to_uppercase();
+    if type_name.arguments.is_empty() {
+        name
+    } else {
+        let arguments: Vec<_> = type_name.arguments.iter().map(i64::to_string).collect();
+        format!("{}({})", name, arguments.join(", "))
+    }
+}
+
+// Longest text or blob a column of the type holds. The first argument of
+// the type is the length, e.g. `VARCHAR(32)`, which must be positive.
+// Without one, numeric columns only have room for a number's worth.
+fn max_size(type_name: &ast::TypeName, affinity: Affinity) -> Option<usize> {
+    match type_name.arguments.first() {
+        Some(&size) => usize::try_from(size).ok().filter(|size| *size > 0),
+        None if affinity.is_numeric() => Some(size_of::<i64>()),
+        None => Some(MAX_RECORD_SIZE),
+    }
+}
@@ -0,0 +1,483 @@
+use std::cmp::Ordering;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
+
+use crate::pager::PAGE_SIZE;
+use crate::value::Value;
+
+// Bytes of records kept in memory before a sorted run is written to the scratch file
+#[cfg(debug_assertions)]
+pub const SORTER_MEMORY_LIMIT: usize = 4 * PAGE_SIZE; // Kept small for testing
+#[cfg(not(debug_assertions))]
+pub const SORTER_MEMORY_LIMIT: usize = 256 * PAGE_SIZE;
+
+// Runs merged at once. Longer lists of runs are merged in several passes,
+// each merging groups of runs into longer runs.
+#[cfg(debug_assertions)]
+pub const SORTER_MAX_MERGE_RUNS: usize = 3; // Kept small for testing
+#[cfg(not(debug_assertions))]
+pub const SORTER_MAX_MERGE_RUNS: usize = 16;
+
+const VALUE_NULL: u8 = 0;
+const VALUE_INTEGER: u8 = 1;
+const VALUE_REAL: u8 = 2;
+const VALUE_TEXT: u8 = 3;
+const VALUE_BLOB: u8 = 4;
+
+/// A record to sort: the values it is sorted on and the values it carries.
+pub struct SortRecord {
+    keys: Vec<Value>,
+    values: Vec<Value>,
+}
+
+impl SortRecord {
+    fn size(&self) -> usize {
+        self.keys
+            .iter()
+            .chain(self.values.iter())
+            .map(encoded_size)
+            .sum()
+    }
+}
+
+/// External merge sort. Records are sorted in memory until they take up
+/// more than `SORTER_MEMORY_LIMIT` bytes, then written out as a sorted run
+/// of pages in a scratch file next to the database. The runs are merged
+/// when reading, reading one page of each run at a time.
+pub struct Sorter {
+    descending: Vec<bool>,
+    // Only this many records are wanted, from the start of the sorted order
+    limit: Option<usize>,
+    records: Vec<SortRecord>,
+    records_size: usize,
+    scratch_prefix: String,
+    scratch: Option<Scratch>,
+    runs: Vec<Run>,
+}
+
+// A sorted run: records packed into consecutive pages of the scratch file
+#[derive(Clone, Copy)]
+struct Run {
+    first_page_num: u64,
+    length: u64,
+}
+
+impl Sorter {
+    /// `descending` holds, for each sort key, whether it sorts high to low.
+    /// The scratch file for the runs is named after `filename`.
+    pub fn new(descending: Vec<bool>, limit: Option<usize>, filename: &str) -> Self {
+        Self {
+            descending,
+            limit,
+            records: Vec::new(),
+            records_size: 0,
+            scratch_prefix: format!("{filename}-sort"),
+            scratch: None,
+            runs: Vec::new(),
+        }
+    }
+
+    pub fn add(&mut self, keys: Vec<Value>, values: Vec<Value>) -> io::Result<()> {
+        if self.limit == Some(0) {
+            return Ok(());
+        }
+        let record = SortRecord { keys, values };
+        self.records_size += record.size();
+        self.records.push(record);
+
+        // Records past the limit can never come out of the sorter
+        if let Some(limit) = self.limit {
+            if self.records.len() >= 2 * limit {
+                self.sort_records();
+                self.records.truncate(limit);
+                self.records_size = self.records.iter().map(SortRecord::size).sum();
+            }
+        }
+
+        if self.records_size > SORTER_MEMORY_LIMIT {
+            self.spill()?;
+        }
+        Ok(())
+    }
+
+    /// Values of the records in sorted order. Records with equal keys
+    /// keep the order they were added in.
+    pub fn finish(mut self) -> io::Result<SortedRows> {
+        let Some(mut scratch) = self.scratch.take() else {
+            self.sort_records();
+            return Ok(SortedRows::InMemory(self.records.into_iter()));
+        };
+
+        if !self.records.is_empty() {
+            self.spill_to(&mut scratch)?;
+        }
+
+        while self.runs.len() > SORTER_MAX_MERGE_RUNS {
+            let runs = std::mem::take(&mut self.runs);
+            for group in runs.chunks(SORTER_MAX_MERGE_RUNS) {
+                let run = self.merge_runs(&mut scratch, group)?;
+                self.runs.push(run);
+            }
+        }
+
+        let merge = Merge::start(&mut scratch, self.descending, &self.runs)?;
+        Ok(SortedRows::Merge(Box::new((scratch, merge))))
+    }
+
+    fn sort_records(&mut self) {
+        let descending = &self.descending;
+        self.records
+            .sort_by(|a, b| compare_keys(descending, &a.keys, &b.keys));
+    }
+
+    fn spill(&mut self) -> io::Result<()> {
+        let mut scratch = match self.scratch.take() {
+            Some(scratch) => scratch,
+            None => Scratch::create(&self.scratch_prefix)?,
+        };
+        let spilled = self.spill_to(&mut scratch);
+        self.scratch = Some(scratch);
+        spilled
+    }
+
+    // Write the records in memory out as a sorted run
+    fn spill_to(&mut self, scratch: &mut Scratch) -> io::Result<()> {
+        self.sort_records();
+        if let Some(limit) = self.limit {
+            self.records.truncate(limit);
+        }
+
+        let mut writer = RunWriter::new(scratch);
+        for record in self.records.drain(..) {
+            writer.write_record(scratch, &record)?;
+        }
+        self.runs.push(writer.finish(scratch)?);
+        self.records_size = 0;
+        Ok(())
+    }
+
+    // Merge a group of runs into a new run at the end of the scratch file
+    fn merge_runs(&self, scratch: &mut Scratch, runs: &[Run]) -> io::Result<Run> {
+        let mut merge = Merge::start(scratch, self.descending.clone(), runs)?;
+        let mut writer = RunWriter::new(scratch);
+        let mut written = 0;
+        while let Some(record) = merge.next_record(scratch)? {
+            if Some(written) == self.limit {
+                break;
+            }
+            writer.write_record(scratch, &record)?;
+            written += 1;
+        }
+        writer.finish(scratch)
+    }
+}
+
+fn compare_keys(descending: &[bool], a: &[Value], b: &[Value]) -> Ordering {
+    for ((a, b), descending) in a.iter().zip(b.iter()).zip(descending.iter()) {
+        let ordering = a.sort_cmp(b);
+        let ordering = if *descending {
+            ordering.reverse()
+        } else {
+            ordering
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+    Ordering::Equal
+}
+
+pub enum SortedRows {
+    InMemory(std::vec::IntoIter<SortRecord>),
+    Merge(Box<(Scratch, Merge)>),
+}
+
+impl Iterator for SortedRows {
+    type Item = io::Result<Vec<Value>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            SortedRows::InMemory(records) => records.next().map(|record| Ok(record.values)),
+            SortedRows::Merge(merge) => {
+                let (scratch, merge) = &mut **merge;
+                merge
+                    .next_record(scratch)
+                    .transpose()
+                    .map(|record| Ok(record?.values))
+            }
+        }
+    }
+}
+
+/// K-way merge of sorted runs in the scratch file.
+pub struct Merge {
+    descending: Vec<bool>,
+    readers: Vec<RunReader>,
+    // Next record of each run, None once the run is exhausted
+    heads: Vec<Option<SortRecord>>,
+}
+
+impl Merge {
+    fn start(scratch: &mut Scratch, descending: Vec<bool>, runs: &[Run]) -> io::Result<Self> {
+        let mut readers: Vec<_> = runs.iter().map(|&run| RunReader::new(run)).collect();
+        let heads = readers
+            .iter_mut()
+            .map(|reader| reader.read_record(scratch))
+            .collect::<io::Result<_>>()?;
+        Ok(Self {
+            descending,
+            readers,
+            heads,
+        })
+    }
+
+    fn next_record(&mut self, scratch: &mut Scratch) -> io::Result<Option<SortRecord>> {
+        // Ties go to the earlier run, which holds the earlier added records
+        let mut smallest: Option<(usize, &SortRecord)> = None;
+        for (run, head) in self.heads.iter().enumerate() {
+            let Some(head) = head else {
+                continue;
+            };
+            let is_smaller = match smallest {
+                Some((_, smallest)) => {
+                    compare_keys(&self.descending, &head.keys, &smallest.keys) == Ordering::Less
+                }
+                None => true,
+            };
+            if is_smaller {
+                smallest = Some((run, head));
+            }
+        }
+
+        let Some((run, _)) = smallest else {
+            return Ok(None);
+        };
+        let head = self.readers[run].read_record(scratch)?;
+        Ok(std::mem::replace(&mut self.heads[run], head))
+    }
+}
+
+/// Scratch file holding the sorted runs, removed when dropped.
+pub struct Scratch {
+    filename: String,
+    file: File,
+    num_pages: u64,
+}
+
+impl Scratch {
+    fn create(prefix: &str) -> io::Result<Self> {
+        static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+        let id = NEXT_ID.fetch_add(1, AtomicOrdering::Relaxed);
+        let filename = format!("{prefix}{}-{id}", process::id());
+
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&filename)?;
+
+        Ok(Self {
+            filename,
+            file,
+            num_pages: 0,
+        })
+    }
+
+    fn read_page(&mut self, page_num: u64, page: &mut [u8; PAGE_SIZE]) -> io::Result<()> {
+        self.file
+            .seek(SeekFrom::Start(page_num * PAGE_SIZE as u64))?;
+        self.file.read_exact(page)
+    }
+
+    fn write_page(&mut self, page_num: u64, page: &[u8; PAGE_SIZE]) -> io::Result<()> {
+        self.file
+            .seek(SeekFrom::Start(page_num * PAGE_SIZE as u64))?;
+        self.file.write_all(page)?;
+        self.num_pages = self.num_pages.max(page_num + 1);
+        Ok(())
+    }
+}
+
+impl Drop for Scratch {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.filename);
+    }
+}
+
+// Writes a run a page at a time, after the last page of the scratch file
+struct RunWriter {
+    run: Run,
+    page: Box<[u8; PAGE_SIZE]>,
+}
+
+impl RunWriter {
+    fn new(scratch: &Scratch) -> Self {
+        Self {
+            run: Run {
+                first_page_num: scratch.num_pages,
+                length: 0,
+            },
+            page: Box::new([0; PAGE_SIZE]),
+        }
+    }
+
+    fn write_record(&mut self, scratch: &mut Scratch, record: &SortRecord) -> io::Result<()> {
+        let bytes = encode_record(record);
+        let mut bytes = bytes.as_slice();
+        while !bytes.is_empty() {
+            let offset = (self.run.length % PAGE_SIZE as u64) as usize;
+            let taken = bytes.len().min(PAGE_SIZE - offset);
+            self.page[offset..offset + taken].copy_from_slice(&bytes[..taken]);
+            bytes = &bytes[taken..];
+            self.run.length += taken as u64;
+            if offset + taken == PAGE_SIZE {
+                self.write_page(scratch)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn write_page(&mut self, scratch: &mut Scratch) -> io::Result<()> {
+        let page_index = (self.run.length - 1) / PAGE_SIZE as u64;
+        scratch.write_page(self.run.first_page_num + page_index, &self.page)
+    }
+
+    fn finish(mut self, scratch: &mut Scratch) -> io::Result<Run> {
+        if !self.run.length.is_multiple_of(PAGE_SIZE as u64) {
+            self.write_page(scratch)?;
+        }
+        Ok(self.run)
+    }
+}
+
+// Reads a run back a page at a time
+struct RunReader {
+    run: Run,
+    position: u64,
+    page: Box<[u8; PAGE_SIZE]>,
+}
+
+impl RunReader {
+    fn new(run: Run) -> Self {
+        Self {
+            run,
+            position: 0,
+            page: Box::new([0; PAGE_SIZE]),
+        }
+    }
+
+    fn read_bytes(&mut self, scratch: &mut Scratch, length: usize) -> io::Result<Vec<u8>> {
+        let mut bytes = Vec::with_capacity(length);
+        while bytes.len() < length {
+            let offset = (self.position % PAGE_SIZE as u64) as usize;
+            if offset == 0 {
+                let page_num = self.run.first_page_num + self.position / PAGE_SIZE as u64;
+                scratch.read_page(page_num, &mut self.page)?;
+            }
+            let taken = (length - bytes.len()).min(PAGE_SIZE - offset);
+            bytes.extend_from_slice(&self.page[offset..offset + taken]);
+            self.position += taken as u64;
+        }
+        Ok(bytes)
+    }
+
+    fn read_record(&mut self, scratch: &mut Scratch) -> io::Result<Option<SortRecord>> {
+        if self.position == self.run.length {
+            return Ok(None);
+        }
+        let length = read_u32(&mut self.read_bytes(scratch, 4)?.as_slice());
+        let bytes = self.read_bytes(scratch, length as usize)?;
+
+        let mut bytes = bytes.as_slice();
+        let num_keys = read_u32(&mut bytes) as usize;
+        let num_values = read_u32(&mut bytes) as usize;
+        let keys = (0..num_keys).map(|_| decode_value(&mut bytes)).collect();
+        let values = (0..num_values).map(|_| decode_value(&mut bytes)).collect();
+        Ok(Some(SortRecord { keys, values }))
+    }
+}
+
+// Records are written as their length, the number of keys and values,
+// then each value as a type tag followed by its data.
+fn encode_record(record: &SortRecord) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(12 + record.size());
+    bytes.extend_from_slice(&[0; 4]);
+    bytes.extend_from_slice(&(record.keys.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(&(record.values.len() as u32).to_le_bytes());
+    for value in record.keys.iter().chain(record.values.iter()) {
+        encode_value(value, &mut bytes);
+    }
+
+    let length = (bytes.len() - 4) as u32;
+    bytes[..4].copy_from_slice(&length.to_le_bytes());
+    bytes
+}
+
+fn encoded_size(value: &Value) -> usize {
+    1 + match value {
+        Value::Null => 0,
+        Value::Integer(_) | Value::Real(_) => 8,
+        Value::Text(text) => 4 + text.len(),
+        Value::Blob(blob) => 4 + blob.len(),
+    }
+}
+
+fn encode_value(value: &Value, bytes: &mut Vec<u8>) {
+    match value {
+        Value::Null => bytes.push(VALUE_NULL),
+        Value::Integer(integer) => {
+            bytes.push(VALUE_INTEGER);
+            bytes.extend_from_slice(&integer.to_le_bytes());
+        }
+        Value::Real(real) => {
+            bytes.push(VALUE_REAL);
+            bytes.extend_from_slice(&real.to_le_bytes());
+        }
+        Value::Text(text) => {
+            bytes.push(VALUE_TEXT);
+            bytes.extend_from_slice(&(text.len() as u32).to_le_bytes());
+            bytes.extend_from_slice(text.as_bytes());
+        }
+        Value::Blob(blob) => {
+            bytes.push(VALUE_BLOB);
+            bytes.extend_from_slice(&(blob.len() as u32).to_le_bytes());
+            bytes.extend_from_slice(blob);
+        }
+    }
+}
+
+fn decode_value(bytes: &mut &[u8]) -> Value {
+    let tag = bytes[0];
+    *bytes = &bytes[1..];
+    match tag {
+        VALUE_NULL => Value::Null,
+        VALUE_INTEGER => Value::Integer(i64::from_le_bytes(take_bytes(bytes))),
+        VALUE_REAL => Value::Real(f64::from_le_bytes(take_bytes(bytes))),
+        VALUE_TEXT => {
+            let length = read_u32(bytes) as usize;
+            let text = String::from_utf8_lossy(&bytes[..length]).into_owned();
+            *bytes = &bytes[length..];
+            Value::Text(text)
+        }
+        VALUE_BLOB => {
+            let length = read_u32(bytes) as usize;
+            let blob = bytes[..length].to_vec();
+            *bytes = &bytes[length..];
+            Value::Blob(blob)
+        }
+        _ => panic!("Unknown value type in sort file. Corrupt file."),
+    }
+}
+
+fn read_u32(bytes: &mut &[u8]) -> u32 {
+    u32::from_le_bytes(take_bytes(bytes))
+}
+
+fn take_bytes<const N: usize>(bytes: &mut &[u8]) -> [u8; N] {
+    let (taken, rest) = bytes.split_at(N);
+    *bytes = rest;
+    taken.try_into().unwrap()
+}
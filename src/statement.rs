@@ -1,113 +1,1778 @@
-use std::borrow::Cow;
 use std::fmt;
+use std::ops::Bound;
 
+use crate::ast::{
+    self, BinaryOperator, Expr, ForeignKey, ForeignKeyAction, Literal, UnaryOperator,
+};
+use crate::catalog::{CATALOG_NAME_MAX_SIZE, CATALOG_TABLE_NAME, SEQUENCE_TABLE_NAME};
 use crate::cursor::Cursor;
+use crate::database::{Database, ForeignKeyCheck, IndexEntry, TableEntry};
+use crate::error::{ConstraintErr, Error};
+use crate::eval::{evaluate, function_arity, Context};
+use crate::header::JournalMode;
+use crate::index::compare_prefix;
+use crate::node::INDEX_MAX_KEY_SIZE;
+use crate::parser::{ParseError, Parser};
 use crate::row::Row;
-use crate::table::Table;
-use crate::InputBuffer;
+use crate::rows::{sort_rows, Scan, SelectScan};
+use crate::schema::{Affinity, Column, IndexSchema, TableSchema, MAX_RECORD_SIZE};
+use crate::sorter::Sorter;
+use crate::value::Value;
 
 pub enum Statement {
-    Select,
-    Insert(Row),
+    CreateTable(TableSchema),
+    /// The table to drop, `None` when `IF EXISTS` found no table.
+    DropTable(Option<String>),
+    CreateIndex(IndexSchema),
+    /// The index to drop, `None` when `IF EXISTS` found no index.
+    DropIndex(Option<String>),
+    Select(Select),
+    /// A select without FROM, giving one row of the values of its result columns.
+    Values(Vec<Expr>),
+    /// Rows to insert, each an expression for every column of the table.
+    Insert(TableEntry, Vec<Vec<Expr>>),
+    Delete(Filter),
+    Update(Vec<Assignment>, Filter),
+    Pragma(Pragma),
+    Begin,
+    Commit,
+    Rollback(Option<String>),
+    Savepoint(String),
+    Release(String),
+    /// Rebuild the database, into the named file if there is one.
+    Vacuum(Option<String>),
 }
 
-pub enum PrepareStatementErr<'a> {
-    SyntaxError,
+/// A setting read or changed with `PRAGMA`. `None` reads the setting.
+pub enum Pragma {
+    CacheSize(Option<usize>),
+    JournalMode(Option<JournalMode>),
+    WalCheckpoint,
+    /// The rows whose foreign keys have no parent, in the named table or in every table.
+    ForeignKeyCheck(Option<String>),
+}
+
+/// A planned query: the rows matching `filter`, sorted by `order_by`,
+/// with `offset` rows skipped and at most `limit` rows returned.
+pub struct Select {
+    pub columns: Vec<Expr>,
+    pub filter: Filter,
+    /// Sort keys and whether each is descending. Empty when the rows can
+    /// be returned in the order the table stores them.
+    pub order_by: Vec<(Expr, bool)>,
+    pub limit: Option<usize>,
+    pub offset: usize,
+}
+
+impl Select {
+    /// Values of the result columns for a row of the table.
+    pub fn project(&self, row: &Row, context: Context) -> Vec<Value> {
+        self.columns
+            .iter()
+            .map(|column| evaluate(column, &self.filter.table.schema, row, context))
+            .collect()
+    }
+
+    /// A sorter for the rows, ordered by the ORDER BY terms. It keeps only
+    /// the rows up to the end of the LIMIT, and spills to a scratch file
+    /// named after the database file.
+    pub fn sorter(&self, filename: &str) -> Sorter {
+        Sorter::new(
+            self.order_by.iter().map(|(_, desc)| *desc).collect(),
+            self.limit.map(|limit| limit.saturating_add(self.offset)),
+            filename,
+        )
+    }
+}
+
+/// A `column = value` pair of an update statement, by column index. The
+/// value is evaluated against the row as it is before the update.
+pub struct Assignment {
+    pub column: usize,
+    pub value: Expr,
+}
+
+/// Rows of a table matched by a where clause. Only the rows with a key in
+/// `key_range` are visited, and of those only the ones the condition is true for match.
+/// With an `index_range`, the rows are looked up through the index instead.
+pub struct Filter {
+    pub table: TableEntry,
+    pub key_range: KeyRange,
+    pub index_range: Option<IndexRange>,
+    pub condition: Option<Expr>,
+}
+
+impl Filter {
+    fn new(table: TableEntry, where_clause: Option<Expr>) -> Result<Self, PrepareStatementErr> {
+        let Some(condition) = where_clause else {
+            return Ok(Self {
+                table,
+                key_range: KeyRange::all(),
+                index_range: None,
+                condition: None,
+            });
+        };
+
+        check_columns(&table.schema, &condition)?;
+        let key_range = KeyRange::from_condition(&condition, &table.schema.key_column_name());
+        let index_range = IndexRange::from_condition(&condition, &table, &key_range);
+        Ok(Self {
+            table,
+            key_range,
+            index_range,
+            condition: Some(condition),
+        })
+    }
+
+    pub fn matches(&self, row: &Row, context: Context) -> bool {
+        match &self.condition {
+            Some(condition) => {
+                evaluate(condition, &self.table.schema, row, context).to_bool() == Some(true)
+            }
+            None => true,
+        }
+    }
+}
+
+/// Range of keys a where clause can match.
+pub struct KeyRange {
+    pub start: Bound<i64>,
+    pub end: Bound<i64>,
+}
+
+impl KeyRange {
+    pub fn all() -> Self {
+        Self {
+            start: Bound::Unbounded,
+            end: Bound::Unbounded,
+        }
+    }
+
+    pub fn empty() -> Self {
+        Self {
+            start: Bound::Excluded(i64::MAX),
+            end: Bound::Unbounded,
+        }
+    }
+
+    pub fn is_all(&self) -> bool {
+        matches!((self.start, self.end), (Bound::Unbounded, Bound::Unbounded))
+    }
+
+    pub fn is_single_key(&self) -> bool {
+        matches!((self.start, self.end), (Bound::Included(start), Bound::Included(end)) if start == end)
+    }
+
+    pub fn contains(&self, key: i64) -> bool {
+        let after_start = match self.start {
+            Bound::Included(start) => key >= start,
+            Bound::Excluded(start) => key > start,
+            Bound::Unbounded => true,
+        };
+        let before_end = match self.end {
+            Bound::Included(end) => key <= end,
+            Bound::Excluded(end) => key < end,
+            Bound::Unbounded => true,
+        };
+        after_start && before_end
+    }
+
+    // The smallest key that can be in the range
+    pub fn first_key(&self) -> Option<i64> {
+        match self.start {
+            Bound::Included(start) => Some(start),
+            Bound::Excluded(start) => start.checked_add(1),
+            Bound::Unbounded => Some(i64::MIN),
+        }
+    }
+
+    // Keys that are in both ranges
+    fn intersect(self, other: Self) -> Self {
+        let start = match (self.start, other.start) {
+            (Bound::Unbounded, start) | (start, Bound::Unbounded) => start,
+            (Bound::Included(a), Bound::Included(b)) => Bound::Included(a.max(b)),
+            (Bound::Excluded(a), Bound::Excluded(b)) => Bound::Excluded(a.max(b)),
+            (Bound::Included(a), Bound::Excluded(b)) | (Bound::Excluded(b), Bound::Included(a)) => {
+                if a > b {
+                    Bound::Included(a)
+                } else {
+                    Bound::Excluded(b)
+                }
+            }
+        };
+        let end = match (self.end, other.end) {
+            (Bound::Unbounded, end) | (end, Bound::Unbounded) => end,
+            (Bound::Included(a), Bound::Included(b)) => Bound::Included(a.min(b)),
+            (Bound::Excluded(a), Bound::Excluded(b)) => Bound::Excluded(a.min(b)),
+            (Bound::Included(a), Bound::Excluded(b)) | (Bound::Excluded(b), Bound::Included(a)) => {
+                if a < b {
+                    Bound::Included(a)
+                } else {
+                    Bound::Excluded(b)
+                }
+            }
+        };
+        Self { start, end }
+    }
+
+    /// The narrowest range found for the keys matched by a condition.
+    /// Comparisons of the key column with integers, `key BETWEEN a AND b`
+    /// and `key IN (...)` narrow the range, so do ANDs of those. Anything
+    /// else can match any key.
+    fn from_condition(condition: &Expr, key_column: &str) -> Self {
+        let is_key = |expr: &Expr| matches!(expr, Expr::Column(column) if column.eq_ignore_ascii_case(key_column));
+
+        match condition {
+            Expr::Binary(left, BinaryOperator::And, right) => {
+                Self::from_condition(left, key_column)
+                    .intersect(Self::from_condition(right, key_column))
+            }
+            Expr::Binary(left, operator, right) => {
+                let (operator, value) = if is_key(left) {
+                    (*operator, right)
+                } else if is_key(right) {
+                    (operator.flip(), left)
+                } else {
+                    return Self::all();
+                };
+                let Some(Value::Integer(id)) = key_value(value) else {
+                    return Self::all();
+                };
+                match operator {
+                    BinaryOperator::Equal => Self::at_least(id).intersect(Self::at_most(id)),
+                    BinaryOperator::Less => Self::below(id),
+                    BinaryOperator::LessEqual => Self::at_most(id),
+                    BinaryOperator::Greater => Self::above(id),
+                    BinaryOperator::GreaterEqual => Self::at_least(id),
+                    _ => Self::all(),
+                }
+            }
+            Expr::Between {
+                expr,
+                low,
+                high,
+                negated: false,
+            } if is_key(expr) => {
+                let start = match key_value(low) {
+                    Some(Value::Integer(low)) => Self::at_least(low),
+                    _ => Self::all(),
+                };
+                let end = match key_value(high) {
+                    Some(Value::Integer(high)) => Self::at_most(high),
+                    _ => Self::all(),
+                };
+                start.intersect(end)
+            }
+            Expr::InList {
+                expr,
+                list,
+                negated: false,
+            } if is_key(expr) => {
+                let mut ids = Vec::new();
+                for item in list {
+                    match key_value(item) {
+                        Some(Value::Integer(id)) => ids.push(id),
+                        // NULL never equals an id
+                        Some(Value::Null) => {}
+                        _ => return Self::all(),
+                    }
+                }
+                match (ids.iter().min(), ids.iter().max()) {
+                    (Some(&min), Some(&max)) => Self::at_least(min).intersect(Self::at_most(max)),
+                    _ => Self::empty(),
+                }
+            }
+            _ => Self::all(),
+        }
+    }
+
+    fn at_least(id: i64) -> Self {
+        Self {
+            start: Bound::Included(id),
+            end: Bound::Unbounded,
+        }
+    }
+
+    fn above(id: i64) -> Self {
+        Self {
+            start: Bound::Excluded(id),
+            end: Bound::Unbounded,
+        }
+    }
+
+    fn at_most(id: i64) -> Self {
+        Self {
+            start: Bound::Unbounded,
+            end: Bound::Included(id),
+        }
+    }
+
+    fn below(id: i64) -> Self {
+        Self {
+            start: Bound::Unbounded,
+            end: Bound::Excluded(id),
+        }
+    }
+}
+
+/// Range of the keys of an index a where clause can match. The keys are
+/// compared with the bounds by their first values, see `compare_prefix`.
+pub struct IndexRange {
+    pub index: IndexEntry,
+    pub start: Bound<Vec<Value>>,
+    pub end: Bound<Vec<Value>>,
+}
+
+impl IndexRange {
+    /// The index that narrows down the rows matched by a condition the
+    /// most, if one does better than the key range. Equality comparisons
+    /// of the first columns of an index with literals narrow the range,
+    /// then a comparison or `BETWEEN` on the column after them, all ANDed.
+    fn from_condition(condition: &Expr, table: &TableEntry, key_range: &KeyRange) -> Option<Self> {
+        let mut terms = Vec::new();
+        and_terms(condition, &mut terms);
+
+        let mut best: Option<(Self, usize, bool)> = None;
+        for index in table.indexes.iter() {
+            let mut values = Vec::new();
+            let mut columns = index.schema.columns.iter();
+            let mut lower = None;
+            let mut upper = None;
+            for &column_index in columns.by_ref() {
+                let column = &table.schema.columns[column_index];
+                let mut equal = None;
+                for term in terms.iter() {
+                    match column_comparison(term, column) {
+                        Some((BinaryOperator::Equal, value)) => equal = equal.or(Some(value)),
+                        Some((BinaryOperator::Greater, value)) => {
+                            lower = lower.or(Some(Bound::Excluded(value)))
+                        }
+                        Some((BinaryOperator::GreaterEqual, value)) => {
+                            lower = lower.or(Some(Bound::Included(value)))
+                        }
+                        Some((BinaryOperator::Less, value)) => {
+                            upper = upper.or(Some(Bound::Excluded(value)))
+                        }
+                        Some((BinaryOperator::LessEqual, value)) => {
+                            upper = upper.or(Some(Bound::Included(value)))
+                        }
+                        _ => {}
+                    }
+                    if let Some((low, high)) = column_between(term, column) {
+                        lower = lower.or(Some(Bound::Included(low)));
+                        upper = upper.or(Some(Bound::Included(high)));
+                    }
+                }
+                match equal {
+                    Some(value) => {
+                        values.push(value);
+                        // A range on a column before the last equality does not count
+                        lower = None;
+                        upper = None;
+                    }
+                    None => break,
+                }
+            }
+
+            let has_range = lower.is_some() || upper.is_some();
+            let useful = (!values.is_empty() && !key_range.is_single_key())
+                || (has_range && key_range.is_all());
+            let better = match &best {
+                Some((_, equalities, best_has_range)) => {
+                    (values.len(), has_range) > (*equalities, *best_has_range)
+                }
+                None => true,
+            };
+            if !useful || !better {
+                continue;
+            }
+
+            let with = |value: Value| {
+                let mut values = values.clone();
+                values.push(value);
+                values
+            };
+            // NULL sorts first, but is never in a range of values
+            let start = match lower {
+                Some(Bound::Included(value)) => Bound::Included(with(value)),
+                Some(Bound::Excluded(value)) => Bound::Excluded(with(value)),
+                _ if upper.is_some() => Bound::Excluded(with(Value::Null)),
+                _ => Bound::Included(values.clone()),
+            };
+            let end = match upper {
+                Some(Bound::Included(value)) => Bound::Included(with(value)),
+                Some(Bound::Excluded(value)) => Bound::Excluded(with(value)),
+                _ if values.is_empty() => Bound::Unbounded,
+                _ => Bound::Included(values.clone()),
+            };
+            let range = Self {
+                index: index.clone(),
+                start,
+                end,
+            };
+            best = Some((range, values.len(), has_range));
+        }
+        best.map(|(range, _, _)| range)
+    }
+}
+
+// The terms of a condition that are ANDed together
+fn and_terms<'a>(condition: &'a Expr, terms: &mut Vec<&'a Expr>) {
+    match condition {
+        Expr::Binary(left, BinaryOperator::And, right) => {
+            and_terms(left, terms);
+            and_terms(right, terms);
+        }
+        _ => terms.push(condition),
+    }
+}
+
+// A comparison of a column with a literal, with the column on the left
+// and the literal converted the way the comparison converts it
+fn column_comparison(term: &Expr, column: &Column) -> Option<(BinaryOperator, Value)> {
+    let Expr::Binary(left, operator, right) = term else {
+        return None;
+    };
+    let is_column =
+        |expr: &Expr| matches!(expr, Expr::Column(name) if name.eq_ignore_ascii_case(&column.name));
+    let (operator, value) = if is_column(left) {
+        (*operator, right)
+    } else if is_column(right) {
+        (operator.flip(), left)
+    } else {
+        return None;
+    };
+    Some((operator, column_value(value, column)?))
+}
+
+// The bounds of `column BETWEEN low AND high`, with literal bounds
+fn column_between(term: &Expr, column: &Column) -> Option<(Value, Value)> {
+    match term {
+        Expr::Between {
+            expr,
+            low,
+            high,
+            negated: false,
+        } if matches!(&**expr, Expr::Column(name) if name.eq_ignore_ascii_case(&column.name)) => {
+            Some((column_value(low, column)?, column_value(high, column)?))
+        }
+        _ => None,
+    }
+}
+
+// A literal compared with a column, converted the way the comparison converts it
+fn column_value(expr: &Expr, column: &Column) -> Option<Value> {
+    literal_value(expr).map(|literal| Value::from_literal(&literal).apply_affinity(column.affinity))
+}
+
+/// Every column an expression refers to must exist.
+pub fn check_columns(schema: &TableSchema, expr: &Expr) -> Result<(), PrepareStatementErr> {
+    let check_columns = |expr| check_columns(schema, expr);
+    match expr {
+        Expr::Literal(_) | Expr::Parameter(_) => Ok(()),
+        Expr::Column(column) if schema.column_index(column).is_some() => Ok(()),
+        Expr::Column(column) => Err(PrepareStatementErr::NoSuchColumn(column.clone())),
+        Expr::Unary(_, expr) | Expr::IsNull { expr, .. } => check_columns(expr),
+        Expr::Binary(left, _, right)
+        | Expr::Like {
+            expr: left,
+            pattern: right,
+            ..
+        } => {
+            check_columns(left)?;
+            check_columns(right)
+        }
+        Expr::InList { expr, list, .. } => {
+            check_columns(expr)?;
+            list.iter().try_for_each(check_columns)
+        }
+        Expr::Between {
+            expr, low, high, ..
+        } => {
+            check_columns(expr)?;
+            check_columns(low)?;
+            check_columns(high)
+        }
+        Expr::Function { name, args } => match function_arity(name) {
+            Some(arity) if arity == args.len() => args.iter().try_for_each(check_columns),
+            Some(_) => Err(PrepareStatementErr::WrongArgumentCount(name.clone())),
+            None => Err(PrepareStatementErr::NoSuchFunction(name.clone())),
+        },
+    }
+}
+
+// Evaluate a literal, possibly with a sign in front of it
+fn literal_value(expr: &Expr) -> Option<Literal> {
+    match expr {
+        Expr::Literal(literal) => Some(literal.clone()),
+        Expr::Unary(UnaryOperator::Plus, expr) => literal_value(expr),
+        Expr::Unary(UnaryOperator::Negate, expr) => match literal_value(expr)? {
+            Literal::Integer(integer) => Some(Literal::Integer(-integer)),
+            Literal::Float(float) => Some(Literal::Float(-float)),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+// A literal compared with the key column, converted the way the
+// comparison converts it
+fn key_value(expr: &Expr) -> Option<Value> {
+    literal_value(expr)
+        .map(|literal| Value::from_literal(&literal).apply_affinity(Affinity::Integer))
+}
+
+// The value of a column an insert leaves out: its default, or NULL. A
+// NULL key is replaced by a new key when the row is inserted.
+fn default_value(schema: &TableSchema, column: &Column) -> Result<Value, PrepareStatementErr> {
+    match &column.default {
+        // A default is constant, so it needs no row or connection to be evaluated
+        Some(default) => {
+            let row = Row::new(Vec::new());
+            fit_value(column, evaluate(default, schema, &row, Context::default()))
+        }
+        None => Ok(Value::Null),
+    }
+}
+
+// The record of a row to write. The values of unbounded columns are only
+// limited by the size of the whole record.
+fn record(row: &Row) -> Result<Vec<u8>, PrepareStatementErr> {
+    let record = row.serialize();
+    if record.len() > MAX_RECORD_SIZE {
+        return Err(PrepareStatementErr::RowTooLarge(record.len()));
+    }
+    Ok(record)
+}
+
+fn fit_value(column: &Column, value: Value) -> Result<Value, PrepareStatementErr> {
+    let value = value.apply_affinity(column.affinity);
+    match &value {
+        Value::Integer(_) | Value::Null => Ok(value),
+        // The primary key is the key of the table's B-tree
+        _ if column.primary_key => Err(PrepareStatementErr::InvalidID),
+        Value::Text(text) if text.len() > column.max_size => {
+            Err(PrepareStatementErr::StringTooLong)
+        }
+        Value::Blob(blob) if blob.len() > column.max_size => {
+            Err(PrepareStatementErr::StringTooLong)
+        }
+        _ => Ok(value),
+    }
+}
+
+// The rows of a table whose column has the value, found through an index
+// on the column when there is one
+fn value_filter(table: &TableEntry, column: usize, value: &Value) -> Result<Filter, Error> {
+    let condition = Expr::Binary(
+        Box::new(Expr::Column(table.schema.columns[column].name.clone())),
+        BinaryOperator::Equal,
+        Box::new(Expr::Literal(value.to_literal())),
+    );
+    Ok(Filter::new(table.clone(), Some(condition))?)
+}
+
+// A pragma value naming a setting, written as a name or as a string
+fn pragma_name_value(expr: &Expr) -> String {
+    match (expr, literal_value(expr)) {
+        (Expr::Column(name), _) => name.clone(),
+        (_, Some(Literal::String(string))) => string,
+        (_, Some(Literal::Integer(integer))) => integer.to_string(),
+        (_, Some(Literal::Float(float))) => float.to_string(),
+        (_, Some(Literal::Null)) => "NULL".to_owned(),
+        (_, Some(Literal::Blob(blob))) => Value::Blob(blob).to_string(),
+        (_, None) => "expression".to_owned(),
+    }
+}
+
+fn bind_integer(clause: &'static str, expr: &Expr) -> Result<i64, PrepareStatementErr> {
+    match literal_value(expr) {
+        Some(Literal::Integer(integer)) => Ok(integer),
+        _ => Err(PrepareStatementErr::NotAnInteger(clause)),
+    }
+}
+
+#[derive(Debug)]
+pub enum PrepareStatementErr {
+    SyntaxError(ParseError),
     StringTooLong,
     InvalidID,
-    UnrecognizedStatement(Cow<'a, str>),
+    NoSuchTable(String),
+    NoSuchColumn(String),
+    ValueCountMismatch { values: usize, columns: usize },
+    ParameterCountMismatch { values: usize, parameters: usize },
+    ParameterOutOfRange(usize),
+    KeyNotUpdatable(String),
+    NotAnInteger(&'static str),
+    OrderByOutOfRange(i64),
+    TableExists(String),
+    DuplicateColumn(String),
+    UnsupportedType(String),
+    InvalidPrimaryKey(String),
+    MultiplePrimaryKeys,
+    MissingPrimaryKey,
+    RowTooLarge(usize),
+    SchemaTooLarge,
+    NameTooLong(String),
+    ReservedName(String),
+    ReadOnlyTable(String),
+    NotPositive(&'static str),
+    UnknownPragma(String),
+    UnknownJournalMode(String),
+    IndexKeyTooLarge(usize),
+    IndexExists(String),
+    NoSuchIndex(String),
+    NotConstant(String),
+    ForeignKeyMismatch(String),
+    ReferencedTable(String, String),
+    NoSuchFunction(String),
+    WrongArgumentCount(String),
+    NoTablesSpecified,
+}
+
+impl From<ParseError> for PrepareStatementErr {
+    fn from(err: ParseError) -> Self {
+        PrepareStatementErr::SyntaxError(err)
+    }
 }
 
-impl<'a> fmt::Display for PrepareStatementErr<'a> {
+impl fmt::Display for PrepareStatementErr {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match &self {
-            &PrepareStatementErr::SyntaxError => {
-                write!(f, "Syntax error: Could not parse statement.")
+        match self {
+            PrepareStatementErr::SyntaxError(err) => {
+                write!(f, "Syntax error at {}.", err)
             }
-            &PrepareStatementErr::StringTooLong => {
+            PrepareStatementErr::StringTooLong => {
                 write!(f, "String is too long.")
             }
-            &PrepareStatementErr::InvalidID => {
+            PrepareStatementErr::InvalidID => {
                 write!(f, "ID is invalid.")
             }
-            &PrepareStatementErr::UnrecognizedStatement(input_buffer) => {
-                write!(f, "Unrecognized keyword at start of '{}'.", input_buffer)
+            PrepareStatementErr::NoSuchTable(table) => {
+                write!(f, "No such table: {}.", table)
+            }
+            PrepareStatementErr::NoSuchColumn(column) => {
+                write!(f, "No such column: {}.", column)
+            }
+            PrepareStatementErr::ValueCountMismatch { values, columns } => {
+                write!(f, "{} values for {} columns.", values, columns)
+            }
+            PrepareStatementErr::ParameterCountMismatch { values, parameters } => {
+                write!(f, "{} values for {} parameters.", values, parameters)
+            }
+            PrepareStatementErr::ParameterOutOfRange(index) => {
+                write!(f, "Parameter index {} is out of range.", index)
+            }
+            PrepareStatementErr::KeyNotUpdatable(column) => {
+                write!(f, "The {} column cannot be updated.", column)
+            }
+            PrepareStatementErr::NotAnInteger(clause) => {
+                write!(f, "{} must be an integer.", clause)
+            }
+            PrepareStatementErr::OrderByOutOfRange(term) => {
+                write!(f, "ORDER BY term {} is out of range.", term)
+            }
+            PrepareStatementErr::TableExists(table) => {
+                write!(f, "Table {} already exists.", table)
+            }
+            PrepareStatementErr::DuplicateColumn(column) => {
+                write!(f, "Duplicate column name: {}.", column)
+            }
+            PrepareStatementErr::UnsupportedType(type_name) => {
+                write!(f, "Unsupported column type: {}.", type_name)
+            }
+            PrepareStatementErr::InvalidPrimaryKey(column) => {
+                write!(f, "Primary key column {} must be an INTEGER.", column)
+            }
+            PrepareStatementErr::MultiplePrimaryKeys => {
+                write!(f, "Table has more than one primary key.")
+            }
+            PrepareStatementErr::MissingPrimaryKey => {
+                write!(f, "Table must have an INTEGER PRIMARY KEY column.")
+            }
+            PrepareStatementErr::RowTooLarge(size) => {
+                write!(
+                    f,
+                    "Row size of {} bytes is larger than the maximum of {} bytes.",
+                    size, MAX_RECORD_SIZE
+                )
+            }
+            PrepareStatementErr::SchemaTooLarge => {
+                write!(f, "Table definition is too long.")
+            }
+            PrepareStatementErr::NameTooLong(name) => {
+                write!(
+                    f,
+                    "Name {} is longer than {} bytes.",
+                    name, CATALOG_NAME_MAX_SIZE
+                )
+            }
+            PrepareStatementErr::ReservedName(name) => {
+                write!(f, "Object name reserved for internal use: {}.", name)
+            }
+            PrepareStatementErr::ReadOnlyTable(table) => {
+                write!(f, "Table {} may not be modified.", table)
+            }
+            PrepareStatementErr::NotPositive(setting) => {
+                write!(f, "{} must be a positive integer.", setting)
+            }
+            PrepareStatementErr::UnknownPragma(name) => {
+                write!(f, "Unknown pragma: {}.", name)
+            }
+            PrepareStatementErr::UnknownJournalMode(name) => {
+                write!(f, "Unknown journal mode: {}.", name)
+            }
+            PrepareStatementErr::IndexKeyTooLarge(size) => {
+                write!(
+                    f,
+                    "Index key size of {} bytes is larger than the maximum of {} bytes.",
+                    size, INDEX_MAX_KEY_SIZE
+                )
+            }
+            PrepareStatementErr::IndexExists(index) => {
+                write!(f, "Index {} already exists.", index)
+            }
+            PrepareStatementErr::NoSuchIndex(index) => {
+                write!(f, "No such index: {}.", index)
+            }
+            PrepareStatementErr::NotConstant(column) => {
+                write!(f, "Default value of column {} is not constant.", column)
+            }
+            PrepareStatementErr::ForeignKeyMismatch(column) => write!(
+                f,
+                "Foreign key of {} does not reference a primary key or UNIQUE column.",
+                column
+            ),
+            PrepareStatementErr::ReferencedTable(table, child) => write!(
+                f,
+                "Table {} is referenced by a foreign key of table {}.",
+                table, child
+            ),
+            PrepareStatementErr::NoSuchFunction(name) => {
+                write!(f, "No such function: {}.", name)
+            }
+            PrepareStatementErr::WrongArgumentCount(name) => {
+                write!(f, "Wrong number of arguments to function {}().", name)
+            }
+            PrepareStatementErr::NoTablesSpecified => {
+                write!(f, "No tables specified.")
             }
         }
     }
 }
 
+pub enum ExecuteOk {
+    Executed,
+    RowsChanged(usize),
+    /// Result rows, each a list of values for the result columns.
+    Rows(Vec<Vec<Value>>),
+}
+
+#[derive(Debug)]
 pub enum ExecuteErr {
-    TableFull,
-    DuplicateKey,
+    TransactionActive,
+    NoTransaction,
+    NoSuchSavepoint(String),
+    FileExists(String),
 }
 
 impl fmt::Display for ExecuteErr {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match &self {
-            &ExecuteErr::TableFull => {
-                write!(f, "Error: Table full.")
+        match self {
+            ExecuteErr::TransactionActive => {
+                write!(f, "Error: A transaction is already active.")
+            }
+            ExecuteErr::NoTransaction => {
+                write!(f, "Error: No transaction is active.")
             }
-            &ExecuteErr::DuplicateKey => {
-                write!(f, "Error: Duplicate key.")
+            ExecuteErr::NoSuchSavepoint(name) => {
+                write!(f, "Error: No such savepoint: {}.", name)
+            }
+            ExecuteErr::FileExists(filename) => {
+                write!(f, "Error: Output file already exists: {}.", filename)
             }
         }
     }
 }
 
 impl Statement {
-    pub fn prepare_statement(input_buffer: &InputBuffer) -> Result<Self, PrepareStatementErr> {
-        match input_buffer.to_lowercase() {
-            buffer if buffer == "select" => {
-                let statement = Statement::Select;
-                Ok(statement)
+    /// Parse and plan a statement. Parameters in it are bound to NULL.
+    pub fn prepare_statement(sql: &str, database: &Database) -> Result<Self, Error> {
+        let mut statement = Parser::new(sql)?.parse_statement()?;
+        statement.bind_parameters(&|_| Literal::Null);
+        Self::plan(statement, database)
+    }
+
+    /// Plan a parsed statement whose parameters have been bound.
+    pub fn plan(statement: ast::Statement, database: &Database) -> Result<Self, Error> {
+        let prepared = match statement {
+            ast::Statement::Select(select) => Self::prepare_select(select, database),
+            ast::Statement::Insert(insert) => Self::prepare_insert(insert, database),
+            ast::Statement::Update(update) => Self::prepare_update(update, database),
+            ast::Statement::Delete(delete) => {
+                let table = Self::find_writable_table(&delete.table, database)?;
+                Ok(Statement::Delete(Filter::new(table, delete.where_clause)?))
             }
-            buffer if buffer.starts_with("insert") => {
-                let row = buffer[7..].parse::<Row>()?;
-                let statement = Statement::Insert(row);
-                Ok(statement)
+            ast::Statement::CreateTable(create) => Self::prepare_create_table(create, database),
+            ast::Statement::DropTable(drop) => Self::prepare_drop_table(drop, database),
+            ast::Statement::CreateIndex(create) => Self::prepare_create_index(create, database),
+            ast::Statement::DropIndex(drop) => Self::prepare_drop_index(drop, database),
+            ast::Statement::Pragma(pragma) => Self::prepare_pragma(pragma, database),
+            ast::Statement::Begin => Ok(Statement::Begin),
+            ast::Statement::Commit => Ok(Statement::Commit),
+            ast::Statement::Rollback(savepoint) => Ok(Statement::Rollback(savepoint)),
+            ast::Statement::Savepoint(name) => Ok(Statement::Savepoint(name)),
+            ast::Statement::Release(name) => Ok(Statement::Release(name)),
+            ast::Statement::Vacuum(filename) => Ok(Statement::Vacuum(filename)),
+        };
+        prepared.map_err(Error::from)
+    }
+
+    fn find_table(name: &str, database: &Database) -> Result<TableEntry, PrepareStatementErr> {
+        database
+            .find_table(name)
+            .cloned()
+            .ok_or_else(|| PrepareStatementErr::NoSuchTable(name.to_owned()))
+    }
+
+    // The catalog is only changed by creating and dropping tables and indexes
+    fn find_writable_table(
+        name: &str,
+        database: &Database,
+    ) -> Result<TableEntry, PrepareStatementErr> {
+        let table = Self::find_table(name, database)?;
+        if table.schema.name == CATALOG_TABLE_NAME {
+            return Err(PrepareStatementErr::ReadOnlyTable(table.schema.name));
+        }
+        Ok(table)
+    }
+
+    fn prepare_create_table(
+        create: ast::CreateTable,
+        database: &Database,
+    ) -> Result<Self, PrepareStatementErr> {
+        if create.name.to_lowercase().starts_with("sqlite_") {
+            return Err(PrepareStatementErr::ReservedName(create.name));
+        }
+        if database.find_table(&create.name).is_some() {
+            return Err(PrepareStatementErr::TableExists(create.name));
+        }
+        if database.find_index(&create.name).is_some() {
+            return Err(PrepareStatementErr::IndexExists(create.name));
+        }
+
+        let schema = TableSchema::from_create_table(create)?;
+        // A foreign key references the primary key or a UNIQUE column, of
+        // another table or of the new table itself
+        for (column, foreign_key) in schema.foreign_keys() {
+            let (parent, indexes) = if foreign_key.table.eq_ignore_ascii_case(&schema.name) {
+                (&schema, &[][..])
+            } else {
+                let parent = database
+                    .tables()
+                    .iter()
+                    .find(|parent| parent.schema.name.eq_ignore_ascii_case(&foreign_key.table))
+                    .ok_or_else(|| PrepareStatementErr::NoSuchTable(foreign_key.table.clone()))?;
+                (&parent.schema, parent.indexes.as_slice())
+            };
+            let Some(parent_column) = parent.referenced_column(foreign_key) else {
+                let name = foreign_key.column.clone().unwrap_or_default();
+                return Err(PrepareStatementErr::NoSuchColumn(name));
+            };
+            let is_unique = parent.columns[parent_column].primary_key
+                || parent.columns[parent_column].unique
+                || indexes
+                    .iter()
+                    .any(|index| index.schema.unique && index.schema.columns == [parent_column]);
+            if !is_unique {
+                let name = schema.column_name(&schema.columns[column]);
+                return Err(PrepareStatementErr::ForeignKeyMismatch(name));
+            }
+        }
+        Ok(Statement::CreateTable(schema))
+    }
+
+    fn prepare_drop_table(
+        drop: ast::DropTable,
+        database: &Database,
+    ) -> Result<Self, PrepareStatementErr> {
+        match Self::find_writable_table(&drop.name, database) {
+            // It goes with the AUTOINCREMENT tables
+            Ok(table) if table.schema.name == SEQUENCE_TABLE_NAME => {
+                Err(PrepareStatementErr::ReservedName(table.schema.name))
+            }
+            Ok(table) => {
+                // Its rows would be left without their parents
+                let child = database.tables().iter().find(|child| {
+                    child.schema.name != table.schema.name
+                        && child.schema.foreign_keys().any(|(_, foreign_key)| {
+                            foreign_key.table.eq_ignore_ascii_case(&table.schema.name)
+                        })
+                });
+                if let Some(child) = child {
+                    let child = child.schema.name.clone();
+                    return Err(PrepareStatementErr::ReferencedTable(
+                        table.schema.name,
+                        child,
+                    ));
+                }
+                Ok(Statement::DropTable(Some(table.schema.name)))
+            }
+            Err(PrepareStatementErr::NoSuchTable(_)) if drop.if_exists => {
+                Ok(Statement::DropTable(None))
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    fn prepare_create_index(
+        create: ast::CreateIndex,
+        database: &Database,
+    ) -> Result<Self, PrepareStatementErr> {
+        let table = Self::find_writable_table(&create.table, database)?;
+        let schema = IndexSchema::from_create_index(create, &table.schema)?;
+        if schema.name.to_lowercase().starts_with("sqlite_") {
+            return Err(PrepareStatementErr::ReservedName(schema.name));
+        }
+        if database.find_index(&schema.name).is_some() {
+            return Err(PrepareStatementErr::IndexExists(schema.name));
+        }
+        if database.find_table(&schema.name).is_some() {
+            return Err(PrepareStatementErr::TableExists(schema.name));
+        }
+        Ok(Statement::CreateIndex(schema))
+    }
+
+    fn prepare_drop_index(
+        drop: ast::DropIndex,
+        database: &Database,
+    ) -> Result<Self, PrepareStatementErr> {
+        match database.find_index(&drop.name) {
+            // It goes with its table
+            Some((_, index)) if index.schema.is_automatic() => {
+                Err(PrepareStatementErr::ReservedName(index.schema.name.clone()))
+            }
+            Some((_, index)) => Ok(Statement::DropIndex(Some(index.schema.name.clone()))),
+            None if drop.if_exists => Ok(Statement::DropIndex(None)),
+            None => Err(PrepareStatementErr::NoSuchIndex(drop.name)),
+        }
+    }
+
+    fn prepare_pragma(
+        pragma: ast::Pragma,
+        database: &Database,
+    ) -> Result<Self, PrepareStatementErr> {
+        match pragma.name.to_lowercase().as_str() {
+            "cache_size" => {
+                let cache_size = match pragma.value {
+                    Some(expr) => Some(
+                        usize::try_from(bind_integer("cache_size", &expr)?)
+                            .ok()
+                            .filter(|&cache_size| cache_size > 0)
+                            .ok_or(PrepareStatementErr::NotPositive("cache_size"))?,
+                    ),
+                    None => None,
+                };
+                Ok(Statement::Pragma(Pragma::CacheSize(cache_size)))
+            }
+            "journal_mode" => {
+                let journal_mode = match pragma.value {
+                    Some(expr) => {
+                        let name = pragma_name_value(&expr);
+                        Some(
+                            JournalMode::from_name(&name)
+                                .ok_or(PrepareStatementErr::UnknownJournalMode(name))?,
+                        )
+                    }
+                    None => None,
+                };
+                Ok(Statement::Pragma(Pragma::JournalMode(journal_mode)))
+            }
+            "wal_checkpoint" => Ok(Statement::Pragma(Pragma::WalCheckpoint)),
+            "foreign_key_check" => {
+                let table = match pragma.value {
+                    Some(expr) => {
+                        let table = Self::find_table(&pragma_name_value(&expr), database)?;
+                        Some(table.schema.name)
+                    }
+                    None => None,
+                };
+                Ok(Statement::Pragma(Pragma::ForeignKeyCheck(table)))
+            }
+            _ => Err(PrepareStatementErr::UnknownPragma(pragma.name)),
+        }
+    }
+
+    fn prepare_values(columns: Vec<ast::ResultColumn>) -> Result<Self, PrepareStatementErr> {
+        let schema = TableSchema::empty();
+        let mut values = Vec::new();
+        for column in columns {
+            match column {
+                ast::ResultColumn::Wildcard => return Err(PrepareStatementErr::NoTablesSpecified),
+                ast::ResultColumn::Expr(expr) => {
+                    check_columns(&schema, &expr)?;
+                    values.push(expr);
+                }
             }
-            _ => Err(PrepareStatementErr::UnrecognizedStatement(Cow::Borrowed(
-                &input_buffer[..],
-            ))),
         }
+        Ok(Statement::Values(values))
     }
 
-    pub fn execute_statement(self, table: &mut Table) -> Result<(), ExecuteErr> {
+    fn prepare_select(
+        select: ast::Select,
+        database: &Database,
+    ) -> Result<Self, PrepareStatementErr> {
+        let Some(table) = &select.table else {
+            return Self::prepare_values(select.columns);
+        };
+        let table = Self::find_table(table, database)?;
+        let schema = &table.schema;
+
+        let mut columns = Vec::new();
+        for column in select.columns {
+            match column {
+                ast::ResultColumn::Wildcard => columns.extend(
+                    schema
+                        .columns
+                        .iter()
+                        .map(|column| Expr::Column(column.name.clone())),
+                ),
+                ast::ResultColumn::Expr(expr) => {
+                    check_columns(schema, &expr)?;
+                    columns.push(expr);
+                }
+            }
+        }
+
+        let mut order_by = Vec::new();
+        for term in select.order_by {
+            // An integer refers to a result column, counting from 1
+            let expr = match literal_value(&term.expr) {
+                Some(Literal::Integer(index)) => usize::try_from(index)
+                    .ok()
+                    .and_then(|index| columns.get(index.checked_sub(1)?))
+                    .cloned()
+                    .ok_or(PrepareStatementErr::OrderByOutOfRange(index))?,
+                _ => term.expr,
+            };
+            check_columns(schema, &expr)?;
+            order_by.push((expr, term.descending));
+        }
+
+        let filter = Filter::new(table, select.where_clause)?;
+
+        // The table is stored in key order, and keys are unique so
+        // any terms after the key cannot change the order. Rows looked
+        // up through an index come in the index's order instead.
+        if let Some((Expr::Column(column), false)) = order_by.first() {
+            let schema = &filter.table.schema;
+            if column.eq_ignore_ascii_case(&schema.key_column_name())
+                && filter.index_range.is_none()
+            {
+                order_by.clear();
+            }
+        }
+
+        // A negative limit means no limit, a negative offset means no offset
+        let limit = match select.limit {
+            Some(limit) => usize::try_from(bind_integer("LIMIT", &limit)?).ok(),
+            None => None,
+        };
+        let offset = match select.offset {
+            Some(offset) => usize::try_from(bind_integer("OFFSET", &offset)?).unwrap_or(0),
+            None => 0,
+        };
+
+        Ok(Statement::Select(Select {
+            columns,
+            filter,
+            order_by,
+            limit,
+            offset,
+        }))
+    }
+
+    fn prepare_insert(
+        insert: ast::Insert,
+        database: &Database,
+    ) -> Result<Self, PrepareStatementErr> {
+        let table = Self::find_writable_table(&insert.table, database)?;
+        let schema = &table.schema;
+
+        let columns = match insert.columns {
+            Some(columns) => columns,
+            None => schema
+                .columns
+                .iter()
+                .map(|column| column.name.clone())
+                .collect(),
+        };
+
+        for column in columns.iter() {
+            if schema.column_index(column).is_none() {
+                return Err(PrepareStatementErr::NoSuchColumn(column.clone()));
+            }
+        }
+
+        let mut rows = Vec::new();
+        for values in insert.rows {
+            if values.len() != columns.len() {
+                return Err(PrepareStatementErr::ValueCountMismatch {
+                    values: values.len(),
+                    columns: columns.len(),
+                });
+            }
+
+            let mut row_values = Vec::new();
+            for column in schema.columns.iter() {
+                let index = columns
+                    .iter()
+                    .position(|name| name.eq_ignore_ascii_case(&column.name));
+                let value = match index {
+                    // There is no row yet for a value to read columns from
+                    Some(index) => {
+                        check_columns(&TableSchema::empty(), &values[index])?;
+                        values[index].clone()
+                    }
+                    None => Expr::Literal(default_value(schema, column)?.to_literal()),
+                };
+                row_values.push(value);
+            }
+            rows.push(row_values);
+        }
+
+        Ok(Statement::Insert(table, rows))
+    }
+
+    fn prepare_update(
+        update: ast::Update,
+        database: &Database,
+    ) -> Result<Self, PrepareStatementErr> {
+        let table = Self::find_writable_table(&update.table, database)?;
+        let schema = &table.schema;
+
+        let mut assignments = Vec::new();
+        for (name, value) in update.assignments {
+            let Some(index) = schema.column_index(&name) else {
+                return Err(PrepareStatementErr::NoSuchColumn(name));
+            };
+            let column = &schema.columns[index];
+            if column.primary_key {
+                return Err(PrepareStatementErr::KeyNotUpdatable(column.name.clone()));
+            }
+
+            check_columns(schema, &value)?;
+            assignments.push(Assignment {
+                column: index,
+                value,
+            });
+        }
+
+        let filter = Filter::new(table, update.where_clause)?;
+
+        Ok(Statement::Update(assignments, filter))
+    }
+
+    /// Run the statement. The rows of a select are all read into memory,
+    /// use `Rows::select` to read them one at a time instead.
+    pub fn execute_statement(&self, database: &mut Database) -> Result<ExecuteOk, Error> {
         match self {
-            Statement::Select => Self::execute_select(table),
-            Statement::Insert(row) => Self::execute_insert(row, table),
+            Statement::CreateTable(schema) => {
+                database.create_table(schema.clone())?;
+                Ok(ExecuteOk::Executed)
+            }
+            Statement::DropTable(name) => {
+                if let Some(name) = name {
+                    database.drop_table(name)?;
+                }
+                Ok(ExecuteOk::Executed)
+            }
+            Statement::CreateIndex(schema) => {
+                database.create_index(schema.clone())?;
+                Ok(ExecuteOk::Executed)
+            }
+            Statement::DropIndex(name) => {
+                if let Some(name) = name {
+                    database.drop_index(name)?;
+                }
+                Ok(ExecuteOk::Executed)
+            }
+            Statement::Values(values) => {
+                let schema = TableSchema::empty();
+                let row = Row::new(Vec::new());
+                let context = database.context();
+                Ok(ExecuteOk::Rows(vec![values
+                    .iter()
+                    .map(|value| evaluate(value, &schema, &row, context))
+                    .collect()]))
+            }
+            Statement::Select(select) if select.order_by.is_empty() => {
+                let mut scan = SelectScan::new(select);
+                let mut rows = Vec::new();
+                while let Some(values) = scan.next_row(select, database)? {
+                    rows.push(values);
+                }
+                Ok(ExecuteOk::Rows(rows))
+            }
+            Statement::Select(select) => {
+                let rows = sort_rows(select, database)?.collect::<Result<_, _>>()?;
+                Ok(ExecuteOk::Rows(rows))
+            }
+            Statement::Insert(table, rows) => {
+                database.run_statement(|database| Self::execute_insert(table, rows, database))
+            }
+            Statement::Delete(filter) => {
+                database.run_statement(|database| Self::execute_delete(filter, database))
+            }
+            Statement::Update(assignments, filter) => database
+                .run_statement(|database| Self::execute_update(assignments, filter, database)),
+            Statement::Pragma(Pragma::CacheSize(cache_size)) => match cache_size {
+                Some(cache_size) => {
+                    database.pager.set_cache_size(*cache_size)?;
+                    Ok(ExecuteOk::Executed)
+                }
+                None => {
+                    let cache_size = Value::Integer(database.pager.cache_size() as i64);
+                    Ok(ExecuteOk::Rows(vec![vec![cache_size]]))
+                }
+            },
+            Statement::Pragma(Pragma::JournalMode(journal_mode)) => {
+                if journal_mode.is_some() && database.in_transaction() {
+                    return Err(ExecuteErr::TransactionActive.into());
+                }
+                if let Some(journal_mode) = journal_mode {
+                    database.pager.set_journal_mode(*journal_mode)?;
+                }
+                let name = database.pager.journal_mode().name();
+                Ok(ExecuteOk::Rows(vec![vec![Value::Text(name.to_owned())]]))
+            }
+            Statement::Begin => {
+                if database.in_transaction() {
+                    return Err(ExecuteErr::TransactionActive.into());
+                }
+                database.begin();
+                Ok(ExecuteOk::Executed)
+            }
+            Statement::Commit => {
+                if !database.in_transaction() {
+                    return Err(ExecuteErr::NoTransaction.into());
+                }
+                Self::check_deferred_foreign_keys(database)?;
+                database.commit()?;
+                Ok(ExecuteOk::Executed)
+            }
+            Statement::Rollback(None) => {
+                if !database.in_transaction() {
+                    return Err(ExecuteErr::NoTransaction.into());
+                }
+                database.roll_back()?;
+                Ok(ExecuteOk::Executed)
+            }
+            Statement::Rollback(Some(name)) => {
+                if !database.has_savepoint(name) {
+                    return Err(ExecuteErr::NoSuchSavepoint(name.clone()).into());
+                }
+                database.roll_back_to_savepoint(name)?;
+                Ok(ExecuteOk::Executed)
+            }
+            Statement::Savepoint(name) => {
+                database.savepoint(name.clone());
+                Ok(ExecuteOk::Executed)
+            }
+            Statement::Release(name) => {
+                if !database.has_savepoint(name) {
+                    return Err(ExecuteErr::NoSuchSavepoint(name.clone()).into());
+                }
+                if database.release_commits(name) {
+                    Self::check_deferred_foreign_keys(database)?;
+                }
+                database.release_savepoint(name)?;
+                Ok(ExecuteOk::Executed)
+            }
+            Statement::Pragma(Pragma::WalCheckpoint) => {
+                let pages_copied = database.pager.checkpoint()?;
+                Ok(ExecuteOk::Rows(vec![vec![Value::Integer(
+                    pages_copied as i64,
+                )]]))
+            }
+            Statement::Pragma(Pragma::ForeignKeyCheck(table)) => {
+                Self::foreign_key_check(table.as_deref(), database)
+            }
+            Statement::Vacuum(filename) => {
+                if database.in_transaction() {
+                    return Err(ExecuteErr::TransactionActive.into());
+                }
+                match filename {
+                    Some(filename) => {
+                        let exists =
+                            std::fs::metadata(filename).is_ok_and(|metadata| metadata.len() > 0);
+                        if exists {
+                            return Err(ExecuteErr::FileExists(filename.clone()).into());
+                        }
+                        database.vacuum_into(filename)?;
+                    }
+                    None => database.vacuum()?,
+                }
+                Ok(ExecuteOk::Executed)
+            }
+        }
+    }
+
+    fn execute_insert(
+        table: &TableEntry,
+        rows: &[Vec<Expr>],
+        database: &mut Database,
+    ) -> Result<ExecuteOk, Error> {
+        let schema = &table.schema;
+        let key_column = schema.key_column();
+        let mut checks = Vec::new();
+        for values in rows {
+            let context = database.context();
+            let values = schema
+                .columns
+                .iter()
+                .zip(values)
+                .map(|(column, value)| {
+                    let value =
+                        evaluate(value, &TableSchema::empty(), &Row::new(Vec::new()), context);
+                    fit_value(column, value)
+                })
+                .collect::<Result<_, _>>()?;
+            let mut row = Row::new(values);
+            // A row inserted without a key gets the next one
+            if row.values[key_column].is_null() {
+                let key = database.next_key(table)?;
+                row.values[key_column] = Value::Integer(key);
+            }
+            let key_to_insert = row.key(schema);
+            schema.check_row(&row, database.context())?;
+            let mut cursor =
+                Cursor::table_find(database.table(table.root_page_num), key_to_insert)?;
+
+            if cursor.is_at_key(key_to_insert)? {
+                return Err(ConstraintErr::DuplicateKey.into());
+            }
+            Self::check_unique(table, &row, database)?;
+
+            let mut cursor =
+                Cursor::table_find(database.table(table.root_page_num), key_to_insert)?;
+            cursor.leaf_node_insert(key_to_insert, record(&row)?)?;
+            for index in table.indexes.iter() {
+                let key = index.schema.key(schema, &row);
+                database.index(index.root_page_num).insert(&key)?;
+            }
+            if schema.columns[key_column].autoincrement {
+                database.update_sequence(table, key_to_insert)?;
+            }
+            database.set_last_insert_rowid(key_to_insert);
+            Self::child_checks(table, &row, None, &mut checks);
         }
+        Self::check_foreign_keys(checks, database)?;
+        Ok(ExecuteOk::RowsChanged(rows.len()))
     }
 
-    fn execute_select(table: &mut Table) -> Result<(), ExecuteErr> {
-        let mut cursor = Cursor::table_start(table);
-        while !cursor.end_of_table {
-            let row = cursor.value();
-            println!("{}", row);
-            cursor.advance();
+    // Fail if another row has the same values as a row in the columns of
+    // a unique index. Checked before the row is written, so that a failed
+    // statement leaves the row as it was.
+    fn check_unique(table: &TableEntry, row: &Row, database: &mut Database) -> Result<(), Error> {
+        let key = row.key(&table.schema);
+        for index in table.indexes.iter().filter(|index| index.schema.unique) {
+            let index_key = index.schema.key(&table.schema, row);
+            let values = &index_key[..index.schema.columns.len()];
+            // NULL is never equal to anything, not even another NULL
+            if values.iter().any(Value::is_null) {
+                continue;
+            }
+
+            let mut start = Bound::Included(values.to_vec());
+            while let Some(found) = database
+                .index(index.root_page_num)
+                .seek(start.as_ref().map(Vec::as_slice))?
+            {
+                if compare_prefix(&found, values).is_ne() {
+                    break;
+                }
+                // The row itself, as it is before an update
+                if found.last() != Some(&Value::Integer(key)) {
+                    let columns = index.schema.column_names(&table.schema);
+                    return Err(ConstraintErr::Unique(columns).into());
+                }
+                start = Bound::Excluded(found);
+            }
         }
         Ok(())
     }
 
-    fn execute_insert(row: Row, table: &mut Table) -> Result<(), ExecuteErr> {
-        let node = table.pager.get_page(table.root_page_num);
-        let num_cells = *node.num_cell_or_keys();
+    fn execute_delete(filter: &Filter, database: &mut Database) -> Result<ExecuteOk, Error> {
+        // Collect the keys first, deleting rebalances the tree under the cursor.
+        // Rows are deleted in key order, whichever order the scan found them
+        // in, for the foreign key actions of one to be seen by the next.
+        let mut keys_to_delete = Self::matching_keys(filter, database)?;
+        keys_to_delete.sort_unstable();
+        let rows_changed = keys_to_delete.len();
+
+        let mut checks = Vec::new();
+        Self::delete_rows(&filter.table, &keys_to_delete, &mut checks, database)?;
+        Self::check_foreign_keys(checks, database)?;
+        Ok(ExecuteOk::RowsChanged(rows_changed))
+    }
+
+    fn execute_update(
+        assignments: &[Assignment],
+        filter: &Filter,
+        database: &mut Database,
+    ) -> Result<ExecuteOk, Error> {
+        let mut keys_to_update = Self::matching_keys(filter, database)?;
+        keys_to_update.sort_unstable();
+        let rows_changed = keys_to_update.len();
 
-        let key_to_insert = row.id;
-        let mut cursor = Cursor::table_find(table, key_to_insert);
-        let cell_num = cursor.cell_num;
+        let mut checks = Vec::new();
+        Self::update_rows(
+            &filter.table,
+            &keys_to_update,
+            assignments,
+            &mut checks,
+            database,
+        )?;
+        Self::check_foreign_keys(checks, database)?;
+        Ok(ExecuteOk::RowsChanged(rows_changed))
+    }
 
-        if cell_num < num_cells {
-            let node = cursor.table.pager.get_page(cursor.table.root_page_num);
-            let key_at_index = *node.node_key(cell_num);
+    // Delete the rows with the given keys and carry out the ON DELETE
+    // actions of the foreign keys referencing them. A row deleted by one
+    // of those actions already is skipped.
+    fn delete_rows(
+        table: &TableEntry,
+        keys: &[i64],
+        checks: &mut Vec<ForeignKeyCheck>,
+        database: &mut Database,
+    ) -> Result<(), Error> {
+        let references = Self::references_to(&table.schema, database);
+        for &key in keys {
+            let mut cursor = Cursor::table_find(database.table(table.root_page_num), key)?;
+            if !cursor.is_at_key(key)? {
+                continue;
+            }
+            if table.indexes.is_empty() && references.is_empty() {
+                cursor.leaf_node_delete()?;
+                continue;
+            }
 
-            if key_at_index == key_to_insert {
-                return Err(ExecuteErr::DuplicateKey);
+            let row = Row::deserialize(&table.schema, &cursor.value()?)?;
+            for index in table.indexes.iter() {
+                let index_key = index.schema.key(&table.schema, &row);
+                database.index(index.root_page_num).delete(&index_key)?;
             }
+            let mut cursor = Cursor::table_find(database.table(table.root_page_num), key)?;
+            cursor.leaf_node_delete()?;
+            Self::parent_actions(&references, &table.schema, &row, None, checks, database)?;
+        }
+        Ok(())
+    }
+
+    // Change the rows with the given keys and carry out the ON UPDATE
+    // actions of the foreign keys referencing the values changed
+    fn update_rows(
+        table: &TableEntry,
+        keys: &[i64],
+        assignments: &[Assignment],
+        checks: &mut Vec<ForeignKeyCheck>,
+        database: &mut Database,
+    ) -> Result<(), Error> {
+        // Only an action can get here with the key column, when the foreign key is on it
+        let key_column = table.schema.key_column();
+        if assignments
+            .iter()
+            .any(|assignment| assignment.column == key_column)
+        {
+            let column = table.schema.key_column_name();
+            return Err(PrepareStatementErr::KeyNotUpdatable(column).into());
         }
 
-        cursor.leaf_node_insert(row.id, row);
+        let references = Self::references_to(&table.schema, database);
+        for &key in keys {
+            let mut cursor = Cursor::table_find(database.table(table.root_page_num), key)?;
+            if !cursor.is_at_key(key)? {
+                continue;
+            }
+            let old_row = Row::deserialize(&table.schema, &cursor.value()?)?;
+            let mut row = old_row.clone();
+            let context = database.context();
+            for assignment in assignments.iter() {
+                let column = &table.schema.columns[assignment.column];
+                let value = evaluate(&assignment.value, &table.schema, &old_row, context);
+                row.values[assignment.column] = fit_value(column, value)?;
+            }
+            table.schema.check_row(&row, database.context())?;
+            Self::check_unique(table, &row, database)?;
+
+            for index in table.indexes.iter() {
+                let old_key = index.schema.key(&table.schema, &old_row);
+                let new_key = index.schema.key(&table.schema, &row);
+                if old_key != new_key {
+                    let mut index = database.index(index.root_page_num);
+                    index.delete(&old_key)?;
+                    index.insert(&new_key)?;
+                }
+            }
+            let mut cursor = Cursor::table_find(database.table(table.root_page_num), key)?;
+            cursor.set_value(record(&row)?)?;
+
+            Self::child_checks(table, &row, Some(&old_row), checks);
+            let new_row = Some(&row);
+            Self::parent_actions(
+                &references,
+                &table.schema,
+                &old_row,
+                new_row,
+                checks,
+                database,
+            )?;
+        }
         Ok(())
     }
+
+    // The foreign keys referencing a table, each with its table and column
+    fn references_to(
+        parent: &TableSchema,
+        database: &Database,
+    ) -> Vec<(TableEntry, usize, ForeignKey)> {
+        let mut references = Vec::new();
+        for child in database.tables() {
+            for (column, foreign_key) in child.schema.foreign_keys() {
+                if foreign_key.table.eq_ignore_ascii_case(&parent.name) {
+                    references.push((child.clone(), column, foreign_key.clone()));
+                }
+            }
+        }
+        references
+    }
+
+    // Leave a check that a row written by an insert or update has a parent
+    // for each of its foreign keys, unless its value is NULL or the update
+    // left it as it was
+    fn child_checks(
+        table: &TableEntry,
+        row: &Row,
+        old_row: Option<&Row>,
+        checks: &mut Vec<ForeignKeyCheck>,
+    ) {
+        for (column, _) in table.schema.foreign_keys() {
+            let value = &row.values[column];
+            if value.is_null() || old_row.is_some_and(|old_row| old_row.values[column] == *value) {
+                continue;
+            }
+            checks.push(ForeignKeyCheck::Row {
+                table: table.schema.name.clone(),
+                key: row.key(&table.schema),
+                column,
+            });
+        }
+    }
+
+    // Do what the foreign keys referencing a row do when it is deleted, or
+    // when an update changes the value they reference to that of `new_row`
+    fn parent_actions(
+        references: &[(TableEntry, usize, ForeignKey)],
+        parent: &TableSchema,
+        old_row: &Row,
+        new_row: Option<&Row>,
+        checks: &mut Vec<ForeignKeyCheck>,
+        database: &mut Database,
+    ) -> Result<(), Error> {
+        // From the last foreign key declared to the first, as in SQLite. An
+        // action may change the rows a later RESTRICT would have failed on.
+        for (child, column, foreign_key) in references.iter().rev() {
+            let Some(parent_column) = parent.referenced_column(foreign_key) else {
+                continue;
+            };
+            let old_value = &old_row.values[parent_column];
+            let new_value = new_row.map(|row| &row.values[parent_column]);
+            if old_value.is_null() || new_value == Some(old_value) {
+                continue;
+            }
+            let keys = Self::matching_keys(&value_filter(child, *column, old_value)?, database)?;
+            if keys.is_empty() {
+                continue;
+            }
+
+            let child_column = &child.schema.columns[*column];
+            let action = match new_row {
+                Some(_) => foreign_key.on_update,
+                None => foreign_key.on_delete,
+            };
+            if action == ForeignKeyAction::Restrict {
+                let name = child.schema.column_name(child_column);
+                return Err(ConstraintErr::ForeignKey(name).into());
+            }
+            // Rows may still reference the value after the action, when
+            // there is none or their default is the value
+            checks.push(ForeignKeyCheck::Value {
+                table: child.schema.name.clone(),
+                column: *column,
+                value: old_value.clone(),
+            });
+            let value = match (action, new_value) {
+                (ForeignKeyAction::NoAction | ForeignKeyAction::Restrict, _) => continue,
+                (ForeignKeyAction::Cascade, None) => {
+                    Self::delete_rows(child, &keys, checks, database)?;
+                    continue;
+                }
+                (ForeignKeyAction::Cascade, Some(value)) => fit_value(child_column, value.clone())?,
+                (ForeignKeyAction::SetNull, _) => Value::Null,
+                (ForeignKeyAction::SetDefault, _) => default_value(&child.schema, child_column)?,
+            };
+            let assignments = [Assignment {
+                column: *column,
+                value: Expr::Literal(value.to_literal()),
+            }];
+            Self::update_rows(child, &keys, &assignments, checks, database)?;
+        }
+        Ok(())
+    }
+
+    // Make the foreign key checks a statement left for its end. Inside a
+    // transaction those of deferred foreign keys wait for the commit.
+    fn check_foreign_keys(
+        checks: Vec<ForeignKeyCheck>,
+        database: &mut Database,
+    ) -> Result<(), Error> {
+        let mut deferred = Vec::new();
+        for check in checks {
+            let (ForeignKeyCheck::Row { table, column, .. }
+            | ForeignKeyCheck::Value { table, column, .. }) = &check;
+            let is_deferred = database
+                .find_table(table)
+                .and_then(|table| table.schema.columns[*column].references.as_ref())
+                .is_some_and(|foreign_key| foreign_key.deferred);
+            if is_deferred && database.in_transaction() {
+                deferred.push(check);
+            } else {
+                Self::verify_foreign_key(&check, database)?;
+            }
+        }
+        database.defer_foreign_key_checks(deferred);
+        Ok(())
+    }
+
+    // Make the checks of deferred foreign keys, before the transaction commits
+    fn check_deferred_foreign_keys(database: &mut Database) -> Result<(), Error> {
+        for check in database.deferred_foreign_key_checks().to_vec() {
+            Self::verify_foreign_key(&check, database)?;
+        }
+        Ok(())
+    }
+
+    fn verify_foreign_key(check: &ForeignKeyCheck, database: &mut Database) -> Result<(), Error> {
+        let (ForeignKeyCheck::Row { table, column, .. }
+        | ForeignKeyCheck::Value { table, column, .. }) = check;
+        // The table may have been dropped since, and another created in its place
+        let Some(child) = database.find_table(table).cloned() else {
+            return Ok(());
+        };
+        let Some(foreign_key) = child
+            .schema
+            .columns
+            .get(*column)
+            .and_then(|column| column.references.clone())
+        else {
+            return Ok(());
+        };
+
+        let value = match check {
+            ForeignKeyCheck::Row { key, .. } => {
+                let mut cursor = Cursor::table_find(database.table(child.root_page_num), *key)?;
+                if !cursor.is_at_key(*key)? {
+                    return Ok(());
+                }
+                let row = Row::deserialize(&child.schema, &cursor.value()?)?;
+                row.values[*column].clone()
+            }
+            ForeignKeyCheck::Value { value, .. } => {
+                let filter = value_filter(&child, *column, value)?;
+                if Scan::new(&filter).next_row(&filter, database)?.is_none() {
+                    return Ok(());
+                }
+                value.clone()
+            }
+        };
+        if value.is_null() || Self::has_parent(&foreign_key, &value, database)? {
+            return Ok(());
+        }
+        let name = child.schema.column_name(&child.schema.columns[*column]);
+        Err(ConstraintErr::ForeignKey(name).into())
+    }
+
+    // Whether the parent table of a foreign key has a row with the value
+    fn has_parent(
+        foreign_key: &ForeignKey,
+        value: &Value,
+        database: &mut Database,
+    ) -> Result<bool, Error> {
+        let Some(parent) = database.find_table(&foreign_key.table).cloned() else {
+            return Ok(false);
+        };
+        let Some(column) = parent.schema.referenced_column(foreign_key) else {
+            return Ok(false);
+        };
+        let filter = value_filter(&parent, column, value)?;
+        Ok(Scan::new(&filter).next_row(&filter, database)?.is_some())
+    }
+
+    // The rows whose foreign keys have no parent, as rows of the table's
+    // name, the row's key, the parent table's name and the foreign key's
+    // number among those of the table
+    fn foreign_key_check(table: Option<&str>, database: &mut Database) -> Result<ExecuteOk, Error> {
+        let children: Vec<TableEntry> = database
+            .tables()
+            .iter()
+            .filter(|child| table.is_none_or(|table| child.schema.name == table))
+            .cloned()
+            .collect();
+
+        let mut rows = Vec::new();
+        for child in children {
+            let foreign_keys: Vec<_> = child
+                .schema
+                .foreign_keys()
+                .map(|(column, foreign_key)| (column, foreign_key.clone()))
+                .collect();
+            if foreign_keys.is_empty() {
+                continue;
+            }
+
+            // Read the rows first, the lookups need the pager the cursor holds
+            let mut child_rows = Vec::new();
+            let mut cursor = Cursor::table_start(database.table(child.root_page_num))?;
+            while !cursor.end_of_table {
+                child_rows.push(Row::deserialize(&child.schema, &cursor.value()?)?);
+                cursor.advance()?;
+            }
+
+            for row in child_rows {
+                for (number, (column, foreign_key)) in foreign_keys.iter().enumerate() {
+                    let value = &row.values[*column];
+                    if value.is_null() || Self::has_parent(foreign_key, value, database)? {
+                        continue;
+                    }
+                    rows.push(vec![
+                        Value::Text(child.schema.name.clone()),
+                        Value::Integer(row.key(&child.schema)),
+                        Value::Text(foreign_key.table.clone()),
+                        Value::Integer(number as i64),
+                    ]);
+                }
+            }
+        }
+        Ok(ExecuteOk::Rows(rows))
+    }
+
+    fn matching_keys(filter: &Filter, database: &mut Database) -> Result<Vec<i64>, Error> {
+        let mut keys = Vec::new();
+        let mut scan = Scan::new(filter);
+        while let Some(row) = scan.next_row(filter, database)? {
+            keys.push(row.key(&filter.table.schema));
+        }
+        Ok(keys)
+    }
 }
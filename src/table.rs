@@ -1,70 +1,288 @@
 use crate::{
-    node::{InternalNodeCell, Node, INTERNAL_NODE_MAX_CELLS},
+    error::{CorruptErr, Error},
+    node::{
+        leaf_node_local_size, LeafNodeCell, Node, INTERNAL_NODE_MAX_CELLS, INTERNAL_NODE_MIN_KEYS,
+        OVERFLOW_PAGE_SPACE_FOR_DATA,
+    },
     pager::{Pager, INVALID_PAGE_NUM},
 };
 
-pub struct Table {
+// An overflow chain visiting more pages than there are leads back to one of its pages
+const OVERFLOW_CHAIN_LOOPS: CorruptErr = CorruptErr::Invalid("Overflow chain links up in a loop");
+
+/// A B-tree in the database file, found by the page its root is on.
+pub struct Table<'a> {
+    pub pager: &'a mut Pager,
     pub root_page_num: u32,
-    pub pager: Pager,
 }
 
-impl Drop for Table {
-    fn drop(&mut self) {
-        self.db_close();
+impl<'a> Table<'a> {
+    pub fn new(pager: &'a mut Pager, root_page_num: u32) -> Self {
+        Self {
+            pager,
+            root_page_num,
+        }
     }
-}
 
-impl Table {
-    pub fn db_open(filename: &str) -> Self {
-        let mut pager = Pager::pager_open(filename);
-        let root_page_num = 0;
+    /// Largest key in the tree, `None` if the tree is empty. Only the root
+    /// can be an empty leaf, deleting merges any other away.
+    pub fn max_key(&mut self) -> Result<Option<i64>, Error> {
+        let root = self.pager.read_page(self.root_page_num)?;
+        if matches!(root, Node::Leaf { .. }) && *root.leaf_node_num_cells()? == 0 {
+            return Ok(None);
+        }
+        self.pager.get_node_max_key(self.root_page_num).map(Some)
+    }
 
-        if pager.num_pages == 0 {
-            // New database file. Initialize page 0 as leaf node.
-            let root_node = pager.get_page(0);
-            root_node.set_node_root(true);
+    /// Give every page of the tree back to the pager for reuse.
+    /// The tree must not be used afterwards.
+    pub fn free_pages(&mut self) -> Result<(), Error> {
+        let mut page_nums = vec![self.root_page_num];
+        let mut overflow_page_nums = Vec::new();
+        let mut i = 0;
+        while i < page_nums.len() {
+            let node = self.pager.read_page(page_nums[i])?;
+            match *node {
+                Node::Internal { num_keys, .. } => {
+                    for child in 0..=num_keys {
+                        page_nums.push(*node.internal_node_child(child)?);
+                    }
+                }
+                Node::Leaf { ref cells, .. } => {
+                    overflow_page_nums.extend(cells.iter().filter_map(LeafNodeCell::overflow_page));
+                }
+                Node::FreelistTrunk { .. }
+                | Node::Overflow { .. }
+                | Node::IndexLeaf { .. }
+                | Node::IndexInternal { .. } => {}
+            }
+            i += 1;
         }
 
-        Self {
-            root_page_num,
-            pager,
+        for page_num in overflow_page_nums {
+            self.free_overflow_pages(page_num)?;
         }
+        for page_num in page_nums {
+            self.pager.free_page(page_num)?;
+        }
+        Ok(())
     }
 
-    fn db_close(&mut self) {
-        for i in 0..self.pager.num_pages {
-            self.pager.pager_flush(i);
+    /// Make the cell holding a value. The part of a value that does not
+    /// fit in the cell is written to a chain of new overflow pages.
+    pub fn create_cell(&mut self, key: i64, mut value: Vec<u8>) -> Result<LeafNodeCell, Error> {
+        let value_len = value.len();
+        let local_size = leaf_node_local_size(value_len);
+        if local_size == value_len {
+            return Ok(LeafNodeCell::new(key, value));
+        }
+
+        let rest = value.split_off(local_size);
+        let mut first_page_num = 0;
+        let mut previous_page_num = None;
+        for data in rest.chunks(OVERFLOW_PAGE_SPACE_FOR_DATA) {
+            let page_num = self.pager.get_unused_page_num()?;
+            *self.pager.get_page(page_num)? = Node::Overflow {
+                next_page: 0,
+                data: data.to_vec(),
+            };
+            match previous_page_num {
+                Some(previous_page_num) => {
+                    if let Node::Overflow { next_page, .. } =
+                        self.pager.get_page(previous_page_num)?
+                    {
+                        *next_page = page_num;
+                    }
+                }
+                None => first_page_num = page_num,
+            }
+            previous_page_num = Some(page_num);
+        }
+        Ok(LeafNodeCell::with_overflow(
+            key,
+            value,
+            value_len,
+            first_page_num,
+        ))
+    }
+
+    /// The whole value of a cell, read from its overflow pages if it has them.
+    pub fn cell_value(&mut self, page_num: u32, cell_num: u32) -> Result<Vec<u8>, Error> {
+        let cell = self.pager.read_page(page_num)?.leaf_node_cell(cell_num)?;
+        let mut value = cell.local_value().to_vec();
+        let value_len = cell.value_len();
+        let Some(mut overflow_page_num) = cell.overflow_page() else {
+            return Ok(value);
+        };
+
+        value.reserve(value_len - value.len());
+        let mut chain_length = 0;
+        while value.len() < value_len {
+            chain_length += 1;
+            if chain_length >= self.pager.num_pages {
+                return Err(OVERFLOW_CHAIN_LOOPS.into());
+            }
+            let Node::Overflow { next_page, data } = self.pager.read_page(overflow_page_num)?
+            else {
+                return Err(
+                    CorruptErr::Invalid("Overflow chain leads to a page of another type").into(),
+                );
+            };
+            if data.is_empty() || value.len() + data.len() > value_len {
+                return Err(
+                    CorruptErr::Invalid("Overflow chain does not match the value length").into(),
+                );
+            }
+            value.extend_from_slice(data);
+            overflow_page_num = *next_page;
         }
+        Ok(value)
     }
 
-    pub fn create_new_root(&mut self, right_child_page_num: u32) {
+    /// Give the overflow pages of a value back to the pager, following the
+    /// chain from its first page.
+    pub fn free_overflow_pages(&mut self, first_page_num: u32) -> Result<(), Error> {
+        let mut page_num = first_page_num;
+        let mut chain_length = 0;
+        while page_num != 0 {
+            chain_length += 1;
+            if chain_length >= self.pager.num_pages {
+                return Err(OVERFLOW_CHAIN_LOOPS.into());
+            }
+            let Node::Overflow { next_page, .. } = *self.pager.read_page(page_num)? else {
+                return Err(
+                    CorruptErr::Invalid("Overflow chain leads to a page of another type").into(),
+                );
+            };
+            self.pager.free_page(page_num)?;
+            page_num = next_page;
+        }
+        Ok(())
+    }
+
+    /// Fill an empty tree with cells given in key order. Each leaf is filled
+    /// before the next one is started, and the leaves and the overflow pages
+    /// of their cells take consecutive pages so a scan reads the file front
+    /// to back. The internal nodes above them are built one level at a time.
+    /// Loading stops at the first error among the cells.
+    pub fn load(
+        &mut self,
+        cells: impl Iterator<Item = Result<(i64, Vec<u8>), Error>>,
+    ) -> Result<(), Error> {
+        // Page and max key of each node of the level being built
+        let mut children = Vec::new();
+        let mut page_num = self.root_page_num;
+        let mut max_key = 0;
+        for cell in cells {
+            let (key, value) = cell?;
+            let cell = self.create_cell(key, value)?;
+            if !self.pager.get_page(page_num)?.leaf_node_fits(&cell)? {
+                let mut new_page_num = self.pager.get_unused_page_num()?;
+                if page_num == self.root_page_num {
+                    // The root becomes an internal node at the end, so the
+                    // cells loaded into it move to the first leaf
+                    let root_cells = self.pager.get_page(page_num)?.leaf_node_take_cells()?;
+                    let first_leaf = self.pager.get_page(new_page_num)?;
+                    for (cell_num, cell) in root_cells.into_iter().enumerate() {
+                        first_leaf.leaf_node_insert_cell(cell_num as u32, cell)?;
+                    }
+                    page_num = new_page_num;
+                    new_page_num = self.pager.get_unused_page_num()?;
+                }
+                *self.pager.get_page(page_num)?.leaf_node_next_leaf()? = new_page_num;
+                children.push((page_num, max_key));
+                page_num = new_page_num;
+            }
+
+            let leaf = self.pager.get_page(page_num)?;
+            let cell_num = *leaf.leaf_node_num_cells()?;
+            leaf.leaf_node_insert_cell(cell_num, cell)?;
+            max_key = key;
+        }
+        if page_num == self.root_page_num {
+            return Ok(());
+        }
+        children.push((page_num, max_key));
+
+        let max_children = INTERNAL_NODE_MAX_CELLS + 1;
+        while children.len() > max_children {
+            let num_nodes = children.len().div_ceil(max_children);
+            let mut level = Vec::new();
+            let mut start = 0;
+            for i in 0..num_nodes {
+                let end = start
+                    + children.len() / num_nodes
+                    + usize::from(i < children.len() % num_nodes);
+                let page_num = self.pager.get_unused_page_num()?;
+                self.load_internal_node(page_num, &children[start..end])?;
+                level.push((page_num, children[end - 1].1));
+                start = end;
+            }
+            children = level;
+        }
+
+        self.load_internal_node(self.root_page_num, &children)?;
+        Ok(())
+    }
+
+    // Make a page an internal node over the given children, keeping whether it is the root
+    fn load_internal_node(&mut self, page_num: u32, children: &[(u32, i64)]) -> Result<(), Error> {
+        let node = self.pager.get_page(page_num)?;
+        let is_root = node.is_node_root();
+        *node = Node::initialize_internal_node();
+        node.set_node_root(is_root)?;
+
+        let (&(right_child_page_num, _), rest) = children
+            .split_last()
+            .expect("load_internal_node: node has children");
+        for (key_num, &(child_page_num, key)) in rest.iter().enumerate() {
+            node.internal_node_insert_cell(key_num as u32, child_page_num, key)?;
+        }
+        *node.internal_node_right_child()? = right_child_page_num;
+
+        for &(child_page_num, _) in children {
+            *self.pager.get_page(child_page_num)?.parent()? = page_num;
+        }
+        Ok(())
+    }
+
+    pub fn create_new_root(&mut self, right_child_page_num: u32) -> Result<(), Error> {
         // Handle splitting the root.
         // Old root copied to new page, becomes left child.
         // Address of right child passed in.
         // Re-initialize root page to contain the new root node.
         // New root node points to two children.
 
-        let root = self.pager.get_page(self.root_page_num);
+        let root = self.pager.get_page(self.root_page_num)?;
 
         let is_root_internal = match root {
             Node::Leaf { .. } => false,
             Node::Internal { .. } => true,
+            Node::FreelistTrunk { .. } => {
+                return Err(CorruptErr::Invalid("Freelist page is part of a B-tree").into())
+            }
+            Node::Overflow { .. } => {
+                return Err(CorruptErr::Invalid("Overflow page is part of a B-tree").into())
+            }
+            Node::IndexLeaf { .. } | Node::IndexInternal { .. } => {
+                return Err(CorruptErr::Invalid("Index page is part of a table B-tree").into())
+            }
         };
 
         let new_left_child = std::mem::replace(root, Node::initialize_internal_node());
 
-        let right_child = self.pager.get_page(right_child_page_num);
+        let right_child = self.pager.get_page(right_child_page_num)?;
 
         if is_root_internal {
             *right_child = Node::initialize_internal_node();
         }
 
-        let left_child_page_num = self.pager.get_unused_page_num();
-        let left_child = self.pager.get_page(left_child_page_num);
+        let left_child_page_num = self.pager.get_unused_page_num()?;
+        let left_child = self.pager.get_page(left_child_page_num)?;
 
         // Left child has data copied from old root
         *left_child = new_left_child;
-        left_child.set_node_root(false);
+        left_child.set_node_root(false)?;
 
         if let Node::Internal {
             num_keys,
@@ -74,183 +292,342 @@ impl Table {
         {
             let mut internal_node_page_num = Vec::new();
             for i in 0..num_keys {
-                let internal_node_child = *left_child.internal_node_child(i);
+                let internal_node_child = *left_child.internal_node_child(i)?;
                 internal_node_page_num.push(internal_node_child);
             }
 
             for i in internal_node_page_num {
-                let child = self.pager.get_page(i);
-                *child.parent() = left_child_page_num;
+                let child = self.pager.get_page(i)?;
+                *child.parent()? = left_child_page_num;
             }
 
-            let child = self.pager.get_page(right_child_pointer);
-            *child.parent() = left_child_page_num;
+            let child = self.pager.get_page(right_child_pointer)?;
+            *child.parent()? = left_child_page_num;
         }
 
         // Root node is a new internal node with one key and two children
-        let left_child_max_key = self.pager.get_node_max_key(left_child_page_num);
-        let root = self.pager.get_page(self.root_page_num);
+        let left_child_max_key = self.pager.get_node_max_key(left_child_page_num)?;
+        let root = self.pager.get_page(self.root_page_num)?;
         *root = Node::initialize_internal_node();
-        root.set_node_root(true);
-        *root.internal_node_num_keys() = 1;
-        *root.internal_node_child(0) = left_child_page_num;
-        *root.internal_node_key(0) = left_child_max_key;
-        *root.internal_node_right_child() = right_child_page_num;
-
-        let left_child = self.pager.get_page(left_child_page_num);
-        *left_child.parent() = self.root_page_num;
-
-        let right_child = self.pager.get_page(right_child_page_num);
-        *right_child.parent() = self.root_page_num;
+        root.set_node_root(true)?;
+        *root.internal_node_num_keys()? = 1;
+        *root.internal_node_child(0)? = left_child_page_num;
+        *root.internal_node_key(0)? = left_child_max_key;
+        *root.internal_node_right_child()? = right_child_page_num;
+
+        let left_child = self.pager.get_page(left_child_page_num)?;
+        *left_child.parent()? = self.root_page_num;
+
+        let right_child = self.pager.get_page(right_child_page_num)?;
+        *right_child.parent()? = self.root_page_num;
+        Ok(())
     }
 
     // Add a new child/key pair to parent that corresponds to child
-    pub fn internal_node_insert(&mut self, parent_page_num: u32, child_page_num: u32) {
-        let child_max_key = self.pager.get_node_max_key(child_page_num);
+    pub fn internal_node_insert(
+        &mut self,
+        parent_page_num: u32,
+        child_page_num: u32,
+    ) -> Result<(), Error> {
+        let child_max_key = self.pager.get_node_max_key(child_page_num)?;
 
-        let parent = self.pager.get_page(parent_page_num);
-        let index = parent.internal_node_find_child(child_max_key);
-        let original_num_keys = *parent.internal_node_num_keys();
+        let parent = self.pager.get_page(parent_page_num)?;
+        let index = parent.internal_node_find_child(child_max_key)?;
+        let original_num_keys = *parent.internal_node_num_keys()?;
 
         if original_num_keys as usize >= INTERNAL_NODE_MAX_CELLS {
-            self.internal_node_split_and_insert(parent_page_num, child_page_num);
-            return;
+            self.internal_node_split_and_insert(parent_page_num, child_page_num)?;
+            return Ok(());
         }
 
-        let right_child_page_num = *parent.internal_node_right_child();
+        let right_child_page_num = *parent.internal_node_right_child()?;
 
         // An internal node with a right child of INVALID_PAGE_NUM is empty
         if right_child_page_num == INVALID_PAGE_NUM {
-            *parent.internal_node_right_child() = child_page_num;
-            return;
+            *parent.internal_node_right_child()? = child_page_num;
+            return Ok(());
         }
 
         // If we are already at the max number of cells for a node, we cannot increment
         // before splitting. Incrementing without inserting a new key/child pair
         // and immediately calling internal_node_split_and_insert has the effect
         // of creating a new key at (max_cells + 1) with an uninitialized value
-        *parent.internal_node_num_keys() = original_num_keys + 1;
+        *parent.internal_node_num_keys()? = original_num_keys + 1;
 
-        let right_child_node_max_key = self.pager.get_node_max_key(right_child_page_num);
+        let right_child_node_max_key = self.pager.get_node_max_key(right_child_page_num)?;
 
         if child_max_key > right_child_node_max_key {
             // Replace right child
-            let parent = self.pager.get_page(parent_page_num);
-            *parent.internal_node_child(original_num_keys) = right_child_page_num;
-            *parent.internal_node_key(original_num_keys) = right_child_node_max_key;
-            *parent.internal_node_right_child() = child_page_num;
+            let parent = self.pager.get_page(parent_page_num)?;
+            *parent.internal_node_child(original_num_keys)? = right_child_page_num;
+            *parent.internal_node_key(original_num_keys)? = right_child_node_max_key;
+            *parent.internal_node_right_child()? = child_page_num;
         } else {
             // Make room for the new cell
-            let parent = self.pager.get_page(parent_page_num);
+            let parent = self.pager.get_page(parent_page_num)?;
             let mut i = original_num_keys;
             while i > index {
-                let source =
-                    std::mem::replace(parent.internal_node_cell(i - 1), InternalNodeCell::new());
-                let destination = parent.internal_node_cell(i);
+                let source = std::mem::take(parent.internal_node_cell(i - 1)?);
+                let destination = parent.internal_node_cell(i)?;
                 *destination = source;
                 i -= 1;
             }
-            *parent.internal_node_child(index) = child_page_num;
-            *parent.internal_node_key(index) = child_max_key;
+            *parent.internal_node_child(index)? = child_page_num;
+            *parent.internal_node_key(index)? = child_max_key;
         }
+        Ok(())
     }
 
-    pub fn internal_node_split_and_insert(&mut self, parent_page_num: u32, child_page_num: u32) {
-        let mut old_page_num = parent_page_num;
-        let old_max = self.pager.get_node_max_key(parent_page_num);
-
-        let child_max = self.pager.get_node_max_key(child_page_num);
-        let new_page_num = self.pager.get_unused_page_num();
-
-        // Declaring a flag before updating pointers which
-        // records whether this operation involves splitting the root -
-        // if it does, we will insert our newly created node during
-        // the step where the table's new root is created. If it does
-        // not, we have to insert the newly created node into its parent
-        // after the old node's keys have been transferred over. We are not
-        // able to do this if the newly created node's parent is not a newly
-        // initialized root node, because in that case its parent may have existing
-        // keys aside from our old node which we are splitting. If that is true, we
-        // need to find a place for our newly created node in its parent, and we
-        // cannot insert it at the correct index if it does not yet have any keys
-        let splitting_root = self.pager.get_page(old_page_num).is_node_root();
-
-        let (parent_page_num, new_node_page_num) = if splitting_root {
-            self.create_new_root(new_page_num);
-            let parent = self.pager.get_page(self.root_page_num);
-            let parent_page_num = self.root_page_num;
-            old_page_num = *parent.internal_node_child(0);
-            let _old_node = self.pager.get_page(old_page_num);
-            (parent_page_num, 0)
+    pub fn internal_node_split_and_insert(
+        &mut self,
+        parent_page_num: u32,
+        child_page_num: u32,
+    ) -> Result<(), Error> {
+        let old_page_num = parent_page_num;
+        let old_max = self.pager.get_node_max_key(old_page_num)?;
+        let child_max = self.pager.get_node_max_key(child_page_num)?;
+
+        // Collect every (child, key) pair of the full node plus the new child, in key order.
+        // The key of the right child is its max key, so the list can be split anywhere.
+        let old_node = self.pager.get_page(old_page_num)?;
+        let old_num_keys = *old_node.internal_node_num_keys()?;
+        let old_right_child = *old_node.internal_node_right_child()?;
+        let mut children = Vec::new();
+        for i in 0..old_num_keys {
+            children.push((
+                *old_node.internal_node_child(i)?,
+                *old_node.internal_node_key(i)?,
+            ));
+        }
+        let old_right_child_max = self.pager.get_node_max_key(old_right_child)?;
+        children.push((old_right_child, old_right_child_max));
+
+        let index = children
+            .iter()
+            .position(|&(_, key)| key >= child_max)
+            .unwrap_or(children.len());
+        children.insert(index, (child_page_num, child_max));
+
+        let right_half = children.split_off(children.len() / 2);
+        let left_half = children;
+
+        // Old node keeps the left half
+        let old_node = self.pager.get_page(old_page_num)?;
+        let is_root = old_node.is_node_root();
+        let old_parent = *old_node.parent()?;
+        *old_node = Node::initialize_internal_node();
+        old_node.set_node_root(is_root)?;
+        *old_node.parent()? = old_parent;
+        self.internal_node_fill(old_page_num, &left_half)?;
+
+        let new_page_num = self.pager.get_unused_page_num()?;
+        *self.pager.get_page(new_page_num)? = Node::initialize_internal_node();
+
+        if is_root {
+            // Old root moves to a new left child, the new node becomes the right child
+            self.create_new_root(new_page_num)?;
+            self.internal_node_fill(new_page_num, &right_half)?;
         } else {
-            let old_node = self.pager.get_page(old_page_num);
-            let parent_page_num = *old_node.parent();
-            let _parent = self.pager.get_page(parent_page_num);
-            let new_node_page_num = new_page_num;
-            let new_node = self.pager.get_page(new_node_page_num);
-            *new_node = Node::initialize_internal_node();
-            (parent_page_num, new_node_page_num)
-        };
+            self.internal_node_fill(new_page_num, &right_half)?;
 
-        let mut cur_page_num = *self
-            .pager
-            .get_page(old_page_num)
-            .internal_node_right_child();
+            let new_old_max = self.pager.get_node_max_key(old_page_num)?;
+            self.pager
+                .get_page(old_parent)?
+                .update_internal_node_key(old_max, new_old_max)?;
 
-        // First put right child into new node and set right child of old node to invalid page number
-        self.internal_node_insert(new_page_num, cur_page_num);
-        *self.pager.get_page(cur_page_num).parent() = new_page_num;
-        *self
-            .pager
-            .get_page(old_page_num)
-            .internal_node_right_child() = INVALID_PAGE_NUM;
-
-        // For each key until you get to the middle key, move the key and the child to the new node
-        let mut i = INTERNAL_NODE_MAX_CELLS - 1;
-        while i > INTERNAL_NODE_MAX_CELLS / 2 {
-            cur_page_num = *self
-                .pager
-                .get_page(old_page_num)
-                .internal_node_child(i as u32);
-            self.internal_node_insert(new_page_num, cur_page_num);
-            *self.pager.get_page(cur_page_num).parent() = new_page_num;
-            *self
-                .pager
-                .get_page(old_page_num)
-                .internal_node_right_child() -= 1;
-            i -= 1;
+            // If the parent is full it gets split as well, which sets the parent of the new node
+            *self.pager.get_page(new_page_num)?.parent()? = old_parent;
+            self.internal_node_insert(old_parent, new_page_num)?;
+        }
+        Ok(())
+    }
+
+    // Set the children of an empty internal node from (child, key) pairs in key order.
+    // The last child becomes the right child.
+    fn internal_node_fill(&mut self, page_num: u32, children: &[(u32, i64)]) -> Result<(), Error> {
+        let (right_child, cells) = children
+            .split_last()
+            .expect("internal_node_fill: an internal node needs at least one child");
+
+        let node = self.pager.get_page(page_num)?;
+        for (i, &(child_page_num, key)) in cells.iter().enumerate() {
+            node.internal_node_insert_cell(i as u32, child_page_num, key)?;
         }
+        *node.internal_node_right_child()? = right_child.0;
 
-        {
-            // Set child before middle key, which is now the highest key, to be node's right child,
-            // and decrement number of keys
-            let old_node = self.pager.get_page(old_page_num);
-            let old_num_keys = *old_node.internal_node_num_keys();
-            *old_node.internal_node_right_child() = *old_node.internal_node_child(old_num_keys - 1);
-            *old_node.internal_node_num_keys() -= 1;
-        }
-
-        // Determine which of the two nodes after the split should contain the child to be inserted,
-        // and insert the child
-        let max_after_split = self.pager.get_node_max_key(old_page_num);
-        let destination_page_num = if child_max < max_after_split {
-            old_page_num
+        for &(child_page_num, _) in children {
+            *self.pager.get_page(child_page_num)?.parent()? = page_num;
+        }
+        Ok(())
+    }
+
+    // Remove the child at child_index from the parent after it has been merged
+    // into its left sibling. The left sibling takes over the key of the removed child,
+    // which is the max key of the merged node.
+    pub fn internal_node_remove_child(
+        &mut self,
+        parent_page_num: u32,
+        child_index: u32,
+    ) -> Result<(), Error> {
+        let parent = self.pager.get_page(parent_page_num)?;
+        let left_child_page_num = *parent.internal_node_child(child_index - 1)?;
+        parent.internal_node_remove_cell(child_index - 1)?;
+        *parent.internal_node_child(child_index - 1)? = left_child_page_num;
+
+        self.internal_node_rebalance(parent_page_num)?;
+        Ok(())
+    }
+
+    /// Fix an underflowing internal node by borrowing a child from a sibling
+    /// that can spare one, or else merging the node with a sibling.
+    /// A root left with a single child is collapsed into that child.
+    fn internal_node_rebalance(&mut self, page_num: u32) -> Result<(), Error> {
+        let node = self.pager.get_page(page_num)?;
+        let num_keys = *node.internal_node_num_keys()?;
+
+        if node.is_node_root() {
+            if num_keys == 0 {
+                self.collapse_root()?;
+            }
+            return Ok(());
+        }
+
+        if num_keys as usize >= INTERNAL_NODE_MIN_KEYS {
+            return Ok(());
+        }
+
+        let parent_page_num = *node.parent()?;
+        let parent = self.pager.get_page(parent_page_num)?;
+        let index = parent.internal_node_child_index(page_num)?;
+        let parent_num_keys = *parent.internal_node_num_keys()?;
+
+        let left_page_num = if index > 0 {
+            Some(*parent.internal_node_child(index - 1)?)
         } else {
-            new_page_num
+            None
         };
+        let right_page_num = if index < parent_num_keys {
+            Some(*parent.internal_node_child(index + 1)?)
+        } else {
+            None
+        };
+
+        if let Some(left_page_num) = left_page_num {
+            let left = self.pager.get_page(left_page_num)?;
+            let left_num_keys = *left.internal_node_num_keys()?;
+
+            if left_num_keys as usize > INTERNAL_NODE_MIN_KEYS {
+                // The right child of the left sibling becomes our first child.
+                // The separator in the parent moves down, and the last key of
+                // the left sibling moves up to replace it.
+                let moved_child_page_num = *left.internal_node_right_child()?;
+                let new_left_right_child = *left.internal_node_child(left_num_keys - 1)?;
+                let new_separator = *left.internal_node_key(left_num_keys - 1)?;
+                left.internal_node_remove_cell(left_num_keys - 1)?;
+                *left.internal_node_right_child()? = new_left_right_child;
+
+                let parent = self.pager.get_page(parent_page_num)?;
+                let separator = *parent.internal_node_key(index - 1)?;
+                *parent.internal_node_key(index - 1)? = new_separator;
+
+                let node = self.pager.get_page(page_num)?;
+                node.internal_node_insert_cell(0, moved_child_page_num, separator)?;
+                *self.pager.get_page(moved_child_page_num)?.parent()? = page_num;
+                return Ok(());
+            }
+        }
+
+        if let Some(right_page_num) = right_page_num {
+            let right = self.pager.get_page(right_page_num)?;
+            let right_num_keys = *right.internal_node_num_keys()?;
+
+            if right_num_keys as usize > INTERNAL_NODE_MIN_KEYS {
+                // The first child of the right sibling becomes our right child.
+                // The separator in the parent moves down, and the first key of
+                // the right sibling moves up to replace it.
+                let moved_child_page_num = *right.internal_node_child(0)?;
+                let new_separator = *right.internal_node_key(0)?;
+                right.internal_node_remove_cell(0)?;
+
+                let parent = self.pager.get_page(parent_page_num)?;
+                let separator = *parent.internal_node_key(index)?;
+                *parent.internal_node_key(index)? = new_separator;
+
+                let node = self.pager.get_page(page_num)?;
+                let old_right_child = *node.internal_node_right_child()?;
+                node.internal_node_insert_cell(num_keys, old_right_child, separator)?;
+                *node.internal_node_right_child()? = moved_child_page_num;
+                *self.pager.get_page(moved_child_page_num)?.parent()? = page_num;
+                return Ok(());
+            }
+        }
+
+        // Neither sibling can spare a child, so merge with one of them.
+        // Children always move from the right node of the pair into the left one.
+        let (left_page_num, right_page_num, right_index) = match (left_page_num, right_page_num) {
+            (Some(left_page_num), _) => (left_page_num, page_num, index),
+            (None, Some(right_page_num)) => (page_num, right_page_num, index + 1),
+            (None, None) => {
+                return Err(CorruptErr::Invalid("Internal node has a single child").into())
+            }
+        };
+
+        let separator = *self
+            .pager
+            .get_page(parent_page_num)?
+            .internal_node_key(right_index - 1)?;
+
+        let right = self.pager.get_page(right_page_num)?;
+        let right_num_keys = *right.internal_node_num_keys()?;
+        let mut moved_cells = Vec::new();
+        for i in 0..right_num_keys {
+            moved_cells.push((*right.internal_node_child(i)?, *right.internal_node_key(i)?));
+        }
+        let right_right_child = *right.internal_node_right_child()?;
+
+        let left = self.pager.get_page(left_page_num)?;
+        let left_num_keys = *left.internal_node_num_keys()?;
+        let left_right_child = *left.internal_node_right_child()?;
+        left.internal_node_insert_cell(left_num_keys, left_right_child, separator)?;
+        for (child_page_num, key) in moved_cells.iter() {
+            let num_keys = *left.internal_node_num_keys()?;
+            left.internal_node_insert_cell(num_keys, *child_page_num, *key)?;
+        }
+        *left.internal_node_right_child()? = right_right_child;
 
-        self.internal_node_insert(destination_page_num, child_page_num);
-        *self.pager.get_page(child_page_num).parent() = destination_page_num;
+        for (child_page_num, _) in moved_cells {
+            *self.pager.get_page(child_page_num)?.parent()? = left_page_num;
+        }
+        *self.pager.get_page(right_right_child)?.parent()? = left_page_num;
+
+        self.internal_node_remove_child(parent_page_num, right_index)?;
+        self.pager.free_page(right_page_num)?;
+        Ok(())
+    }
+
+    // The root has a single child left. Move the child into the root page,
+    // reducing the height of the tree by one.
+    fn collapse_root(&mut self) -> Result<(), Error> {
+        let root = self.pager.get_page(self.root_page_num)?;
+        let child_page_num = *root.internal_node_right_child()?;
+
+        let child = self.pager.get_page(child_page_num)?;
+        let mut new_root = std::mem::replace(child, Node::initialize_leaf_node());
+        new_root.set_node_root(true)?;
+
+        let mut grandchildren = Vec::new();
+        if let Node::Internal { num_keys, .. } = new_root {
+            for i in 0..=num_keys {
+                grandchildren.push(*new_root.internal_node_child(i)?);
+            }
+        }
 
-        let old_node_max_key = self.pager.get_node_max_key(old_page_num);
-        self.pager
-            .get_page(parent_page_num)
-            .update_internal_node_key(old_max, old_node_max_key);
+        *self.pager.get_page(self.root_page_num)? = new_root;
 
-        if !splitting_root {
-            let old_node_parent = *self.pager.get_page(old_page_num).parent();
-            self.internal_node_insert(old_node_parent, new_page_num);
-            *self.pager.get_page(new_node_page_num).parent() = old_node_parent;
+        for grandchild_page_num in grandchildren {
+            *self.pager.get_page(grandchild_page_num)?.parent()? = self.root_page_num;
         }
+        self.pager.free_page(child_page_num)?;
+        Ok(())
     }
 }
@@ -0,0 +1,170 @@
+use std::cmp::Ordering;
+use std::fmt;
+
+use crate::ast::Literal;
+use crate::schema::Affinity;
+
+/// A value produced while evaluating an expression.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Null,
+    Integer(i64),
+    Real(f64),
+    Text(String),
+    Blob(Vec<u8>),
+}
+
+impl Value {
+    pub fn from_literal(literal: &Literal) -> Self {
+        match literal {
+            Literal::Null => Value::Null,
+            Literal::Integer(integer) => Value::Integer(*integer),
+            Literal::Float(float) => Value::Real(*float),
+            Literal::String(string) => Value::Text(string.clone()),
+            Literal::Blob(blob) => Value::Blob(blob.clone()),
+        }
+    }
+
+    pub fn to_literal(&self) -> Literal {
+        match self {
+            Value::Null => Literal::Null,
+            Value::Integer(integer) => Literal::Integer(*integer),
+            Value::Real(real) => Literal::Float(*real),
+            Value::Text(text) => Literal::String(text.clone()),
+            Value::Blob(blob) => Literal::Blob(blob.clone()),
+        }
+    }
+
+    pub fn from_bool(value: bool) -> Self {
+        Value::Integer(value as i64)
+    }
+
+    pub fn is_null(&self) -> bool {
+        matches!(self, Value::Null)
+    }
+
+    /// Truth value of the value in a condition, `None` for NULL.
+    /// Text counts as the number it spells, or zero if it is not a number.
+    pub fn to_bool(&self) -> Option<bool> {
+        match self.to_numeric() {
+            Value::Null => None,
+            Value::Integer(integer) => Some(integer != 0),
+            Value::Real(real) => Some(real != 0.0),
+            Value::Text(_) | Value::Blob(_) => {
+                unreachable!("to_numeric never returns text or a blob")
+            }
+        }
+    }
+
+    /// Convert text to a number for arithmetic, reading a blob as text.
+    /// Text that is not a number counts as zero. Numbers and NULL are unchanged.
+    pub fn to_numeric(&self) -> Value {
+        match self {
+            Value::Text(_) | Value::Blob(_) => {
+                parse_number(&self.to_text()).unwrap_or(Value::Integer(0))
+            }
+            value => value.clone(),
+        }
+    }
+
+    /// Convert the value to the type a column with the affinity prefers,
+    /// if it can be converted without losing information.
+    pub fn apply_affinity(self, affinity: Affinity) -> Value {
+        match (affinity, self) {
+            (Affinity::Text, value @ (Value::Integer(_) | Value::Real(_))) => {
+                Value::Text(value.to_string())
+            }
+            (Affinity::Integer | Affinity::Numeric, Value::Text(text)) => match parse_number(&text)
+            {
+                Some(number) => number.apply_affinity(Affinity::Integer),
+                None => Value::Text(text),
+            },
+            (Affinity::Real, Value::Text(text)) => match parse_number(&text) {
+                Some(number) => number.apply_affinity(Affinity::Real),
+                None => Value::Text(text),
+            },
+            // A real with no fractional part that an i64 holds exactly
+            (Affinity::Integer | Affinity::Numeric, Value::Real(real))
+                if real.fract() == 0.0 && (-(2f64.powi(63))..2f64.powi(63)).contains(&real) =>
+            {
+                Value::Integer(real as i64)
+            }
+            (Affinity::Real, Value::Integer(integer)) => Value::Real(integer as f64),
+            (_, value) => value,
+        }
+    }
+
+    /// The value as text, for concatenation and LIKE. The bytes of a blob
+    /// are read as UTF-8.
+    pub fn to_text(&self) -> String {
+        match self {
+            Value::Blob(blob) => String::from_utf8_lossy(blob).into_owned(),
+            value => value.to_string(),
+        }
+    }
+
+    /// Compare two values, `None` if either of them is NULL.
+    /// Numbers sort before text and text before blobs, as they do in SQLite.
+    pub fn compare(&self, other: &Value) -> Option<Ordering> {
+        match (self, other) {
+            (Value::Null, _) | (_, Value::Null) => None,
+            (Value::Integer(a), Value::Integer(b)) => Some(a.cmp(b)),
+            (Value::Integer(a), Value::Real(b)) => Some((*a as f64).total_cmp(b)),
+            (Value::Real(a), Value::Integer(b)) => Some(a.total_cmp(&(*b as f64))),
+            (Value::Real(a), Value::Real(b)) => Some(a.total_cmp(b)),
+            (Value::Text(a), Value::Text(b)) => Some(a.cmp(b)),
+            (Value::Blob(a), Value::Blob(b)) => Some(a.cmp(b)),
+            (Value::Blob(_), _) => Some(Ordering::Greater),
+            (_, Value::Blob(_)) => Some(Ordering::Less),
+            (Value::Text(_), _) => Some(Ordering::Greater),
+            (_, Value::Text(_)) => Some(Ordering::Less),
+        }
+    }
+
+    /// Order used for sorting, which is `compare` with NULL before everything else.
+    pub fn sort_cmp(&self, other: &Value) -> Ordering {
+        match (self, other) {
+            (Value::Null, Value::Null) => Ordering::Equal,
+            (Value::Null, _) => Ordering::Less,
+            (_, Value::Null) => Ordering::Greater,
+            _ => self
+                .compare(other)
+                .expect("sort_cmp: only NULL is incomparable"),
+        }
+    }
+}
+
+// The number text spells, allowing whitespace around it. Spellings such as
+// `inf` or `NaN` that Rust accepts but SQL does not are not numbers.
+fn parse_number(text: &str) -> Option<Value> {
+    let text = text.trim();
+    if let Ok(integer) = text.parse::<i64>() {
+        return Some(Value::Integer(integer));
+    }
+    let is_decimal = text.bytes().any(|byte| byte.is_ascii_digit())
+        && text
+            .bytes()
+            .all(|byte| byte.is_ascii_digit() || b"+-.eE".contains(&byte));
+    match text.parse::<f64>() {
+        Ok(real) if is_decimal => Some(Value::Real(real)),
+        _ => None,
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Null => write!(f, "NULL"),
+            Value::Integer(integer) => write!(f, "{}", integer),
+            Value::Real(real) => write!(f, "{:?}", real),
+            Value::Text(text) => write!(f, "{}", text),
+            Value::Blob(blob) => {
+                write!(f, "X'")?;
+                for byte in blob {
+                    write!(f, "{:02X}", byte)?;
+                }
+                write!(f, "'")
+            }
+        }
+    }
+}
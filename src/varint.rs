@@ -0,0 +1,47 @@
+// Variable-length integers as SQLite writes them: big-endian groups of
+// seven bits, each byte but the last with its high bit set. A ninth byte,
+// if there is one, holds eight bits, so any u64 takes at most nine bytes.
+
+pub const VARINT_MAX_SIZE: usize = 9;
+
+/// Bytes `value` takes up as a varint.
+pub fn varint_size(value: u64) -> usize {
+    if value >> 56 != 0 {
+        return VARINT_MAX_SIZE;
+    }
+    let bits = 64 - value.leading_zeros() as usize;
+    bits.div_ceil(7).max(1)
+}
+
+pub fn put_varint(bytes: &mut Vec<u8>, value: u64) {
+    if value >> 56 != 0 {
+        // Eight groups of seven bits, then the low eight bits whole
+        for i in (0..8).rev() {
+            bytes.push(((value >> (8 + 7 * i)) & 0x7f) as u8 | 0x80);
+        }
+        bytes.push(value as u8);
+        return;
+    }
+
+    let size = varint_size(value);
+    for i in (0..size).rev() {
+        let group = ((value >> (7 * i)) & 0x7f) as u8;
+        bytes.push(if i == 0 { group } else { group | 0x80 });
+    }
+}
+
+/// Read a varint from the start of `bytes`, returning its value and size.
+/// `None` if `bytes` ends before the varint does.
+pub fn read_varint(bytes: &[u8]) -> Option<(u64, usize)> {
+    let mut value = 0u64;
+    for (i, &byte) in bytes.iter().enumerate().take(VARINT_MAX_SIZE) {
+        if i == VARINT_MAX_SIZE - 1 {
+            return Some(((value << 8) | byte as u64, VARINT_MAX_SIZE));
+        }
+        value = (value << 7) | (byte & 0x7f) as u64;
+        if byte & 0x80 == 0 {
+            return Some((value, i + 1));
+        }
+    }
+    None
+}
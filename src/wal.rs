@@ -0,0 +1,298 @@
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Seek, Write};
+
+use crate::journal::checksum;
+use crate::pager::PAGE_SIZE;
+
+pub const WAL_MAGIC: &[u8; WAL_MAGIC_SIZE] = b"rust-sqlite wal\0";
+
+// Frames in the log before a commit checkpoints it into the database file
+#[cfg(debug_assertions)]
+pub const WAL_AUTOCHECKPOINT: usize = 64; // Kept small for testing
+#[cfg(not(debug_assertions))]
+pub const WAL_AUTOCHECKPOINT: usize = 1000;
+
+// WAL Header Layout
+pub const WAL_MAGIC_SIZE: usize = 16;
+pub const WAL_MAGIC_OFFSET: usize = 0;
+pub const WAL_PAGE_SIZE_SIZE: usize = std::mem::size_of::<u32>();
+pub const WAL_PAGE_SIZE_OFFSET: usize = WAL_MAGIC_OFFSET + WAL_MAGIC_SIZE;
+pub const WAL_HEADER_SIZE: usize = WAL_MAGIC_SIZE + WAL_PAGE_SIZE_SIZE;
+
+// WAL Frame Layout
+pub const FRAME_PAGE_NUM_SIZE: usize = std::mem::size_of::<u32>();
+pub const FRAME_PAGE_NUM_OFFSET: usize = 0;
+pub const FRAME_PAGE_COUNT_SIZE: usize = std::mem::size_of::<u32>();
+pub const FRAME_PAGE_COUNT_OFFSET: usize = FRAME_PAGE_NUM_OFFSET + FRAME_PAGE_NUM_SIZE;
+pub const FRAME_CHECKSUM_SIZE: usize = std::mem::size_of::<u32>();
+pub const FRAME_CHECKSUM_OFFSET: usize = FRAME_PAGE_COUNT_OFFSET + FRAME_PAGE_COUNT_SIZE;
+pub const FRAME_HEADER_SIZE: usize =
+    FRAME_PAGE_NUM_SIZE + FRAME_PAGE_COUNT_SIZE + FRAME_CHECKSUM_SIZE;
+pub const FRAME_PAGE_OFFSET: usize = FRAME_HEADER_SIZE;
+pub const WAL_FRAME_SIZE: usize = FRAME_HEADER_SIZE + PAGE_SIZE;
+
+// WAL Format
+// |-------------+-------------+---------+-----+---------|
+// | bytes 0-15  | bytes 16-19 | frame 0 | ... | frame n |
+// | magic       | page_size   |         |     |         |
+// |-------------+-------------+---------+-----+---------|
+//
+// WAL Frame Format
+// |----------+------------+----------+------------|
+// | 4 bytes  | 4 bytes    | 4 bytes  | 4096 bytes |
+// | page_num | page_count | checksum | page       |
+// |----------+------------+----------+------------|
+//
+// The page count is 0 except in the last frame of a commit, where it is
+// the number of pages in the database after the commit. The checksum
+// covers the page number, the page count and the page.
+
+/// Path of the write-ahead log kept next to a database file.
+pub fn wal_path(filename: &str) -> String {
+    format!("{filename}-wal")
+}
+
+/// The write-ahead log. Changed pages are appended to it as frames instead
+/// of being written over the database file, and the WAL index tells which
+/// frame holds the newest version of each page. A checkpoint copies the
+/// newest versions into the database file and empties the log.
+pub struct Wal {
+    file: File,
+    path: String,
+    // WAL index: offset of the newest committed frame of each page
+    index: HashMap<u32, u64>,
+    // Offsets of the frames written since the last commit
+    pending: HashMap<u32, u64>,
+    // Offset just past the last committed frame
+    end: u64,
+    // Offset where the next frame is written
+    next: u64,
+    // Pages in the database as of the last commit, if anything was committed
+    page_count: Option<u32>,
+}
+
+impl Wal {
+    /// Open the log of a database file, or create an empty one. The WAL
+    /// index is rebuilt from the committed frames. Frames after the last
+    /// commit marker, torn or with a bad checksum are dropped.
+    pub fn open(path: String) -> io::Result<Self> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)?;
+
+        let mut bytes = Vec::new();
+        file.read_to_end(&mut bytes)?;
+
+        let mut wal = Self {
+            file,
+            path,
+            index: HashMap::new(),
+            pending: HashMap::new(),
+            end: WAL_HEADER_SIZE as u64,
+            next: WAL_HEADER_SIZE as u64,
+            page_count: None,
+        };
+
+        let valid_header = bytes.len() >= WAL_HEADER_SIZE
+            && &bytes[WAL_MAGIC_OFFSET..WAL_MAGIC_OFFSET + WAL_MAGIC_SIZE] == WAL_MAGIC
+            && read_u32(&bytes, WAL_PAGE_SIZE_OFFSET) == PAGE_SIZE as u32;
+        if !valid_header {
+            wal.reset()?;
+            return Ok(wal);
+        }
+
+        let mut offset = WAL_HEADER_SIZE;
+        for frame in bytes[WAL_HEADER_SIZE..].chunks_exact(WAL_FRAME_SIZE) {
+            let stored_checksum = read_u32(frame, FRAME_CHECKSUM_OFFSET);
+            if frame_checksum(frame) != stored_checksum {
+                break;
+            }
+
+            let page_num = read_u32(frame, FRAME_PAGE_NUM_OFFSET);
+            wal.pending.insert(page_num, offset as u64);
+            offset += WAL_FRAME_SIZE;
+
+            let page_count = read_u32(frame, FRAME_PAGE_COUNT_OFFSET);
+            if page_count != 0 {
+                wal.index.extend(wal.pending.drain());
+                wal.end = offset as u64;
+                wal.page_count = Some(page_count);
+            }
+        }
+        wal.pending.clear();
+        wal.next = wal.end;
+        wal.file.set_len(wal.end)?;
+
+        Ok(wal)
+    }
+
+    /// Pages in the database as of the last commit in the log,
+    /// `None` if the log holds no commit.
+    pub fn page_count(&self) -> Option<u32> {
+        self.page_count
+    }
+
+    /// Frames in the log, committed or not.
+    pub fn frame_count(&self) -> usize {
+        (self.next as usize - WAL_HEADER_SIZE) / WAL_FRAME_SIZE
+    }
+
+    /// Whether the log holds a version of the page.
+    pub fn contains(&self, page_num: u32) -> bool {
+        self.pending.contains_key(&page_num) || self.index.contains_key(&page_num)
+    }
+
+    /// Newest version of a page in the log, including frames not committed yet.
+    pub fn read_page(&mut self, page_num: u32) -> io::Result<Option<[u8; PAGE_SIZE]>> {
+        let Some(&offset) = self
+            .pending
+            .get(&page_num)
+            .or_else(|| self.index.get(&page_num))
+        else {
+            return Ok(None);
+        };
+        Ok(Some(self.read_frame_page(offset)?))
+    }
+
+    fn read_frame_page(&mut self, offset: u64) -> io::Result<[u8; PAGE_SIZE]> {
+        self.file
+            .seek(io::SeekFrom::Start(offset + FRAME_PAGE_OFFSET as u64))?;
+        let mut page = [0; PAGE_SIZE];
+        self.file.read_exact(&mut page)?;
+
+        Ok(page)
+    }
+
+    /// Append a changed page. It only becomes part of the database
+    /// once a commit frame follows it.
+    pub fn write_page(&mut self, page_num: u32, page: &[u8; PAGE_SIZE]) -> io::Result<()> {
+        self.write_frame(page_num, 0, page)
+    }
+
+    /// Append the last page of a commit, marking the database as `page_count`
+    /// pages long, and sync the log. This is the commit point.
+    pub fn commit(
+        &mut self,
+        page_num: u32,
+        page: &[u8; PAGE_SIZE],
+        page_count: u32,
+    ) -> io::Result<()> {
+        self.write_frame(page_num, page_count, page)?;
+        self.file.sync_all()?;
+
+        self.index.extend(self.pending.drain());
+        self.end = self.next;
+        self.page_count = Some(page_count);
+        Ok(())
+    }
+
+    /// Drop the frames written since the last commit.
+    pub fn roll_back(&mut self) -> io::Result<()> {
+        self.pending.clear();
+        self.next = self.end;
+        self.file.set_len(self.end)
+    }
+
+    /// Whether pages were written since the last commit.
+    pub fn has_pending(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    fn write_frame(
+        &mut self,
+        page_num: u32,
+        page_count: u32,
+        page: &[u8; PAGE_SIZE],
+    ) -> io::Result<()> {
+        // A page written again before the commit overwrites its frame. The
+        // commit frame is always appended, so it stays the last frame.
+        let offset = match self.pending.get(&page_num) {
+            Some(&offset) if page_count == 0 => offset,
+            _ => {
+                self.next += WAL_FRAME_SIZE as u64;
+                self.next - WAL_FRAME_SIZE as u64
+            }
+        };
+
+        let mut frame = vec![0; WAL_FRAME_SIZE];
+        frame[FRAME_PAGE_NUM_OFFSET..FRAME_PAGE_NUM_OFFSET + FRAME_PAGE_NUM_SIZE]
+            .copy_from_slice(&page_num.to_le_bytes());
+        frame[FRAME_PAGE_COUNT_OFFSET..FRAME_PAGE_COUNT_OFFSET + FRAME_PAGE_COUNT_SIZE]
+            .copy_from_slice(&page_count.to_le_bytes());
+        frame[FRAME_PAGE_OFFSET..].copy_from_slice(page);
+        let checksum = frame_checksum(&frame);
+        frame[FRAME_CHECKSUM_OFFSET..FRAME_CHECKSUM_OFFSET + FRAME_CHECKSUM_SIZE]
+            .copy_from_slice(&checksum.to_le_bytes());
+
+        self.file.seek(io::SeekFrom::Start(offset))?;
+        self.file.write_all(&frame)?;
+        self.pending.insert(page_num, offset);
+        Ok(())
+    }
+
+    /// Copy the newest committed version of every page in the log into the
+    /// database file and sync it. Returns the number of pages copied.
+    /// The log is emptied unless frames are waiting for a commit.
+    pub fn checkpoint(&mut self, file: &mut File) -> io::Result<usize> {
+        let Some(page_count) = self.page_count else {
+            return Ok(0);
+        };
+
+        let mut page_nums: Vec<_> = self.index.keys().copied().collect();
+        page_nums.sort_unstable();
+        for &page_num in &page_nums {
+            let page = self.read_frame_page(self.index[&page_num])?;
+            file.seek(io::SeekFrom::Start(page_num as u64 * PAGE_SIZE as u64))?;
+            file.write_all(&page)?;
+        }
+        file.set_len(page_count as u64 * PAGE_SIZE as u64)?;
+        file.sync_all()?;
+
+        if self.pending.is_empty() {
+            self.reset()?;
+        }
+        Ok(page_nums.len())
+    }
+
+    /// Delete the log. It must have been checkpointed.
+    pub fn delete(self) -> io::Result<()> {
+        std::fs::remove_file(&self.path)
+    }
+
+    // Start an empty log
+    fn reset(&mut self) -> io::Result<()> {
+        let mut header = [0; WAL_HEADER_SIZE];
+        header[WAL_MAGIC_OFFSET..WAL_MAGIC_OFFSET + WAL_MAGIC_SIZE].copy_from_slice(WAL_MAGIC);
+        header[WAL_PAGE_SIZE_OFFSET..WAL_PAGE_SIZE_OFFSET + WAL_PAGE_SIZE_SIZE]
+            .copy_from_slice(&(PAGE_SIZE as u32).to_le_bytes());
+
+        self.file.set_len(0)?;
+        self.file.seek(io::SeekFrom::Start(0))?;
+        self.file.write_all(&header)?;
+        self.file.sync_all()?;
+
+        self.index.clear();
+        self.pending.clear();
+        self.end = WAL_HEADER_SIZE as u64;
+        self.next = WAL_HEADER_SIZE as u64;
+        self.page_count = None;
+        Ok(())
+    }
+}
+
+// Checksum of a frame, over everything but the checksum itself
+fn frame_checksum(frame: &[u8]) -> u32 {
+    let mut bytes = frame[..FRAME_CHECKSUM_OFFSET].to_vec();
+    bytes.extend_from_slice(&frame[FRAME_PAGE_OFFSET..]);
+    checksum(&bytes)
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    let mut u32_bytes = [0; std::mem::size_of::<u32>()];
+    u32_bytes.copy_from_slice(&bytes[offset..offset + std::mem::size_of::<u32>()]);
+    u32::from_le_bytes(u32_bytes)
+}
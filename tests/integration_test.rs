@@ -3,19 +3,22 @@ use std::io::Write;
 use std::process::{Command, Stdio};
 use std::str;
 
+use rust_sqlite::{Connection, Value};
+
 #[test]
 fn insert_and_retrieve_row() {
     let input = vec![
-        "insert 1 user1 person1@example.com".to_owned(),
-        "select".to_owned(),
+        "insert into users values (1, 'user1', 'person1@example.com')".to_owned(),
+        "select * from users".to_owned(),
         ".exit".to_owned(),
     ];
 
     let tempfile = TempFile::new();
+    create_users_table(&tempfile);
     let output = spawn_rust_sqlite(&tempfile, input);
 
     let expected_output = vec![
-        "db > Executed.".to_owned(),
+        "db > Executed. Rows changed: 1.".to_owned(),
         "db > (1, user1, person1@example.com)".to_owned(),
         "Executed.".to_owned(),
         "db > ".to_owned(),
@@ -28,11 +31,12 @@ fn insert_and_retrieve_row() {
 #[should_panic]
 fn print_error_when_row_is_full() {
     let mut input: Vec<_> = (0..=1937)
-        .map(|i| format!("insert {i} user{i} person{i}@example.com"))
+        .map(|i| format!("insert into users values ({i}, 'user{i}', 'person{i}@example.com')"))
         .collect();
     input.push(".exit".to_owned());
 
     let tempfile = TempFile::new();
+    create_users_table(&tempfile);
     let output = spawn_rust_sqlite(&tempfile, input);
     let output = &output[output.len() - 2];
 
@@ -46,16 +50,17 @@ fn allow_inserting_string_at_maximum_length() {
     let username = ['a'; 32].iter().cloned().collect::<String>();
     let email = ['a'; 255].iter().cloned().collect::<String>();
     let input = vec![
-        format!("insert 1 {username} {email}"),
-        "select".to_owned(),
+        format!("insert into users values (1, '{username}', '{email}')"),
+        "select * from users".to_owned(),
         ".exit".to_owned(),
     ];
 
     let tempfile = TempFile::new();
+    create_users_table(&tempfile);
     let output = spawn_rust_sqlite(&tempfile, input);
 
     let expected_output = vec![
-        "db > Executed.".to_owned(),
+        "db > Executed. Rows changed: 1.".to_owned(),
         format!("db > (1, {username}, {email})"),
         "Executed.".to_owned(),
         "db > ".to_owned(),
@@ -69,12 +74,13 @@ fn prints_error_message_if_string_are_too_long() {
     let username = ['a'; 33].iter().cloned().collect::<String>();
     let email = ['a'; 255].iter().cloned().collect::<String>();
     let input = vec![
-        format!("insert 1 {username} {email}"),
-        "select".to_owned(),
+        format!("insert into users values (1, '{username}', '{email}')"),
+        "select * from users".to_owned(),
         ".exit".to_owned(),
     ];
 
     let tempfile = TempFile::new();
+    create_users_table(&tempfile);
     let output = spawn_rust_sqlite(&tempfile, input);
 
     let expected_output = vec![
@@ -88,12 +94,13 @@ fn prints_error_message_if_string_are_too_long() {
     let username = ['a'; 32].iter().cloned().collect::<String>();
     let email = ['a'; 256].iter().cloned().collect::<String>();
     let input = vec![
-        format!("insert 1 {username} {email}"),
-        "select".to_owned(),
+        format!("insert into users values (1, '{username}', '{email}')"),
+        "select * from users".to_owned(),
         ".exit".to_owned(),
     ];
 
     let tempfile = TempFile::new();
+    create_users_table(&tempfile);
     let output = spawn_rust_sqlite(&tempfile, input);
 
     let expected_output = vec![
@@ -106,19 +113,35 @@ fn prints_error_message_if_string_are_too_long() {
 }
 
 #[test]
-fn prints_error_message_if_id_is_negative() {
+fn accepts_negative_and_64_bit_ids() {
     let input = vec![
-        "insert -1 foo bar@email.com".to_owned(),
-        "select".to_owned(),
+        "insert into users values (4294967296, 'big', 'big@email.com')".to_owned(),
+        "insert into users values (-1, 'foo', 'bar@email.com')".to_owned(),
+        "insert into users values (-9223372036854775808, 'min', 'min@email.com')".to_owned(),
+        "insert into users values ('one', 'baz', 'baz@email.com')".to_owned(),
+        "select * from users".to_owned(),
+        "select username from users where id > 4294967295".to_owned(),
+        "select username from users where id < -1".to_owned(),
         ".exit".to_owned(),
     ];
 
     let tempfile = TempFile::new();
+    create_users_table(&tempfile);
     let output = spawn_rust_sqlite(&tempfile, input);
 
     let expected_output = vec![
+        "db > Executed. Rows changed: 1.".to_owned(),
+        "db > Executed. Rows changed: 1.".to_owned(),
+        "db > Executed. Rows changed: 1.".to_owned(),
         "db > ID is invalid.".to_owned(),
-        "db > Executed.".to_owned(),
+        "db > (-9223372036854775808, min, min@email.com)".to_owned(),
+        "(-1, foo, bar@email.com)".to_owned(),
+        "(4294967296, big, big@email.com)".to_owned(),
+        "Executed.".to_owned(),
+        "db > (big)".to_owned(),
+        "Executed.".to_owned(),
+        "db > (min)".to_owned(),
+        "Executed.".to_owned(),
         "db > ".to_owned(),
     ];
 
@@ -128,16 +151,20 @@ fn prints_error_message_if_id_is_negative() {
 #[test]
 fn keeps_data_after_closing_connection() {
     let tempfile = TempFile::new();
+    create_users_table(&tempfile);
 
     let input = vec![
-        "insert 1 user1 person1@example.com".to_owned(),
+        "insert into users values (1, 'user1', 'person1@example.com')".to_owned(),
         ".exit".to_owned(),
     ];
     let output = spawn_rust_sqlite(&tempfile, input);
-    let expected_output = vec!["db > Executed.".to_owned(), "db > ".to_owned()];
+    let expected_output = vec![
+        "db > Executed. Rows changed: 1.".to_owned(),
+        "db > ".to_owned(),
+    ];
     assert_eq!(output, expected_output);
 
-    let input = vec!["select".to_owned(), ".exit".to_owned()];
+    let input = vec!["select * from users".to_owned(), ".exit".to_owned()];
     let output = spawn_rust_sqlite(&tempfile, input);
     let expected_output = vec![
         "db > (1, user1, person1@example.com)".to_owned(),
@@ -151,23 +178,23 @@ fn keeps_data_after_closing_connection() {
 #[test]
 fn keeps_data_after_closing_connection2() {
     let tempfile = TempFile::new();
+    create_users_table(&tempfile);
 
-    let mut input: Vec<_> = (1..=15)
-        .map(|i| format!("insert {i} user{i} person{i}@example.com"))
-        .collect();
+    let mut input: Vec<_> = (1..=15).map(insert_full_user).collect();
     input.push(".exit".to_owned());
 
     let output = spawn_rust_sqlite(&tempfile, input);
-    let expected_output = vec!["db > Executed.".to_owned(), "db > ".to_owned()];
+    let expected_output = vec![
+        "db > Executed. Rows changed: 1.".to_owned(),
+        "db > ".to_owned(),
+    ];
     assert_eq!(output[14..], expected_output);
 
-    let input = vec!["select".to_owned(), ".exit".to_owned()];
+    let input = vec!["select * from users".to_owned(), ".exit".to_owned()];
     let output = spawn_rust_sqlite(&tempfile, input);
 
-    let mut expected_output: Vec<_> = (2..=15)
-        .map(|i| format!("({i}, user{i}, person{i}@example.com)"))
-        .collect();
-    expected_output.insert(0, "db > (1, user1, person1@example.com)".to_owned());
+    let mut expected_output: Vec<_> = (1..=15).map(full_user_row).collect();
+    expected_output[0] = format!("db > {}", expected_output[0]);
     expected_output.push("Executed.".to_owned());
     expected_output.push("db > ".to_owned());
 
@@ -177,17 +204,16 @@ fn keeps_data_after_closing_connection2() {
 #[test]
 fn prints_constants() {
     let tempfile = TempFile::new();
+    create_users_table(&tempfile);
 
     let input = vec![".constants".to_owned(), ".exit".to_owned()];
     let output = spawn_rust_sqlite(&tempfile, input);
     let expected_output = vec![
         "db > Constants:".to_owned(),
-        "ROW_SIZE: 291".to_owned(),
         "COMMON_NODE_HEADER_SIZE: 6".to_owned(),
-        "LEAF_NODE_HEADER_SIZE: 14".to_owned(),
-        "LEAF_NODE_CELL_SIZE: 295".to_owned(),
-        "LEAF_NODE_SPACE_FOR_CELLS: 4082".to_owned(),
-        "LEAF_NODE_MAX_CELLS: 13".to_owned(),
+        "LEAF_NODE_HEADER_SIZE: 18".to_owned(),
+        "LEAF_NODE_SPACE_FOR_CELLS: 4078".to_owned(),
+        "LEAF_NODE_MAX_VALUE_SIZE: 1346".to_owned(),
         "db > ".to_owned(),
     ];
     assert_eq!(output, expected_output);
@@ -196,19 +222,20 @@ fn prints_constants() {
 #[test]
 fn allows_printing_out_the_structure_of_a_one_node_btree() {
     let tempfile = TempFile::new();
+    create_users_table(&tempfile);
 
     let mut input: Vec<_> = [3, 1, 2]
         .iter()
-        .map(|i| format!("insert {i} user{i} person{i}@example.com"))
+        .map(|i| format!("insert into users values ({i}, 'user{i}', 'person{i}@example.com')"))
         .collect();
     input.push(".btree".to_owned());
     input.push(".exit".to_owned());
     let output = spawn_rust_sqlite(&tempfile, input);
 
     let expected_output = vec![
-        "db > Executed.".to_owned(),
-        "db > Executed.".to_owned(),
-        "db > Executed.".to_owned(),
+        "db > Executed. Rows changed: 1.".to_owned(),
+        "db > Executed. Rows changed: 1.".to_owned(),
+        "db > Executed. Rows changed: 1.".to_owned(),
         "db > Tree:".to_owned(),
         "- leaf (size 3)".to_owned(),
         " - 1".to_owned(),
@@ -222,12 +249,11 @@ fn allows_printing_out_the_structure_of_a_one_node_btree() {
 #[test]
 fn allows_printing_out_the_structure_of_a_3_leaf_node_btree() {
     let tempfile = TempFile::new();
+    create_users_table(&tempfile);
 
-    let mut input: Vec<_> = (1..=14)
-        .map(|i| format!("insert {i} user{i} person{i}@example.com"))
-        .collect();
+    let mut input: Vec<_> = (1..=14).map(insert_full_user).collect();
     input.push(".btree".to_owned());
-    input.push("insert 15 user15 person15@example.com".to_owned());
+    input.push("insert into users values (15, 'user15', 'person15@example.com')".to_owned());
     input.push(".exit".to_owned());
 
     let output = spawn_rust_sqlite(&tempfile, input);
@@ -252,7 +278,7 @@ fn allows_printing_out_the_structure_of_a_3_leaf_node_btree() {
         "  - 12".to_owned(),
         "  - 13".to_owned(),
         "  - 14".to_owned(),
-        "db > Executed.".to_owned(),
+        "db > Executed. Rows changed: 1.".to_owned(),
         "db > ".to_owned(),
     ];
     assert_eq!(output[14..], expected_output);
@@ -261,18 +287,15 @@ fn allows_printing_out_the_structure_of_a_3_leaf_node_btree() {
 #[test]
 fn prints_all_rows_in_a_multi_level_tree() {
     let tempfile = TempFile::new();
+    create_users_table(&tempfile);
 
-    let mut input: Vec<_> = (1..=15)
-        .map(|i| format!("insert {i} user{i} person{i}@example.com"))
-        .collect();
-    input.push("select".to_owned());
+    let mut input: Vec<_> = (1..=15).map(insert_full_user).collect();
+    input.push("select * from users".to_owned());
     input.push(".exit".to_owned());
 
     let output = spawn_rust_sqlite(&tempfile, input);
-    let mut expected_output: Vec<_> = (2..=15)
-        .map(|i| format!("({i}, user{i}, person{i}@example.com)"))
-        .collect();
-    expected_output.insert(0, "db > (1, user1, person1@example.com)".to_owned());
+    let mut expected_output: Vec<_> = (1..=15).map(full_user_row).collect();
+    expected_output[0] = format!("db > {}", expected_output[0]);
     expected_output.push("Executed.".to_owned());
     expected_output.push("db > ".to_owned());
 
@@ -282,16 +305,17 @@ fn prints_all_rows_in_a_multi_level_tree() {
 #[test]
 fn prints_an_error_message_if_there_is_a_duplicate_id() {
     let tempfile = TempFile::new();
+    create_users_table(&tempfile);
 
     let input = vec![
-        "insert 1 user1 person1@example.com".to_owned(),
-        "insert 1 user1 person1@example.com".to_owned(),
-        "select".to_owned(),
+        "insert into users values (1, 'user1', 'person1@example.com')".to_owned(),
+        "insert into users values (1, 'user1', 'person1@example.com')".to_owned(),
+        "select * from users".to_owned(),
         ".exit".to_owned(),
     ];
     let output = spawn_rust_sqlite(&tempfile, input);
     let expected_output = vec![
-        "db > Executed.".to_owned(),
+        "db > Executed. Rows changed: 1.".to_owned(),
         "db > Error: Duplicate key.".to_owned(),
         "db > (1, user1, person1@example.com)".to_owned(),
         "Executed.".to_owned(),
@@ -303,41 +327,17 @@ fn prints_an_error_message_if_there_is_a_duplicate_id() {
 #[test]
 fn allows_printing_out_the_structure_of_a_4_leaf_node_btree() {
     let tempfile = TempFile::new();
-
-    let input = vec![
-        "insert 18 user18 person18@example.com".to_owned(),
-        "insert 7 user7 person7@example.com".to_owned(),
-        "insert 10 user10 person10@example.com".to_owned(),
-        "insert 29 user29 person29@example.com".to_owned(),
-        "insert 23 user23 person23@example.com".to_owned(),
-        "insert 4 user4 person4@example.com".to_owned(),
-        "insert 14 user14 person14@example.com".to_owned(),
-        "insert 30 user30 person30@example.com".to_owned(),
-        "insert 15 user15 person15@example.com".to_owned(),
-        "insert 26 user26 person26@example.com".to_owned(),
-        "insert 22 user22 person22@example.com".to_owned(),
-        "insert 19 user19 person19@example.com".to_owned(),
-        "insert 2 user2 person2@example.com".to_owned(),
-        "insert 1 user1 person1@example.com".to_owned(),
-        "insert 21 user21 person21@example.com".to_owned(),
-        "insert 11 user11 person11@example.com".to_owned(),
-        "insert 6 user6 person6@example.com".to_owned(),
-        "insert 20 user20 person20@example.com".to_owned(),
-        "insert 5 user5 person5@example.com".to_owned(),
-        "insert 8 user8 person8@example.com".to_owned(),
-        "insert 9 user9 person9@example.com".to_owned(),
-        "insert 3 user3 person3@example.com".to_owned(),
-        "insert 12 user12 person12@example.com".to_owned(),
-        "insert 27 user27 person27@example.com".to_owned(),
-        "insert 17 user17 person17@example.com".to_owned(),
-        "insert 16 user16 person16@example.com".to_owned(),
-        "insert 13 user13 person13@example.com".to_owned(),
-        "insert 24 user24 person24@example.com".to_owned(),
-        "insert 25 user25 person25@example.com".to_owned(),
-        "insert 28 user28 person28@example.com".to_owned(),
-        ".btree".to_owned(),
-        ".exit".to_owned(),
-    ];
+    create_users_table(&tempfile);
+
+    let mut input: Vec<_> = [
+        18, 7, 10, 29, 23, 4, 14, 30, 15, 26, 22, 19, 2, 1, 21, 11, 6, 20, 5, 8, 9, 3, 12, 27, 17,
+        16, 13, 24, 25, 28,
+    ]
+    .into_iter()
+    .map(insert_full_user)
+    .collect();
+    input.push(".btree".to_owned());
+    input.push(".exit".to_owned());
 
     let output = spawn_rust_sqlite(&tempfile, input);
 
@@ -389,82 +389,25 @@ fn allows_printing_out_the_structure_of_a_4_leaf_node_btree() {
 #[test]
 fn allows_printing_out_the_structure_of_a_7_leaf_node_btree() {
     let tempfile = TempFile::new();
-
-    let input = vec![
-        "insert 58 user58 person58@example.com".to_owned(),
-        "insert 56 user56 person56@example.com".to_owned(),
-        "insert 8 user8 person8@example.com".to_owned(),
-        "insert 54 user54 person54@example.com".to_owned(),
-        "insert 77 user77 person77@example.com".to_owned(),
-        "insert 7 user7 person7@example.com".to_owned(),
-        "insert 25 user25 person25@example.com".to_owned(),
-        "insert 71 user71 person71@example.com".to_owned(),
-        "insert 13 user13 person13@example.com".to_owned(),
-        "insert 22 user22 person22@example.com".to_owned(),
-        "insert 53 user53 person53@example.com".to_owned(),
-        "insert 51 user51 person51@example.com".to_owned(),
-        "insert 59 user59 person59@example.com".to_owned(),
-        "insert 32 user32 person32@example.com".to_owned(),
-        "insert 36 user36 person36@example.com".to_owned(),
-        "insert 79 user79 person79@example.com".to_owned(),
-        "insert 10 user10 person10@example.com".to_owned(),
-        "insert 33 user33 person33@example.com".to_owned(),
-        "insert 20 user20 person20@example.com".to_owned(),
-        "insert 4 user4 person4@example.com".to_owned(),
-        "insert 35 user35 person35@example.com".to_owned(),
-        "insert 76 user76 person76@example.com".to_owned(),
-        "insert 49 user49 person49@example.com".to_owned(),
-        "insert 24 user24 person24@example.com".to_owned(),
-        "insert 70 user70 person70@example.com".to_owned(),
-        "insert 48 user48 person48@example.com".to_owned(),
-        "insert 39 user39 person39@example.com".to_owned(),
-        "insert 15 user15 person15@example.com".to_owned(),
-        "insert 47 user47 person47@example.com".to_owned(),
-        "insert 30 user30 person30@example.com".to_owned(),
-        "insert 86 user86 person86@example.com".to_owned(),
-        "insert 31 user31 person31@example.com".to_owned(),
-        "insert 68 user68 person68@example.com".to_owned(),
-        "insert 37 user37 person37@example.com".to_owned(),
-        "insert 66 user66 person66@example.com".to_owned(),
-        "insert 63 user63 person63@example.com".to_owned(),
-        "insert 40 user40 person40@example.com".to_owned(),
-        "insert 78 user78 person78@example.com".to_owned(),
-        "insert 19 user19 person19@example.com".to_owned(),
-        "insert 46 user46 person46@example.com".to_owned(),
-        "insert 14 user14 person14@example.com".to_owned(),
-        "insert 81 user81 person81@example.com".to_owned(),
-        "insert 72 user72 person72@example.com".to_owned(),
-        "insert 6 user6 person6@example.com".to_owned(),
-        "insert 50 user50 person50@example.com".to_owned(),
-        "insert 85 user85 person85@example.com".to_owned(),
-        "insert 67 user67 person67@example.com".to_owned(),
-        "insert 2 user2 person2@example.com".to_owned(),
-        "insert 55 user55 person55@example.com".to_owned(),
-        "insert 69 user69 person69@example.com".to_owned(),
-        "insert 5 user5 person5@example.com".to_owned(),
-        "insert 65 user65 person65@example.com".to_owned(),
-        "insert 52 user52 person52@example.com".to_owned(),
-        "insert 1 user1 person1@example.com".to_owned(),
-        "insert 29 user29 person29@example.com".to_owned(),
-        "insert 9 user9 person9@example.com".to_owned(),
-        "insert 43 user43 person43@example.com".to_owned(),
-        "insert 75 user75 person75@example.com".to_owned(),
-        "insert 21 user21 person21@example.com".to_owned(),
-        "insert 82 user82 person82@example.com".to_owned(),
-        "insert 12 user12 person12@example.com".to_owned(),
-        "insert 18 user18 person18@example.com".to_owned(),
-        "insert 60 user60 person60@example.com".to_owned(),
-        "insert 44 user44 person44@example.com".to_owned(),
-        ".btree".to_owned(),
-        ".exit".to_owned(),
-    ];
+    create_users_table(&tempfile);
+
+    let mut input: Vec<_> = [
+        58, 56, 8, 54, 77, 7, 25, 71, 13, 22, 53, 51, 59, 32, 36, 79, 10, 33, 20, 4, 35, 76, 49,
+        24, 70, 48, 39, 15, 47, 30, 86, 31, 68, 37, 66, 63, 40, 78, 19, 46, 14, 81, 72, 6, 50, 85,
+        67, 2, 55, 69, 5, 65, 52, 1, 29, 9, 43, 75, 21, 82, 12, 18, 60, 44,
+    ]
+    .into_iter()
+    .map(insert_full_user)
+    .collect();
+    input.push(".btree".to_owned());
+    input.push(".exit".to_owned());
 
     let output = spawn_rust_sqlite(&tempfile, input);
 
     let expected_output = vec![
         "db > Tree:".to_owned(),
         "- internal (size 1)".to_owned(),
-        " - internal (size 3)".to_owned(),
+        " - internal (size 2)".to_owned(),
         "  - leaf (size 7)".to_owned(),
         "   - 1".to_owned(),
         "   - 2".to_owned(),
@@ -496,21 +439,7 @@ fn allows_printing_out_the_structure_of_a_7_leaf_node_btree() {
         "   - 32".to_owned(),
         "   - 33".to_owned(),
         "   - 35".to_owned(),
-        "  - key 35".to_owned(),
-        "  - leaf (size 12)".to_owned(),
-        "   - 36".to_owned(),
-        "   - 37".to_owned(),
-        "   - 39".to_owned(),
-        "   - 40".to_owned(),
-        "   - 43".to_owned(),
-        "   - 44".to_owned(),
-        "   - 46".to_owned(),
-        "   - 47".to_owned(),
-        "   - 48".to_owned(),
-        "   - 49".to_owned(),
-        "   - 50".to_owned(),
-        "   - 51".to_owned(),
-        " - key 51".to_owned(),
+        " - key 35".to_owned(),
         " - internal (size 3)".to_owned(),
         "  - leaf (size 12)".to_owned(),
         "   - 36".to_owned(),
@@ -562,34 +491,2151 @@ fn allows_printing_out_the_structure_of_a_7_leaf_node_btree() {
     assert_eq!(output[64..], expected_output);
 }
 
-fn spawn_rust_sqlite(tempfile: &TempFile, input: Vec<String>) -> Vec<String> {
-    let mut process = rust_sqlite_exe()
-        .arg(&tempfile.filepath)
-        .stdin(Stdio::piped())
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .spawn()
-        .expect("Unable to start the process.");
+#[test]
+fn deletes_a_row() {
+    let tempfile = TempFile::new();
+    create_users_table(&tempfile);
 
-    let stdin = process
-        .stdin
-        .as_mut()
-        .expect("Unable to pipe stdin to process.");
+    let input = vec![
+        "insert into users values (1, 'user1', 'person1@example.com')".to_owned(),
+        "insert into users values (2, 'user2', 'person2@example.com')".to_owned(),
+        "delete from users where id = 1".to_owned(),
+        "delete from users where id = 3".to_owned(),
+        "select * from users".to_owned(),
+        ".exit".to_owned(),
+    ];
+    let output = spawn_rust_sqlite(&tempfile, input);
+    let expected_output = vec![
+        "db > Executed. Rows changed: 1.".to_owned(),
+        "db > Executed. Rows changed: 1.".to_owned(),
+        "db > Executed. Rows changed: 1.".to_owned(),
+        "db > Executed. Rows changed: 0.".to_owned(),
+        "db > (2, user2, person2@example.com)".to_owned(),
+        "Executed.".to_owned(),
+        "db > ".to_owned(),
+    ];
+    assert_eq!(output, expected_output);
+}
 
-    for line in input {
-        stdin
-            .write_all(format!("{}\n", line).as_bytes())
-            .expect(&format!("Unable to write command `{}`", line));
-    }
+#[test]
+fn deletes_a_range_of_rows() {
+    let tempfile = TempFile::new();
+    create_users_table(&tempfile);
 
-    let output = process
-        .wait_with_output()
-        .expect("Unable to get output from the process.");
+    let mut input: Vec<_> = (1..=10)
+        .map(|i| format!("insert into users values ({i}, 'user{i}', 'person{i}@example.com')"))
+        .collect();
+    input.push("delete from users where id between 3 and 8".to_owned());
+    input.push("delete from users where id >= 10".to_owned());
+    input.push("select * from users".to_owned());
+    input.push(".exit".to_owned());
 
-    str::from_utf8(&output.stdout)
-        .expect("Could not get process output.")
-        .lines()
-        .into_iter()
+    let output = spawn_rust_sqlite(&tempfile, input);
+    let expected_output = vec![
+        "db > (1, user1, person1@example.com)".to_owned(),
+        "(2, user2, person2@example.com)".to_owned(),
+        "(9, user9, person9@example.com)".to_owned(),
+        "Executed.".to_owned(),
+        "db > ".to_owned(),
+    ];
+    assert_eq!(output[12..], expected_output);
+}
+
+#[test]
+fn merges_leaf_nodes_and_collapses_the_root_after_deleting_rows() {
+    let tempfile = TempFile::new();
+    create_users_table(&tempfile);
+
+    let mut input: Vec<_> = (1..=15).map(insert_full_user).collect();
+    input.push("delete from users where id = 3".to_owned());
+    input.push("delete from users where id > 7".to_owned());
+    input.push(".btree".to_owned());
+    input.push(".exit".to_owned());
+
+    let output = spawn_rust_sqlite(&tempfile, input);
+    let expected_output = vec![
+        "db > Tree:".to_owned(),
+        "- leaf (size 6)".to_owned(),
+        " - 1".to_owned(),
+        " - 2".to_owned(),
+        " - 4".to_owned(),
+        " - 5".to_owned(),
+        " - 6".to_owned(),
+        " - 7".to_owned(),
+        "db > ".to_owned(),
+    ];
+    assert_eq!(output[17..], expected_output);
+}
+
+#[test]
+fn keeps_the_tree_balanced_after_deleting_most_rows() {
+    let tempfile = TempFile::new();
+    create_users_table(&tempfile);
+
+    let mut input: Vec<_> = (1..=100).map(insert_full_user).collect();
+    input.extend(
+        (1..=100)
+            .filter(|i| i % 10 != 0)
+            .map(|i| format!("delete from users where id = {i}")),
+    );
+    input.push(".btree".to_owned());
+    input.push("select * from users".to_owned());
+    input.push(".exit".to_owned());
+
+    let output = spawn_rust_sqlite(&tempfile, input);
+    // Each leaf is left at least a quarter full
+    let mut expected_output = vec![
+        "db > Tree:".to_owned(),
+        "- internal (size 1)".to_owned(),
+        " - leaf (size 4)".to_owned(),
+    ];
+    expected_output.extend((1..=4).map(|i| format!("  - {}", i * 10)));
+    expected_output.push(" - key 40".to_owned());
+    expected_output.push(" - leaf (size 6)".to_owned());
+    expected_output.extend((5..=10).map(|i| format!("  - {}", i * 10)));
+    expected_output.push(format!("db > {}", full_user_row(10)));
+    expected_output.extend((2..=10).map(|i| full_user_row(i * 10)));
+    expected_output.push("Executed.".to_owned());
+    expected_output.push("db > ".to_owned());
+
+    assert_eq!(output[190..], expected_output);
+}
+
+#[test]
+fn updates_a_row_in_place() {
+    let tempfile = TempFile::new();
+    create_users_table(&tempfile);
+
+    let input = vec![
+        "insert into users values (1, 'user1', 'person1@example.com')".to_owned(),
+        "insert into users values (2, 'user2', 'person2@example.com')".to_owned(),
+        "update users set username = 'alice', email = 'alice@example.com' where id = 2".to_owned(),
+        ".exit".to_owned(),
+    ];
+    let output = spawn_rust_sqlite(&tempfile, input);
+    assert_eq!(output[2], "db > Executed. Rows changed: 1.");
+
+    let input = vec!["select * from users".to_owned(), ".exit".to_owned()];
+    let output = spawn_rust_sqlite(&tempfile, input);
+    let expected_output = vec![
+        "db > (1, user1, person1@example.com)".to_owned(),
+        "(2, alice, alice@example.com)".to_owned(),
+        "Executed.".to_owned(),
+        "db > ".to_owned(),
+    ];
+    assert_eq!(output, expected_output);
+}
+
+#[test]
+fn evaluates_the_values_of_inserts_and_updates() {
+    let tempfile = TempFile::new();
+
+    let input = vec![
+        "create table counters (id integer primary key, name text(10), n integer)".to_owned(),
+        "insert into counters values (1, 'one', 2 * 3), (2 + 1, 'three', -(4 - 1))".to_owned(),
+        "insert into counters values (4, 'four', n)".to_owned(),
+        "update counters set n = n + id, name = 'x' where n > 0".to_owned(),
+        "update counters set n = n * 2 where id = 3".to_owned(),
+        "select * from counters".to_owned(),
+        ".exit".to_owned(),
+    ];
+    let output = spawn_rust_sqlite(&tempfile, input);
+    let expected_output = vec![
+        "db > Executed.",
+        "db > Executed. Rows changed: 2.",
+        "db > No such column: n.",
+        "db > Executed. Rows changed: 1.",
+        "db > Executed. Rows changed: 1.",
+        "db > (1, x, 7)",
+        "(3, three, -6)",
+        "Executed.",
+        "db > ",
+    ];
+    assert_eq!(output, expected_output);
+}
+
+#[test]
+fn updates_a_range_of_rows_across_leaf_nodes() {
+    let tempfile = TempFile::new();
+    create_users_table(&tempfile);
+
+    let mut input: Vec<_> = (1..=20).map(insert_full_user).collect();
+    input.push("update users set email = 'moved@example.com' where id >= 5".to_owned());
+    input.push(".exit".to_owned());
+
+    let output = spawn_rust_sqlite(&tempfile, input);
+    assert_eq!(output[20], "db > Executed. Rows changed: 16.");
+}
+
+#[test]
+fn prints_error_message_when_updating_a_missing_or_too_long_value() {
+    let tempfile = TempFile::new();
+    create_users_table(&tempfile);
+
+    let username = ['a'; 33].iter().cloned().collect::<String>();
+    let input = vec![
+        "insert into users values (1, 'user1', 'person1@example.com')".to_owned(),
+        "update users set username = 'bob' where id = 2".to_owned(),
+        format!("update users set username = '{username}' where id = 1"),
+        "update users set id = 5 where id = 1".to_owned(),
+        "select * from users".to_owned(),
+        ".exit".to_owned(),
+    ];
+    let output = spawn_rust_sqlite(&tempfile, input);
+    let expected_output = vec![
+        "db > Executed. Rows changed: 1.".to_owned(),
+        "db > Executed. Rows changed: 0.".to_owned(),
+        "db > String is too long.".to_owned(),
+        "db > The id column cannot be updated.".to_owned(),
+        "db > (1, user1, person1@example.com)".to_owned(),
+        "Executed.".to_owned(),
+        "db > ".to_owned(),
+    ];
+    assert_eq!(output, expected_output);
+}
+
+#[test]
+fn selects_rows_matching_a_where_clause() {
+    let tempfile = TempFile::new();
+    create_users_table(&tempfile);
+
+    let input = vec![
+        "insert into users values (1, 'alice', 'alice@example.com'), (2, 'bob', 'bob@test.org')"
+            .to_owned(),
+        "insert into users values (3, 'carol', 'carol@example.com'), (4, 'dave', 'dave@test.org')"
+            .to_owned(),
+        "select * from users where email like '%@EXAMPLE.com' and not username = 'alice'"
+            .to_owned(),
+        "select * from users where id in (4, 1, null) or username = 'bob'".to_owned(),
+        "select * from users where id * 2 - 1 = 5 or username || '!' = 'dave!'".to_owned(),
+        "select * from users where id not in (1, null)".to_owned(),
+        "select * from users where age > 30".to_owned(),
+        ".exit".to_owned(),
+    ];
+    let output = spawn_rust_sqlite(&tempfile, input);
+    let expected_output = vec![
+        "db > Executed. Rows changed: 2.".to_owned(),
+        "db > Executed. Rows changed: 2.".to_owned(),
+        "db > (3, carol, carol@example.com)".to_owned(),
+        "Executed.".to_owned(),
+        "db > (1, alice, alice@example.com)".to_owned(),
+        "(2, bob, bob@test.org)".to_owned(),
+        "(4, dave, dave@test.org)".to_owned(),
+        "Executed.".to_owned(),
+        "db > (3, carol, carol@example.com)".to_owned(),
+        "(4, dave, dave@test.org)".to_owned(),
+        "Executed.".to_owned(),
+        "db > Executed.".to_owned(),
+        "db > No such column: age.".to_owned(),
+        "db > ".to_owned(),
+    ];
+    assert_eq!(output, expected_output);
+}
+
+#[test]
+fn selects_a_range_of_ids_across_leaf_nodes() {
+    let tempfile = TempFile::new();
+    create_users_table(&tempfile);
+
+    let mut input: Vec<_> = (1..=30).map(insert_full_user).collect();
+    input.push(
+        "select * from users where id between 12 and 16 and not username like 'user14%'".to_owned(),
+    );
+    input.push("select * from users where id > 28 or id < -5".to_owned());
+    input.push("select * from users where id < 0".to_owned());
+    input.push(".exit".to_owned());
+
+    let output = spawn_rust_sqlite(&tempfile, input);
+    let expected_output = vec![
+        format!("db > {}", full_user_row(12)),
+        full_user_row(13),
+        full_user_row(15),
+        full_user_row(16),
+        "Executed.".to_owned(),
+        format!("db > {}", full_user_row(29)),
+        full_user_row(30),
+        "Executed.".to_owned(),
+        "db > Executed.".to_owned(),
+        "db > ".to_owned(),
+    ];
+    assert_eq!(output[30..], expected_output);
+}
+
+#[test]
+fn seeks_rows_by_id_instead_of_scanning_the_table() {
+    let tempfile = TempFile::new();
+    create_users_table(&tempfile);
+
+    let mut input: Vec<_> = (1..=300).map(insert_full_user).collect();
+    input.push(".exit".to_owned());
+    spawn_rust_sqlite(&tempfile, input);
+
+    // A fresh process starts with an empty page cache, so every page read is a miss
+    let input = vec![
+        "select * from users where id = 170".to_owned(),
+        ".stats".to_owned(),
+        ".exit".to_owned(),
+    ];
+    let output = spawn_rust_sqlite(&tempfile, input);
+    assert_eq!(output[0], format!("db > {}", full_user_row(170)));
+    assert_eq!(output[5], "cache misses: 6");
+
+    let (username, _) = full_user(170);
+    let input = vec![
+        format!("select * from users where username = '{username}'"),
+        ".stats".to_owned(),
+        ".exit".to_owned(),
+    ];
+    let output = spawn_rust_sqlite(&tempfile, input);
+    assert_eq!(output[0], format!("db > {}", full_user_row(170)));
+    assert_eq!(output[5], "cache misses: 47");
+
+    // A scan picks up its cursor after each row rather than seeking the next one
+    let input = vec![
+        "select id from users".to_owned(),
+        ".stats".to_owned(),
+        ".exit".to_owned(),
+    ];
+    let output = spawn_rust_sqlite(&tempfile, input);
+    assert_eq!(
+        output[300..305],
+        [
+            "Executed.",
+            "db > cache size: 16",
+            "cached pages: 16",
+            "cache hits: 869",
+            "cache misses: 47"
+        ]
+    );
+}
+
+#[test]
+fn projects_sorts_and_limits_selected_rows() {
+    let tempfile = TempFile::new();
+    create_users_table(&tempfile);
+
+    let input = vec![
+        "insert into users values (1, 'alice', 'a@x.com'), (2, 'bob', 'b@y.org'), (3, 'carol', 'c@x.com')".to_owned(),
+        "insert into users values (4, 'dave', 'd@z.net'), (5, 'bob', 'a@a.com')".to_owned(),
+        "select username, email from users order by email desc limit 2 offset 1".to_owned(),
+        "select username, id from users order by username, id desc".to_owned(),
+        "select id * 10, username || '!' from users where id > 1 order by 2 limit 2".to_owned(),
+        "select * from users order by id limit 2 offset 3".to_owned(),
+        "select id from users order by 3".to_owned(),
+        "select id from users limit 'ten'".to_owned(),
+        ".exit".to_owned(),
+    ];
+    let output = spawn_rust_sqlite(&tempfile, input);
+    let expected_output = vec![
+        "db > Executed. Rows changed: 3.".to_owned(),
+        "db > Executed. Rows changed: 2.".to_owned(),
+        "db > (carol, c@x.com)".to_owned(),
+        "(bob, b@y.org)".to_owned(),
+        "Executed.".to_owned(),
+        "db > (alice, 1)".to_owned(),
+        "(bob, 5)".to_owned(),
+        "(bob, 2)".to_owned(),
+        "(carol, 3)".to_owned(),
+        "(dave, 4)".to_owned(),
+        "Executed.".to_owned(),
+        "db > (20, bob!)".to_owned(),
+        "(50, bob!)".to_owned(),
+        "Executed.".to_owned(),
+        "db > (4, dave, d@z.net)".to_owned(),
+        "(5, bob, a@a.com)".to_owned(),
+        "Executed.".to_owned(),
+        "db > ORDER BY term 3 is out of range.".to_owned(),
+        "db > LIMIT must be an integer.".to_owned(),
+        "db > ".to_owned(),
+    ];
+    assert_eq!(output, expected_output);
+}
+
+#[test]
+fn sorts_results_larger_than_the_sorter_memory() {
+    let tempfile = TempFile::new();
+    create_users_table(&tempfile);
+
+    // Long emails so the sorted rows spill to disk in more runs than are
+    // merged at once
+    let padding = "x".repeat(200);
+    let email = |i: u32| format!("{:03}{padding}@example.com", (i * 37) % 350);
+    let mut input: Vec<_> = (1..=350)
+        .map(|i| format!("insert into users values ({i}, 'user{i}', '{}')", email(i)))
+        .collect();
+    input.push("select id, email from users order by email desc, id".to_owned());
+    input.push(".exit".to_owned());
+
+    let output = spawn_rust_sqlite(&tempfile, input);
+
+    let mut ids: Vec<u32> = (1..=350).collect();
+    ids.sort_by_key(|&i| (std::cmp::Reverse(email(i)), i));
+    let mut expected_output: Vec<_> = ids
+        .iter()
+        .map(|&i| format!("({i}, {})", email(i)))
+        .collect();
+    expected_output[0] = format!("db > {}", expected_output[0]);
+    expected_output.push("Executed.".to_owned());
+    expected_output.push("db > ".to_owned());
+
+    assert_eq!(output[350..], expected_output);
+}
+
+#[test]
+fn keeps_only_the_rows_up_to_the_limit_when_sorting() {
+    let tempfile = TempFile::new();
+    create_users_table(&tempfile);
+
+    let padding = "x".repeat(200);
+    let email = |i: u32| format!("{:03}{padding}@example.com", (i * 37) % 350);
+    let mut input: Vec<_> = (1..=350)
+        .map(|i| format!("insert into users values ({i}, 'user{i}', '{}')", email(i)))
+        .collect();
+    input.push("select id from users order by email desc, id limit 3 offset 2".to_owned());
+    input.push("select id from users order by email limit 0".to_owned());
+    input.push(".exit".to_owned());
+
+    let output = spawn_rust_sqlite(&tempfile, input);
+
+    let mut ids: Vec<u32> = (1..=350).collect();
+    ids.sort_by_key(|&i| (std::cmp::Reverse(email(i)), i));
+    let expected_output = vec![
+        format!("db > ({})", ids[2]),
+        format!("({})", ids[3]),
+        format!("({})", ids[4]),
+        "Executed.".to_owned(),
+        "db > Executed.".to_owned(),
+        "db > ".to_owned(),
+    ];
+    assert_eq!(output[350..], expected_output);
+
+    // No scratch files are left next to the database
+    let prefix = format!("{}-sort", tempfile.filepath);
+    let scratch_files = std::fs::read_dir(std::env::temp_dir())
+        .unwrap()
+        .filter(|entry| {
+            let path = entry.as_ref().unwrap().path();
+            path.to_str().unwrap().starts_with(&prefix)
+        })
+        .count();
+    assert_eq!(scratch_files, 0);
+}
+
+#[test]
+fn preserves_case_and_whitespace_in_quoted_strings() {
+    let tempfile = TempFile::new();
+    create_users_table(&tempfile);
+
+    let input = vec![
+        "INSERT INTO users (email, id, username) VALUES ('O''Brien@Example.com', 1, 'Mary O''Brien') -- comment".to_owned(),
+        "Insert Into Users Values (2, 'Bob', 'bob@example.com'), (3, 'Carol /* not a comment */', 'c@example.com');".to_owned(),
+        "SELECT * FROM users".to_owned(),
+        ".exit".to_owned(),
+    ];
+    let output = spawn_rust_sqlite(&tempfile, input);
+    let expected_output = vec![
+        "db > Executed. Rows changed: 1.".to_owned(),
+        "db > Executed. Rows changed: 2.".to_owned(),
+        "db > (1, Mary O'Brien, O'Brien@Example.com)".to_owned(),
+        "(2, Bob, bob@example.com)".to_owned(),
+        "(3, Carol /* not a comment */, c@example.com)".to_owned(),
+        "Executed.".to_owned(),
+        "db > ".to_owned(),
+    ];
+    assert_eq!(output, expected_output);
+}
+
+#[test]
+fn prints_syntax_errors_with_their_position() {
+    let tempfile = TempFile::new();
+    create_users_table(&tempfile);
+
+    let input = vec![
+        "select * form users".to_owned(),
+        "insert into users values (1, 'user1', 'person1@example.com'".to_owned(),
+        "insert into users values (1, 'user1)".to_owned(),
+        "delete from accounts where id = 1".to_owned(),
+        "insert into users values (1, 'user1')".to_owned(),
+        "truncate table users".to_owned(),
+        ".exit".to_owned(),
+    ];
+    let output = spawn_rust_sqlite(&tempfile, input);
+    let expected_output = vec![
+        "db > Syntax error at line 1, column 10: expected end of statement, found form.".to_owned(),
+        "db > Syntax error at line 1, column 60: expected ), found end of input.".to_owned(),
+        "db > Syntax error at line 1, column 30: unterminated quoted string.".to_owned(),
+        "db > No such table: accounts.".to_owned(),
+        "db > 2 values for 3 columns.".to_owned(),
+        "db > Syntax error at line 1, column 1: expected a statement, found truncate.".to_owned(),
+        "db > ".to_owned(),
+    ];
+    assert_eq!(output, expected_output);
+}
+
+#[test]
+fn keeps_a_created_table_after_closing_connection() {
+    let tempfile = TempFile::new();
+
+    let input = vec![
+        "create table things (code integer primary key, name text(8), count int)".to_owned(),
+        "insert into things (name, count, code) values ('bolt', -3, 7)".to_owned(),
+        ".exit".to_owned(),
+    ];
+    let output = spawn_rust_sqlite(&tempfile, input);
+    assert_eq!(
+        output,
+        vec!["db > Executed.", "db > Executed. Rows changed: 1.", "db > "]
+    );
+
+    let input = vec![
+        ".schema".to_owned(),
+        "select name, count * 2 from things where code = 7".to_owned(),
+        "insert into things values (8, 'too long a name', 1)".to_owned(),
+        "insert into things values (8, 'nut', 'many')".to_owned(),
+        "select * from users".to_owned(),
+        ".exit".to_owned(),
+    ];
+    let output = spawn_rust_sqlite(&tempfile, input);
+    let expected_output = vec![
+        "db > CREATE TABLE things (code INTEGER PRIMARY KEY, name TEXT(8), count INT);",
+        "db > (bolt, -6)",
+        "Executed.",
+        "db > String is too long.",
+        "db > Executed. Rows changed: 1.",
+        "db > No such table: users.",
+        "db > ",
+    ];
+    assert_eq!(output, expected_output);
+}
+
+#[test]
+fn prints_error_messages_for_invalid_table_definitions() {
+    let tempfile = TempFile::new();
+
+    let input = vec![
+        "select * from users".to_owned(),
+        "create table t (a int primary key, a text)".to_owned(),
+        "create table t (a int primary key, b char(0))".to_owned(),
+        "create table t (a text primary key)".to_owned(),
+        "create table t (a int primary key, b int primary key)".to_owned(),
+        "create table t (a int)".to_owned(),
+        "create table t (a int primary key, b char(2000000000))".to_owned(),
+        CREATE_USERS_TABLE.to_owned(),
+        CREATE_USERS_TABLE.to_owned(),
+        ".exit".to_owned(),
+    ];
+    let output = spawn_rust_sqlite(&tempfile, input);
+    let expected_output = vec![
+        "db > No such table: users.",
+        "db > Duplicate column name: a.",
+        "db > Unsupported column type: CHAR(0).",
+        "db > Primary key column a must be an INTEGER.",
+        "db > Table has more than one primary key.",
+        "db > Table must have an INTEGER PRIMARY KEY column.",
+        "db > Row size of 2000000015 bytes is larger than the maximum of 1000000000 bytes.",
+        "db > Executed.",
+        "db > Table users already exists.",
+        "db > ",
+    ];
+    assert_eq!(output, expected_output);
+}
+
+#[test]
+fn stores_values_of_every_type_with_column_affinity() {
+    let tempfile = TempFile::new();
+
+    let input = vec![
+        "create table items (id integer primary key, name text, price real, qty numeric, data blob, note)".to_owned(),
+        "insert into items values (1, 'bolt', 2, '10', X'00ff', NULL)".to_owned(),
+        "insert into items values (2, 3, '2.5', 'many', 'text', 4.0)".to_owned(),
+        "insert into items values ('3', NULL, NULL, '3.0', NULL, 'x')".to_owned(),
+        ".exit".to_owned(),
+    ];
+    spawn_rust_sqlite(&tempfile, input);
+
+    let input = vec![
+        "select * from items".to_owned(),
+        // The number is compared as text with a text column
+        "select id from items where name = 3".to_owned(),
+        // Text that is not a number sorts after every number
+        "select id from items where qty > 5".to_owned(),
+        "select id from items where id between '2' and 2.5".to_owned(),
+        // A comparison with NULL is neither true nor false
+        "select id from items where price > 1 or note is null".to_owned(),
+        "select id from items where not (price > 1)".to_owned(),
+        "select id, price in (2, NULL) from items".to_owned(),
+        "select id from items order by data desc".to_owned(),
+        ".exit".to_owned(),
+    ];
+    let output = spawn_rust_sqlite(&tempfile, input);
+    let expected_output = vec![
+        "db > (1, bolt, 2.0, 10, X'00FF', NULL)",
+        "(2, 3, 2.5, many, text, 4.0)",
+        "(3, NULL, NULL, 3, NULL, x)",
+        "Executed.",
+        "db > (2)",
+        "Executed.",
+        "db > (1)",
+        "(2)",
+        "Executed.",
+        "db > (2)",
+        "Executed.",
+        "db > (1)",
+        "(2)",
+        "Executed.",
+        "db > Executed.",
+        "db > (1, 1)",
+        "(2, NULL)",
+        "(3, NULL)",
+        "Executed.",
+        "db > (1)",
+        "(2)",
+        "(3)",
+        "Executed.",
+        "db > ",
+    ];
+    assert_eq!(output, expected_output);
+}
+
+#[test]
+fn keeps_integer_arithmetic_at_the_ends_of_64_bits() {
+    let tempfile = TempFile::new();
+
+    let input = vec![
+        "select -9223372036854775808, 9223372036854775808, -9223372036854775808.0".to_owned(),
+        "select -9223372036854775808 % -1, 7 % -1, -(-9223372036854775808)".to_owned(),
+        ".exit".to_owned(),
+    ];
+    let output = spawn_rust_sqlite(&tempfile, input);
+    let expected_output = vec![
+        "db > (-9223372036854775808, 9.223372036854776e18, -9.223372036854776e18)",
+        "Executed.",
+        "db > (0, 0, 9.223372036854776e18)",
+        "Executed.",
+        "db > ",
+    ];
+    assert_eq!(output, expected_output);
+}
+
+#[test]
+fn keeps_several_tables_in_one_file() {
+    let tempfile = TempFile::new();
+    create_users_table(&tempfile);
+
+    let mut input = vec!["create table orders (id integer primary key, total int)".to_owned()];
+    input
+        .extend((1..=20).map(|i| {
+            format!("insert into users values ({i}, 'user{i}', 'person{i}@example.com')")
+        }));
+    input.extend((1..=20).map(|i| format!("insert into orders values ({i}, {})", i * 100)));
+    input.push(".exit".to_owned());
+    spawn_rust_sqlite(&tempfile, input);
+
+    let input = vec![
+        ".schema".to_owned(),
+        "select name, tbl_name from sqlite_master".to_owned(),
+        "select username from users where id = 20".to_owned(),
+        "select total from orders where id = 7".to_owned(),
+        "delete from sqlite_master".to_owned(),
+        "create table orders (id integer primary key)".to_owned(),
+        "create table sqlite_stat (id integer primary key)".to_owned(),
+        ".exit".to_owned(),
+    ];
+    let output = spawn_rust_sqlite(&tempfile, input);
+    let expected_output = vec![
+        "db > CREATE TABLE users (id INTEGER PRIMARY KEY, username VARCHAR(32), email VARCHAR(255));",
+        "CREATE TABLE orders (id INTEGER PRIMARY KEY, total INT);",
+        "db > (users, users)",
+        "(orders, orders)",
+        "Executed.",
+        "db > (user20)",
+        "Executed.",
+        "db > (700)",
+        "Executed.",
+        "db > Table sqlite_master may not be modified.",
+        "db > Table orders already exists.",
+        "db > Object name reserved for internal use: sqlite_stat.",
+        "db > ",
+    ];
+    assert_eq!(output, expected_output);
+}
+
+#[test]
+fn reuses_the_pages_of_a_dropped_table() {
+    let tempfile = TempFile::new();
+    create_users_table(&tempfile);
+
+    let inserts: Vec<_> = (1..=30)
+        .map(|i| format!("insert into users values ({i}, 'user{i}', 'person{i}@example.com')"))
+        .collect();
+    let mut input = inserts.clone();
+    input.push(".exit".to_owned());
+    spawn_rust_sqlite(&tempfile, input);
+    let file_length = std::fs::metadata(&tempfile.filepath).unwrap().len();
+
+    let mut input = vec![
+        "drop table users".to_owned(),
+        "drop table users".to_owned(),
+        "drop table if exists users".to_owned(),
+        CREATE_USERS_TABLE.to_owned(),
+    ];
+    input.extend(inserts);
+    input.push(".exit".to_owned());
+    let output = spawn_rust_sqlite(&tempfile, input);
+    assert_eq!(
+        output[..4],
+        [
+            "db > Executed.",
+            "db > No such table: users.",
+            "db > Executed.",
+            "db > Executed."
+        ]
+    );
+    assert_eq!(
+        std::fs::metadata(&tempfile.filepath).unwrap().len(),
+        file_length
+    );
+}
+
+#[test]
+fn keeps_pages_freed_by_deletes_on_a_freelist() {
+    let tempfile = TempFile::new();
+    create_users_table(&tempfile);
+
+    let inserts: Vec<_> = (3..=30).map(insert_full_user).collect();
+    let mut input = vec![
+        "insert into users values (1, 'user1', 'person1@example.com')".to_owned(),
+        "insert into users values (2, 'user2', 'person2@example.com')".to_owned(),
+    ];
+    input.extend(inserts.clone());
+    input.push(".exit".to_owned());
+    spawn_rust_sqlite(&tempfile, input);
+    let file_length = std::fs::metadata(&tempfile.filepath).unwrap().len();
+
+    let input = vec![
+        "delete from users where id > 2".to_owned(),
+        ".exit".to_owned(),
+    ];
+    spawn_rust_sqlite(&tempfile, input);
+    assert_eq!(
+        std::fs::metadata(&tempfile.filepath).unwrap().len(),
+        file_length
+    );
+
+    // The freelist survives reopening the file, and new rows take from it
+    let mut input = vec![".dbinfo".to_owned()];
+    input.extend(inserts);
+    input.push(".dbinfo".to_owned());
+    input.push("select id from users where id > 28".to_owned());
+    input.push(".exit".to_owned());
+    let output = spawn_rust_sqlite(&tempfile, input);
+    assert_ne!(output[3], "freelist head: 0");
+    assert_ne!(output[4], "freelist count: 0");
+    assert_eq!(output[39], "freelist count: 0");
+    assert_eq!(output[42..], ["db > (29)", "(30)", "Executed.", "db > "]);
+    assert_eq!(
+        std::fs::metadata(&tempfile.filepath).unwrap().len(),
+        file_length
+    );
+}
+
+#[test]
+fn vacuums_the_database_into_a_smaller_file() {
+    let tempfile = TempFile::new();
+    let vacuum_file = TempFile::new();
+    create_users_table(&tempfile);
+
+    let mut input: Vec<_> = (1..=30).map(insert_full_user).collect();
+    input.push("delete from users where id > 2 and id < 29".to_owned());
+    input.push(".exit".to_owned());
+    spawn_rust_sqlite(&tempfile, input);
+    let file_length = std::fs::metadata(&tempfile.filepath).unwrap().len();
+
+    let input = vec![
+        "begin".to_owned(),
+        "vacuum".to_owned(),
+        "rollback".to_owned(),
+        format!("vacuum into '{}'", vacuum_file.filepath),
+        format!("vacuum into '{}'", vacuum_file.filepath),
+        "vacuum".to_owned(),
+        ".dbinfo".to_owned(),
+        "select * from users".to_owned(),
+        ".exit".to_owned(),
+    ];
+    let output = spawn_rust_sqlite(&tempfile, input);
+    assert_eq!(
+        output[..6],
+        [
+            "db > Executed.".to_owned(),
+            "db > Error: A transaction is already active.".to_owned(),
+            "db > Executed.".to_owned(),
+            "db > Executed.".to_owned(),
+            format!(
+                "db > Error: Output file already exists: {}.",
+                vacuum_file.filepath
+            ),
+            "db > Executed.".to_owned(),
+        ]
+    );
+    assert_eq!(
+        output[8..11],
+        ["page count: 3", "freelist head: 0", "freelist count: 0"]
+    );
+    let rows = [1, 2, 29, 30].map(full_user_row);
+    assert_eq!(output[13], format!("db > {}", rows[0]));
+    assert_eq!(output[14..17], rows[1..]);
+
+    let vacuumed_length = std::fs::metadata(&tempfile.filepath).unwrap().len();
+    assert!(vacuumed_length < file_length);
+    assert_eq!(
+        std::fs::metadata(&vacuum_file.filepath).unwrap().len(),
+        vacuumed_length
+    );
+
+    let input = vec!["select * from users".to_owned(), ".exit".to_owned()];
+    let output = spawn_rust_sqlite(&vacuum_file, input);
+    assert_eq!(output[0], format!("db > {}", rows[0]));
+    assert_eq!(output[1..4], rows[1..]);
+}
+
+#[test]
+fn packs_rows_of_any_length_into_leaf_pages() {
+    let tempfile = TempFile::new();
+
+    // Short rows take up only the space they need, so a hundred fit in one leaf
+    let mut input = vec!["create table notes (id integer primary key, body text(1000))".to_owned()];
+    input.extend((1..=100).map(|i| format!("insert into notes values ({i}, 'n{i}')")));
+    input.push(".btree".to_owned());
+    input.push("delete from notes".to_owned());
+    input.push(".exit".to_owned());
+    let output = spawn_rust_sqlite(&tempfile, input);
+    assert_eq!(output[101], "db > Tree:");
+    assert_eq!(output[102], "- leaf (size 100)");
+
+    // Four long rows fill most of a leaf. The space of deleted and shrunk
+    // rows is reused for new rows before the leaf splits.
+    let body = "x".repeat(900);
+    let mut input: Vec<_> = (1..=4)
+        .map(|i| format!("insert into notes values ({i}, '{body}')"))
+        .collect();
+    input.push("delete from notes where id = 1 or id = 3".to_owned());
+    input.push("update notes set body = 'short' where id = 2".to_owned());
+    input.extend((5..=7).map(|i| format!("insert into notes values ({i}, '{body}')")));
+    input.push(".btree".to_owned());
+    input.push(format!("insert into notes values (8, '{body}')"));
+    input.push(".btree".to_owned());
+    input.push(".exit".to_owned());
+    let output = spawn_rust_sqlite(&tempfile, input);
+    assert_eq!(
+        output[9..16],
+        [
+            "db > Tree:",
+            "- leaf (size 5)",
+            " - 2",
+            " - 4",
+            " - 5",
+            " - 6",
+            " - 7"
+        ]
+    );
+    assert_eq!(
+        output[17..20],
+        ["db > Tree:", "- internal (size 1)", " - leaf (size 3)"]
+    );
+}
+
+#[test]
+fn keeps_tables_larger_than_the_page_cache() {
+    let tempfile = TempFile::new();
+
+    // Three rows fill a page, so the table needs well over 100 pages
+    let mut input = vec![
+        "create table notes (id integer primary key, body text(1000))".to_owned(),
+        "pragma cache_size = 8".to_owned(),
+        "pragma cache_size".to_owned(),
+    ];
+    let body = "x".repeat(990);
+    input.extend((1..=400).map(|i| format!("insert into notes values ({i}, 'note{i}{body}')")));
+    input.push(".stats".to_owned());
+    input.push(".exit".to_owned());
+    let output = spawn_rust_sqlite(&tempfile, input);
+    assert_eq!(
+        output[..3],
+        ["db > Executed.", "db > Executed.", "db > (8)"]
+    );
+    assert_eq!(output[404], "db > cache size: 8");
+    assert_eq!(output[405], "cached pages: 8");
+    assert!(output[408] != "evictions: 0");
+    assert!(std::fs::metadata(&tempfile.filepath).unwrap().len() > 100 * 4096);
+
+    let input = vec![
+        "pragma cache_size = 0".to_owned(),
+        "pragma page_size".to_owned(),
+        "select id from notes where id = 1 or id = 200 or id = 400".to_owned(),
+        ".exit".to_owned(),
+    ];
+    let output = spawn_rust_sqlite(&tempfile, input);
+    assert_eq!(
+        output,
+        vec![
+            "db > cache_size must be a positive integer.",
+            "db > Unknown pragma: page_size.",
+            "db > (1)",
+            "(200)",
+            "(400)",
+            "Executed.",
+            "db > ",
+        ]
+    );
+}
+
+#[test]
+fn rolls_back_a_hot_journal_on_open() {
+    let tempfile = TempFile::new();
+    create_users_table(&tempfile);
+    let journal_path = format!("{}-journal", tempfile.filepath);
+
+    let input = vec![
+        "insert into users values (1, 'user1', 'person1@example.com')".to_owned(),
+        ".exit".to_owned(),
+    ];
+    spawn_rust_sqlite(&tempfile, input);
+    assert!(!std::path::Path::new(&journal_path).exists());
+    let original = std::fs::read(&tempfile.filepath).unwrap();
+
+    let mut input: Vec<_> = (2..=30).map(insert_full_user).collect();
+    input.push(".exit".to_owned());
+    spawn_rust_sqlite(&tempfile, input);
+    assert!(std::fs::metadata(&tempfile.filepath).unwrap().len() > original.len() as u64);
+
+    // The journal a crash would leave behind if it happened while the
+    // inserts were written: the original image of every page, the last one torn
+    let mut journal = b"rust-sqlite jrnl".to_vec();
+    journal.extend((original.len() as u32 / 4096).to_le_bytes());
+    for (page_num, page) in original.chunks(4096).enumerate() {
+        let mut record = (page_num as u32).to_le_bytes().to_vec();
+        record.extend(page);
+        let checksum = journal_checksum(&record);
+        record.extend(checksum.to_le_bytes());
+        journal.extend(record);
+    }
+    journal.extend(2u32.to_le_bytes());
+    journal.extend([0xAB; 100]);
+    std::fs::write(&journal_path, journal).unwrap();
+
+    let input = vec!["select * from users".to_owned(), ".exit".to_owned()];
+    let output = spawn_rust_sqlite(&tempfile, input);
+    assert_eq!(
+        output,
+        vec!["db > (1, user1, person1@example.com)", "Executed.", "db > "]
+    );
+    assert!(!std::path::Path::new(&journal_path).exists());
+    assert_eq!(std::fs::read(&tempfile.filepath).unwrap(), original);
+}
+
+#[test]
+fn writes_changes_to_a_write_ahead_log_in_wal_mode() {
+    let tempfile = TempFile::new();
+    create_users_table(&tempfile);
+    let wal_path = format!("{}-wal", tempfile.filepath);
+
+    let mut input = vec![
+        "pragma journal_mode".to_owned(),
+        "pragma journal_mode = wal".to_owned(),
+        "pragma journal_mode = 'memory'".to_owned(),
+    ];
+    input
+        .extend((1..=100).map(|i| {
+            format!("insert into users values ({i}, 'user{i}', 'person{i}@example.com')")
+        }));
+    input.push("pragma wal_checkpoint".to_owned());
+    input.push("pragma wal_checkpoint".to_owned());
+    input.push(".exit".to_owned());
+    let output = spawn_rust_sqlite(&tempfile, input);
+    assert_eq!(output[..3], ["db > (delete)", "Executed.", "db > (wal)"]);
+    assert_eq!(output[4], "db > Unknown journal mode: memory.");
+    assert_eq!(output[106], "Executed.");
+    assert_eq!(output[107], "db > (0)");
+    assert!(!std::path::Path::new(&wal_path).exists());
+
+    let input = vec![
+        "pragma journal_mode".to_owned(),
+        "select * from users where id >= 99".to_owned(),
+        "pragma journal_mode = delete".to_owned(),
+        ".exit".to_owned(),
+    ];
+    let output = spawn_rust_sqlite(&tempfile, input);
+    assert_eq!(
+        output,
+        vec![
+            "db > (wal)",
+            "Executed.",
+            "db > (99, user99, person99@example.com)",
+            "(100, user100, person100@example.com)",
+            "Executed.",
+            "db > (delete)",
+            "Executed.",
+            "db > ",
+        ]
+    );
+    assert!(!std::path::Path::new(&wal_path).exists());
+}
+
+#[test]
+fn recovers_commits_from_a_write_ahead_log_on_open() {
+    let tempfile = TempFile::new();
+    create_users_table(&tempfile);
+    let wal_path = format!("{}-wal", tempfile.filepath);
+    let original = std::fs::read(&tempfile.filepath).unwrap();
+
+    let mut input: Vec<_> = (1..=30)
+        .map(|i| format!("insert into users values ({i}, 'user{i}', 'person{i}@example.com')"))
+        .collect();
+    input.push(".exit".to_owned());
+    spawn_rust_sqlite(&tempfile, input);
+    let committed = std::fs::read(&tempfile.filepath).unwrap();
+
+    // The log a crash would leave behind: every page of the committed file
+    // with the header page as the commit frame, then a frame not committed
+    let wal_frame = |page_num: u32, page_count: u32, page: &[u8]| {
+        let mut frame = page_num.to_le_bytes().to_vec();
+        frame.extend(page_count.to_le_bytes());
+        let mut checked = frame.clone();
+        checked.extend(page);
+        frame.extend(journal_checksum(&checked).to_le_bytes());
+        frame.extend(page);
+        frame
+    };
+    let page_count = committed.len() as u32 / 4096;
+    let mut wal = b"rust-sqlite wal\0".to_vec();
+    wal.extend(4096u32.to_le_bytes());
+    for (page_num, page) in committed.chunks(4096).enumerate().skip(1) {
+        wal.extend(wal_frame(page_num as u32, 0, page));
+    }
+    wal.extend(wal_frame(0, page_count, &committed[..4096]));
+    wal.extend(wal_frame(1, 0, &[0; 4096]));
+    std::fs::write(&tempfile.filepath, original).unwrap();
+    std::fs::write(&wal_path, wal).unwrap();
+
+    let input = vec![
+        "select * from users where id <= 2".to_owned(),
+        ".exit".to_owned(),
+    ];
+    let output = spawn_rust_sqlite(&tempfile, input);
+    assert_eq!(
+        output,
+        vec![
+            "db > (1, user1, person1@example.com)",
+            "(2, user2, person2@example.com)",
+            "Executed.",
+            "db > "
+        ]
+    );
+    assert!(!std::path::Path::new(&wal_path).exists());
+    assert_eq!(std::fs::read(&tempfile.filepath).unwrap(), committed);
+}
+
+#[test]
+fn groups_statements_into_transactions() {
+    let tempfile = TempFile::new();
+    create_users_table(&tempfile);
+
+    let input = vec![
+        "begin".to_owned(),
+        "insert into users values (1, 'user1', 'person1@example.com')".to_owned(),
+        "create table notes (id integer primary key, body text)".to_owned(),
+        "rollback".to_owned(),
+        "select * from notes".to_owned(),
+        "begin transaction".to_owned(),
+        "insert into users values (2, 'user2', 'person2@example.com')".to_owned(),
+        "begin".to_owned(),
+        "pragma journal_mode = wal".to_owned(),
+        "commit transaction".to_owned(),
+        "commit".to_owned(),
+        "rollback".to_owned(),
+        "select * from users".to_owned(),
+        "begin".to_owned(),
+        "insert into users values (3, 'user3', 'person3@example.com')".to_owned(),
+        ".exit".to_owned(),
+    ];
+    let output = spawn_rust_sqlite(&tempfile, input);
+    assert_eq!(
+        output,
+        vec![
+            "db > Executed.",
+            "db > Executed. Rows changed: 1.",
+            "db > Executed.",
+            "db > Executed.",
+            "db > No such table: notes.",
+            "db > Executed.",
+            "db > Executed. Rows changed: 1.",
+            "db > Error: A transaction is already active.",
+            "db > Error: A transaction is already active.",
+            "db > Executed.",
+            "db > Error: No transaction is active.",
+            "db > Error: No transaction is active.",
+            "db > (2, user2, person2@example.com)",
+            "Executed.",
+            "db > Executed.",
+            "db > Executed. Rows changed: 1.",
+            "db > ",
+        ]
+    );
+
+    // The transaction left open was rolled back on exit
+    let input = vec!["select * from users".to_owned(), ".exit".to_owned()];
+    let output = spawn_rust_sqlite(&tempfile, input);
+    assert_eq!(
+        output,
+        vec!["db > (2, user2, person2@example.com)", "Executed.", "db > "]
+    );
+}
+
+#[test]
+fn rolls_back_changes_written_out_by_the_page_cache() {
+    for journal_mode in ["delete", "wal"] {
+        let tempfile = TempFile::new();
+        create_users_table(&tempfile);
+
+        let mut input = vec![
+            format!("pragma journal_mode = {journal_mode}"),
+            "pragma cache_size = 2".to_owned(),
+            "insert into users values (1, 'user1', 'person1@example.com')".to_owned(),
+            "begin".to_owned(),
+            "delete from users".to_owned(),
+        ];
+        input.extend((2..=100).map(|i| {
+            format!("insert into users values ({i}, 'user{i}', 'person{i}@example.com')")
+        }));
+        input.push("rollback".to_owned());
+        input.push("select * from users".to_owned());
+        input.push(".exit".to_owned());
+        let output = spawn_rust_sqlite(&tempfile, input);
+        assert_eq!(
+            output[output.len() - 4..],
+            [
+                "db > Executed.",
+                "db > (1, user1, person1@example.com)",
+                "Executed.",
+                "db > "
+            ]
+        );
+
+        let input = vec![".btree".to_owned(), ".exit".to_owned()];
+        let output = spawn_rust_sqlite(&tempfile, input);
+        assert_eq!(
+            output,
+            vec!["db > Tree:", "- leaf (size 1)", " - 1", "db > "]
+        );
+    }
+}
+
+#[test]
+fn rolls_back_to_nested_savepoints() {
+    let tempfile = TempFile::new();
+    create_users_table(&tempfile);
+
+    let insert =
+        |i: usize| format!("insert into users values ({i}, 'user{i}', 'person{i}@example.com')");
+    let mut input = vec![
+        "pragma cache_size = 2".to_owned(),
+        "savepoint outer".to_owned(),
+        insert(1),
+        "savepoint inner".to_owned(),
+    ];
+    input.extend((2..=50).map(insert));
+    input.push("create table notes (id integer primary key)".to_owned());
+    input.push("rollback to inner".to_owned());
+    input.push("select * from notes".to_owned());
+    input.extend((51..=60).map(insert));
+    input.push("rollback transaction to savepoint outer".to_owned());
+    input.push(insert(61));
+    input.push("release missing".to_owned());
+    input.push("release savepoint outer".to_owned());
+    input.push("rollback".to_owned());
+    input.push("select * from users".to_owned());
+    input.push(".exit".to_owned());
+    let output = spawn_rust_sqlite(&tempfile, input);
+    assert_eq!(
+        output[output.len() - 7..],
+        [
+            "db > Executed. Rows changed: 1.",
+            "db > Error: No such savepoint: missing.",
+            "db > Executed.",
+            "db > Error: No transaction is active.",
+            "db > (61, user61, person61@example.com)",
+            "Executed.",
+            "db > ",
+        ]
+    );
+    assert_eq!(output[55], "db > No such table: notes.");
+
+    // Savepoints within BEGIN leave the transaction open when released
+    let input = vec![
+        "begin".to_owned(),
+        "savepoint a".to_owned(),
+        insert(62),
+        "release a".to_owned(),
+        "rollback to a".to_owned(),
+        "rollback".to_owned(),
+        ".btree".to_owned(),
+        ".exit".to_owned(),
+    ];
+    let output = spawn_rust_sqlite(&tempfile, input);
+    assert_eq!(
+        output,
+        vec![
+            "db > Executed.",
+            "db > Executed.",
+            "db > Executed. Rows changed: 1.",
+            "db > Executed.",
+            "db > Error: No such savepoint: a.",
+            "db > Executed.",
+            "db > Tree:",
+            "- leaf (size 1)",
+            " - 61",
+            "db > ",
+        ]
+    );
+}
+
+#[test]
+fn records_the_file_layout_in_a_header() {
+    let tempfile = TempFile::new();
+    create_users_table(&tempfile);
+
+    let input = vec![
+        "insert into users values (1, 'user1', 'person1@example.com')".to_owned(),
+        ".dbinfo".to_owned(),
+        ".exit".to_owned(),
+    ];
+    let output = spawn_rust_sqlite(&tempfile, input);
+    let expected_output = vec![
+        "db > Executed. Rows changed: 1.",
+        "db > database page size: 4096",
+        "format version: 3",
+        "page count: 3",
+        "freelist head: 0",
+        "freelist count: 0",
+        "schema cookie: 1",
+        "journal mode: delete",
+        "db > ",
+    ];
+    assert_eq!(output, expected_output);
+
+    let bytes = std::fs::read(&tempfile.filepath).unwrap();
+    assert_eq!(bytes.len(), 3 * 4096);
+    assert_eq!(&bytes[..16], b"rust-sqlite fmt\0");
+}
+
+#[test]
+fn refuses_to_open_a_file_that_is_not_a_database() {
+    let tempfile = TempFile::new();
+    let contents: Vec<u8> = (0..2 * 4096).map(|i| (i % 251) as u8).collect();
+    std::fs::write(&tempfile.filepath, &contents).unwrap();
+
+    let output = rust_sqlite_exe()
+        .arg(&tempfile.filepath)
+        .stdin(Stdio::null())
+        .output()
+        .expect("Unable to start the process.");
+
+    assert!(!output.status.success());
+    assert_eq!(
+        str::from_utf8(&output.stderr).unwrap(),
+        "Error: File is not a database.\n"
+    );
+    assert_eq!(std::fs::read(&tempfile.filepath).unwrap(), contents);
+}
+
+#[test]
+fn prints_an_error_for_a_corrupt_page_and_keeps_running() {
+    let tempfile = TempFile::new();
+    create_users_table(&tempfile);
+
+    // Page 2 is the root of the users table, give it an unknown page type
+    let mut bytes = std::fs::read(&tempfile.filepath).unwrap();
+    bytes[2 * 4096] = 7;
+    std::fs::write(&tempfile.filepath, &bytes).unwrap();
+
+    let input = vec![
+        "select * from users".to_owned(),
+        "insert into users values (1, 'user1', 'person1@example.com')".to_owned(),
+        "select * from sqlite_master where name = 'nothing'".to_owned(),
+        ".exit".to_owned(),
+    ];
+    let output = spawn_rust_sqlite(&tempfile, input);
+    let expected_output = vec![
+        "db > Error: Unknown page type. Corrupt file.",
+        "db > Error: Unknown page type. Corrupt file.",
+        "db > Executed.",
+        "db > ",
+    ];
+    assert_eq!(output, expected_output);
+}
+
+#[test]
+fn prints_an_error_for_leaves_that_link_up_in_a_loop() {
+    let tempfile = TempFile::new();
+    create_users_table(&tempfile);
+
+    let mut input: Vec<_> = (1..=30).map(insert_full_user).collect();
+    input.push(".exit".to_owned());
+    spawn_rust_sqlite(&tempfile, input);
+
+    // Point the second leaf of the users table, page 3, back at itself
+    let mut bytes = std::fs::read(&tempfile.filepath).unwrap();
+    assert_eq!(bytes[3 * 4096], 0);
+    bytes[3 * 4096 + 10..3 * 4096 + 14].copy_from_slice(&3u32.to_le_bytes());
+    std::fs::write(&tempfile.filepath, &bytes).unwrap();
+
+    // Vacuum walks the leaves of each table from the first to the last
+    let input = vec!["vacuum".to_owned(), ".exit".to_owned()];
+    let output = spawn_rust_sqlite(&tempfile, input);
+    assert_eq!(
+        output,
+        vec![
+            "db > Error: Leaves of a table link up in a loop. Corrupt file.",
+            "db > "
+        ]
+    );
+
+    // Then at page 2, the root of the table, which is an internal node
+    bytes[3 * 4096 + 10..3 * 4096 + 14].copy_from_slice(&2u32.to_le_bytes());
+    std::fs::write(&tempfile.filepath, &bytes).unwrap();
+
+    let input = vec!["vacuum".to_owned(), ".exit".to_owned()];
+    let output = spawn_rust_sqlite(&tempfile, input);
+    assert_eq!(
+        output,
+        vec![
+            "db > Error: Page is not a table leaf. Corrupt file.",
+            "db > "
+        ]
+    );
+}
+
+#[test]
+fn prints_usage_without_a_database_filename() {
+    let output = rust_sqlite_exe()
+        .stdin(Stdio::null())
+        .output()
+        .expect("Unable to start the process.");
+
+    assert!(!output.status.success());
+    let stderr = str::from_utf8(&output.stderr).unwrap();
+    assert!(stderr.starts_with("Usage: "), "{stderr}");
+    assert!(stderr.ends_with(" DATABASE\n"), "{stderr}");
+}
+
+#[test]
+fn runs_statements_through_a_connection() {
+    let tempfile = TempFile::new();
+    let connection = Connection::open(&tempfile.filepath).unwrap();
+    connection.execute(CREATE_USERS_TABLE, &[]).unwrap();
+    for i in 1..=20 {
+        let sql = format!("insert into users values ({i}, 'user{i}', 'person{i}@example.com')");
+        assert_eq!(connection.execute(&sql, &[]).unwrap(), 1);
+    }
+
+    // Rows are read as they are iterated, other statements can run in between
+    let mut select = connection
+        .prepare("select id, username from users where id >= 14 limit 3")
+        .unwrap();
+    let mut rows = select.query(&[]).unwrap();
+    let first_row = rows.next().unwrap().unwrap();
+    assert_eq!(
+        first_row,
+        vec![Value::Integer(14), Value::Text("user14".to_owned())]
+    );
+    let rows_changed = connection
+        .execute("delete from users where id > 15", &[])
+        .unwrap();
+    assert_eq!(rows_changed, 5);
+    let rest: Vec<_> = rows.map(Result::unwrap).collect();
+    assert_eq!(
+        rest,
+        vec![vec![Value::Integer(15), Value::Text("user15".to_owned())]]
+    );
+
+    let count = select.query(&[]).unwrap().count();
+    assert_eq!(count, 2);
+
+    let err = connection.execute("insert into users values (1, 'a', 'b')", &[]);
+    assert_eq!(err.unwrap_err().to_string(), "Error: Duplicate key.");
+    drop(select);
+    drop(connection);
+
+    // The changes were committed as they were made
+    let output = spawn_rust_sqlite(
+        &tempfile,
+        vec!["select id from users where id > 13".to_owned()],
+    );
+    assert_eq!(output, vec!["db > (14)", "(15)", "Executed.", "db > "]);
+}
+
+#[test]
+fn keeps_values_larger_than_a_page_on_overflow_pages() {
+    let tempfile = TempFile::new();
+    let connection = Connection::open(&tempfile.filepath).unwrap();
+    connection
+        .execute(
+            "create table docs (id integer primary key, body text, data blob)",
+            &[],
+        )
+        .unwrap();
+
+    let body = |i: i64| format!("document {i}. ").repeat(1000);
+    let data = |i: i64| vec![i as u8; 20000];
+    let mut insert = connection
+        .prepare("insert into docs values (?, ?, ?)")
+        .unwrap();
+    for i in 1..=10 {
+        let params = [
+            Value::Integer(i),
+            Value::Text(body(i)),
+            Value::Blob(data(i)),
+        ];
+        insert.execute(&params).unwrap();
+    }
+    drop(insert);
+    assert!(connection.database().pager.num_pages > 80);
+
+    let mut select = connection
+        .prepare("select * from docs where id = ?")
+        .unwrap();
+    let rows: Vec<_> = select
+        .query(&[Value::Integer(7)])
+        .unwrap()
+        .map(Result::unwrap)
+        .collect();
+    let row = |i: i64| {
+        vec![
+            Value::Integer(i),
+            Value::Text(body(i)),
+            Value::Blob(data(i)),
+        ]
+    };
+    assert_eq!(rows, vec![row(7)]);
+
+    // Shrinking and deleting rows frees their overflow pages, growing a
+    // row takes pages from the freelist
+    connection
+        .execute(
+            "update docs set body = 'short', data = null where id <= 5",
+            &[],
+        )
+        .unwrap();
+    connection
+        .execute("delete from docs where id > 8", &[])
+        .unwrap();
+    let freelist_count = connection.database().pager.freelist_count().unwrap();
+    assert!(freelist_count > 50);
+    connection
+        .execute(
+            "update docs set body = ?, data = ? where id = 1",
+            &[Value::Text(body(1)), Value::Blob(data(1))],
+        )
+        .unwrap();
+    assert!(connection.database().pager.freelist_count().unwrap() < freelist_count);
+
+    connection.execute("vacuum", &[]).unwrap();
+    assert_eq!(connection.database().pager.freelist_count().unwrap(), 0);
+    drop(select);
+    drop(connection);
+
+    let connection = Connection::open(&tempfile.filepath).unwrap();
+    let mut select = connection
+        .prepare("select * from docs where id = 1 or id > 5")
+        .unwrap();
+    let rows: Vec<_> = select.query(&[]).unwrap().map(Result::unwrap).collect();
+    assert_eq!(rows, vec![row(1), row(6), row(7), row(8)]);
+    let short_row = vec![
+        Value::Integer(2),
+        Value::Text("short".to_owned()),
+        Value::Null,
+    ];
+    let mut select = connection
+        .prepare("select * from docs where id = 2")
+        .unwrap();
+    let rows: Vec<_> = select.query(&[]).unwrap().map(Result::unwrap).collect();
+    assert_eq!(rows, vec![short_row]);
+
+    // Index keys have no overflow pages
+    let err = connection
+        .execute("create index docs_body on docs (body)", &[])
+        .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Index key size of 12005 bytes is larger than the maximum of 1014 bytes."
+    );
+}
+
+#[test]
+fn prepares_a_statement_again_after_the_schema_changes() {
+    let tempfile = TempFile::new();
+    let connection = Connection::open(&tempfile.filepath).unwrap();
+    connection
+        .execute("create table t (id integer primary key, a text(8))", &[])
+        .unwrap();
+    connection
+        .execute("insert into t values (1, 'one')", &[])
+        .unwrap();
+
+    let mut statement = connection.prepare("select * from t").unwrap();
+    let rows: Vec<_> = statement.query(&[]).unwrap().map(Result::unwrap).collect();
+    assert_eq!(
+        rows,
+        vec![vec![Value::Integer(1), Value::Text("one".to_owned())]]
+    );
+
+    connection.execute("drop table t", &[]).unwrap();
+    let err = statement.query(&[]).err().unwrap();
+    assert_eq!(err.to_string(), "No such table: t.");
+
+    connection
+        .execute(
+            "create table t (id integer primary key, b text(8), c integer)",
+            &[],
+        )
+        .unwrap();
+    connection
+        .execute("insert into t values (2, 'two', 3)", &[])
+        .unwrap();
+    let rows: Vec<_> = statement.query(&[]).unwrap().map(Result::unwrap).collect();
+    assert_eq!(
+        rows,
+        vec![vec![
+            Value::Integer(2),
+            Value::Text("two".to_owned()),
+            Value::Integer(3)
+        ]]
+    );
+}
+
+#[test]
+fn binds_values_to_the_parameters_of_a_statement() {
+    let tempfile = TempFile::new();
+    let connection = Connection::open(&tempfile.filepath).unwrap();
+    connection.execute(CREATE_USERS_TABLE, &[]).unwrap();
+
+    // Values are never formatted into the SQL, so quotes need no escaping
+    let mut insert = connection
+        .prepare("insert into users values (?, ?, ?)")
+        .unwrap();
+    assert_eq!(insert.parameter_count(), 3);
+    for i in 1..=3 {
+        let params = [
+            Value::Integer(i),
+            Value::Text(format!("o'user {i}")),
+            Value::Text(format!("person{i}@example.com")),
+        ];
+        assert_eq!(insert.execute(&params).unwrap(), 1);
+    }
+    let err = insert.execute(&[Value::Integer(4)]).unwrap_err();
+    assert_eq!(err.to_string(), "1 values for 3 parameters.");
+
+    let mut select = connection
+        .prepare("select username from users where id between :low and ?5 or id = :low")
+        .unwrap();
+    assert_eq!(select.parameter_count(), 5);
+    assert_eq!(select.parameter_index(":low"), Some(1));
+    assert_eq!(select.parameter_name(5), None);
+    select.bind_int(1, 2).unwrap();
+    select.bind_int(5, 3).unwrap();
+    let rows: Vec<_> = select.query(&[]).unwrap().map(Result::unwrap).collect();
+    assert_eq!(
+        rows,
+        vec![
+            vec![Value::Text("o'user 2".to_owned())],
+            vec![Value::Text("o'user 3".to_owned())]
+        ]
+    );
+
+    // Rebinding plans the statement again with the new values
+    select.bind_null(5).unwrap();
+    let rows: Vec<_> = select.query(&[]).unwrap().map(Result::unwrap).collect();
+    assert_eq!(rows, vec![vec![Value::Text("o'user 2".to_owned())]]);
+    let err = select.bind_text(6, "x").unwrap_err();
+    assert_eq!(err.to_string(), "Parameter index 6 is out of range.");
+
+    let mut select = connection
+        .prepare("select id, ? from users where ? = X'6869' and id < 3")
+        .unwrap();
+    select.bind_blob(1, &[0xca, 0xfe]).unwrap();
+    select.bind_blob(2, b"hi").unwrap();
+    let rows: Vec<_> = select.query(&[]).unwrap().map(Result::unwrap).collect();
+    assert_eq!(
+        rows,
+        vec![
+            vec![Value::Integer(1), Value::Blob(vec![0xca, 0xfe])],
+            vec![Value::Integer(2), Value::Blob(vec![0xca, 0xfe])]
+        ]
+    );
+    select.clear_bindings();
+    assert_eq!(select.query(&[]).unwrap().count(), 0);
+
+    let mut select = connection.prepare("select ?, ? is null").unwrap();
+    select.bind(1, Value::Real(1.5)).unwrap();
+    select.bind(2, Value::Real(f64::NAN)).unwrap();
+    let rows: Vec<_> = select.query(&[]).unwrap().map(Result::unwrap).collect();
+    assert_eq!(rows, vec![vec![Value::Real(1.5), Value::Integer(1)]]);
+}
+
+#[test]
+fn looks_up_rows_through_secondary_indexes() {
+    let tempfile = TempFile::new();
+    let connection = Connection::open(&tempfile.filepath).unwrap();
+    connection.execute(CREATE_USERS_TABLE, &[]).unwrap();
+    let mut insert = connection
+        .prepare("insert into users values (?, ?, ?)")
+        .unwrap();
+    for i in 1..=200 {
+        let params = [
+            Value::Integer(i),
+            Value::Text(format!("user{}", i % 50)),
+            Value::Text(format!("person{i}@example.com")),
+        ];
+        insert.execute(&params).unwrap();
+    }
+    drop(insert);
+
+    connection
+        .execute("create index users_username on users (username)", &[])
+        .unwrap();
+    connection
+        .execute("create unique index on users (email)", &[])
+        .unwrap();
+    let err = connection
+        .execute("create unique index on users (username)", &[])
+        .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Error: UNIQUE constraint failed: users.username."
+    );
+    let err = connection
+        .execute("create index users_username on users (email)", &[])
+        .unwrap_err();
+    assert_eq!(err.to_string(), "Index users_username already exists.");
+
+    let err = connection
+        .execute(
+            "insert into users values (201, 'user1', 'person7@example.com')",
+            &[],
+        )
+        .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Error: UNIQUE constraint failed: users.email."
+    );
+    let err = connection
+        .execute(
+            "update users set email = 'person8@example.com' where id = 9",
+            &[],
+        )
+        .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Error: UNIQUE constraint failed: users.email."
+    );
+
+    let ids = |sql: &str| -> Vec<i64> {
+        let mut statement = connection.prepare(sql).unwrap();
+        let rows = statement.query(&[]).unwrap();
+        rows.map(|row| match row.unwrap()[0] {
+            Value::Integer(id) => id,
+            _ => panic!("id is not an integer"),
+        })
+        .collect()
+    };
+    assert_eq!(
+        ids("select id from users where username = 'user7'"),
+        vec![7, 57, 107, 157]
+    );
+    // Rows come in the order of the index, by username and then by id
+    assert_eq!(
+        ids("select id from users where username > 'user47' and username < 'user5'"),
+        vec![48, 98, 148, 198, 49, 99, 149, 199]
+    );
+    assert_eq!(
+        ids("select id from users where email = 'person123@example.com'"),
+        vec![123]
+    );
+
+    // Updates and deletes keep the indexes up to date
+    connection
+        .execute(
+            "update users set username = 'renamed' where username = 'user7' and id > 100",
+            &[],
+        )
+        .unwrap();
+    connection
+        .execute("delete from users where username = 'user7'", &[])
+        .unwrap();
+    assert_eq!(
+        ids("select id from users where username = 'renamed'"),
+        vec![107, 157]
+    );
+    assert_eq!(
+        ids("select id from users where username = 'user7'"),
+        Vec::<i64>::new()
+    );
+    connection
+        .execute(
+            "insert into users values (7, 'user7', 'person7@example.com')",
+            &[],
+        )
+        .unwrap();
+
+    connection.execute("vacuum", &[]).unwrap();
+    drop(connection);
+
+    // The indexes are kept in the database file
+    let connection = Connection::open(&tempfile.filepath).unwrap();
+    let mut select = connection
+        .prepare("select id from users where username between 'user6' and 'user7'")
+        .unwrap();
+    let rows: Vec<_> = select.query(&[]).unwrap().map(Result::unwrap).collect();
+    let expected: Vec<_> = [6, 56, 106, 156, 7]
+        .into_iter()
+        .map(|id| vec![Value::Integer(id)])
+        .collect();
+    assert_eq!(rows, expected);
+    drop(select);
+
+    connection
+        .execute("drop index users_username", &[])
+        .unwrap();
+    let err = connection
+        .execute("drop index users_username", &[])
+        .unwrap_err();
+    assert_eq!(err.to_string(), "No such index: users_username.");
+    connection
+        .execute("drop index if exists users_username", &[])
+        .unwrap();
+    // Dropping the table drops its other index, leaving only the catalog
+    connection.execute("drop table users", &[]).unwrap();
+    let mut database = connection.database();
+    let freelist_count = database.pager.freelist_count().unwrap();
+    assert_eq!(freelist_count + 2, database.pager.num_pages);
+}
+
+#[test]
+fn enforces_column_constraints() {
+    let tempfile = TempFile::new();
+    let connection = Connection::open(&tempfile.filepath).unwrap();
+    connection
+        .execute(
+            "create table accounts (id integer primary key, \
+             name text(20) not null, \
+             email text(40) unique, \
+             balance integer default 100 check (balance >= 0), \
+             note text default ('new' || ' account'))",
+            &[],
+        )
+        .unwrap();
+
+    connection
+        .execute("insert into accounts (id, name) values (1, 'alice')", &[])
+        .unwrap();
+    let errors = [
+        (
+            "insert into accounts (id, email) values (2, 'bob@example.com')",
+            "Error: NOT NULL constraint failed: accounts.name.",
+        ),
+        (
+            "insert into accounts (id, name, balance) values (2, 'bob', -5)",
+            "Error: CHECK constraint failed: accounts.balance.",
+        ),
+        (
+            "update accounts set balance = -1 where id = 1",
+            "Error: CHECK constraint failed: accounts.balance.",
+        ),
+        (
+            "update accounts set name = null where id = 1",
+            "Error: NOT NULL constraint failed: accounts.name.",
+        ),
+    ];
+    for (sql, message) in errors {
+        let err = connection.execute(sql, &[]).unwrap_err();
+        assert_eq!(err.to_string(), message, "{sql}");
+    }
+
+    // NULLs never clash in a UNIQUE column
+    connection
+        .execute(
+            "insert into accounts (id, name, email) values \
+             (2, 'bob', 'bob@example.com'), (3, 'carol', null), (4, 'dave', null)",
+            &[],
+        )
+        .unwrap();
+    let err = connection
+        .execute(
+            "update accounts set email = 'bob@example.com' where id = 3",
+            &[],
+        )
+        .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Error: UNIQUE constraint failed: accounts.email."
+    );
+
+    drop(connection);
+    let connection = Connection::open(&tempfile.filepath).unwrap();
+    let err = connection
+        .execute(
+            "insert into accounts (id, name, email) values (5, 'eve', 'bob@example.com')",
+            &[],
+        )
+        .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Error: UNIQUE constraint failed: accounts.email."
+    );
+
+    let mut select = connection
+        .prepare("select * from accounts where id < 3")
+        .unwrap();
+    let rows: Vec<_> = select.query(&[]).unwrap().map(Result::unwrap).collect();
+    let text = |text: &str| Value::Text(text.to_owned());
+    assert_eq!(
+        rows,
+        vec![
+            vec![
+                Value::Integer(1),
+                text("alice"),
+                Value::Null,
+                Value::Integer(100),
+                text("new account")
+            ],
+            vec![
+                Value::Integer(2),
+                text("bob"),
+                text("bob@example.com"),
+                Value::Integer(100),
+                text("new account")
+            ]
+        ]
+    );
+
+    let err = connection
+        .execute(
+            "create table t (id integer primary key, a integer default (id + 1))",
+            &[],
+        )
+        .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Default value of column a is not constant."
+    );
+}
+
+#[test]
+fn enforces_foreign_keys() {
+    let tempfile = TempFile::new();
+    let connection = Connection::open(&tempfile.filepath).unwrap();
+    for sql in [
+        "create table teams (id integer primary key, code text unique)",
+        "create table players (id integer primary key, \
+         team integer default 1 references teams on delete set default, \
+         code text references teams (code) on update cascade on delete set null)",
+        "create table goals (id integer primary key, \
+         player integer references players on delete cascade)",
+        "create table fouls (id integer primary key, \
+         player integer references players on delete restrict)",
+        "insert into teams values (1, 'red'), (2, 'blue')",
+        "insert into players values (10, 1, 'red'), (11, 2, 'blue'), (12, null, null)",
+        "insert into goals values (100, 10), (101, 11), (102, 11)",
+        "insert into fouls values (200, 10)",
+    ] {
+        connection.execute(sql, &[]).unwrap();
+    }
+    let rows = |sql: &str| -> Vec<Vec<Value>> {
+        let mut statement = connection.prepare(sql).unwrap();
+        let rows = statement.query(&[]).unwrap().map(Result::unwrap).collect();
+        rows
+    };
+    let int = |integer: i64| Value::Integer(integer);
+    let text = |text: &str| Value::Text(text.to_owned());
+
+    let errors = [
+        (
+            "insert into players values (13, 3, null)",
+            "Error: FOREIGN KEY constraint failed: players.team.",
+        ),
+        (
+            "update players set code = 'green' where id = 12",
+            "Error: FOREIGN KEY constraint failed: players.code.",
+        ),
+        (
+            "delete from players where id = 10",
+            "Error: FOREIGN KEY constraint failed: fouls.player.",
+        ),
+        (
+            "create table bench (id integer primary key, team text references teams (code), \
+             player integer references fouls (player))",
+            "Foreign key of bench.player does not reference a primary key or UNIQUE column.",
+        ),
+        (
+            "drop table teams",
+            "Table teams is referenced by a foreign key of table players.",
+        ),
+    ];
+    for (sql, message) in errors {
+        let err = connection.execute(sql, &[]).unwrap_err();
+        assert_eq!(err.to_string(), message, "{sql}");
+    }
+
+    // Deleting a team moves its players to the default team and deletes
+    // the goals of players deleted along the way
+    connection
+        .execute("update teams set code = 'navy' where id = 2", &[])
+        .unwrap();
+    connection
+        .execute("delete from teams where id = 2", &[])
+        .unwrap();
+    assert_eq!(
+        rows("select * from players"),
+        vec![
+            vec![int(10), int(1), text("red")],
+            vec![int(11), int(1), Value::Null],
+            vec![int(12), Value::Null, Value::Null],
+        ]
+    );
+    connection
+        .execute("delete from players where id = 11", &[])
+        .unwrap();
+    assert_eq!(rows("select id from goals"), vec![vec![int(100)]]);
+
+    // A statement failing inside a transaction changes nothing, the
+    // statements before it keep their changes
+    connection.execute("begin", &[]).unwrap();
+    connection
+        .execute("insert into goals values (103, 10)", &[])
+        .unwrap();
+    let err = connection
+        .execute("insert into goals values (104, 12), (105, 99)", &[])
+        .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Error: FOREIGN KEY constraint failed: goals.player."
+    );
+    connection.execute("commit", &[]).unwrap();
+    assert_eq!(
+        rows("select id from goals"),
+        vec![vec![int(100)], vec![int(103)]]
+    );
+
+    // A deferred foreign key is checked when the transaction commits
+    connection
+        .execute(
+            "create table cards (id integer primary key, \
+             player integer references players deferrable initially deferred)",
+            &[],
+        )
+        .unwrap();
+    connection.execute("begin", &[]).unwrap();
+    connection
+        .execute("insert into cards values (300, 13)", &[])
+        .unwrap();
+    assert_eq!(
+        rows("pragma foreign_key_check"),
+        vec![vec![text("cards"), int(300), text("players"), int(0)]]
+    );
+    let err = connection.execute("commit", &[]).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Error: FOREIGN KEY constraint failed: cards.player."
+    );
+    connection
+        .execute("insert into players values (13, 1, null)", &[])
+        .unwrap();
+    connection.execute("commit", &[]).unwrap();
+    assert!(rows("pragma foreign_key_check(cards)").is_empty());
+}
+
+#[test]
+fn assigns_keys_to_rows_inserted_without_one() {
+    let tempfile = TempFile::new();
+    let connection = Connection::open(&tempfile.filepath).unwrap();
+    for sql in [
+        "create table notes (id integer primary key, body text)",
+        "create table events (id integer primary key autoincrement, name text)",
+    ] {
+        connection.execute(sql, &[]).unwrap();
+    }
+    assert_eq!(connection.last_insert_rowid(), 0);
+
+    // The next key is one more than the largest, NULL counts as left out
+    connection
+        .execute("insert into notes (body) values ('a'), ('b')", &[])
+        .unwrap();
+    connection
+        .execute("insert into notes values (?, 'c')", &[Value::Null])
+        .unwrap();
+    assert_eq!(connection.last_insert_rowid(), 3);
+    connection
+        .execute("insert into notes values (10, 'd')", &[])
+        .unwrap();
+    assert_eq!(connection.last_insert_rowid(), 10);
+    connection
+        .execute("delete from notes where id = 10", &[])
+        .unwrap();
+    connection
+        .execute("insert into notes (body) values ('e')", &[])
+        .unwrap();
+    assert_eq!(connection.last_insert_rowid(), 4);
+
+    // AUTOINCREMENT never hands out the key of a deleted row again, not
+    // even after the database is reopened
+    connection
+        .execute("insert into events (name) values ('start'), ('stop')", &[])
+        .unwrap();
+    connection
+        .execute("delete from events where id = 2", &[])
+        .unwrap();
+    drop(connection);
+    let connection = Connection::open(&tempfile.filepath).unwrap();
+    connection
+        .execute("insert into events (name) values ('restart')", &[])
+        .unwrap();
+    assert_eq!(connection.last_insert_rowid(), 3);
+
+    // A sequence row is keyed by its table's row in the catalog
+    let mut select = connection.prepare("select * from sqlite_sequence").unwrap();
+    let rows: Vec<_> = select.query(&[]).unwrap().map(Result::unwrap).collect();
+    assert_eq!(
+        rows,
+        vec![vec![
+            Value::Integer(2),
+            Value::Text("events".to_owned()),
+            Value::Integer(3)
+        ]]
+    );
+    drop(select);
+
+    connection
+        .execute(
+            "insert into notes values (9223372036854775807, 'last')",
+            &[],
+        )
+        .unwrap();
+    let err = connection
+        .execute("insert into notes (body) values ('full')", &[])
+        .unwrap_err();
+    assert_eq!(err.to_string(), "Error: Database full.");
+    let err = connection
+        .execute("drop table sqlite_sequence", &[])
+        .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Object name reserved for internal use: sqlite_sequence."
+    );
+}
+
+#[test]
+fn selects_the_last_inserted_key_with_a_function() {
+    let tempfile = TempFile::new();
+    create_users_table(&tempfile);
+
+    let input = vec![
+        "select last_insert_rowid()".to_owned(),
+        insert_full_user(1),
+        insert_full_user(7),
+        "select last_insert_rowid(), 1 + 2".to_owned(),
+        "select id * 2 from users where id = LAST_INSERT_ROWID()".to_owned(),
+        "select *".to_owned(),
+        "select random()".to_owned(),
+        "select last_insert_rowid(id) from users".to_owned(),
+        ".exit".to_owned(),
+    ];
+    let output = spawn_rust_sqlite(&tempfile, input);
+    let expected_output = vec![
+        "db > (0)".to_owned(),
+        "Executed.".to_owned(),
+        "db > Executed. Rows changed: 1.".to_owned(),
+        "db > Executed. Rows changed: 1.".to_owned(),
+        "db > (7, 3)".to_owned(),
+        "Executed.".to_owned(),
+        "db > (14)".to_owned(),
+        "Executed.".to_owned(),
+        "db > No tables specified.".to_owned(),
+        "db > No such function: random.".to_owned(),
+        "db > Wrong number of arguments to function last_insert_rowid().".to_owned(),
+        "db > ".to_owned(),
+    ];
+    assert_eq!(output, expected_output);
+}
+
+const CREATE_USERS_TABLE: &str =
+    "create table users (id integer primary key, username varchar(32), email varchar(255))";
+
+// Checksum of a journal record or WAL frame, as computed by the pager
+fn journal_checksum(bytes: &[u8]) -> u32 {
+    let (mut s1, mut s2) = (0u32, 0u32);
+    for word in bytes.chunks(4) {
+        let mut word_bytes = [0; 4];
+        word_bytes[..word.len()].copy_from_slice(word);
+        s1 = s1
+            .wrapping_add(u32::from_le_bytes(word_bytes))
+            .wrapping_add(s2);
+        s2 = s2.wrapping_add(s1);
+    }
+    s1 ^ s2
+}
+
+fn create_users_table(tempfile: &TempFile) {
+    let output = spawn_rust_sqlite(tempfile, vec![CREATE_USERS_TABLE.to_owned()]);
+    assert_eq!(output, vec!["db > Executed.", "db > "]);
+}
+
+// An insert of a row as wide as the users table allows, so that 13 rows fill a leaf
+fn insert_full_user(id: u32) -> String {
+    let (username, email) = full_user(id);
+    format!("insert into users values ({id}, '{username}', '{email}')")
+}
+
+// A full user row as the select prints it
+fn full_user_row(id: u32) -> String {
+    let (username, email) = full_user(id);
+    format!("({id}, {username}, {email})")
+}
+
+// Username and email of a full user row, padded to the lengths of their columns
+fn full_user(id: u32) -> (String, String) {
+    (
+        format!("{:.<32}", format!("user{id}")),
+        format!("{:.<255}", format!("person{id}@example.com")),
+    )
+}
+
+fn spawn_rust_sqlite(tempfile: &TempFile, input: Vec<String>) -> Vec<String> {
+    let mut process = rust_sqlite_exe()
+        .arg(&tempfile.filepath)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Unable to start the process.");
+
+    let stdin = process
+        .stdin
+        .as_mut()
+        .expect("Unable to pipe stdin to process.");
+
+    for line in input {
+        stdin
+            .write_all(format!("{}\n", line).as_bytes())
+            .unwrap_or_else(|_| panic!("Unable to write command `{}`", line));
+    }
+
+    let output = process
+        .wait_with_output()
+        .expect("Unable to get output from the process.");
+
+    str::from_utf8(&output.stdout)
+        .expect("Could not get process output.")
+        .lines()
         .map(str::to_owned)
         .collect()
 }
@@ -618,7 +2664,7 @@ struct TempFile {
 
 impl Drop for TempFile {
     fn drop(&mut self) {
-        if let Err(_) = std::fs::remove_file(&self.filepath) {
+        if std::fs::remove_file(&self.filepath).is_err() {
             println!("Could not delete the tempfile {}", self.filepath);
         }
     }